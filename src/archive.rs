@@ -0,0 +1,150 @@
+use std::{
+    fs::File,
+    io::Write,
+    path::{Component, Path, PathBuf},
+    str::FromStr,
+};
+
+use anyhow::{bail, Result};
+use flate2::{write::GzEncoder, Compression};
+use zip::{write::SimpleFileOptions, CompressionMethod, ZipWriter};
+
+use crate::{
+    cancel::{CancellationToken, Cancelled},
+    permissions::Permissions,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveFormat {
+    TarGz,
+    Zip,
+}
+
+///An archive file given with `--archive`, its format taken from the
+///extension.
+#[derive(Debug, Clone)]
+pub struct ArchiveTarget {
+    pub path: PathBuf,
+    pub format: ArchiveFormat,
+}
+
+impl FromStr for ArchiveTarget {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let lowercase = value.to_lowercase();
+        let format = if lowercase.ends_with(".tar.gz") || lowercase.ends_with(".tgz") {
+            ArchiveFormat::TarGz
+        } else if lowercase.ends_with(".zip") {
+            ArchiveFormat::Zip
+        } else {
+            return Err(format!(
+                "cannot infer the archive format of '{value}', use a .tar.gz, .tgz or .zip file"
+            ));
+        };
+        Ok(ArchiveTarget {
+            path: PathBuf::from(value),
+            format,
+        })
+    }
+}
+
+enum ArchiveWriter {
+    TarGz(Box<tar::Builder<GzEncoder<File>>>),
+    Zip(Box<ZipWriter<File>>),
+}
+
+impl ArchiveWriter {
+    fn create(path: &Path, format: ArchiveFormat) -> Result<Self> {
+        let file = File::create(path)?;
+        Ok(match format {
+            ArchiveFormat::TarGz => {
+                let encoder = GzEncoder::new(file, Compression::default());
+                ArchiveWriter::TarGz(Box::new(tar::Builder::new(encoder)))
+            }
+            ArchiveFormat::Zip => ArchiveWriter::Zip(Box::new(ZipWriter::new(file))),
+        })
+    }
+
+    fn add(&mut self, name: &str, contents: &[u8], mode: u32) -> Result<()> {
+        match self {
+            ArchiveWriter::TarGz(builder) => {
+                let mut header = tar::Header::new_gnu();
+                header.set_size(contents.len() as u64);
+                header.set_mode(mode);
+                header.set_entry_type(tar::EntryType::Regular);
+                builder.append_data(&mut header, name, contents)?;
+            }
+            ArchiveWriter::Zip(writer) => {
+                let options = SimpleFileOptions::default()
+                    .compression_method(CompressionMethod::Deflated)
+                    .unix_permissions(mode);
+                writer.start_file(name, options)?;
+                writer.write_all(contents)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(self) -> Result<()> {
+        match self {
+            ArchiveWriter::TarGz(builder) => builder.into_inner()?.finish()?.sync_all()?,
+            ArchiveWriter::Zip(writer) => writer.finish()?.sync_all()?,
+        }
+        Ok(())
+    }
+}
+
+///Name of an output file inside an archive: relative, with forward slashes.
+fn entry_name(path: &Path) -> String {
+    path.components()
+        .filter_map(|component| match component {
+            Component::Normal(part) => Some(part.to_string_lossy()),
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+///Writes `entries`, pairs of a relative path and its content, into the
+///archive. The archive is built next to its target and renamed into place
+///once complete. Returns the archived paths.
+pub fn write_archive(
+    target: &ArchiveTarget,
+    entries: &[(PathBuf, Vec<u8>)],
+    force: bool,
+    permissions: &Permissions,
+    cancel: &CancellationToken,
+) -> Result<Vec<PathBuf>> {
+    if target.path.exists() && !force {
+        bail!("Archive {:?} already exists", target.path);
+    }
+    let mut tmp_name = target.path.file_name().unwrap_or_default().to_os_string();
+    tmp_name.push(".tmp");
+    let tmp_path = target.path.with_file_name(tmp_name);
+
+    let result = (|| {
+        let mut writer = ArchiveWriter::create(&tmp_path, target.format)?;
+        for (path, content) in entries {
+            if cancel.is_cancelled() {
+                return Err(Cancelled {
+                    files_written: Vec::new(),
+                }
+                .into());
+            }
+            writer.add(&entry_name(path), content, permissions.archived_file_mode())?;
+        }
+        writer.finish()
+    })();
+
+    match result {
+        Ok(()) => {
+            std::fs::rename(&tmp_path, &target.path)?;
+            Ok(entries.iter().map(|(path, _)| path.clone()).collect())
+        }
+        Err(e) => {
+            let _ = std::fs::remove_file(&tmp_path);
+            Err(e)
+        }
+    }
+}
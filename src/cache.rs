@@ -0,0 +1,95 @@
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    input::{Export, InputFormat},
+    migrate::write_atomic,
+};
+
+///Bumped whenever the cached data changes shape, older entries are parsed
+///again
+const CACHE_VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "camelCase")]
+struct Entry {
+    version: u32,
+    ///Parsers of other releases may read the same file differently
+    tool_version: String,
+    export: Export,
+}
+
+///Parsed exports kept in a directory with `--cache-dir`, keyed by the hash
+///of the export and the format it was parsed as. Every problem with an
+///entry, a corrupt file or one of another version, counts as a miss, and
+///failing to store one is ignored. Exports holding credentials are never
+///stored.
+///
+///```
+///use subscription_migrator::cache::ParseCache;
+///use subscription_migrator::input::{parse_export, InputFormat};
+///
+///let dir = std::env::temp_dir().join(format!("parse-cache-{}", std::process::id()));
+///let cache = ParseCache::new(&dir);
+///let xml = br#"<applications><application name="billing"/></applications>"#;
+///let hash = subscription_migrator::plan::content_hash(xml);
+///assert!(cache.get(&hash, InputFormat::Xml).is_none());
+///
+///cache.put(&hash, InputFormat::Xml, &parse_export(&xml[..], InputFormat::Xml)?);
+///let cached = cache.get(&hash, InputFormat::Xml).unwrap();
+///assert_eq!(cached.applications[0].name, "billing");
+///assert!(cache.get(&hash, InputFormat::Json).is_none());
+///
+///std::fs::write(dir.join(format!("{}-xml.json", hash)), "{ not json")?;
+///assert!(cache.get(&hash, InputFormat::Xml).is_none());
+///# std::fs::remove_dir_all(&dir)?;
+///# anyhow::Ok(())
+///```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseCache {
+    directory: PathBuf,
+}
+
+impl ParseCache {
+    pub fn new(directory: &Path) -> Self {
+        ParseCache {
+            directory: directory.to_path_buf(),
+        }
+    }
+
+    fn path(&self, hash: &str, format: InputFormat) -> PathBuf {
+        let format = format!("{:?}", format).to_lowercase();
+        self.directory.join(format!("{}-{}.json", hash, format))
+    }
+
+    ///The export stored for `hash`, if there is a usable one.
+    pub fn get(&self, hash: &str, format: InputFormat) -> Option<Export> {
+        let content = std::fs::read(self.path(hash, format)).ok()?;
+        let entry = serde_json::from_slice::<Entry>(&content).ok()?;
+        let current =
+            entry.version == CACHE_VERSION && entry.tool_version == env!("CARGO_PKG_VERSION");
+        current.then_some(entry.export)
+    }
+
+    pub fn put(&self, hash: &str, format: InputFormat, export: &Export) {
+        if export
+            .applications
+            .iter()
+            .any(|app| app.credentials.is_some())
+        {
+            return;
+        }
+        let entry = Entry {
+            version: CACHE_VERSION,
+            tool_version: env!("CARGO_PKG_VERSION").to_string(),
+            export: export.clone(),
+        };
+        let Ok(json) = serde_json::to_vec(&entry) else {
+            return;
+        };
+        if std::fs::create_dir_all(&self.directory).is_ok() {
+            let _ = write_atomic(&self.path(hash, format), &json);
+        }
+    }
+}
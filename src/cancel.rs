@@ -0,0 +1,103 @@
+use std::{
+    fmt,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+///Shared flag asking a long running operation to stop at the next safe point.
+///Writes mark themselves with [`CancellationToken::writing`], so an
+///interrupt can tell whether stopping at once would leave output half
+///written.
+///
+///```
+///use subscription_migrator::cancel::CancellationToken;
+///
+///let cancel = CancellationToken::default();
+///assert!(!cancel.is_writing());
+///{
+///    let _writing = cancel.writing();
+///    assert!(cancel.clone().is_writing());
+///}
+///assert!(!cancel.is_writing());
+///```
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+    ///Writes in progress
+    writes: Arc<AtomicUsize>,
+    ///Checks left before the token cancels itself, see
+    ///[`CancellationToken::cancel_after`]
+    #[cfg(any(test, feature = "testing"))]
+    checks_left: Option<Arc<AtomicUsize>>,
+}
+
+impl CancellationToken {
+    ///A token that cancels itself once it was checked `checks` times, like
+    ///an interrupt arriving at a known point of a write. Only for tests.
+    #[cfg(any(test, feature = "testing"))]
+    pub fn cancel_after(checks: usize) -> Self {
+        CancellationToken {
+            checks_left: Some(Arc::new(AtomicUsize::new(checks))),
+            ..Default::default()
+        }
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        #[cfg(any(test, feature = "testing"))]
+        if let Some(checks_left) = &self.checks_left {
+            let left = checks_left.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |left| {
+                left.checked_sub(1)
+            });
+            if left.is_err() {
+                self.cancel();
+            }
+        }
+        self.cancelled.load(Ordering::SeqCst)
+    }
+
+    ///Marks a write as in progress until the guard is dropped.
+    pub fn writing(&self) -> Writing {
+        self.writes.fetch_add(1, Ordering::SeqCst);
+        Writing(Arc::clone(&self.writes))
+    }
+
+    pub fn is_writing(&self) -> bool {
+        self.writes.load(Ordering::SeqCst) > 0
+    }
+}
+
+///A write in progress, see [`CancellationToken::writing`].
+#[derive(Debug)]
+pub struct Writing(Arc<AtomicUsize>);
+
+impl Drop for Writing {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+///Returned when writing stopped because the token was cancelled.
+#[derive(Debug)]
+pub struct Cancelled {
+    ///Files completely written before the cancellation
+    pub files_written: Vec<PathBuf>,
+}
+
+impl fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Cancelled after writing {} file(s)",
+            self.files_written.len()
+        )
+    }
+}
+
+impl std::error::Error for Cancelled {}
@@ -0,0 +1,130 @@
+use std::{
+    collections::BTreeSet,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use anyhow::{bail, Context, Result};
+
+///The export files of a git work tree that differ from a commit, for
+///`--only-changed`. Paths are below the scanned root, like the ones the scan
+///finds.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ChangedExports {
+    ///Modified, added and untracked exports
+    pub changed: BTreeSet<PathBuf>,
+    ///Exports deleted since the commit, whose applications are candidates
+    ///for decommissioning
+    pub deleted: Vec<PathBuf>,
+}
+
+impl ChangedExports {
+    ///Lists the exports below `root` that differ from `reference` in the
+    ///git work tree `root` is part of.
+    pub fn since(root: &Path, reference: &str) -> Result<Self> {
+        let inside = git(root, &["rev-parse", "--is-inside-work-tree"]);
+        if !inside.is_ok_and(|output| output.trim() == "true") {
+            bail!(
+                "--only-changed needs {:?} to be inside a git work tree, drop the flag or use --since instead",
+                root
+            );
+        }
+        git(
+            root,
+            &[
+                "rev-parse",
+                "--verify",
+                "--quiet",
+                &format!("{}^{{commit}}", reference),
+            ],
+        )
+        .with_context(|| {
+            format!(
+                "{:?} is not a commit of the repository at {:?}",
+                reference, root
+            )
+        })?;
+        let diff = git(
+            root,
+            &[
+                "diff",
+                "--name-status",
+                "--no-renames",
+                "--relative",
+                "-z",
+                reference,
+                "--",
+            ],
+        )?;
+        let mut changes = Self::from_name_status(root, &diff);
+        let untracked = git(root, &["ls-files", "--others", "--exclude-standard", "-z"])?;
+        changes.changed.extend(
+            untracked
+                .split('\0')
+                .filter(|path| is_export(path))
+                .map(|path| root.join(path)),
+        );
+        Ok(changes)
+    }
+
+    ///Reads the output of `git diff --name-status -z`, keeping the exports.
+    ///
+    ///```
+    ///use std::path::Path;
+    ///use subscription_migrator::changes::ChangedExports;
+    ///
+    ///let output = "M\0app-billing/subscribe.xml\0D\0app-legacy/subscribe.xml\0M\0README.md\0A\0app-new/subscribe.json\0";
+    ///let changes = ChangedExports::from_name_status(Path::new("exports"), output);
+    ///assert_eq!(
+    ///    changes.changed.into_iter().collect::<Vec<_>>(),
+    ///    [Path::new("exports/app-billing/subscribe.xml"), Path::new("exports/app-new/subscribe.json")]
+    ///);
+    ///assert_eq!(changes.deleted, [Path::new("exports/app-legacy/subscribe.xml")]);
+    ///```
+    pub fn from_name_status(root: &Path, output: &str) -> Self {
+        let mut changes = ChangedExports::default();
+        let mut fields = output.split('\0');
+        while let (Some(status), Some(path)) = (fields.next(), fields.next()) {
+            if !is_export(path) {
+                continue;
+            }
+            match status {
+                "D" => changes.deleted.push(root.join(path)),
+                _ => {
+                    changes.changed.insert(root.join(path));
+                }
+            }
+        }
+        changes
+    }
+
+    ///Whether `export` is one of the changed exports.
+    pub fn contains(&self, export: &Path) -> bool {
+        self.changed.contains(export)
+    }
+}
+
+///Whether `path` names an export the scan picks up.
+fn is_export(path: &str) -> bool {
+    let path = Path::new(path);
+    path.file_name()
+        .is_some_and(|name| name == "subscribe.xml" || name == "subscribe.json")
+        || path.extension().is_some_and(|ext| ext == "zip")
+}
+
+fn git(root: &Path, args: &[&str]) -> Result<String> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(root)
+        .args(args)
+        .output()
+        .context("Failed to run git, is it installed?")?;
+    if !output.status.success() {
+        bail!(
+            "git {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    String::from_utf8(output.stdout).context("git printed a path that is not UTF-8")
+}
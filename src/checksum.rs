@@ -0,0 +1,94 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+
+use crate::{
+    line_ending::LineEnding,
+    migrate::write_atomic,
+    plan::content_hash,
+    validate::{Finding, Severity},
+};
+
+pub const CHECKSUM_DRIFT: &str = "checksum-drift";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ChecksumAlgorithm {
+    Sha256,
+}
+
+impl ChecksumAlgorithm {
+    fn extension(self) -> &'static str {
+        match self {
+            ChecksumAlgorithm::Sha256 => "sha256",
+        }
+    }
+
+    pub fn digest(self, contents: &[u8]) -> String {
+        match self {
+            ChecksumAlgorithm::Sha256 => content_hash(contents),
+        }
+    }
+
+    ///The sidecar of `path`, e.g. `subscription.yaml.sha256`.
+    pub fn sidecar_path(self, path: &Path) -> PathBuf {
+        let mut name = path.file_name().unwrap_or_default().to_os_string();
+        name.push(".");
+        name.push(self.extension());
+        path.with_file_name(name)
+    }
+}
+
+///A sidecar line in `sha256sum` format, naming the file relative to the
+///sidecar so `sha256sum -c` works from its directory.
+pub fn sidecar_content(digest: &str, path: &Path) -> String {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    format!("{digest}  {name}\n")
+}
+
+///Writes the sidecar of the file at `path` from the bytes on disk. A sidecar
+///that already holds the same line is left alone. Returns the digest.
+pub fn write_sidecar(
+    algorithm: ChecksumAlgorithm,
+    path: &Path,
+    line_ending: LineEnding,
+) -> Result<String> {
+    let contents = std::fs::read(path)?;
+    let digest = algorithm.digest(&contents);
+    let sidecar = algorithm.sidecar_path(path);
+    let line = line_ending
+        .apply(&sidecar_content(&digest, path))
+        .into_owned();
+    if std::fs::read_to_string(&sidecar).ok().as_deref() != Some(line.as_str()) {
+        write_atomic(&sidecar, line.as_bytes())
+            .with_context(|| format!("Failed to write {sidecar:?}"))?;
+    }
+    Ok(digest)
+}
+
+///Compares a file against its sidecar, if it has one.
+pub fn verify_sidecar(algorithm: ChecksumAlgorithm, path: &Path) -> Option<Finding> {
+    let sidecar = algorithm.sidecar_path(path);
+    let expected = std::fs::read_to_string(&sidecar).ok()?;
+    let expected = expected.split_whitespace().next().unwrap_or_default();
+    let message = match std::fs::read(path) {
+        Ok(contents) if algorithm.digest(&contents) == expected => return None,
+        Ok(contents) => format!(
+            "content has {} digest {}, {:?} records {}",
+            algorithm.extension(),
+            algorithm.digest(&contents),
+            sidecar,
+            expected
+        ),
+        Err(e) => format!("cannot be read to verify {:?}: {}", sidecar, e),
+    };
+    Some(Finding {
+        severity: Severity::Error,
+        rule: CHECKSUM_DRIFT,
+        application: String::new(),
+        api: None,
+        version: None,
+        source: path.to_path_buf(),
+        message,
+    })
+}
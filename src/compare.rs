@@ -0,0 +1,194 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+};
+
+use serde::Serialize;
+
+use crate::migrate::XmlApplication;
+
+///API name to version to environments, merged over every source of an
+///application so ordering and duplicate entries don't matter.
+type ApiIndex = BTreeMap<String, BTreeMap<String, BTreeSet<String>>>;
+
+#[derive(Debug, Default, Serialize)]
+pub struct Comparison {
+    added_applications: Vec<String>,
+    removed_applications: Vec<String>,
+    changed_applications: Vec<ApplicationChanges>,
+}
+
+#[derive(Debug, Default, Serialize)]
+struct ApplicationChanges {
+    name: String,
+    added_apis: Vec<String>,
+    removed_apis: Vec<String>,
+    version_changes: Vec<VersionChange>,
+    environment_changes: Vec<EnvironmentChange>,
+}
+
+#[derive(Debug, Serialize)]
+struct VersionChange {
+    api: String,
+    added: Vec<String>,
+    removed: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct EnvironmentChange {
+    api: String,
+    version: String,
+    added: Vec<String>,
+    removed: Vec<String>,
+}
+
+fn index(applications: &[XmlApplication]) -> BTreeMap<String, ApiIndex> {
+    let mut index: BTreeMap<String, ApiIndex> = BTreeMap::new();
+    for app in applications {
+        let apis = index.entry(app.name.clone()).or_default();
+        for sub in &app.apis {
+            apis.entry(sub.api_name.clone())
+                .or_default()
+                .entry(sub.api_version.clone())
+                .or_default()
+                .extend(sub.env.iter().cloned());
+        }
+    }
+    index
+}
+
+///Items of `a` missing in `b`.
+fn missing<'a, T: Ord + Clone + 'a>(
+    a: impl IntoIterator<Item = &'a T>,
+    b: &BTreeSet<&T>,
+) -> Vec<T> {
+    a.into_iter()
+        .filter(|item| !b.contains(item))
+        .cloned()
+        .collect()
+}
+
+impl Comparison {
+    pub fn new(left: &[XmlApplication], right: &[XmlApplication]) -> Self {
+        let left = index(left);
+        let right = index(right);
+        let left_names = left.keys().collect::<BTreeSet<_>>();
+        let right_names = right.keys().collect::<BTreeSet<_>>();
+
+        let mut comparison = Comparison {
+            added_applications: missing(right.keys(), &left_names),
+            removed_applications: missing(left.keys(), &right_names),
+            changed_applications: Vec::new(),
+        };
+
+        for (name, left_apis) in &left {
+            let Some(right_apis) = right.get(name) else {
+                continue;
+            };
+            let left_api_names = left_apis.keys().collect::<BTreeSet<_>>();
+            let right_api_names = right_apis.keys().collect::<BTreeSet<_>>();
+            let mut changes = ApplicationChanges {
+                name: name.clone(),
+                added_apis: missing(right_apis.keys(), &left_api_names),
+                removed_apis: missing(left_apis.keys(), &right_api_names),
+                ..Default::default()
+            };
+
+            for (api, left_versions) in left_apis {
+                let Some(right_versions) = right_apis.get(api) else {
+                    continue;
+                };
+                let left_version_names = left_versions.keys().collect::<BTreeSet<_>>();
+                let right_version_names = right_versions.keys().collect::<BTreeSet<_>>();
+                let added = missing(right_versions.keys(), &left_version_names);
+                let removed = missing(left_versions.keys(), &right_version_names);
+                if !added.is_empty() || !removed.is_empty() {
+                    changes.version_changes.push(VersionChange {
+                        api: api.clone(),
+                        added,
+                        removed,
+                    });
+                }
+
+                for (version, left_envs) in left_versions {
+                    let Some(right_envs) = right_versions.get(version) else {
+                        continue;
+                    };
+                    let added = missing(right_envs, &left_envs.iter().collect());
+                    let removed = missing(left_envs, &right_envs.iter().collect());
+                    if !added.is_empty() || !removed.is_empty() {
+                        changes.environment_changes.push(EnvironmentChange {
+                            api: api.clone(),
+                            version: version.clone(),
+                            added,
+                            removed,
+                        });
+                    }
+                }
+            }
+
+            if !changes.is_empty() {
+                comparison.changed_applications.push(changes);
+            }
+        }
+
+        comparison
+    }
+
+    pub fn is_identical(&self) -> bool {
+        self.added_applications.is_empty()
+            && self.removed_applications.is_empty()
+            && self.changed_applications.is_empty()
+    }
+}
+
+impl ApplicationChanges {
+    fn is_empty(&self) -> bool {
+        self.added_apis.is_empty()
+            && self.removed_apis.is_empty()
+            && self.version_changes.is_empty()
+            && self.environment_changes.is_empty()
+    }
+}
+
+impl fmt::Display for Comparison {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_identical() {
+            return writeln!(f, "No differences");
+        }
+        for name in &self.added_applications {
+            writeln!(f, "+ {}", name)?;
+        }
+        for name in &self.removed_applications {
+            writeln!(f, "- {}", name)?;
+        }
+        for app in &self.changed_applications {
+            writeln!(f, "~ {}", app.name)?;
+            for api in &app.added_apis {
+                writeln!(f, "  + api {}", api)?;
+            }
+            for api in &app.removed_apis {
+                writeln!(f, "  - api {}", api)?;
+            }
+            for change in &app.version_changes {
+                writeln!(f, "  ~ api {}", change.api)?;
+                for version in &change.added {
+                    writeln!(f, "    + version {}", version)?;
+                }
+                for version in &change.removed {
+                    writeln!(f, "    - version {}", version)?;
+                }
+            }
+            for change in &app.environment_changes {
+                writeln!(f, "  ~ api {} version {}", change.api, change.version)?;
+                for env in &change.added {
+                    writeln!(f, "    + environment {}", env)?;
+                }
+                for env in &change.removed {
+                    writeln!(f, "    - environment {}", env)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,28 @@
+use std::{collections::BTreeMap, path::Path};
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+
+use crate::token::YamlSecurity;
+
+///Settings read from the TOML file passed with `--config`. Command line
+///arguments take precedence over it.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    ///Environment names subscriptions may use
+    pub allowed_envs: Option<Vec<String>>,
+    ///Written for tokens that never expire
+    pub unlimited_token_validity: Option<String>,
+    ///Security blocks for token types without a built in mapping
+    #[serde(default)]
+    pub token_type_map: BTreeMap<String, YamlSecurity>,
+}
+
+impl Config {
+    pub fn load(path: &Path) -> Result<Self> {
+        let content =
+            std::fs::read_to_string(path).with_context(|| format!("Failed to read {:?}", path))?;
+        toml::from_str(&content).with_context(|| format!("Failed to parse config {:?}", path))
+    }
+}
@@ -0,0 +1,1111 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    path::PathBuf,
+    str::FromStr,
+};
+
+use anyhow::{bail, Result};
+use serde::Serialize;
+
+use crate::{
+    field_order::FieldOrder,
+    key_style::KeyStyle,
+    migrate::{
+        parse_xml_file, XmlApplication, XmlSubscription, YamlApi, YamlApiSubscription,
+        YamlApplication, YamlEnvironment, YamlEnvironmentName, YamlSubscription,
+        NON_PROD_PLANE_URL, PROD_PLANE_URL,
+    },
+};
+
+///Placeholder of [`ConvertOptions::description_template`] replaced by the
+///application name.
+pub const NAME_PLACEHOLDER: &str = "{name}";
+
+///How applications are turned into documents. The default produces what the
+///CLI writes without any flags.
+///
+///```
+///use subscription_migrator::convert::{convert_applications, ConvertOptions};
+///use subscription_migrator::migrate::{XmlApplication, XmlSubscription};
+///
+///let app = XmlApplication {
+///    name: "billing".to_string(),
+///    apis: vec![XmlSubscription {
+///        api_name: "invoices".to_string(),
+///        api_version: "v1".to_string(),
+///        env: vec!["production".to_string()],
+///    }],
+///    ..Default::default()
+///};
+///let options = ConvertOptions::default()
+///    .prod_plane_url("https://cp.example.com")
+///    .env_alias("production", "prod")
+///    .description_template("{name} (migrated)");
+///let documents = convert_applications(vec![app], &options);
+///
+///let environments = &documents[0].environments;
+///assert_eq!(environments[0].control_plane_url, "https://cp.example.com");
+///assert_eq!(environments[0].environments[0].name, "prod");
+///assert_eq!(documents[0].subscription.application.description, "billing (migrated)");
+///```
+#[derive(Debug, Clone)]
+pub struct ConvertOptions {
+    pub prod_plane_url: String,
+    pub non_prod_plane_url: String,
+    ///Environments listed under the prod control plane, after aliasing
+    pub prod_environments: Vec<String>,
+    ///Environment names replaced before anything else looks at them
+    pub env_aliases: BTreeMap<String, String>,
+    ///Description of every application, with [`NAME_PLACEHOLDER`] replaced
+    pub description_template: String,
+    ///Merge applications of the same name into one document
+    pub merge_by_name: bool,
+    pub key_style: KeyStyle,
+    pub field_order: FieldOrder,
+    ///Added to every document that doesn't have them yet
+    pub added_environments: Vec<AddedEnvironment>,
+    ///Write the tags of every application as its `labels`
+    pub emit_labels: bool,
+    ///List environments under one block per control plane URL, see
+    ///[`merge_environment_blocks`]
+    pub merge_env_blocks: bool,
+    ///Sort whole subscriptions into the control plane blocks, as the single
+    ///command does: a subscription listing a prod environment puts all of its
+    ///environments under the prod block, one listing any other environment
+    ///puts them all under the non-prod block
+    pub split_by_subscription: bool,
+}
+
+///An environment added to every document, given as `NAME[=URL]`. Without a
+///URL it goes under the prod or non-prod control plane like any other.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AddedEnvironment {
+    pub name: String,
+    pub control_plane_url: Option<String>,
+}
+
+impl FromStr for AddedEnvironment {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (name, url) = match value.split_once('=') {
+            Some((name, url)) => (name.trim(), Some(url.trim())),
+            None => (value.trim(), None),
+        };
+        if name.is_empty() || url.is_some_and(str::is_empty) {
+            return Err(format!("expected NAME or NAME=URL, got {:?}", value));
+        }
+        Ok(AddedEnvironment {
+            name: name.to_string(),
+            control_plane_url: url.map(parse_control_plane_url).transpose()?,
+        })
+    }
+}
+
+///Checks that `value` is an `http` or `https` URL with a host, an optional
+///port and path but no query or fragment, as a control plane URL has to be.
+///
+///```
+///use subscription_migrator::convert::parse_control_plane_url;
+///
+///for url in [
+///    "https://cp.example.com",
+///    "https://cp.example.com/",
+///    "http://localhost:8080/control-plane",
+///    "https://10.0.0.1",
+///] {
+///    assert_eq!(parse_control_plane_url(url).as_deref(), Ok(url));
+///}
+///
+///for url in [
+///    "",
+///    "cp.example.com",
+///    "htps://cp.example.com",
+///    "ftp://cp.example.com",
+///    "https://",
+///    "https:///path",
+///    "https://cp.example.com:port",
+///    "https://cp.example.com:",
+///    "https://cp example.com",
+///    "https://cp.example.com?env=prod",
+///    "https://cp.example.com#prod",
+///    "https://user@cp.example.com",
+///    "https://-cp.example.com",
+///    "https://cp..example.com",
+///] {
+///    assert!(parse_control_plane_url(url).is_err(), "{:?}", url);
+///}
+///```
+pub fn parse_control_plane_url(value: &str) -> Result<String, String> {
+    let invalid = |reason: &str| Err(format!("{:?} is no control plane URL, {}", value, reason));
+    let Some((scheme, rest)) = value.split_once("://") else {
+        return invalid("expected http:// or https:// followed by a host");
+    };
+    if !matches!(scheme, "http" | "https") {
+        return invalid("the scheme has to be http or https");
+    }
+    if rest.contains(|c: char| c.is_whitespace() || matches!(c, '?' | '#' | '@')) {
+        return invalid("it may not contain whitespace, a query, a fragment or credentials");
+    }
+    let authority = rest.split('/').next().unwrap_or_default();
+    let (host, port) = match authority.rsplit_once(':') {
+        Some((host, port)) => (host, Some(port)),
+        None => (authority, None),
+    };
+    if port.is_some_and(|port| port.parse::<u16>().is_err()) {
+        return invalid("the port has to be a number");
+    }
+    let label_ok = |label: &str| {
+        !label.is_empty()
+            && !label.starts_with('-')
+            && !label.ends_with('-')
+            && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+    };
+    if host.is_empty() || !host.split('.').all(label_ok) {
+        return invalid("the host is missing or malformed");
+    }
+    Ok(value.to_string())
+}
+
+impl Default for ConvertOptions {
+    fn default() -> Self {
+        ConvertOptions {
+            prod_plane_url: PROD_PLANE_URL.to_string(),
+            non_prod_plane_url: NON_PROD_PLANE_URL.to_string(),
+            prod_environments: vec!["prod".to_string()],
+            env_aliases: BTreeMap::new(),
+            description_template: format!("{NAME_PLACEHOLDER}-subscription"),
+            merge_by_name: true,
+            key_style: KeyStyle::default(),
+            field_order: FieldOrder::default(),
+            added_environments: Vec::new(),
+            emit_labels: false,
+            merge_env_blocks: true,
+            split_by_subscription: false,
+        }
+    }
+}
+
+impl ConvertOptions {
+    pub fn prod_plane_url(mut self, url: impl Into<String>) -> Self {
+        self.prod_plane_url = url.into();
+        self
+    }
+
+    pub fn non_prod_plane_url(mut self, url: impl Into<String>) -> Self {
+        self.non_prod_plane_url = url.into();
+        self
+    }
+
+    pub fn prod_environments(mut self, environments: Vec<String>) -> Self {
+        self.prod_environments = environments;
+        self
+    }
+
+    pub fn env_alias(mut self, from: impl Into<String>, to: impl Into<String>) -> Self {
+        self.env_aliases.insert(from.into(), to.into());
+        self
+    }
+
+    pub fn description_template(mut self, template: impl Into<String>) -> Self {
+        self.description_template = template.into();
+        self
+    }
+
+    pub fn add_environment(mut self, environment: AddedEnvironment) -> Self {
+        self.added_environments.push(environment);
+        self
+    }
+
+    pub fn key_style(mut self, style: KeyStyle) -> Self {
+        self.key_style = style;
+        self
+    }
+
+    pub fn field_order(mut self, order: FieldOrder) -> Self {
+        self.field_order = order;
+        self
+    }
+
+    pub fn emit_labels(mut self, emit: bool) -> Self {
+        self.emit_labels = emit;
+        self
+    }
+
+    pub fn merge_env_blocks(mut self, merge: bool) -> Self {
+        self.merge_env_blocks = merge;
+        self
+    }
+
+    ///Sorts whole subscriptions into the control plane blocks, see
+    ///[`ConvertOptions::split_by_subscription`].
+    ///
+    ///```
+    ///use subscription_migrator::convert::{convert_application, ConvertOptions};
+    ///use subscription_migrator::migrate::{XmlApplication, XmlSubscription};
+    ///
+    ///let app = XmlApplication {
+    ///    name: "shop".to_string(),
+    ///    apis: vec![XmlSubscription {
+    ///        api_name: "orders".to_string(),
+    ///        api_version: "1".to_string(),
+    ///        env: vec!["dev".to_string(), "prod".to_string()],
+    ///    }],
+    ///    ..Default::default()
+    ///};
+    ///let blocks = |options: &ConvertOptions| {
+    ///    let document = convert_application(app.clone(), options);
+    ///    document
+    ///        .environments
+    ///        .iter()
+    ///        .map(|block| block.environments.iter().map(|env| env.name.clone()).collect())
+    ///        .collect::<Vec<Vec<_>>>()
+    ///};
+    ///let options = ConvertOptions::default();
+    ///assert_eq!(blocks(&options), [vec!["dev"], vec!["prod"]]);
+    ///let options = options.split_by_subscription(true);
+    ///assert_eq!(blocks(&options), [vec!["dev", "prod"], vec!["dev", "prod"]]);
+    ///```
+    pub fn split_by_subscription(mut self, split: bool) -> Self {
+        self.split_by_subscription = split;
+        self
+    }
+
+    pub fn merge_by_name(mut self, merge: bool) -> Self {
+        self.merge_by_name = merge;
+        self
+    }
+
+    ///`env` after applying the aliases.
+    pub fn alias<'a>(&'a self, env: &'a str) -> &'a str {
+        self.env_aliases.get(env).map_or(env, String::as_str)
+    }
+
+    ///Whether the aliased `env` goes under the prod control plane.
+    pub fn is_prod(&self, env: &str) -> bool {
+        self.prod_environments.iter().any(|prod| prod == env)
+    }
+}
+
+///Converts applications into documents. With
+///[`ConvertOptions::merge_by_name`] the subscriptions of same-named
+///applications are merged in the order of their sources, otherwise every
+///application becomes its own document.
+pub fn convert_applications(
+    applications: Vec<XmlApplication>,
+    options: &ConvertOptions,
+) -> Vec<YamlApiSubscription> {
+    if !options.merge_by_name {
+        return applications
+            .into_iter()
+            .map(|app| convert_application(app, options))
+            .collect();
+    }
+
+    let mut ordered = applications;
+    ordered.sort_by(|a, b| a.source.cmp(&b.source));
+    let mut accumulator = ApplicationAccumulator::new(options.clone());
+    for app in ordered {
+        accumulator.add(app);
+    }
+    accumulator.finish()
+}
+
+///Converts applications like [`convert_applications`] and hands them back
+///without their subscriptions, credentials and comments, which only the
+///documents hold from then on. Callers that still need the names, sources
+///and token settings keep a single copy of the subscriptions this way.
+///
+///```
+///use subscription_migrator::convert::{convert_applications_stripped, ConvertOptions};
+///use subscription_migrator::migrate::parse_xml_file;
+///
+///let xml = r#"<applications>
+///  <application name="checkout" tokenValidity="3600">
+///    <subscription apiName="orders" apiVersion="1" environment="dev"/>
+///  </application>
+///  <application name="checkout">
+///    <subscription apiName="users" apiVersion="2" environment="dev"/>
+///  </application>
+///</applications>"#;
+///let (applications, _) = parse_xml_file(xml.as_bytes())?;
+///
+///let (stripped, documents) = convert_applications_stripped(applications, &ConvertOptions::default());
+///assert_eq!(documents.len(), 1);
+///assert_eq!(documents[0].subscription.application.apis.len(), 2);
+///assert_eq!(stripped.len(), 2);
+///assert!(stripped.iter().all(|app| app.name == "checkout" && app.apis.is_empty()));
+///assert_eq!(stripped[0].token_validity, 3600);
+///# anyhow::Ok(())
+///```
+pub fn convert_applications_stripped(
+    applications: Vec<XmlApplication>,
+    options: &ConvertOptions,
+) -> (Vec<XmlApplication>, Vec<YamlApiSubscription>) {
+    let mut stripped = Vec::with_capacity(applications.len());
+    let full = applications
+        .into_iter()
+        .map(|mut app| {
+            let apis = std::mem::take(&mut app.apis);
+            let credentials = app.credentials.take();
+            let comments = std::mem::take(&mut app.comments);
+            let full = XmlApplication {
+                apis,
+                credentials,
+                comments,
+                ..app.clone()
+            };
+            stripped.push(app);
+            full
+        })
+        .collect();
+    (stripped, convert_applications(full, options))
+}
+
+///Converts a subscribe.xml held in memory into one YAML document per
+///application, without touching the filesystem. This is all the crate
+///needs without the `cli` feature, e.g. in a browser.
+///
+///```
+///use subscription_migrator::convert::{convert_xml_str_to_yaml_string, ConvertOptions};
+///
+///let xml = r#"<applications>
+///  <application name="checkout" tokenType="JWT" tokenValidity="3600">
+///    <subscription apiName="orders" apiVersion="1" environment="dev"/>
+///  </application>
+///  <application name="billing" tokenType="JWT" tokenValidity="3600">
+///    <subscription apiName="invoices" apiVersion="2.0" environment="prod"/>
+///  </application>
+///</applications>"#;
+///
+///let documents = convert_xml_str_to_yaml_string(xml, &ConvertOptions::default())?;
+///assert_eq!(documents.len(), 2);
+///assert!(documents[0].contains("name: billing"));
+///assert!(documents[1].contains("name: checkout"));
+///assert!(documents[1].contains("- name: dev"));
+///# anyhow::Ok(())
+///```
+pub fn convert_xml_str_to_yaml_string(xml: &str, options: &ConvertOptions) -> Result<Vec<String>> {
+    let (applications, _) = parse_xml_file(xml.as_bytes())?;
+    convert_applications(applications, options)
+        .iter()
+        .map(|document| document.to_yaml())
+        .collect()
+}
+
+///An application merged from every source added so far, with the
+///subscriptions it already holds.
+#[derive(Debug)]
+struct Merged {
+    app: XmlApplication,
+    seen: HashSet<XmlSubscription>,
+}
+
+///Merges same-named applications as they are added, so the parse output of
+///every file can be dropped right away instead of holding all of them.
+///Adding the applications in source order gives the same documents as
+///[`convert_applications`].
+///
+///```
+///use subscription_migrator::convert::{convert_applications, ApplicationAccumulator, ConvertOptions};
+///use subscription_migrator::migrate::{
+///    unify_applilcations, XmlApplication, XmlSubscription, YamlApiSubscription,
+///};
+///
+///let applications = (0..3000)
+///    .map(|i| XmlApplication {
+///        name: format!("app-{}", i % 250),
+///        source: format!("export-{:04}/subscribe.xml", i).into(),
+///        token_validity: i as i32,
+///        apis: (0..i % 4)
+///            .map(|api| XmlSubscription {
+///                api_name: format!("api-{}", (i + api) % 7),
+///                api_version: format!("v{}", api % 2),
+///                env: vec![["dev", "test", "prod"][i % 3].to_string()],
+///            })
+///            .collect(),
+///        ..Default::default()
+///    })
+///    .collect::<Vec<_>>();
+///let yaml = |documents: Vec<YamlApiSubscription>| {
+///    documents
+///        .iter()
+///        .map(|document| document.to_yaml())
+///        .collect::<Result<Vec<_>, _>>()
+///};
+///
+///// the batch functions get every application at once, in no particular order
+///let mut shuffled = applications.clone();
+///shuffled.reverse();
+///let options = ConvertOptions::default().merge_by_name(true);
+///let batch = yaml(convert_applications(shuffled.clone(), &options))?;
+///assert_eq!(yaml(unify_applilcations(&shuffled))?, batch);
+///
+///let mut accumulator = ApplicationAccumulator::new(options);
+///for app in applications {
+///    accumulator.add(app);
+///}
+///assert_eq!(yaml(accumulator.finish())?, batch);
+///# anyhow::Ok(())
+///```
+#[derive(Debug, Default)]
+pub struct ApplicationAccumulator {
+    options: ConvertOptions,
+    merged: BTreeMap<String, Merged>,
+}
+
+impl ApplicationAccumulator {
+    pub fn new(options: ConvertOptions) -> Self {
+        ApplicationAccumulator {
+            options,
+            merged: BTreeMap::new(),
+        }
+    }
+
+    ///Merges `app` into the application of the same name. Credentials and
+    ///other per-application values of the first added one are kept, exact
+    ///duplicate subscriptions are dropped.
+    pub fn add(&mut self, app: XmlApplication) {
+        let Some(merged) = self.merged.get_mut(&app.name) else {
+            let mut app = app;
+            let mut seen = HashSet::new();
+            app.apis.retain(|sub| seen.insert(sub.clone()));
+            self.merged.insert(app.name.clone(), Merged { app, seen });
+            return;
+        };
+        let target = &mut merged.app;
+        target.duplicate_subscriptions += app.duplicate_subscriptions;
+        if target.credentials.is_none() {
+            target.credentials = app.credentials;
+        }
+        if target.description.is_none() {
+            target.description = app.description;
+        }
+        for comment in app.comments {
+            if !target.comments.contains(&comment) {
+                target.comments.push(comment);
+            }
+        }
+        target.tags.extend(app.tags);
+        target.tags.sort();
+        target.tags.dedup();
+        for sub in app.apis {
+            if !merged.seen.contains(&sub) {
+                merged.seen.insert(sub.clone());
+                target.apis.push(sub);
+            }
+        }
+    }
+
+    ///The documents of the merged applications, sorted by name.
+    pub fn finish(self) -> Vec<YamlApiSubscription> {
+        let options = self.options;
+        self.merged
+            .into_values()
+            .map(|merged| convert_application(merged.app, &options))
+            .collect()
+    }
+
+    ///The merged applications, sorted by name, for preparing them before
+    ///they are converted.
+    pub fn into_applications(self) -> Vec<XmlApplication> {
+        self.merged.into_values().map(|merged| merged.app).collect()
+    }
+}
+
+///What [`drop_environments`] removed.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct DroppedEnvironments {
+    ///Environments removed from subscriptions, counted per subscription
+    pub references: usize,
+    ///Subscriptions left without environments, which are removed too
+    pub subscriptions: usize,
+    ///Applications left without subscriptions
+    pub emptied: Vec<EmptiedApplication>,
+}
+
+impl DroppedEnvironments {
+    ///Adds what was dropped from the next applications.
+    pub fn merge(&mut self, other: DroppedEnvironments) {
+        self.references += other.references;
+        self.subscriptions += other.subscriptions;
+        self.emptied.extend(other.emptied);
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct EmptiedApplication {
+    pub name: String,
+    pub source: PathBuf,
+}
+
+///Removes the `dropped` environments, compared after aliasing, from every
+///subscription. A subscription that loses all its environments is removed,
+///an application that loses all its subscriptions is kept and converted
+///without APIs like any other empty application.
+///
+///```
+///use subscription_migrator::convert::{drop_environments, ConvertOptions};
+///use subscription_migrator::migrate::{XmlApplication, XmlSubscription};
+///
+///let app = |name: &str, envs: &[&str]| XmlApplication {
+///    name: name.to_string(),
+///    apis: vec![XmlSubscription {
+///        api_name: "orders".to_string(),
+///        api_version: "v1".to_string(),
+///        env: envs.iter().map(|env| env.to_string()).collect(),
+///    }],
+///    ..Default::default()
+///};
+///let mut apps = [app("shop", &["dev", "qa2"]), app("legacy", &["QA2"])];
+///let options = ConvertOptions::default().env_alias("QA2", "qa2");
+///
+///let dropped = drop_environments(&mut apps, &["qa2".to_string()], &options);
+///assert_eq!((dropped.references, dropped.subscriptions), (2, 1));
+///assert_eq!(apps[0].apis[0].env, ["dev"]);
+///assert!(apps[1].apis.is_empty());
+///assert_eq!(dropped.emptied.len(), 1);
+///assert_eq!(dropped.emptied[0].name, "legacy");
+///```
+pub fn drop_environments(
+    applications: &mut [XmlApplication],
+    dropped: &[String],
+    options: &ConvertOptions,
+) -> DroppedEnvironments {
+    let mut report = DroppedEnvironments::default();
+    if dropped.is_empty() {
+        return report;
+    }
+    for app in applications {
+        let before = app.apis.len();
+        app.apis.retain_mut(|sub| {
+            let envs = sub.env.len();
+            sub.env
+                .retain(|env| !dropped.iter().any(|drop| drop == options.alias(env)));
+            report.references += envs - sub.env.len();
+            envs == 0 || !sub.env.is_empty()
+        });
+        report.subscriptions += before - app.apis.len();
+        if before > 0 && app.apis.is_empty() {
+            report.emptied.push(EmptiedApplication {
+                name: app.name.clone(),
+                source: app.source.clone(),
+            });
+        }
+    }
+    report
+}
+
+///Merges subscriptions of the same API and version into one holding the
+///union of their environments, in the order they first appear.
+pub fn merge_environments(subscriptions: Vec<XmlSubscription>) -> Vec<XmlSubscription> {
+    let mut merged = Vec::<XmlSubscription>::new();
+    let mut positions = HashMap::new();
+    for sub in subscriptions {
+        let key = (sub.api_name.clone(), sub.api_version.clone());
+        let Some(&position) = positions.get(&key) else {
+            positions.insert(key, merged.len());
+            merged.push(sub);
+            continue;
+        };
+        let target = &mut merged[position];
+        for env in sub.env {
+            if !target.env.contains(&env) {
+                target.env.push(env);
+            }
+        }
+    }
+    merged
+}
+
+///Converts a single application. Subscriptions of the same API and version
+///are merged first, so an API subscribed in several environments is listed
+///once and every environment counts towards the control plane blocks.
+///
+///```
+///use subscription_migrator::convert::{convert_application, ConvertOptions};
+///use subscription_migrator::migrate::{XmlApplication, XmlSubscription};
+///
+///let subscription = |env: &str| XmlSubscription {
+///    api_name: "orders".to_string(),
+///    api_version: "1".to_string(),
+///    env: vec![env.to_string()],
+///};
+///let app = XmlApplication {
+///    name: "shop".to_string(),
+///    apis: vec![subscription("dev"), subscription("prod")],
+///    ..Default::default()
+///};
+///let document = convert_application(app, &ConvertOptions::default());
+///let environments = document
+///    .environments
+///    .iter()
+///    .map(|block| block.environments[0].name.as_str())
+///    .collect::<Vec<_>>();
+///assert_eq!(environments, ["dev", "prod"]);
+///assert_eq!(document.subscription.application.apis.len(), 1);
+///
+///// added environments join or open a block, once
+///let options = ConvertOptions::default()
+///    .add_environment("sandbox=https://sandbox.control-plane.com".parse()?)
+///    .add_environment("test".parse()?)
+///    .add_environment("prod".parse()?);
+///let app = XmlApplication {
+///    name: "shop".to_string(),
+///    apis: vec![subscription("dev"), subscription("prod")],
+///    ..Default::default()
+///};
+///let document = convert_application(app, &options);
+///let blocks = document
+///    .environments
+///    .iter()
+///    .map(|block| {
+///        let names = block.environments.iter().map(|env| env.name.as_str());
+///        (block.control_plane_url.as_str(), names.collect::<Vec<_>>())
+///    })
+///    .collect::<Vec<_>>();
+///assert_eq!(
+///    blocks,
+///    [
+///        ("https://non-prod.control-plane.com", vec!["dev", "test"]),
+///        ("https://prod.control-plane.com", vec!["prod"]),
+///        ("https://sandbox.control-plane.com", vec!["sandbox"]),
+///    ]
+///);
+///# Ok::<(), String>(())
+///```
+pub fn convert_application(app: XmlApplication, options: &ConvertOptions) -> YamlApiSubscription {
+    let mut non_prod_envs = Vec::<String>::new();
+    let mut prod_envs = Vec::<String>::new();
+    if options.split_by_subscription {
+        for sub in &app.apis {
+            let envs = sub.env.iter().map(|env| options.alias(env));
+            if envs.clone().any(|env| !options.is_prod(env)) {
+                add_environments(&mut non_prod_envs, envs.clone());
+            }
+            if envs.clone().any(|env| options.is_prod(env)) {
+                add_environments(&mut prod_envs, envs);
+            }
+        }
+    }
+    let subscriptions = merge_environments(app.apis);
+    if !options.split_by_subscription {
+        for env in subscriptions.iter().flat_map(|sub| &sub.env) {
+            let env = options.alias(env);
+            if options.is_prod(env) {
+                add_environments(&mut prod_envs, [env].into_iter());
+            } else {
+                add_environments(&mut non_prod_envs, [env].into_iter());
+            }
+        }
+    }
+
+    let mut environments = Vec::new();
+    for (url, envs) in [
+        (&options.non_prod_plane_url, non_prod_envs),
+        (&options.prod_plane_url, prod_envs),
+    ] {
+        if !envs.is_empty() {
+            environments.push(YamlEnvironment {
+                control_plane_url: url.clone(),
+                environments: envs
+                    .into_iter()
+                    .map(|name| YamlEnvironmentName { name })
+                    .collect(),
+            });
+        }
+    }
+
+    for added in &options.added_environments {
+        let present = environments
+            .iter()
+            .flat_map(|block| &block.environments)
+            .any(|env| env.name == added.name);
+        if present {
+            continue;
+        }
+        let url =
+            added
+                .control_plane_url
+                .clone()
+                .unwrap_or_else(|| match options.is_prod(&added.name) {
+                    true => options.prod_plane_url.clone(),
+                    false => options.non_prod_plane_url.clone(),
+                });
+        let name = YamlEnvironmentName {
+            name: added.name.clone(),
+        };
+        match environments
+            .iter_mut()
+            .find(|block| block.control_plane_url == url)
+        {
+            Some(block) => block.environments.push(name),
+            None => environments.push(YamlEnvironment {
+                control_plane_url: url,
+                environments: vec![name],
+            }),
+        }
+    }
+
+    if options.merge_env_blocks {
+        environments = merge_environment_blocks(environments);
+    }
+
+    let apis = subscriptions
+        .into_iter()
+        .map(|sub| YamlApi {
+            name: sub.api_name,
+            version: sub.api_version,
+        })
+        .collect();
+
+    let application = YamlApplication {
+        description: app.description.unwrap_or_else(|| {
+            options
+                .description_template
+                .replace(NAME_PLACEHOLDER, &app.name)
+        }),
+        name: app.name,
+        apis,
+        token_validity: None,
+        security: None,
+        credentials: app.credentials,
+        labels: match options.emit_labels {
+            true => app.tags,
+            false => Vec::new(),
+        },
+    };
+
+    YamlApiSubscription {
+        schema_version: None,
+        environments,
+        subscription: YamlSubscription { application },
+        metadata: None,
+        comments: app.comments,
+        key_style: options.key_style,
+        field_order: options.field_order,
+        part: None,
+    }
+}
+
+///Appends the `names` not in `envs` yet.
+fn add_environments<'a>(envs: &mut Vec<String>, names: impl Iterator<Item = &'a str>) {
+    for name in names {
+        if !envs.iter().any(|known| known == name) {
+            envs.push(name.to_string());
+        }
+    }
+}
+
+///Merges blocks with the same control plane URL into the first of them,
+///listing the sorted union of their environments. Other blocks are left
+///as they are. The platform rejects a
+///URL registered twice, which happens when e.g. the prod and non-prod URLs
+///are the same.
+///
+///```
+///use subscription_migrator::convert::{convert_application, merge_environment_blocks, ConvertOptions};
+///use subscription_migrator::key_style::KeyStyle;
+///use subscription_migrator::migrate::{XmlApplication, XmlSubscription, YamlEnvironment, YamlEnvironmentName};
+///
+///let block = |url: &str, envs: &[&str]| YamlEnvironment {
+///    control_plane_url: url.to_string(),
+///    environments: envs
+///        .iter()
+///        .map(|name| YamlEnvironmentName { name: name.to_string() })
+///        .collect(),
+///};
+///let names = |blocks: &[YamlEnvironment]| {
+///    blocks
+///        .iter()
+///        .map(|block| {
+///            let envs = block.environments.iter().map(|env| env.name.clone());
+///            (block.control_plane_url.clone(), envs.collect::<Vec<_>>())
+///        })
+///        .collect::<Vec<_>>()
+///};
+///
+///// two environments under one URL
+///let merged = merge_environment_blocks(vec![
+///    block("https://cp.example.com", &["test", "dev"]),
+///    block("https://other.example.com", &["qa"]),
+///    block("https://cp.example.com", &["prod", "dev"]),
+///]);
+///assert_eq!(
+///    names(&merged),
+///    [
+///        ("https://cp.example.com".to_string(), vec!["dev".to_string(), "prod".into(), "test".into()]),
+///        ("https://other.example.com".to_string(), vec!["qa".to_string()]),
+///    ]
+///);
+///
+///// distinct URLs stay as they are
+///let distinct = vec![block("https://a.example.com", &["dev"]), block("https://b.example.com", &["prod"])];
+///assert_eq!(merge_environment_blocks(distinct.clone()), distinct);
+///
+///// the prod and non-prod split with the same URL for both
+///let app = XmlApplication {
+///    name: "shop".to_string(),
+///    apis: vec![XmlSubscription {
+///        api_name: "orders".to_string(),
+///        api_version: "1".to_string(),
+///        env: vec!["prod".to_string(), "dev".to_string()],
+///    }],
+///    ..Default::default()
+///};
+///let options = ConvertOptions::default()
+///    .prod_plane_url("https://cp.example.com")
+///    .non_prod_plane_url("https://cp.example.com");
+///let document = convert_application(app.clone(), &options);
+///assert_eq!(
+///    names(&document.environments),
+///    [("https://cp.example.com".to_string(), vec!["dev".to_string(), "prod".into()])]
+///);
+///let v2 = convert_application(app.clone(), &options.clone().key_style(KeyStyle::V2));
+///assert_eq!(v2.to_yaml()?.matches("controlPlaneURL").count(), 1);
+///let document = convert_application(app, &options.merge_env_blocks(false));
+///assert_eq!(document.environments.len(), 2);
+///# anyhow::Ok(())
+///```
+pub fn merge_environment_blocks(blocks: Vec<YamlEnvironment>) -> Vec<YamlEnvironment> {
+    let mut merged = Vec::<(YamlEnvironment, bool)>::with_capacity(blocks.len());
+    for block in blocks {
+        match merged
+            .iter_mut()
+            .find(|(known, _)| known.control_plane_url == block.control_plane_url)
+        {
+            Some((known, extended)) => {
+                known.environments.extend(block.environments);
+                *extended = true;
+            }
+            None => merged.push((block, false)),
+        }
+    }
+    merged
+        .into_iter()
+        .map(|(mut block, extended)| {
+            if extended {
+                block.environments.sort_by(|a, b| a.name.cmp(&b.name));
+                block.environments.dedup();
+            }
+            block
+        })
+        .collect()
+}
+
+///Checks that every environment is listed in at most one control plane
+///block, that no block is empty and that every block has a URL. Conversion
+///upholds this for any sensible settings, a violation means the settings
+///contradict each other.
+///
+///```
+///use subscription_migrator::convert::{check_environment_blocks, convert_application, ConvertOptions};
+///use subscription_migrator::migrate::{XmlApplication, XmlSubscription, YamlEnvironment};
+///
+///let app = XmlApplication {
+///    name: "shop".to_string(),
+///    apis: vec![XmlSubscription {
+///        api_name: "orders".to_string(),
+///        api_version: "1".to_string(),
+///        env: vec!["staging".to_string(), "prod".to_string()],
+///    }],
+///    ..Default::default()
+///};
+///let document = convert_application(app.clone(), &ConvertOptions::default());
+///assert!(check_environment_blocks(&document).is_ok());
+///
+///// staging listed under both control planes
+///let mut both = document.clone();
+///let staging = both.environments[0].environments[0].clone();
+///both.environments[1].environments.push(staging);
+///assert!(check_environment_blocks(&both).is_err());
+///
+///// a block without environments
+///let mut empty = document.clone();
+///empty.environments.push(YamlEnvironment {
+///    control_plane_url: "https://cp.example.com".to_string(),
+///    environments: Vec::new(),
+///});
+///assert!(check_environment_blocks(&empty).is_err());
+///
+///// an empty --prod-plane-url
+///let document = convert_application(app, &ConvertOptions::default().prod_plane_url(""));
+///assert!(check_environment_blocks(&document).is_err());
+///```
+pub fn check_environment_blocks(document: &YamlApiSubscription) -> Result<()> {
+    let mut problems = Vec::new();
+    let mut planes = BTreeMap::<&str, Vec<&str>>::new();
+    for block in &document.environments {
+        if block.control_plane_url.trim().is_empty() {
+            problems.push(
+                "a control plane block has no URL, check --prod-plane-url and --non-prod-plane-url"
+                    .to_string(),
+            );
+        }
+        if block.environments.is_empty() {
+            problems.push(format!(
+                "the block of {:?} lists no environment",
+                block.control_plane_url
+            ));
+        }
+        for env in &block.environments {
+            let urls = planes.entry(&env.name).or_default();
+            if !urls.contains(&block.control_plane_url.as_str()) {
+                urls.push(&block.control_plane_url);
+            }
+        }
+    }
+    for (env, urls) in planes.iter().filter(|(_, urls)| urls.len() > 1) {
+        problems.push(format!(
+            "environment {:?} is listed under {}, check --env-alias and --prod-plane-envs",
+            env,
+            urls.iter()
+                .map(|url| format!("{:?}", url))
+                .collect::<Vec<_>>()
+                .join(" and ")
+        ));
+    }
+    if !problems.is_empty() {
+        bail!(
+            "Invalid environments in the document of {:?}:\n  {}",
+            document.name(),
+            problems.join("\n  ")
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block(url: &str, envs: &[&str]) -> YamlEnvironment {
+        YamlEnvironment {
+            control_plane_url: url.to_string(),
+            environments: envs
+                .iter()
+                .map(|name| YamlEnvironmentName {
+                    name: name.to_string(),
+                })
+                .collect(),
+        }
+    }
+
+    fn names(blocks: &[YamlEnvironment]) -> Vec<(&str, Vec<&str>)> {
+        blocks
+            .iter()
+            .map(|block| {
+                let envs = block.environments.iter().map(|env| env.name.as_str());
+                (block.control_plane_url.as_str(), envs.collect())
+            })
+            .collect()
+    }
+
+    fn shop(envs: &[&str]) -> XmlApplication {
+        XmlApplication {
+            name: "shop".to_string(),
+            apis: vec![XmlSubscription {
+                api_name: "orders".to_string(),
+                api_version: "1".to_string(),
+                env: envs.iter().map(|env| env.to_string()).collect(),
+            }],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn two_environments_on_one_url_share_a_block() {
+        let merged = merge_environment_blocks(vec![
+            block("https://cp.example.com", &["test"]),
+            block("https://cp.example.com", &["dev"]),
+        ]);
+        assert_eq!(
+            names(&merged),
+            [("https://cp.example.com", vec!["dev", "test"])]
+        );
+    }
+
+    #[test]
+    fn merged_block_keeps_the_position_of_the_first() {
+        let merged = merge_environment_blocks(vec![
+            block("https://b.example.com", &["qa"]),
+            block("https://a.example.com", &["prod"]),
+            block("https://b.example.com", &["dev", "qa"]),
+        ]);
+        assert_eq!(
+            names(&merged),
+            [
+                ("https://b.example.com", vec!["dev", "qa"]),
+                ("https://a.example.com", vec!["prod"]),
+            ]
+        );
+    }
+
+    #[test]
+    fn distinct_urls_are_left_untouched() {
+        let blocks = vec![
+            block("https://a.example.com", &["test", "dev"]),
+            block("https://b.example.com", &["prod"]),
+            block("https://c.example.com", &["sandbox"]),
+        ];
+        assert_eq!(merge_environment_blocks(blocks.clone()), blocks);
+        assert!(merge_environment_blocks(Vec::new()).is_empty());
+    }
+
+    #[test]
+    fn default_split_keeps_two_blocks() {
+        let document = convert_application(shop(&["prod", "dev"]), &ConvertOptions::default());
+        assert_eq!(
+            names(&document.environments),
+            [
+                (NON_PROD_PLANE_URL, vec!["dev"]),
+                (PROD_PLANE_URL, vec!["prod"])
+            ]
+        );
+    }
+
+    #[test]
+    fn split_with_one_url_merges_into_one_block() {
+        let options = ConvertOptions::default()
+            .prod_plane_url("https://cp.example.com")
+            .non_prod_plane_url("https://cp.example.com");
+        for key_style in [KeyStyle::Legacy, KeyStyle::V2] {
+            let document = convert_application(
+                shop(&["prod", "dev"]),
+                &options.clone().key_style(key_style),
+            );
+            assert_eq!(
+                names(&document.environments),
+                [("https://cp.example.com", vec!["dev", "prod"])],
+                "{:?}",
+                key_style
+            );
+        }
+
+        let document =
+            convert_application(shop(&["prod", "dev"]), &options.merge_env_blocks(false));
+        assert_eq!(
+            names(&document.environments),
+            [
+                ("https://cp.example.com", vec!["dev"]),
+                ("https://cp.example.com", vec!["prod"]),
+            ]
+        );
+    }
+
+    #[test]
+    fn added_environment_on_the_prod_url_joins_its_block() {
+        let options = ConvertOptions::default().add_environment(AddedEnvironment {
+            name: "dr".to_string(),
+            control_plane_url: Some(PROD_PLANE_URL.to_string()),
+        });
+        let document = convert_application(shop(&["prod", "dev"]), &options);
+        assert_eq!(
+            names(&document.environments),
+            [
+                (NON_PROD_PLANE_URL, vec!["dev"]),
+                (PROD_PLANE_URL, vec!["prod", "dr"])
+            ]
+        );
+    }
+}
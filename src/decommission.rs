@@ -0,0 +1,153 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+
+use crate::{
+    migrate::{subscription_path, write_atomic, YamlApiSubscription},
+    plan::{application_name, generated_files},
+};
+
+///Subscriptions of the existing output that a fresh export no longer has,
+///for a cleanup job to remove from the control plane.
+///
+///```
+///use subscription_migrator::decommission::Decommission;
+///use subscription_migrator::migrate::{parse_xml_file, subscription_path, unify_applilcations};
+///
+///let output = std::env::temp_dir().join(format!("decommission-doc-{}", std::process::id()));
+///let convert = |xml: &str| unify_applilcations(&parse_xml_file(xml.as_bytes()).unwrap().0);
+///let old = convert(r#"<applications>
+///    <application name="billing">
+///        <subscription apiName="invoices" apiVersion="v1" environment="dev"/>
+///        <subscription apiName="refunds" apiVersion="v1" environment="dev"/>
+///    </application>
+///    <application name="ledger">
+///        <subscription apiName="accounts" apiVersion="v2" environment="prod"/>
+///    </application>
+///</applications>"#);
+///for document in &old {
+///    let path = subscription_path(&output, document);
+///    std::fs::create_dir_all(path.parent().unwrap())?;
+///    std::fs::write(path, document.to_yaml()?)?;
+///}
+///
+///let new = convert(r#"<applications>
+///    <application name="billing">
+///        <subscription apiName="invoices" apiVersion="v1" environment="dev"/>
+///    </application>
+///</applications>"#);
+///let report = Decommission::new(&output, &new)?;
+///assert_eq!(report.applications[0].name, "billing");
+///assert_eq!(report.applications[0].subscriptions[0].api, "refunds");
+///assert_eq!(report.removed_applications[0].name, "ledger");
+///std::fs::remove_dir_all(&output)?;
+///# anyhow::Ok(())
+///```
+#[derive(Debug, Default, Serialize)]
+pub struct Decommission {
+    ///Applications still exported that lost subscriptions
+    pub applications: Vec<DecommissionedApplication>,
+    ///Applications missing from the export entirely
+    pub removed_applications: Vec<RemovedApplication>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DecommissionedApplication {
+    pub name: String,
+    pub path: PathBuf,
+    pub subscriptions: Vec<RemovedSubscription>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RemovedApplication {
+    pub name: String,
+    pub path: PathBuf,
+    ///Every subscription of the existing document
+    pub subscriptions: Vec<RemovedSubscription>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub struct RemovedSubscription {
+    pub api: String,
+    pub version: String,
+    pub environment: String,
+}
+
+///Every API, version and environment a document subscribes.
+fn subscriptions(document: &YamlApiSubscription) -> BTreeSet<RemovedSubscription> {
+    let environments = document
+        .environments
+        .iter()
+        .flat_map(|block| &block.environments)
+        .map(|env| env.name.clone())
+        .collect::<BTreeSet<_>>();
+    let mut subscriptions = BTreeSet::new();
+    for api in &document.subscription.application.apis {
+        for environment in &environments {
+            subscriptions.insert(RemovedSubscription {
+                api: api.name.clone(),
+                version: api.version.clone(),
+                environment: environment.clone(),
+            });
+        }
+    }
+    subscriptions
+}
+
+impl Decommission {
+    ///Compares the generated documents in `output` with the freshly
+    ///converted `documents`. Both must come from the same conversion
+    ///settings, otherwise renamed APIs or versions show up as removed.
+    pub fn new(output: &Path, documents: &[YamlApiSubscription]) -> Result<Self> {
+        let fresh = documents
+            .iter()
+            .map(|document| (subscription_path(output, document), document))
+            .collect::<BTreeMap<_, _>>();
+        let mut report = Decommission::default();
+        for path in generated_files(output)? {
+            let content = std::fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read {:?}", path))?;
+            let existing = YamlApiSubscription::from_yaml(&content)
+                .with_context(|| format!("Failed to parse {:?}", path))?;
+            let name = match existing.name() {
+                "" => application_name(&path),
+                name => name.to_string(),
+            };
+            let existing = subscriptions(&existing);
+            match fresh.get(&path) {
+                Some(document) => {
+                    let remaining = subscriptions(document);
+                    let removed = existing.difference(&remaining).cloned().collect::<Vec<_>>();
+                    if !removed.is_empty() {
+                        report.applications.push(DecommissionedApplication {
+                            name,
+                            path,
+                            subscriptions: removed,
+                        });
+                    }
+                }
+                None => report.removed_applications.push(RemovedApplication {
+                    name,
+                    path,
+                    subscriptions: existing.into_iter().collect(),
+                }),
+            }
+        }
+        Ok(report)
+    }
+
+    pub fn count(&self) -> usize {
+        self.applications
+            .iter()
+            .map(|app| app.subscriptions.len())
+            .sum()
+    }
+
+    pub fn write(&self, path: &Path) -> Result<()> {
+        write_atomic(path, serde_json::to_string_pretty(self)?.as_bytes())
+    }
+}
@@ -0,0 +1,174 @@
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::migrate::XmlApplication;
+
+///Name of the override file read next to the export of a directory
+pub const OVERRIDES_FILE: &str = "migrator-overrides.yaml";
+
+///One-off tweaks for the applications of a single source directory, read
+///from the `migrator-overrides.yaml` next to its export.
+///
+///```
+///use subscription_migrator::directory_overrides::OVERRIDES_FILE;
+///use subscription_migrator::pipeline::{self, SourceDir};
+///use subscription_migrator::timing::Timings;
+///
+///let root = std::env::temp_dir().join(format!("directory-overrides-doc-{}", std::process::id()));
+///let xml = r#"<applications><application name="checkout">
+///    <subscription apiName="orders" apiVersion="v1" environment="dev"/>
+///    <subscription apiName="legacy" apiVersion="v1" environment="dev"/>
+///</application></applications>"#;
+///for (app, overrides) in [
+///    ("app-plain", None),
+///    ("app-tweaked", Some("rename: shop\nextra_environments: [test]\nexcluded_apis: [legacy]\n")),
+///    ("app-invalid", Some("rename: shop\nowner: payments\n")),
+///] {
+///    std::fs::create_dir_all(root.join(app))?;
+///    std::fs::write(root.join(app).join("subscribe.xml"), xml)?;
+///    if let Some(overrides) = overrides {
+///        std::fs::write(root.join(app).join(OVERRIDES_FILE), overrides)?;
+///    }
+///}
+///let parse = |app: &str| pipeline::parse(&[SourceDir::new(root.join(app))], &mut Timings::default());
+///
+///let plain = parse("app-plain")?;
+///assert_eq!(plain.applications[0].name, "checkout");
+///assert_eq!(plain.applications[0].apis.len(), 2);
+///assert!(plain.directory_overrides.is_empty());
+///
+///let tweaked = parse("app-tweaked")?;
+///let app = &tweaked.applications[0];
+///assert_eq!((app.name.as_str(), app.original_name.as_str()), ("shop", "checkout"));
+///assert_eq!(app.apis.len(), 1);
+///assert_eq!(app.apis[0].env, ["dev", "test"]);
+///assert_eq!(tweaked.directory_overrides[0].changes.len(), 3);
+///
+///let error = format!("{:#}", parse("app-invalid").unwrap_err());
+///assert!(error.contains(OVERRIDES_FILE) && error.contains("owner"));
+///std::fs::remove_dir_all(&root)?;
+///# anyhow::Ok(())
+///```
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DirectoryOverrides {
+    ///Replaces the description rendered from the template
+    pub description: Option<String>,
+    ///New name of the application, the directory must hold only one
+    pub rename: Option<String>,
+    ///Added to every subscription
+    #[serde(default)]
+    pub extra_environments: Vec<String>,
+    ///Removed from every subscription, one left without environments is
+    ///dropped
+    #[serde(default)]
+    pub removed_environments: Vec<String>,
+    ///APIs whose subscriptions are dropped
+    #[serde(default)]
+    pub excluded_apis: Vec<String>,
+}
+
+///An override file and what it changed.
+#[derive(Debug, Clone, Serialize)]
+pub struct AppliedDirectoryOverride {
+    pub file: PathBuf,
+    ///One line per change
+    pub changes: Vec<String>,
+}
+
+impl fmt::Display for AppliedDirectoryOverride {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for change in &self.changes {
+            writeln!(f, "Applied {:?}: {}", self.file, change)?;
+        }
+        Ok(())
+    }
+}
+
+impl DirectoryOverrides {
+    ///The overrides of `directory`, `None` if it has no override file.
+    pub fn load(directory: &Path) -> Result<Option<(PathBuf, Self)>> {
+        let path = directory.join(OVERRIDES_FILE);
+        if !path.is_file() {
+            return Ok(None);
+        }
+        let content =
+            std::fs::read_to_string(&path).with_context(|| format!("Failed to read {:?}", path))?;
+        let overrides = serde_yaml::from_str(&content)
+            .with_context(|| format!("Failed to parse overrides {:?}", path))?;
+        Ok(Some((path, overrides)))
+    }
+
+    ///Applies the overrides to the applications of their directory,
+    ///returning the changes.
+    pub fn apply(&self, applications: &mut [XmlApplication]) -> Result<Vec<String>> {
+        let mut changes = Vec::new();
+        if self.rename.is_some() && applications.len() != 1 {
+            bail!(
+                "rename needs a directory with exactly one application, found {}",
+                applications.len()
+            );
+        }
+        for app in applications {
+            let name = app.name.clone();
+            let before = app.apis.len();
+            app.apis
+                .retain(|sub| !self.excluded_apis.contains(&sub.api_name));
+            if app.apis.len() < before {
+                changes.push(format!(
+                    "excluded {} subscription(s) of {:?}",
+                    before - app.apis.len(),
+                    name
+                ));
+            }
+
+            let mut removed = 0;
+            let mut added = 0;
+            let before = app.apis.len();
+            app.apis.retain_mut(|sub| {
+                let envs = sub.env.len();
+                sub.env
+                    .retain(|env| !self.removed_environments.contains(env));
+                removed += envs - sub.env.len();
+                if envs > 0 && sub.env.is_empty() {
+                    return false;
+                }
+                for env in &self.extra_environments {
+                    if !sub.env.contains(env) {
+                        sub.env.push(env.clone());
+                        added += 1;
+                    }
+                }
+                true
+            });
+            if removed > 0 {
+                changes.push(format!(
+                    "removed {} environment(s) of {:?}, dropping {} subscription(s)",
+                    removed,
+                    name,
+                    before - app.apis.len()
+                ));
+            }
+            if added > 0 {
+                changes.push(format!("added {} environment(s) to {:?}", added, name));
+            }
+
+            if let Some(description) = &self.description {
+                app.description = Some(description.clone());
+                changes.push(format!("set the description of {:?}", name));
+            }
+            if let Some(rename) = &self.rename {
+                if *rename != app.name {
+                    app.name = rename.clone();
+                    changes.push(format!("renamed {:?} to {:?}", name, rename));
+                }
+            }
+        }
+        Ok(changes)
+    }
+}
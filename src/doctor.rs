@@ -0,0 +1,276 @@
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    config::Config,
+    convert::parse_control_plane_url,
+    input::InputFormat,
+    migrate::probe_writable,
+    rename::RenameMap,
+    scan::{parse_directories, scan_directories, subscribe_file, MatchMode, Slice},
+};
+
+///File created and removed again to test that the output path is writable
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Status {
+    Pass,
+    Warn,
+    Fail,
+}
+
+impl Status {
+    ///Exit code for a run whose worst check had this status
+    pub fn exit_code(self) -> i32 {
+        match self {
+            Status::Pass => 0,
+            Status::Warn => 1,
+            Status::Fail => 2,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Check {
+    pub name: &'static str,
+    pub status: Status,
+    pub message: String,
+    ///What to do about a warning or failure
+    pub hint: Option<String>,
+}
+
+impl Check {
+    fn pass(name: &'static str, message: String) -> Self {
+        Check {
+            name,
+            status: Status::Pass,
+            message,
+            hint: None,
+        }
+    }
+
+    fn warn(name: &'static str, message: String, hint: &str) -> Self {
+        Check {
+            name,
+            status: Status::Warn,
+            message,
+            hint: Some(hint.to_string()),
+        }
+    }
+
+    fn fail(name: &'static str, message: String, hint: &str) -> Self {
+        Check {
+            name,
+            status: Status::Fail,
+            message,
+            hint: Some(hint.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for Check {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let status = match self.status {
+            Status::Pass => "pass",
+            Status::Warn => "warn",
+            Status::Fail => "fail",
+        };
+        write!(f, "[{}] {}: {}", status, self.name, self.message)?;
+        if let Some(hint) = &self.hint {
+            write!(f, "\n       hint: {}", hint)?;
+        }
+        Ok(())
+    }
+}
+
+///What doctor looks at, mirroring the arguments of a bulk run.
+pub struct Setup<'a> {
+    pub input: &'a Path,
+    pub name_prefix: &'a str,
+    pub include_hidden: bool,
+    pub mode: MatchMode,
+    pub output: &'a Path,
+    pub rename_map: Option<&'a Path>,
+    pub config: Option<&'a Path>,
+    ///Control plane URL overrides, each with the option that set it
+    pub control_plane_urls: &'a [(String, String)],
+    ///How many matched directories to parse
+    pub sample: usize,
+}
+
+///Runs every check in order. Checks that depend on an earlier one are skipped
+///when it failed. Scanning, parsing and writing go through the same functions
+///a migration uses.
+pub fn diagnose(setup: &Setup) -> Vec<Check> {
+    let mut checks = Vec::new();
+
+    let readable = std::fs::read_dir(setup.input).is_ok();
+    checks.push(if readable {
+        Check::pass("input", format!("{:?} is readable", setup.input))
+    } else if setup.input.exists() {
+        Check::fail(
+            "input",
+            format!("{:?} can't be read", setup.input),
+            "check the permissions of the input directory",
+        )
+    } else {
+        Check::fail(
+            "input",
+            format!("{:?} does not exist", setup.input),
+            "pass the export tree with --path",
+        )
+    });
+
+    if readable {
+        checks.extend(check_matches(setup));
+    }
+    checks.push(check_output(setup.output));
+    if let Some(path) = setup.rename_map {
+        checks.push(match RenameMap::from_file(path) {
+            Ok(_) => Check::pass("rename-map", format!("{:?} parses", path)),
+            Err(e) => Check::fail(
+                "rename-map",
+                format!("{:?}: {:#}", path, e),
+                "fix the rename map, it is TOML for .toml files and YAML otherwise",
+            ),
+        });
+    }
+    if let Some(path) = setup.config {
+        checks.push(match Config::load(path) {
+            Ok(_) => Check::pass("config", format!("{:?} parses", path)),
+            Err(e) => Check::fail(
+                "config",
+                format!("{:#}", e),
+                "fix the config file, unknown keys are rejected",
+            ),
+        });
+    }
+    checks.extend(
+        setup
+            .control_plane_urls
+            .iter()
+            .map(|(option, url)| check_control_plane_url(option, url)),
+    );
+    checks
+}
+
+fn check_control_plane_url(option: &str, url: &str) -> Check {
+    match parse_control_plane_url(url) {
+        Ok(url) => Check::pass(
+            "control-plane",
+            format!("{} {:?} is a valid URL", option, url),
+        ),
+        Err(e) => Check::fail(
+            "control-plane",
+            format!("{}: {}", option, e),
+            "pass a URL like https://cp.example.com, it is written into every document",
+        ),
+    }
+}
+
+fn check_matches(setup: &Setup) -> Vec<Check> {
+    let scan = match scan_directories(
+        setup.input,
+        setup.name_prefix,
+        setup.include_hidden,
+        setup.mode,
+    ) {
+        Ok(scan) => scan,
+        Err(e) => {
+            return vec![Check::fail(
+                "matches",
+                format!("{:#}", e),
+                "fix the .migratorignore file",
+            )]
+        }
+    };
+    if scan.matched.is_empty() {
+        return vec![Check::fail(
+            "matches",
+            format!(
+                "no directory matches prefix {:?}: {}",
+                setup.name_prefix, scan
+            ),
+            "check --name-prefix, or pass --hidden for directories starting with a dot",
+        )];
+    }
+
+    let total = scan.matched.len();
+    let mut checks = vec![Check::pass(
+        "matches",
+        format!("{} directories match {:?}", total, setup.name_prefix),
+    )];
+    let sample = Slice::Sample {
+        size: setup.sample,
+        seed: 0,
+    }
+    .select(scan.matched);
+    let failed = sample
+        .iter()
+        .filter_map(|directory| {
+            parse_directories(std::slice::from_ref(directory), InputFormat::Auto)
+                .err()
+                .map(|e| (subscribe_file(directory), e))
+        })
+        .collect::<Vec<(PathBuf, anyhow::Error)>>();
+
+    if failed.is_empty() {
+        checks.push(Check::pass(
+            "parse",
+            format!("{} of {} subscribe.xml files parse", sample.len(), total),
+        ));
+    }
+    for (file, e) in failed {
+        checks.push(Check::fail(
+            "parse",
+            format!("{:?}: {:#}", file, e),
+            "fix or ignore the directory in .migratorignore",
+        ));
+    }
+    checks
+}
+
+fn check_output(output: &Path) -> Check {
+    if !output.is_dir() {
+        return Check::fail(
+            "output",
+            format!("{:?} is not a directory", output),
+            "create the output directory or pass another one with --output-path",
+        );
+    }
+    if let Err(e) = probe_writable(output) {
+        return Check::fail(
+            "output",
+            format!("{:?} is not writable: {:#}", output, e),
+            "check the permissions of the output directory",
+        );
+    }
+
+    let existing = std::fs::read_dir(output)
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok())
+                .filter(|entry| {
+                    entry
+                        .file_name()
+                        .to_string_lossy()
+                        .ends_with("-subscription")
+                })
+                .count()
+        })
+        .unwrap_or_default();
+    if existing > 0 {
+        Check::warn(
+            "output",
+            format!(
+                "{:?} already contains {} generated directories",
+                output, existing
+            ),
+            "pass --force to overwrite them or choose an empty output directory",
+        )
+    } else {
+        Check::pass("output", format!("{:?} is writable", output))
+    }
+}
@@ -0,0 +1,112 @@
+use std::fmt;
+
+use anyhow::{bail, Result};
+use serde::Serialize;
+
+///A count a bulk run was expected to reach and the one it did.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct ExpectedCount {
+    pub expected: usize,
+    pub actual: usize,
+    ///How far `actual` may be off, in percent of `expected`
+    pub tolerance: f64,
+}
+
+impl ExpectedCount {
+    pub fn matches(&self) -> bool {
+        let allowed = self.expected as f64 * self.tolerance / 100.0;
+        (self.actual as f64 - self.expected as f64).abs() <= allowed
+    }
+}
+
+impl fmt::Display for ExpectedCount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "expected {}", self.expected)?;
+        if self.tolerance > 0.0 {
+            write!(f, " ±{}%", self.tolerance)?;
+        }
+        write!(f, ", found {}", self.actual)
+    }
+}
+
+///The counts of `--expect-applications` and `--expect-directories`, taken
+///after every filter, right before writing.
+///
+///```
+///use subscription_migrator::expect::{ExpectedCount, Expectations};
+///
+///let expectations = Expectations {
+///    applications: Some(ExpectedCount { expected: 312, actual: 300, tolerance: 5.0 }),
+///    directories: None,
+///};
+///assert!(expectations.check().is_ok());
+///
+///let expectations = Expectations {
+///    applications: Some(ExpectedCount { expected: 312, actual: 280, tolerance: 5.0 }),
+///    directories: Some(ExpectedCount { expected: 40, actual: 40, tolerance: 0.0 }),
+///};
+///assert_eq!(
+///    expectations.check().unwrap_err().to_string(),
+///    "Applications: expected 312 ±5%, found 280. Nothing was written, check the scan or update the expectation"
+///);
+///```
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct Expectations {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub applications: Option<ExpectedCount>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub directories: Option<ExpectedCount>,
+}
+
+impl Expectations {
+    pub fn is_empty(&self) -> bool {
+        self.applications.is_none() && self.directories.is_none()
+    }
+
+    ///Each expectation with its name.
+    pub fn counts(&self) -> impl Iterator<Item = (&'static str, &ExpectedCount)> {
+        [
+            ("Applications", &self.applications),
+            ("Directories", &self.directories),
+        ]
+        .into_iter()
+        .filter_map(|(name, count)| Some((name, count.as_ref()?)))
+    }
+
+    ///Fails listing the counts that are off.
+    pub fn check(&self) -> Result<()> {
+        let mismatches = self
+            .counts()
+            .filter(|(_, count)| !count.matches())
+            .map(|(name, count)| format!("{}: {}", name, count))
+            .collect::<Vec<_>>();
+        if !mismatches.is_empty() {
+            bail!(
+                "{}. Nothing was written, check the scan or update the expectation",
+                mismatches.join("; ")
+            );
+        }
+        Ok(())
+    }
+}
+
+///Parses `--expect-tolerance`, a percentage with or without `%`.
+///
+///```
+///use subscription_migrator::expect::parse_tolerance;
+///
+///assert_eq!(parse_tolerance("5%"), Ok(5.0));
+///assert_eq!(parse_tolerance("2.5"), Ok(2.5));
+///assert!(parse_tolerance("-1%").is_err());
+///assert!(parse_tolerance("five").is_err());
+///```
+pub fn parse_tolerance(value: &str) -> std::result::Result<f64, String> {
+    let percent = value
+        .trim_end_matches('%')
+        .parse::<f64>()
+        .map_err(|_| format!("{:?} is not a percentage like 5%", value))?;
+    match percent.is_finite() && percent >= 0.0 {
+        true => Ok(percent),
+        false => Err(format!("{:?} is not a percentage like 5%", value)),
+    }
+}
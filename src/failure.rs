@@ -0,0 +1,297 @@
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Result;
+use serde::Serialize;
+
+use crate::{
+    cancel::Cancelled,
+    migrate::{write_atomic, Conflict, WriteStatus, WrittenFile},
+    scan::{ExportProblem, NothingToMigrate, UnreadableExport},
+};
+
+///The step of a bulk run that failed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Phase {
+    Scan,
+    Parse,
+    Convert,
+    Write,
+}
+
+///Why a step failed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FailureKind {
+    Io,
+    ///The directory has no export
+    MissingExport,
+    ///The export is a symlink to nothing
+    DanglingSymlink,
+    ///The export is a directory
+    ExportIsDirectory,
+    ///The export can't be read
+    PermissionDenied,
+    ///The export could not be read as XML, JSON, CSV or a WSO2 archive
+    InvalidExport,
+    ///A check or conversion setting rejected the applications
+    Check,
+    ///An existing output directory was not overwritten
+    Conflict,
+    NothingToMigrate,
+    Cancelled,
+    Other,
+}
+
+impl FailureKind {
+    ///Classifies `error` by the typed errors in its chain, falling back to
+    ///what usually fails in `phase`.
+    pub fn of(error: &anyhow::Error, phase: Phase) -> Self {
+        for cause in error.chain() {
+            if cause.is::<Cancelled>() {
+                return FailureKind::Cancelled;
+            }
+            if cause.is::<NothingToMigrate>() {
+                return FailureKind::NothingToMigrate;
+            }
+            if let Some(unreadable) = cause.downcast_ref::<UnreadableExport>() {
+                return match unreadable.problem {
+                    ExportProblem::Missing => FailureKind::MissingExport,
+                    ExportProblem::DanglingSymlink { .. } => FailureKind::DanglingSymlink,
+                    ExportProblem::IsDirectory => FailureKind::ExportIsDirectory,
+                    ExportProblem::PermissionDenied => FailureKind::PermissionDenied,
+                };
+            }
+            if cause.is::<std::io::Error>() {
+                return FailureKind::Io;
+            }
+        }
+        match phase {
+            Phase::Parse => FailureKind::InvalidExport,
+            Phase::Convert => FailureKind::Check,
+            Phase::Scan | Phase::Write => FailureKind::Other,
+        }
+    }
+}
+
+///Returned when some documents were skipped or failed to be written.
+#[derive(Debug)]
+pub struct NotWritten {
+    pub files: Vec<WrittenFile>,
+}
+
+impl fmt::Display for NotWritten {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} file(s) were skipped or failed", self.files.len())
+    }
+}
+
+impl std::error::Error for NotWritten {}
+
+///Returned before writing anything when output directories already exist
+///and neither `--force` nor `--if-exists skip` was given.
+#[derive(Debug)]
+pub struct Conflicts {
+    pub conflicts: Vec<Conflict>,
+}
+
+impl fmt::Display for Conflicts {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} application(s) would be written into an existing directory, nothing was written. \
+             Pass --force to overwrite them or --if-exists skip to leave them alone:",
+            self.conflicts.len()
+        )?;
+        for conflict in &self.conflicts {
+            write!(f, "\n  {}", conflict)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for Conflicts {}
+
+///Returned at the end of a run that left out applications with
+///`--if-exists skip` and wrote everything else.
+#[derive(Debug)]
+pub struct SkippedExisting {
+    pub conflicts: Vec<Conflict>,
+}
+
+impl SkippedExisting {
+    ///Fails with the skipped applications, if there are any.
+    pub fn check(conflicts: &[Conflict]) -> Result<()> {
+        match conflicts.is_empty() {
+            true => Ok(()),
+            false => Err(SkippedExisting {
+                conflicts: conflicts.to_vec(),
+            }
+            .into()),
+        }
+    }
+}
+
+impl fmt::Display for SkippedExisting {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} application(s) were skipped by --if-exists skip",
+            self.conflicts.len()
+        )
+    }
+}
+
+impl std::error::Error for SkippedExisting {}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Failure {
+    ///The source directory, or the output directory of a document that
+    ///wasn't written
+    pub directory: PathBuf,
+    pub phase: Phase,
+    pub kind: FailureKind,
+    pub message: String,
+    ///Position of the application in the export, counted from 1, when a
+    ///single application of a concatenated export failed to parse
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub application_index: Option<usize>,
+}
+
+///Every failure of a bulk run, written as a JSON array with
+///`--error-report`.
+///
+///```
+///use subscription_migrator::cancel::CancellationToken;
+///use subscription_migrator::convert::ConvertOptions;
+///use subscription_migrator::failure::{ErrorReport, Phase};
+///use subscription_migrator::pipeline::{self, Sink, SourceDir, WritePolicy};
+///use subscription_migrator::timing::Timings;
+///
+///let root = std::env::temp_dir().join(format!("failure-doc-{}", std::process::id()));
+///for (app, xml) in [
+///    ("app-billing", r#"<applications><application name="billing">
+///        <subscription apiName="invoices" apiVersion="v1" environment="dev"/>
+///    </application></applications>"#),
+///    ("app-broken", "<applications><application"),
+///] {
+///    std::fs::create_dir_all(root.join(app))?;
+///    std::fs::write(root.join(app).join("subscribe.xml"), xml)?;
+///}
+///// billing was migrated before, so its directory is in the way
+///let output = root.join("out");
+///std::fs::create_dir_all(output.join("billing-subscription"))?;
+///
+///let sources = ["app-billing", "app-broken"].map(|app| SourceDir::new(root.join(app)));
+///let mut timings = Timings::default();
+///let mut parsed = pipeline::parse_skipping_failures(&sources, &mut timings);
+///let mut errors = ErrorReport::default();
+///for (directory, e) in parsed.failures.drain(..) {
+///    errors.record(Phase::Parse, &directory, &e);
+///}
+///let documents = pipeline::convert(&parsed, &ConvertOptions::default());
+///let written = pipeline::write(
+///    &documents,
+///    &parsed.applications,
+///    &Sink::Directory(output.clone()),
+///    &WritePolicy::default(),
+///    &CancellationToken::default(),
+///    &mut timings,
+///);
+///assert!(errors.check(Phase::Write, &output, written).is_err());
+///
+///let json = serde_json::to_value(&errors)?;
+///assert_eq!(json[0]["directory"], root.join("app-broken").to_str().unwrap());
+///assert_eq!(json[0]["kind"], "invalid_export");
+///assert_eq!(json[1]["phase"], "write");
+///assert_eq!(json[1]["kind"], "conflict");
+///assert_eq!(json[1]["directory"], output.join("billing-subscription").to_str().unwrap());
+///std::fs::remove_dir_all(&root)?;
+///# anyhow::Ok(())
+///```
+#[derive(Debug, Default, Serialize)]
+#[serde(transparent)]
+pub struct ErrorReport(pub Vec<Failure>);
+
+impl ErrorReport {
+    ///Adds `error` of `phase`. A [`NotWritten`] error is added as one
+    ///failure per document, [`Conflicts`] and [`SkippedExisting`] as one
+    ///per application.
+    pub fn record(&mut self, phase: Phase, directory: &Path, error: &anyhow::Error) {
+        let conflicts = error
+            .downcast_ref::<Conflicts>()
+            .map(|e| &e.conflicts)
+            .or_else(|| {
+                error
+                    .downcast_ref::<SkippedExisting>()
+                    .map(|e| &e.conflicts)
+            });
+        if let Some(conflicts) = conflicts {
+            for conflict in conflicts {
+                self.0.push(Failure {
+                    directory: conflict.directory.clone(),
+                    phase,
+                    kind: FailureKind::Conflict,
+                    message: conflict.to_string(),
+                    application_index: None,
+                });
+            }
+            return;
+        }
+        if let Some(not_written) = error.downcast_ref::<NotWritten>() {
+            for file in &not_written.files {
+                let (kind, message) = match &file.status {
+                    WriteStatus::Skipped(reason) => (FailureKind::Conflict, reason),
+                    WriteStatus::Failed(reason) => (FailureKind::Io, reason),
+                    _ => continue,
+                };
+                self.0.push(Failure {
+                    directory: file.path.parent().unwrap_or(&file.path).to_path_buf(),
+                    phase,
+                    kind,
+                    message: message.clone(),
+                    application_index: None,
+                });
+            }
+            return;
+        }
+        self.0.push(Failure {
+            directory: directory.to_path_buf(),
+            phase,
+            kind: FailureKind::of(error, phase),
+            message: format!("{:#}", error),
+            application_index: None,
+        });
+    }
+
+    ///Adds the parse failure of the `index`th application of `file`,
+    ///counted from 1, while the other applications are still migrated.
+    pub fn record_application(&mut self, file: &Path, index: usize, error: &anyhow::Error) {
+        self.0.push(Failure {
+            directory: file.to_path_buf(),
+            phase: Phase::Parse,
+            kind: FailureKind::of(error, Phase::Parse),
+            message: format!("{:#}", error),
+            application_index: Some(index),
+        });
+    }
+
+    ///Records the error of `result`, if any, and passes it on.
+    pub fn check<T>(&mut self, phase: Phase, directory: &Path, result: Result<T>) -> Result<T> {
+        if let Err(e) = &result {
+            self.record(phase, directory, e);
+        }
+        result
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn write(&self, path: &Path) -> Result<()> {
+        write_atomic(path, serde_json::to_string_pretty(self)?.as_bytes())
+    }
+}
@@ -0,0 +1,134 @@
+use serde_yaml::{Mapping, Value};
+
+use crate::key_style::KeyStyle;
+
+///Order of the keys of the generated documents. Documents are serialized in
+///the order of their fields and reordered right before writing, both orders
+///are read back.
+///
+///```
+///use subscription_migrator::convert::{convert_application, ConvertOptions};
+///use subscription_migrator::field_order::FieldOrder;
+///use subscription_migrator::key_style::KeyStyle;
+///use subscription_migrator::migrate::{XmlApplication, XmlSubscription, YamlApiSubscription};
+///
+///let app = XmlApplication {
+///    name: "billing".to_string(),
+///    apis: vec![XmlSubscription {
+///        api_name: "invoices".to_string(),
+///        api_version: "v1".to_string(),
+///        env: vec!["dev".to_string()],
+///    }],
+///    ..Default::default()
+///};
+///let options = ConvertOptions::default().field_order(FieldOrder::SubscriptionFirst);
+///let document = convert_application(app.clone(), &options);
+///assert_eq!(
+///    document.to_yaml()?,
+///    "subscriptions:
+///  application:
+///    name: billing
+///    description: billing-subscription
+///    apis:
+///    - name: invoices
+///      version: v1
+///environments:
+///- environment:
+///  - name: dev
+///  controlPlaneUrl: https://non-prod.control-plane.com
+///"
+///);
+///
+///let v2 = convert_application(app.clone(), &options.key_style(KeyStyle::V2));
+///assert!(v2.to_yaml()?.starts_with("subscription:\n"));
+///assert!(v2.to_yaml()?.contains("- environment:\n  - name: dev\n  controlPlaneURL: "));
+///
+///// either order reads back into the same document, keeping its order
+///let read = YamlApiSubscription::from_yaml(&document.to_yaml()?)?;
+///assert_eq!(read.field_order, FieldOrder::SubscriptionFirst);
+///let default = convert_application(app, &ConvertOptions::default());
+///assert_eq!(YamlApiSubscription::from_yaml(&default.to_yaml()?)?.field_order, FieldOrder::EnvironmentsFirst);
+///assert!(read.same_content(&default));
+///# anyhow::Ok(())
+///```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum FieldOrder {
+    ///`environments` first, each block with its control plane URL before
+    ///its environment names
+    #[default]
+    EnvironmentsFirst,
+    ///`subscriptions` first, each block with its environment names before
+    ///its control plane URL
+    SubscriptionFirst,
+}
+
+const ENVIRONMENTS_KEY: &str = "environments";
+const ENVIRONMENT_NAMES_KEY: &str = "environment";
+
+impl FieldOrder {
+    ///The order of a serialized document, judged by its top level keys.
+    pub fn detect(document: &Value) -> Self {
+        let Some(mapping) = document.as_mapping() else {
+            return FieldOrder::default();
+        };
+        let position = |keys: &[&str]| {
+            mapping
+                .keys()
+                .position(|key| key.as_str().is_some_and(|key| keys.contains(&key)))
+        };
+        match (
+            position(&KeyStyle::subscription_keys()),
+            position(&[ENVIRONMENTS_KEY]),
+        ) {
+            (Some(subscription), Some(environments)) if subscription < environments => {
+                FieldOrder::SubscriptionFirst
+            }
+            _ => FieldOrder::EnvironmentsFirst,
+        }
+    }
+
+    ///Reorders the keys of a document serialized in field order, in either
+    ///key style.
+    pub fn apply(self, mut document: Value) -> Value {
+        if self == FieldOrder::EnvironmentsFirst {
+            return document;
+        }
+        if let Value::Mapping(mapping) = &mut document {
+            move_before(mapping, &KeyStyle::subscription_keys(), ENVIRONMENTS_KEY);
+            if let Some(Value::Sequence(blocks)) = mapping.get_mut(ENVIRONMENTS_KEY) {
+                for block in blocks.iter_mut().filter_map(Value::as_mapping_mut) {
+                    let urls = KeyStyle::control_plane_keys();
+                    let url = urls.iter().find(|key| block.contains_key(**key));
+                    if let Some(url) = url {
+                        move_before(block, &[ENVIRONMENT_NAMES_KEY], url);
+                    }
+                }
+            }
+        }
+        document
+    }
+}
+
+///Moves the first of `keys` in `mapping` right before `anchor`, keeping the
+///order of everything else.
+fn move_before(mapping: &mut Mapping, keys: &[&str], anchor: &str) {
+    let Some(key) = keys.iter().find(|key| mapping.contains_key(**key)) else {
+        return;
+    };
+    if !mapping.contains_key(anchor) {
+        return;
+    }
+    let moved = mapping.remove_entry(*key).expect("the key is present");
+    let mut moved = Some(moved);
+    *mapping = std::mem::take(mapping)
+        .into_iter()
+        .flat_map(|(key, value)| {
+            let before = match key.as_str() == Some(anchor) {
+                true => moved.take(),
+                false => None,
+            };
+            before.into_iter().chain([(key, value)])
+        })
+        .collect();
+}
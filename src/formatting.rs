@@ -0,0 +1,74 @@
+#[cfg(feature = "cli")]
+use std::path::{Path, PathBuf};
+
+#[cfg(feature = "cli")]
+use anyhow::{Context, Result};
+
+#[cfg(feature = "cli")]
+use crate::{
+    line_ending::{normalize, LineEnding},
+    migrate::{write_atomic, YamlApiSubscription},
+};
+
+pub const SUBSCRIPTION_FILE: &str = "subscription.yaml";
+
+#[derive(Debug, Default)]
+#[cfg(feature = "cli")]
+pub struct FormatOutcome {
+    ///Files whose canonical form differs from their content
+    pub changed: Vec<PathBuf>,
+    pub unchanged: Vec<PathBuf>,
+    ///Files that could not be read or deserialized, with the reason
+    pub failed: Vec<(PathBuf, anyhow::Error)>,
+}
+
+///Re-serializes existing subscription files through the canonical writer.
+///Without `check` changed files are rewritten, in place or below `output` at
+///the same relative path. Files only differing in their line endings count as
+///unchanged. A file that fails does not stop the others.
+#[cfg(feature = "cli")]
+pub fn format_files(
+    root: &Path,
+    files: &[PathBuf],
+    output: Option<&Path>,
+    check: bool,
+    line_ending: LineEnding,
+) -> FormatOutcome {
+    let mut outcome = FormatOutcome::default();
+    for file in files {
+        match format_file(root, file, output, check, line_ending) {
+            Ok(true) => outcome.changed.push(file.clone()),
+            Ok(false) => outcome.unchanged.push(file.clone()),
+            Err(e) => outcome.failed.push((file.clone(), e)),
+        }
+    }
+    outcome
+}
+
+#[cfg(feature = "cli")]
+fn format_file(
+    root: &Path,
+    file: &Path,
+    output: Option<&Path>,
+    check: bool,
+    line_ending: LineEnding,
+) -> Result<bool> {
+    let content = std::fs::read_to_string(file)?;
+    let document = YamlApiSubscription::from_yaml(&content).context("Failed to deserialize")?;
+    let formatted = document.to_yaml()?;
+    let changed = formatted != normalize(&content);
+
+    if !check {
+        let target = match output {
+            Some(output) => output.join(file.strip_prefix(root).unwrap_or(file)),
+            None => file.to_path_buf(),
+        };
+        if changed || target != file {
+            if let Some(parent) = target.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            write_atomic(&target, line_ending.apply(&formatted).as_bytes())?;
+        }
+    }
+    Ok(changed)
+}
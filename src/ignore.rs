@@ -0,0 +1,81 @@
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use globset::{GlobBuilder, GlobMatcher};
+
+pub const IGNORE_FILE: &str = ".migratorignore";
+
+///Gitignore style patterns excluding directories from the scan. Later rules
+///take precedence, `!` negates a rule, a trailing `/` only matches
+///directories and a leading `/` anchors the pattern at the scan root.
+#[derive(Debug, Default)]
+pub struct IgnoreFile {
+    rules: Vec<IgnoreRule>,
+}
+
+#[derive(Debug)]
+struct IgnoreRule {
+    matcher: GlobMatcher,
+    negated: bool,
+    dir_only: bool,
+}
+
+impl IgnoreFile {
+    ///Loads the ignore file at the scan root, if there is one.
+    pub fn load(root: &Path) -> Result<Option<Self>> {
+        let path = root.join(IGNORE_FILE);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let content =
+            std::fs::read_to_string(&path).with_context(|| format!("Failed to read {:?}", path))?;
+        Self::parse(&content)
+            .map(Some)
+            .with_context(|| format!("Invalid ignore file {:?}", path))
+    }
+
+    pub fn parse(content: &str) -> Result<Self> {
+        let mut rules = Vec::new();
+        for (index, line) in content.lines().enumerate() {
+            let line = line.trim_end();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (negated, pattern) = match line.strip_prefix('!') {
+                Some(pattern) => (true, pattern),
+                None => (false, line.strip_prefix('\\').unwrap_or(line)),
+            };
+            let (dir_only, pattern) = match pattern.strip_suffix('/') {
+                Some(pattern) => (true, pattern),
+                None => (false, pattern),
+            };
+            let pattern = match pattern.strip_prefix('/') {
+                Some(anchored) => anchored.to_string(),
+                None if pattern.contains('/') => pattern.to_string(),
+                None => format!("**/{}", pattern),
+            };
+
+            let matcher = GlobBuilder::new(&pattern)
+                .literal_separator(true)
+                .build()
+                .with_context(|| format!("line {}: invalid pattern {:?}", index + 1, line))?
+                .compile_matcher();
+            rules.push(IgnoreRule {
+                matcher,
+                negated,
+                dir_only,
+            });
+        }
+        Ok(IgnoreFile { rules })
+    }
+
+    ///Whether `relative`, a path relative to the scan root, is excluded.
+    pub fn is_ignored(&self, relative: &Path, is_dir: bool) -> bool {
+        self.rules
+            .iter()
+            .rev()
+            .find(|rule| (is_dir || !rule.dir_only) && rule.matcher.is_match(relative))
+            .is_some_and(|rule| !rule.negated)
+    }
+}
@@ -0,0 +1,158 @@
+use std::{collections::BTreeMap, io::Read};
+
+use anyhow::{bail, Context, Result};
+
+use crate::migrate::{XmlApplication, XmlSubscription};
+
+///Header names of the CSV inventory columns. The environment column is
+///optional, rows without one get a subscription without an environment.
+#[derive(Debug, Clone)]
+pub struct CsvColumns {
+    pub application: String,
+    pub api: String,
+    pub version: String,
+    pub environment: String,
+}
+
+impl Default for CsvColumns {
+    fn default() -> Self {
+        CsvColumns {
+            application: "application".to_string(),
+            api: "api".to_string(),
+            version: "version".to_string(),
+            environment: "environment".to_string(),
+        }
+    }
+}
+
+impl CsvColumns {
+    ///The default headers with the given `(column, header)` overrides.
+    pub fn with_overrides(overrides: &[(String, String)]) -> Result<Self> {
+        let mut columns = CsvColumns::default();
+        for (column, header) in overrides {
+            let field = match column.as_str() {
+                "application" => &mut columns.application,
+                "api" => &mut columns.api,
+                "version" => &mut columns.version,
+                "environment" => &mut columns.environment,
+                _ => bail!(
+                    "Unknown column {:?}, expected application, api, version or environment",
+                    column
+                ),
+            };
+            field.clone_from(header);
+        }
+        Ok(columns)
+    }
+}
+
+///Reads an inventory with one subscription per row and groups the rows into
+///applications, in the order they first appear. Exact duplicate rows are
+///dropped like duplicate subscriptions in a subscribe.xml. Every row
+///missing a required value is reported with its line number. The `source`
+///of the returned applications is left empty for the caller to fill in.
+///
+///The same inventory as XML converts to the same documents:
+///
+///```
+///use subscription_migrator::import::{parse_csv_file, CsvColumns};
+///use subscription_migrator::migrate::{parse_xml_file, unify_applilcations};
+///
+///let csv = "App,API,Version,Stage
+///billing,invoices,v1,dev
+///billing,invoices,v1,dev
+///billing,invoices,v2,prod
+///";
+///let xml = r#"<applications>
+///    <application name="billing">
+///        <subscription apiName="invoices" apiVersion="v1" environment="dev"/>
+///        <subscription apiName="invoices" apiVersion="v2" environment="prod"/>
+///    </application>
+///</applications>"#;
+///let columns = CsvColumns {
+///    application: "App".to_string(),
+///    api: "API".to_string(),
+///    version: "Version".to_string(),
+///    environment: "Stage".to_string(),
+///};
+///
+///let from_csv = unify_applilcations(&parse_csv_file(csv.as_bytes(), &columns)?);
+///let from_xml = unify_applilcations(&parse_xml_file(xml.as_bytes())?.0);
+///assert_eq!(from_csv[0].to_yaml()?, from_xml[0].to_yaml()?);
+///# anyhow::Ok(())
+///```
+pub fn parse_csv_file(file: impl Read, columns: &CsvColumns) -> Result<Vec<XmlApplication>> {
+    let mut reader = csv::ReaderBuilder::new().flexible(true).from_reader(file);
+    let headers = reader.headers().context("Failed to read the CSV header")?;
+    let position = |header: &str| headers.iter().position(|h| h.trim() == header);
+    let mut missing_headers = Vec::new();
+    let mut required = Vec::new();
+    for header in [&columns.application, &columns.api, &columns.version] {
+        match position(header) {
+            Some(index) => required.push(index),
+            None => missing_headers.push(header.as_str()),
+        }
+    }
+    if !missing_headers.is_empty() {
+        bail!(
+            "The CSV header has no column {}",
+            missing_headers.join(", ")
+        );
+    }
+    let environment = position(&columns.environment);
+
+    let mut order = Vec::<String>::new();
+    let mut subscriptions = BTreeMap::<String, Vec<XmlSubscription>>::new();
+    let mut invalid_rows = Vec::new();
+    for record in reader.records() {
+        let record = record?;
+        let line = record.position().map_or(0, |position| position.line());
+        let value = |index: usize| record.get(index).unwrap_or_default().trim().to_string();
+        let [application, api, version] = [0, 1, 2].map(|i| value(required[i]));
+        let missing = [
+            (&columns.application, &application),
+            (&columns.api, &api),
+            (&columns.version, &version),
+        ]
+        .into_iter()
+        .filter(|(_, value)| value.is_empty())
+        .map(|(header, _)| header.as_str())
+        .collect::<Vec<_>>();
+        if !missing.is_empty() {
+            invalid_rows.push(format!("line {}: missing {}", line, missing.join(", ")));
+            continue;
+        }
+        if !subscriptions.contains_key(&application) {
+            order.push(application.clone());
+        }
+        let env = environment.map(value).filter(|env| !env.is_empty());
+        subscriptions
+            .entry(application)
+            .or_default()
+            .push(XmlSubscription {
+                api_name: api,
+                api_version: version,
+                env: env.into_iter().collect(),
+            });
+    }
+    if !invalid_rows.is_empty() {
+        bail!(
+            "{} row(s) are missing required values:\n  {}",
+            invalid_rows.len(),
+            invalid_rows.join("\n  ")
+        );
+    }
+
+    Ok(order
+        .into_iter()
+        .map(|name| {
+            let subscriptions = subscriptions.remove(&name).unwrap_or_default();
+            XmlApplication {
+                original_name: name.clone(),
+                name,
+                ..Default::default()
+            }
+            .with_subscriptions(subscriptions)
+        })
+        .collect())
+}
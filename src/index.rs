@@ -0,0 +1,124 @@
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    line_ending::LineEnding,
+    migrate::{write_atomic, YamlApiSubscription},
+    plan::content_hash,
+    yaml_quoting::quote_yaml_11_scalars,
+};
+
+pub const INDEX_FILE: &str = "index.yaml";
+
+///Every generated subscription below an output path, sorted by name.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Index {
+    applications: Vec<IndexEntry>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct IndexEntry {
+    name: String,
+    ///Relative to the output path
+    path: PathBuf,
+    apis: usize,
+    environments: Vec<String>,
+    ///sha256 of the subscription file
+    hash: String,
+}
+
+impl IndexEntry {
+    fn new(document: &YamlApiSubscription, path: PathBuf, contents: &[u8]) -> Self {
+        let mut environments = Vec::<String>::new();
+        for env in document
+            .environments
+            .iter()
+            .flat_map(|env| &env.environments)
+        {
+            if !environments.contains(&env.name) {
+                environments.push(env.name.clone());
+            }
+        }
+        IndexEntry {
+            name: document.name().to_string(),
+            path,
+            apis: document.subscription.application.apis.len(),
+            environments,
+            hash: content_hash(contents),
+        }
+    }
+}
+
+impl Index {
+    ///Reads the index of a previous run below `output`, empty if there is none.
+    pub fn load(output: &Path) -> Result<Self> {
+        let path = output.join(INDEX_FILE);
+        match std::fs::read_to_string(&path) {
+            Ok(content) => serde_yaml::from_str(&content)
+                .with_context(|| format!("Failed to read {path:?}, remove it or pass --no-index")),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Index::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    ///Index of documents that are not on disk, like those in an archive.
+    ///`entries` are the relative paths and contents the documents are stored
+    ///with.
+    pub fn from_documents(
+        documents: &[YamlApiSubscription],
+        entries: &[(PathBuf, Vec<u8>)],
+    ) -> Self {
+        let mut index = Index::default();
+        for (document, (path, contents)) in documents.iter().zip(entries) {
+            index
+                .applications
+                .push(IndexEntry::new(document, path.clone(), contents));
+        }
+        index.applications.sort_by(|a, b| a.name.cmp(&b.name));
+        index
+    }
+
+    ///Replaces the entries of the applications written this run, one per
+    ///file of a split document, and drops entries whose file is gone.
+    ///Entries of applications this run didn't touch are kept.
+    pub fn update(
+        &mut self,
+        output: &Path,
+        documents: &[YamlApiSubscription],
+        files_written: &[PathBuf],
+    ) -> Result<()> {
+        let names = documents
+            .iter()
+            .map(|document| document.name())
+            .collect::<HashSet<_>>();
+        self.applications
+            .retain(|entry| !names.contains(entry.name.as_str()));
+        for (document, file) in documents.iter().zip(files_written) {
+            let contents = std::fs::read(file)?;
+            let path = file.strip_prefix(output).unwrap_or(file).to_path_buf();
+            self.applications
+                .push(IndexEntry::new(document, path, &contents));
+        }
+        self.applications
+            .retain(|entry| output.join(&entry.path).is_file());
+        self.applications.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(())
+    }
+
+    pub fn to_yaml(&self) -> Result<String> {
+        Ok(quote_yaml_11_scalars(&serde_yaml::to_string(self)?))
+    }
+
+    pub fn write(&self, output: &Path, line_ending: LineEnding) -> Result<PathBuf> {
+        let path = output.join(INDEX_FILE);
+        write_atomic(&path, line_ending.apply(&self.to_yaml()?).as_bytes())?;
+        Ok(path)
+    }
+}
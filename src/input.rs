@@ -0,0 +1,220 @@
+use std::{
+    collections::BTreeMap,
+    io::{BufRead, BufReader, Read},
+};
+
+#[cfg(not(feature = "archive"))]
+use anyhow::bail;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    migrate::{parse_xml_file_until, Deadline, XmlApplication, XmlSubscription},
+    secret::{Credentials, Secret},
+};
+
+///Format of the exports read from every source directory.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum InputFormat {
+    Xml,
+    Json,
+    ///An `apictl export app` zip archive
+    Wso2,
+    ///JSON if the file starts with `{` or `[`, WSO2 if it is a zip archive,
+    ///XML otherwise
+    #[default]
+    Auto,
+}
+
+const ZIP_MAGIC: &[u8] = b"PK\x03\x04";
+
+///The JSON export of the legacy exporter. Keys are camelCase like the XML
+///attributes, snake_case is accepted as well.
+#[derive(Debug, Deserialize)]
+struct JsonExport {
+    applications: Vec<JsonApplication>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct JsonApplication {
+    name: String,
+    #[serde(default, alias = "token_type")]
+    token_type: Option<String>,
+    #[serde(default, alias = "token_validity")]
+    token_validity: Option<i32>,
+    #[serde(default, alias = "consumer_key")]
+    consumer_key: Option<String>,
+    #[serde(default, alias = "consumer_secret")]
+    consumer_secret: Option<String>,
+    #[serde(default)]
+    subscriptions: Vec<JsonSubscription>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct JsonSubscription {
+    #[serde(alias = "api_name")]
+    api_name: String,
+    #[serde(alias = "api_version")]
+    api_version: String,
+    #[serde(default)]
+    environment: Option<String>,
+}
+
+///Values are trimmed and empty ones dropped, like XML attributes.
+fn non_empty(value: Option<String>) -> Option<String> {
+    value
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty())
+}
+
+impl From<JsonApplication> for XmlApplication {
+    fn from(app: JsonApplication) -> Self {
+        let name = app.name.trim().to_string();
+        let credentials = match (non_empty(app.consumer_key), non_empty(app.consumer_secret)) {
+            (None, None) => None,
+            (key, secret) => Some(Credentials {
+                consumer_key: Secret::new(key.unwrap_or_default()),
+                consumer_secret: Secret::new(secret.unwrap_or_default()),
+            }),
+        };
+        let subscriptions = app
+            .subscriptions
+            .into_iter()
+            .map(|sub| XmlSubscription {
+                api_name: sub.api_name.trim().to_string(),
+                api_version: sub.api_version.trim().to_string(),
+                env: non_empty(sub.environment).into_iter().collect(),
+            })
+            .collect();
+        XmlApplication {
+            original_name: name.clone(),
+            name,
+            token_type: non_empty(app.token_type).unwrap_or_default(),
+            token_validity: app.token_validity.unwrap_or_default(),
+            credentials,
+            ..Default::default()
+        }
+        .with_subscriptions(subscriptions)
+    }
+}
+
+///Parses all applications of a JSON export. The `source` of the returned
+///applications is left empty for the caller to fill in.
+///
+///The same export as XML converts to the same documents:
+///
+///```
+///use subscription_migrator::input::parse_json_file;
+///use subscription_migrator::migrate::{parse_xml_file, unify_applilcations};
+///
+///let json = r#"{"applications": [{
+///    "name": "billing",
+///    "token_type": "JWT",
+///    "subscriptions": [{"apiName": "invoices", "api_version": "v1", "environment": "prod"}]
+///}]}"#;
+///let xml = r#"<applications>
+///    <application name="billing" tokenType="JWT">
+///        <subscription apiName="invoices" apiVersion="v1" environment="prod"/>
+///    </application>
+///</applications>"#;
+///
+///let from_json = unify_applilcations(&parse_json_file(json.as_bytes())?);
+///let from_xml = unify_applilcations(&parse_xml_file(xml.as_bytes())?.0);
+///assert_eq!(from_json[0].to_yaml()?, from_xml[0].to_yaml()?);
+///# anyhow::Ok(())
+///```
+pub fn parse_json_file(file: impl Read) -> Result<Vec<XmlApplication>> {
+    let export: JsonExport = serde_json::from_reader(file).context("Invalid JSON export")?;
+    Ok(export.applications.into_iter().map(Into::into).collect())
+}
+
+///What [`parse_export`] read from an export.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct Export {
+    pub applications: Vec<XmlApplication>,
+    ///How often each element the XML parser doesn't know occurs
+    pub unknown_elements: BTreeMap<String, usize>,
+    ///Archive entries that hold no application definition
+    pub ignored_entries: Vec<String>,
+}
+
+///Parses an export in `format`.
+pub fn parse_export(file: impl Read, format: InputFormat) -> Result<Export> {
+    parse_export_until(file, format, None)
+}
+
+///Like [`parse_export`], but gives up on an XML export once `deadline` has
+///passed. JSON and WSO2 exports are parsed without one.
+pub fn parse_export_until(
+    file: impl Read,
+    format: InputFormat,
+    deadline: Option<Deadline>,
+) -> Result<Export> {
+    let mut reader = BufReader::new(file);
+    let format = match format {
+        InputFormat::Auto => sniff(&mut reader)?,
+        format => format,
+    };
+    match format {
+        InputFormat::Json => Ok(Export {
+            applications: parse_json_file(reader)?,
+            ..Default::default()
+        }),
+        InputFormat::Wso2 => parse_wso2_export(reader),
+        InputFormat::Xml | InputFormat::Auto => {
+            let (applications, unknown_elements) = parse_xml_file_until(reader, deadline)?;
+            Ok(Export {
+                applications,
+                unknown_elements,
+                ..Default::default()
+            })
+        }
+    }
+}
+
+#[cfg(feature = "archive")]
+fn parse_wso2_export(mut reader: impl Read) -> Result<Export> {
+    // zip archives are read from the end, so they're buffered
+    let mut buffer = Vec::new();
+    reader.read_to_end(&mut buffer)?;
+    let (application, ignored_entries) =
+        crate::wso2::parse_wso2_archive(std::io::Cursor::new(buffer))?;
+    Ok(Export {
+        applications: vec![application],
+        ignored_entries,
+        ..Default::default()
+    })
+}
+
+#[cfg(not(feature = "archive"))]
+fn parse_wso2_export(_reader: impl Read) -> Result<Export> {
+    bail!("Reading WSO2 export archives needs a build with the archive feature")
+}
+
+///Tells the format apart by the first non-whitespace bytes, consuming only
+///the whitespace.
+fn sniff(reader: &mut impl BufRead) -> Result<InputFormat> {
+    loop {
+        let buffer = reader.fill_buf()?;
+        if buffer.starts_with(ZIP_MAGIC) {
+            return Ok(InputFormat::Wso2);
+        }
+        let Some(&first) = buffer.first() else {
+            return Ok(InputFormat::Xml);
+        };
+        let whitespace = buffer
+            .iter()
+            .take_while(|byte| byte.is_ascii_whitespace())
+            .count();
+        if whitespace == 0 {
+            return Ok(match first {
+                b'{' | b'[' => InputFormat::Json,
+                _ => InputFormat::Xml,
+            });
+        }
+        reader.consume(whitespace);
+    }
+}
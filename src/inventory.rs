@@ -0,0 +1,106 @@
+use std::{
+    collections::BTreeMap,
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use serde::Serialize;
+
+///Elements the parser reads, everything else inside an application is unknown
+const KNOWN_ELEMENTS: [&str; 5] = [
+    "application",
+    "subscription",
+    "consumerKey",
+    "consumerSecret",
+    "tag",
+];
+
+///Counts the unknown elements of a single subscribe.xml. Elements enclosing
+///an application, like `<applications>`, are wrappers and not counted.
+#[derive(Debug, Default)]
+pub struct ElementTracker {
+    ///Open elements outside of any application and whether they contain one
+    wrappers: Vec<(String, bool)>,
+    ///Nesting depth inside the current application, 0 outside of one
+    application_depth: usize,
+    pub unknown: BTreeMap<String, usize>,
+}
+
+impl ElementTracker {
+    pub fn start(&mut self, name: &str) {
+        if self.application_depth > 0 {
+            self.application_depth += 1;
+            if !KNOWN_ELEMENTS.contains(&name) {
+                *self.unknown.entry(name.to_string()).or_default() += 1;
+            }
+        } else if name == "application" {
+            self.application_depth = 1;
+            for (_, contains_application) in &mut self.wrappers {
+                *contains_application = true;
+            }
+        } else {
+            self.wrappers.push((name.to_string(), false));
+        }
+    }
+
+    pub fn end(&mut self) {
+        if self.application_depth > 0 {
+            self.application_depth -= 1;
+        } else if let Some((name, false)) = self.wrappers.pop() {
+            *self.unknown.entry(name).or_default() += 1;
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct UnknownElement {
+    pub count: usize,
+    ///The first file the element was found in
+    pub example: PathBuf,
+}
+
+///Unknown elements aggregated over every parsed file.
+#[derive(Debug, Default, Serialize)]
+#[serde(transparent)]
+pub struct ElementInventory(BTreeMap<String, UnknownElement>);
+
+impl ElementInventory {
+    pub fn add(&mut self, unknown: BTreeMap<String, usize>, source: &Path) {
+        for (name, count) in unknown {
+            self.0
+                .entry(name)
+                .or_insert_with(|| UnknownElement {
+                    count: 0,
+                    example: source.to_path_buf(),
+                })
+                .count += count;
+        }
+    }
+
+    ///Adds the elements of another inventory, keeping the examples already
+    ///found.
+    pub fn merge(&mut self, other: ElementInventory) {
+        for (name, element) in other.0 {
+            self.0
+                .entry(name)
+                .or_insert(UnknownElement {
+                    count: 0,
+                    example: element.example,
+                })
+                .count += element.count;
+        }
+    }
+}
+
+impl fmt::Display for ElementInventory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (name, element) in &self.0 {
+            writeln!(
+                f,
+                "Unknown element <{}>: {} time(s), e.g. in {:?}",
+                name, element.count, element.example
+            )?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,121 @@
+use serde_yaml::{Mapping, Value};
+
+///Key names of the generated documents. Documents are serialized with the
+///legacy names and renamed right before writing, both are read back.
+///
+///```
+///use subscription_migrator::convert::{convert_application, ConvertOptions};
+///use subscription_migrator::key_style::KeyStyle;
+///use subscription_migrator::migrate::{XmlApplication, XmlSubscription, YamlApiSubscription};
+///
+///let app = XmlApplication {
+///    name: "billing".to_string(),
+///    apis: vec![XmlSubscription {
+///        api_name: "invoices".to_string(),
+///        api_version: "v1".to_string(),
+///        env: vec!["dev".to_string()],
+///    }],
+///    ..Default::default()
+///};
+///let legacy = convert_application(app.clone(), &ConvertOptions::default());
+///assert_eq!(
+///    legacy.to_yaml()?,
+///    "environments:
+///- controlPlaneUrl: https://non-prod.control-plane.com
+///  environment:
+///  - name: dev
+///subscriptions:
+///  application:
+///    name: billing
+///    description: billing-subscription
+///    apis:
+///    - name: invoices
+///      version: v1
+///"
+///);
+///
+///let v2 = convert_application(app, &ConvertOptions::default().key_style(KeyStyle::V2));
+///assert_eq!(
+///    v2.to_yaml()?,
+///    "environments:
+///- controlPlaneURL: https://non-prod.control-plane.com
+///  environment:
+///  - name: dev
+///subscription:
+///  application:
+///    name: billing
+///    description: billing-subscription
+///    apis:
+///    - name: invoices
+///      version: v1
+///"
+///);
+///
+///// either style reads back into the same document, keeping its style
+///let read = YamlApiSubscription::from_yaml(&v2.to_yaml()?)?;
+///assert_eq!(read.key_style, KeyStyle::V2);
+///assert!(read.same_content(&legacy));
+///# anyhow::Ok(())
+///```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum KeyStyle {
+    ///`subscriptions` and `controlPlaneUrl`
+    #[default]
+    Legacy,
+    ///`subscription` and `controlPlaneURL`
+    V2,
+}
+
+const SUBSCRIPTION_KEY: (&str, &str) = ("subscriptions", "subscription");
+const CONTROL_PLANE_KEY: (&str, &str) = ("controlPlaneUrl", "controlPlaneURL");
+
+impl KeyStyle {
+    ///The top level key of the subscription in either style.
+    pub fn subscription_keys() -> [&'static str; 2] {
+        [SUBSCRIPTION_KEY.0, SUBSCRIPTION_KEY.1]
+    }
+
+    ///The control plane URL key of an environment block in either style.
+    pub fn control_plane_keys() -> [&'static str; 2] {
+        [CONTROL_PLANE_KEY.0, CONTROL_PLANE_KEY.1]
+    }
+
+    ///The style of a serialized document, judged by its top level key.
+    pub fn detect(document: &Value) -> Self {
+        match document.get(SUBSCRIPTION_KEY.1) {
+            Some(_) => KeyStyle::V2,
+            None => KeyStyle::Legacy,
+        }
+    }
+
+    ///Renames the keys of a document serialized with the legacy names.
+    pub fn apply(self, mut document: Value) -> Value {
+        if self == KeyStyle::Legacy {
+            return document;
+        }
+        if let Value::Mapping(mapping) = &mut document {
+            rename(mapping, SUBSCRIPTION_KEY);
+            if let Some(Value::Sequence(blocks)) = mapping.get_mut("environments") {
+                for block in blocks.iter_mut().filter_map(Value::as_mapping_mut) {
+                    rename(block, CONTROL_PLANE_KEY);
+                }
+            }
+        }
+        document
+    }
+}
+
+///Renames the `(from, to)` key of `mapping` in place, keeping the order.
+fn rename(mapping: &mut Mapping, (from, to): (&str, &str)) {
+    if !mapping.contains_key(from) {
+        return;
+    }
+    *mapping = std::mem::take(mapping)
+        .into_iter()
+        .map(|(key, value)| match key.as_str() == Some(from) {
+            true => (Value::from(to), value),
+            false => (key, value),
+        })
+        .collect();
+}
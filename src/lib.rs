@@ -0,0 +1,86 @@
+//!Migrates WSO2 application subscriptions from `subscribe.xml` exports to
+//!the YAML subscription format. The `subscription_migrator` binary is built
+//!on top of this library.
+
+#[cfg(all(feature = "cli", feature = "archive"))]
+pub mod archive;
+#[cfg(feature = "cli")]
+pub mod cache;
+pub mod cancel;
+#[cfg(feature = "git")]
+pub mod changes;
+#[cfg(feature = "cli")]
+pub mod checksum;
+pub mod compare;
+#[cfg(feature = "cli")]
+pub mod config;
+pub mod convert;
+#[cfg(feature = "cli")]
+pub mod decommission;
+#[cfg(feature = "cli")]
+pub mod directory_overrides;
+#[cfg(feature = "cli")]
+pub mod doctor;
+pub mod expect;
+#[cfg(feature = "cli")]
+pub mod failure;
+pub mod field_order;
+pub mod formatting;
+#[cfg(feature = "cli")]
+pub mod ignore;
+pub mod import;
+#[cfg(feature = "cli")]
+pub mod index;
+pub mod input;
+pub mod inventory;
+pub mod key_style;
+pub mod limits;
+pub mod line_ending;
+pub mod lint;
+#[cfg(feature = "cli")]
+pub mod lock;
+#[cfg(feature = "cli")]
+pub mod manifest;
+pub mod metrics;
+pub mod migrate;
+pub mod naming;
+pub mod objects;
+#[cfg(feature = "cli")]
+pub mod output;
+#[cfg(feature = "cli")]
+pub mod permissions;
+#[cfg(feature = "cli")]
+pub mod pipeline;
+#[cfg(feature = "cli")]
+pub mod plan;
+pub mod provenance;
+pub mod readme;
+#[cfg(feature = "cli")]
+pub mod rename;
+#[cfg(feature = "cli")]
+pub mod report;
+pub mod retry;
+#[cfg(feature = "cli")]
+pub mod scan;
+pub mod schema;
+pub mod secret;
+pub mod selection;
+pub mod selftest;
+pub mod split;
+pub mod stats;
+#[cfg(feature = "cli")]
+pub mod style;
+#[cfg(feature = "cli")]
+pub mod template;
+#[cfg(feature = "testing")]
+pub mod testing;
+#[cfg(feature = "cli")]
+pub mod timing;
+pub mod token;
+pub mod validate;
+#[cfg(feature = "cli")]
+pub mod version_overrides;
+pub mod versions;
+#[cfg(feature = "archive")]
+pub mod wso2;
+pub mod yaml_quoting;
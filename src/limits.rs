@@ -0,0 +1,126 @@
+use anyhow::{bail, Result};
+
+use crate::migrate::XmlApplication;
+
+pub const DEFAULT_MAX_APPLICATIONS: usize = 50_000;
+pub const DEFAULT_MAX_APIS_PER_APPLICATION: usize = 5_000;
+pub const DEFAULT_MAX_OUTPUT_FILES: usize = 200_000;
+
+///Guardrails against malformed exports blowing up a run. A limit of `0`
+///means unlimited. Exceeding one is an error, nothing is truncated.
+///
+///```
+///use subscription_migrator::limits::Limits;
+///use subscription_migrator::migrate::{XmlApplication, XmlSubscription};
+///
+///let limits = Limits {
+///    max_applications: 2,
+///    max_apis_per_application: 2,
+///    max_output_files: 2,
+///};
+///let app = |source: &str, apis: usize| XmlApplication {
+///    name: "billing".to_string(),
+///    source: source.into(),
+///    apis: vec![XmlSubscription::default(); apis],
+///    ..Default::default()
+///};
+///
+///assert!(limits.check_applications(&[app("a.xml", 2), app("b.xml", 2)]).is_ok());
+///let error = limits
+///    .check_applications(&[app("a.xml", 1), app("b.xml", 1), app("c.xml", 1)])
+///    .unwrap_err();
+///assert!(error.to_string().contains("c.xml"));
+///let error = limits.check_applications(&[app("a.xml", 3)]).unwrap_err();
+///assert!(error.to_string().contains("3 subscriptions"));
+///
+///let error = limits
+///    .check_more_applications(1, &[app("b.xml", 1), app("c.xml", 1)])
+///    .unwrap_err();
+///assert!(error.to_string().starts_with("3 applications"));
+///assert!(error.to_string().contains("c.xml"));
+///
+///assert!(limits.check_output_files(2).is_ok());
+///assert!(limits.check_output_files(3).is_err());
+///
+///let unlimited = Limits { max_applications: 0, max_apis_per_application: 0, max_output_files: 0 };
+///assert!(unlimited.check_applications(&vec![app("a.xml", 100); 100]).is_ok());
+///assert!(unlimited.check_output_files(usize::MAX).is_ok());
+///```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    pub max_applications: usize,
+    pub max_apis_per_application: usize,
+    pub max_output_files: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            max_applications: DEFAULT_MAX_APPLICATIONS,
+            max_apis_per_application: DEFAULT_MAX_APIS_PER_APPLICATION,
+            max_output_files: DEFAULT_MAX_OUTPUT_FILES,
+        }
+    }
+}
+
+///Whether `count` is over `limit`, `0` being unlimited.
+fn exceeds(count: usize, limit: usize) -> bool {
+    limit != 0 && count > limit
+}
+
+impl Limits {
+    ///Checks the number of parsed applications and the subscriptions of
+    ///each. `applications` are in source order, so the error can name the
+    ///file the limit was crossed in.
+    pub fn check_applications(&self, applications: &[XmlApplication]) -> Result<()> {
+        self.check_more_applications(0, applications)
+    }
+
+    ///Like [`Limits::check_applications`] for `applications` parsed after
+    ///`read` others that were already checked, so the limits hold while the
+    ///sources are parsed one at a time.
+    pub fn check_more_applications(
+        &self,
+        read: usize,
+        applications: &[XmlApplication],
+    ) -> Result<()> {
+        let total = read + applications.len();
+        if exceeds(total, self.max_applications) {
+            bail!(
+                "{} applications exceed --max-applications {}, the limit was crossed in {:?}. \
+                 Check the export for repeated application elements, or raise the limit",
+                total,
+                self.max_applications,
+                applications[self.max_applications.saturating_sub(read)].source
+            );
+        }
+        if let Some(app) = applications
+            .iter()
+            .find(|app| exceeds(app.apis.len(), self.max_apis_per_application))
+        {
+            bail!(
+                "Application {:?} in {:?} has {} subscriptions, more than --max-apis-per-application {}. \
+                 Check the export for repeated subscription elements, or raise the limit",
+                app.name,
+                app.source,
+                app.apis.len(),
+                self.max_apis_per_application
+            );
+        }
+        Ok(())
+    }
+
+    ///Checks the number of files a write would create, before anything is
+    ///written.
+    pub fn check_output_files(&self, files: usize) -> Result<()> {
+        if exceeds(files, self.max_output_files) {
+            bail!(
+                "Writing {} files exceeds --max-output-files {}. \
+                 Migrate fewer directories with --limit or --name-prefix, or raise the limit",
+                files,
+                self.max_output_files
+            );
+        }
+        Ok(())
+    }
+}
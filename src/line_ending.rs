@@ -0,0 +1,42 @@
+use std::borrow::Cow;
+
+///Newline style of generated files. Everything is serialized with `\n` and
+///converted right before writing.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum LineEnding {
+    #[default]
+    Lf,
+    Crlf,
+    ///CRLF on Windows, LF everywhere else
+    Native,
+}
+
+impl LineEnding {
+    fn is_crlf(self) -> bool {
+        match self {
+            LineEnding::Lf => false,
+            LineEnding::Crlf => true,
+            LineEnding::Native => cfg!(windows),
+        }
+    }
+
+    ///Converts serialized `\n` text to this style.
+    pub fn apply(self, text: &str) -> Cow<'_, str> {
+        if self.is_crlf() {
+            Cow::Owned(normalize(text).replace('\n', "\r\n"))
+        } else {
+            Cow::Borrowed(text)
+        }
+    }
+}
+
+///Converts CRLF line endings to LF, so files only differing in their newline
+///style compare equal.
+pub fn normalize(text: &str) -> Cow<'_, str> {
+    if text.contains("\r\n") {
+        Cow::Owned(text.replace("\r\n", "\n"))
+    } else {
+        Cow::Borrowed(text)
+    }
+}
@@ -0,0 +1,134 @@
+use std::{collections::BTreeSet, path::Path};
+
+use crate::{
+    migrate::{YamlApiSubscription, NON_PROD_PLANE_URL, PROD_PLANE_URL},
+    validate::{Finding, Severity},
+};
+
+pub const EMPTY_APIS: &str = "empty-apis";
+pub const UNKNOWN_CONTROL_PLANE: &str = "unknown-control-plane";
+pub const DUPLICATE_API: &str = "duplicate-api";
+pub const PROD_UNDER_NON_PROD: &str = "prod-under-non-prod";
+pub const NAME_MISMATCH: &str = "name-mismatch";
+///Reported for files that can't be read or deserialized, can't be disabled
+pub const INVALID_FILE: &str = "invalid-file";
+
+pub const RULES: [&str; 5] = [
+    EMPTY_APIS,
+    UNKNOWN_CONTROL_PLANE,
+    DUPLICATE_API,
+    PROD_UNDER_NON_PROD,
+    NAME_MISMATCH,
+];
+
+///Runs every rule not listed in `disabled` against a subscription document.
+///The name rule needs the file the document was read from and is skipped
+///without one.
+pub fn lint_document(
+    document: &YamlApiSubscription,
+    file: Option<&Path>,
+    disabled: &[String],
+) -> Vec<Finding> {
+    let enabled = |rule: &str| !disabled.iter().any(|d| d == rule);
+    let application = &document.subscription.application;
+    let finding =
+        |severity, rule, api: Option<&str>, version: Option<&str>, message: String| Finding {
+            severity,
+            rule,
+            application: application.name.clone(),
+            api: api.map(str::to_string),
+            version: version.map(str::to_string),
+            source: file.map(Path::to_path_buf).unwrap_or_default(),
+            message,
+        };
+    let mut findings = Vec::new();
+
+    if enabled(EMPTY_APIS) && application.apis.is_empty() {
+        findings.push(finding(
+            Severity::Warning,
+            EMPTY_APIS,
+            None,
+            None,
+            "application has no APIs".to_string(),
+        ));
+    }
+
+    for env in &document.environments {
+        let url = env.control_plane_url.as_str();
+        if enabled(UNKNOWN_CONTROL_PLANE) && url != PROD_PLANE_URL && url != NON_PROD_PLANE_URL {
+            findings.push(finding(
+                Severity::Error,
+                UNKNOWN_CONTROL_PLANE,
+                None,
+                None,
+                format!("unknown controlPlaneUrl {:?}", url),
+            ));
+        }
+        if enabled(PROD_UNDER_NON_PROD)
+            && url == NON_PROD_PLANE_URL
+            && env.environments.iter().any(|name| name.name == "prod")
+        {
+            findings.push(finding(
+                Severity::Error,
+                PROD_UNDER_NON_PROD,
+                None,
+                None,
+                "prod is listed under the non-prod control plane".to_string(),
+            ));
+        }
+    }
+
+    if enabled(DUPLICATE_API) {
+        let mut seen = BTreeSet::new();
+        for api in &application.apis {
+            if !seen.insert((&api.name, &api.version)) {
+                findings.push(finding(
+                    Severity::Warning,
+                    DUPLICATE_API,
+                    Some(&api.name),
+                    Some(&api.version),
+                    "API is listed more than once".to_string(),
+                ));
+            }
+        }
+    }
+
+    let directory = file
+        .and_then(Path::parent)
+        .and_then(Path::file_name)
+        .map(|name| name.to_string_lossy());
+    if let Some(directory) = directory.filter(|_| enabled(NAME_MISMATCH)) {
+        let expected = format!("{}-subscription", application.name);
+        if directory != expected {
+            findings.push(finding(
+                Severity::Error,
+                NAME_MISMATCH,
+                None,
+                None,
+                format!("expected to be in a directory named {:?}", expected),
+            ));
+        }
+    }
+
+    findings
+}
+
+///Lints a subscription file on disk.
+#[cfg(feature = "cli")]
+pub fn lint_file(file: &Path, disabled: &[String]) -> Vec<Finding> {
+    let document = std::fs::read_to_string(file)
+        .map_err(anyhow::Error::from)
+        .and_then(|content| Ok(serde_yaml::from_str::<YamlApiSubscription>(&content)?));
+    match document {
+        Ok(document) => lint_document(&document, Some(file), disabled),
+        Err(e) => vec![Finding {
+            severity: Severity::Error,
+            rule: INVALID_FILE,
+            application: String::new(),
+            api: None,
+            version: None,
+            source: file.to_path_buf(),
+            message: format!("{:#}", e),
+        }],
+    }
+}
@@ -0,0 +1,139 @@
+use std::{
+    fs::OpenOptions,
+    io::{ErrorKind, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{Duration, Instant, SystemTime},
+};
+
+use anyhow::{Context, Result};
+
+use crate::style::warn;
+
+pub const LOCK_FILE: &str = ".migrator.lock";
+
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+///Lock files held by this process, removed by the Ctrl-C handler.
+static HELD_LOCKS: Mutex<Vec<PathBuf>> = Mutex::new(Vec::new());
+
+///Exclusive lock on an output path, released when dropped.
+#[derive(Debug)]
+pub struct OutputLock {
+    path: PathBuf,
+}
+
+impl OutputLock {
+    ///Creates the lock file in `output`. If another live process holds it, this
+    ///fails right away or, with `wait`, polls until the lock is released or the
+    ///wait times out. Locks of processes that no longer exist are reclaimed.
+    pub fn acquire(output: &Path, wait: Option<Duration>) -> Result<Self> {
+        std::fs::create_dir_all(output)?;
+        let path = output.join(LOCK_FILE);
+        let deadline = wait.map(|wait| Instant::now() + wait);
+
+        loop {
+            match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(mut file) => {
+                    let started = chrono::DateTime::<chrono::Utc>::from(SystemTime::now());
+                    writeln!(file, "pid={}", std::process::id())?;
+                    writeln!(file, "started={}", started.to_rfc3339())?;
+                    HELD_LOCKS.lock().unwrap().push(path.clone());
+                    return Ok(OutputLock { path });
+                }
+                Err(e) if e.kind() == ErrorKind::AlreadyExists => {}
+                Err(e) => {
+                    return Err(e).with_context(|| format!("Failed to create lock file {:?}", path))
+                }
+            }
+
+            let content = std::fs::read_to_string(&path).unwrap_or_default();
+            let owner = lock_owner(&content);
+            if let Some(pid) = owner.filter(|pid| !process_alive(*pid)) {
+                warn(format_args!(
+                    "removing stale lock {:?} of process {} which is no longer running",
+                    path, pid
+                ));
+                reclaim(&path, &content)?;
+                continue;
+            }
+
+            match deadline {
+                Some(deadline) if Instant::now() < deadline => std::thread::sleep(POLL_INTERVAL),
+                _ => {
+                    let owner = owner.map_or("an unknown process".to_string(), |pid| {
+                        format!("process {}", pid)
+                    });
+                    return Err(anyhow::anyhow!(
+                        "Output path is locked by {}, see {:?}. Wait for that run to finish or use --wait-lock",
+                        owner,
+                        path
+                    ));
+                }
+            }
+        }
+    }
+}
+
+impl Drop for OutputLock {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+        HELD_LOCKS.lock().unwrap().retain(|held| held != &self.path);
+    }
+}
+
+///Removes every lock held by this process, for exits that skip destructors.
+pub fn release_all() {
+    if let Ok(held) = HELD_LOCKS.lock() {
+        for path in held.iter() {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+///Removes the stale lock at `path` that held `stale`. Another process may
+///have reclaimed it and taken the lock since it was read, so the lock is
+///moved aside first, which only one process can do, and put back if it
+///turns out to be a fresh one.
+fn reclaim(path: &Path, stale: &str) -> Result<()> {
+    let aside = path.with_file_name(format!("{}.stale-{}", LOCK_FILE, std::process::id()));
+    match std::fs::rename(path, &aside) {
+        // reclaimed by another process, try to take it again
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(()),
+        result => result.with_context(|| format!("Failed to remove stale lock {:?}", path))?,
+    }
+    let current = std::fs::read_to_string(&aside).unwrap_or_default();
+    if current != stale {
+        // fails if yet another process took the lock meanwhile, which then
+        // holds it
+        match std::fs::hard_link(&aside, path) {
+            Err(e) if e.kind() != ErrorKind::AlreadyExists => {
+                return Err(e).with_context(|| format!("Failed to restore lock {:?}", path))
+            }
+            _ => {}
+        }
+    }
+    std::fs::remove_file(&aside).with_context(|| format!("Failed to remove stale lock {:?}", aside))
+}
+
+fn lock_owner(content: &str) -> Option<u32> {
+    content
+        .lines()
+        .find_map(|line| line.strip_prefix("pid="))
+        .and_then(|pid| pid.trim().parse().ok())
+}
+
+#[cfg(unix)]
+fn process_alive(pid: u32) -> bool {
+    let Ok(pid) = libc::pid_t::try_from(pid) else {
+        return false;
+    };
+    // signal 0 only checks whether the process exists
+    let result = unsafe { libc::kill(pid, 0) };
+    result == 0 || std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+#[cfg(not(unix))]
+fn process_alive(_pid: u32) -> bool {
+    true
+}
@@ -1,17 +1,275 @@
-use anyhow::Result;
-use clap::{Args, Parser, Subcommand, ValueEnum};
-use migrate::{parse_xml_file, unify_applilcations, write_to_file, YamlApiSubscription};
-use std::path::PathBuf;
+use anyhow::{Context, Result};
+use clap::{
+    builder::BoolishValueParser, error::ErrorKind, parser::ValueSource, ArgAction, ArgMatches,
+    Args, CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum,
+};
+use regex::Regex;
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+#[cfg(feature = "archive")]
+use subscription_migrator::archive::ArchiveTarget;
+use subscription_migrator::cache::ParseCache;
+use subscription_migrator::cancel::{CancellationToken, Cancelled};
+use subscription_migrator::checksum::{verify_sidecar, ChecksumAlgorithm};
+use subscription_migrator::compare::Comparison;
+use subscription_migrator::config::Config;
+use subscription_migrator::convert::{
+    check_environment_blocks, convert_applications, drop_environments, parse_control_plane_url,
+    AddedEnvironment, ApplicationAccumulator, ConvertOptions, DroppedEnvironments,
+};
+use subscription_migrator::decommission::Decommission;
+use subscription_migrator::doctor::{diagnose, Setup, Status};
+use subscription_migrator::expect::{parse_tolerance, Expectations, ExpectedCount};
+use subscription_migrator::failure::{ErrorReport, NotWritten, Phase, SkippedExisting};
+use subscription_migrator::field_order::FieldOrder;
+use subscription_migrator::formatting::{format_files, SUBSCRIPTION_FILE};
+use subscription_migrator::import::{parse_csv_file, CsvColumns};
+use subscription_migrator::input::InputFormat;
+use subscription_migrator::key_style::KeyStyle;
+use subscription_migrator::limits::{
+    Limits, DEFAULT_MAX_APIS_PER_APPLICATION, DEFAULT_MAX_APPLICATIONS, DEFAULT_MAX_OUTPUT_FILES,
+};
+use subscription_migrator::line_ending::LineEnding;
+use subscription_migrator::lint::{
+    lint_document, lint_file, PROD_UNDER_NON_PROD, RULES, UNKNOWN_CONTROL_PLANE,
+};
+use subscription_migrator::lock::OutputLock;
+use subscription_migrator::metrics::RunMetrics;
+use subscription_migrator::migrate::{
+    prepare_output_dir, write_atomic, ApplicationIter, WriteStatus, WrittenFile, XmlApplication,
+    YamlApiSubscription, NON_PROD_PLANE_URL, PROD_PLANE_URL,
+};
+use subscription_migrator::naming::{
+    check_case_collisions, check_names, fix_names, normalize_whitespace, original_names,
+    parse_name_pattern, CaseMerge, NameCase, NameCasing, NameFix, NameRules,
+    PLATFORM_MAX_NAME_LENGTH, PLATFORM_NAME_PATTERN,
+};
+use subscription_migrator::objects::ObjectUrl;
+use subscription_migrator::output::{print_grouped, print_porcelain, write_preview};
+use subscription_migrator::permissions::{parse_mode, Permissions};
+use subscription_migrator::pipeline::{
+    self, Matcher, ParseOutcome, Sink, SourceDir, WriteOutcome, WritePolicy,
+};
+use subscription_migrator::plan::{Action, Plan};
+use subscription_migrator::provenance::{
+    ApiProvenance, ApplicationProvenance, ProvenanceReport, ProvenanceTracker,
+};
+use subscription_migrator::readme::Readme;
+use subscription_migrator::rename::{RenameMap, RenameReport};
+use subscription_migrator::report::MigrationReport;
+use subscription_migrator::retry::RetryPolicy;
+use subscription_migrator::scan::{
+    find_files_named, parse_duration, parse_since, partition_modified_since, CandidateOutcome,
+    MatchMode, NothingToMigrate, Scan, Slice, UnreadableExport,
+};
+use subscription_migrator::schema::{upgrade_document, Upgrade, SCHEMA_VERSIONS};
+use subscription_migrator::secret::redact_yaml;
+use subscription_migrator::selection::{
+    unexpected_environments, unmapped_only, Selection, SelectionReport, SkipReason,
+    SkippedApplication, UnexpectedEnvironments, UnmappedApplication,
+};
+use subscription_migrator::selftest::{self, Outcome};
+use subscription_migrator::split::fit_documents;
+use subscription_migrator::stats::{api_fanout, ApiFanout, Stats};
+use subscription_migrator::style::{is_plain, paint, warn, ColorMode, ERROR};
+use subscription_migrator::template::ExtraFile;
+use subscription_migrator::timing::{Timings, SLOWEST_SHOWN};
+use subscription_migrator::token::{
+    apply_security, apply_token_validity, check_token_types, check_token_validity,
+    TokenValidityFormat,
+};
+use subscription_migrator::validate::{
+    check_allowed_envs, check_env_parity, check_token_conflicts, check_version_parity,
+    report_findings, Finding,
+};
+use subscription_migrator::version_overrides::{AppliedOverride, OverrideReport, VersionOverrides};
+use subscription_migrator::versions::{
+    apply_version_map, apply_version_policy_with, check_version_format, parse_version_format,
+    parse_version_mapping, VersionPolicy, DEFAULT_VERSION_FORMAT,
+};
+#[cfg(feature = "git")]
+use subscription_migrator::{changes::ChangedExports, scan::subscribe_file};
 
-mod migrate;
+///The optional cargo features and whether this binary was built with them
+const FEATURES: &[(&str, bool)] = &[
+    ("archive", cfg!(feature = "archive")),
+    ("sink-s3", cfg!(feature = "sink-s3")),
+    ("git", cfg!(feature = "git")),
+];
+
+///The sinks and archive formats this binary can write
+const OUTPUT_FORMATS: &[(&str, bool)] = &[
+    ("directory", true),
+    ("tar.gz", cfg!(feature = "archive")),
+    ("zip", cfg!(feature = "archive")),
+    ("s3", cfg!(feature = "sink-s3")),
+];
+
+fn enabled(options: &[(&'static str, bool)]) -> Vec<&'static str> {
+    options
+        .iter()
+        .filter(|(_, enabled)| *enabled)
+        .map(|(option, _)| *option)
+        .collect()
+}
+
+///Printed by `--version`: the version with the commit it was built from and
+///the enabled optional features.
+fn print_version() {
+    let commit = env!("BUILD_COMMIT");
+    let short = commit.get(..7).unwrap_or(commit);
+    println!("Migrator {} ({})", env!("CARGO_PKG_VERSION"), short);
+    match enabled(FEATURES).join(", ") {
+        features if features.is_empty() => println!("features: none"),
+        features => println!("features: {}", features),
+    }
+}
+
+///Printed by `--version --json`. Rollout tooling checks these keys, only ever
+///add to them.
+fn print_version_json() {
+    let built_at = env!("BUILD_TIMESTAMP")
+        .parse()
+        .ok()
+        .and_then(|seconds| chrono::DateTime::<chrono::Utc>::from_timestamp(seconds, 0))
+        .map(|time| time.to_rfc3339_opts(chrono::SecondsFormat::Secs, true));
+    let version = serde_json::json!({
+        "version": env!("CARGO_PKG_VERSION"),
+        "commit": env!("BUILD_COMMIT"),
+        "build_timestamp": built_at,
+        "features": enabled(FEATURES),
+        "schema_versions": SCHEMA_VERSIONS,
+        "output_formats": enabled(OUTPUT_FORMATS),
+    });
+    println!("{}", serde_json::to_string_pretty(&version).unwrap());
+}
 
 #[derive(Parser)]
 #[command(name = "Migrator")]
-#[command(version = "1.0")]
+#[command(disable_version_flag = true)]
 #[command(about = "migrate subscription from xml to yaml", long_about = None)]
 struct Cli {
     #[command(subcommand)]
-    command: Commands,
+    command: Option<Commands>,
+    #[arg(short = 'V', long, help = "Print version")]
+    version: bool,
+    #[arg(
+        id = "version_json",
+        long = "json",
+        requires = "version",
+        help = "Print the version, build and capabilities as JSON, with --version"
+    )]
+    json: bool,
+    #[command(flatten)]
+    global: GlobalArgs,
+}
+
+#[derive(Args)]
+struct GlobalArgs {
+    #[arg(long, short, global = true, help = "Print details about every step")]
+    verbose: bool,
+    #[arg(
+        long,
+        global = true,
+        value_name = "FILE",
+        help = "TOML file with default settings"
+    )]
+    config: Option<PathBuf>,
+    #[arg(
+        long,
+        global = true,
+        value_enum,
+        default_value_t,
+        help = "When to color the output"
+    )]
+    color: ColorMode,
+    #[arg(
+        long,
+        global = true,
+        conflicts_with = "color",
+        help = "Uncolored, line oriented output that never prompts, also set by the NO_COLOR and CI environment variables"
+    )]
+    plain: bool,
+    #[arg(
+        long,
+        global = true,
+        value_enum,
+        default_value_t,
+        help = "Format of the exports in the source directories"
+    )]
+    input_format: InputFormat,
+    #[arg(
+        long,
+        global = true,
+        value_name = "N",
+        default_value = "0",
+        help = "Retry reading exports and writing documents N times on transient IO errors"
+    )]
+    io_retries: u32,
+    #[arg(
+        long,
+        global = true,
+        value_name = "MS",
+        default_value = "100",
+        help = "Delay before the first IO retry, doubled for every further one"
+    )]
+    io_retry_delay: u64,
+    #[arg(
+        long,
+        global = true,
+        help = "Print every option with its value and where it came from: flag, env, config or default, then exit"
+    )]
+    print_config: bool,
+    #[arg(
+        long,
+        global = true,
+        value_name = "SECONDS",
+        value_parser = parse_timeout,
+        help = "Give up on an export that takes longer than SECONDS to parse, durations like 500ms work as well"
+    )]
+    parse_timeout: Option<Duration>,
+    #[arg(
+        long,
+        global = true,
+        value_name = "PATH",
+        help = "Reuse the parse of exports that haven't changed since an earlier run, kept in PATH"
+    )]
+    cache_dir: Option<PathBuf>,
+    ///Loaded from `config` after parsing
+    #[arg(skip)]
+    settings: Config,
+}
+
+///Parses `--parse-timeout`: whole seconds, or a duration such as `500ms`.
+fn parse_timeout(value: &str) -> Result<Duration, String> {
+    match value.parse() {
+        Ok(seconds) => Ok(Duration::from_secs(seconds)),
+        Err(_) => parse_duration(value),
+    }
+}
+
+impl GlobalArgs {
+    ///The export of `directory`, read and parsed as the global options say.
+    fn source(&self, directory: PathBuf) -> SourceDir {
+        SourceDir::new(directory)
+            .format(self.input_format)
+            .retry(self.retry_policy())
+            .parse_timeout(self.parse_timeout)
+            .cache(self.cache_dir.as_deref().map(ParseCache::new))
+    }
+
+    fn retry_policy(&self) -> RetryPolicy {
+        RetryPolicy {
+            retries: self.io_retries,
+            delay: Duration::from_millis(self.io_retry_delay),
+            verbose: self.verbose,
+        }
+    }
 }
 
 #[derive(Subcommand)]
@@ -19,31 +277,1067 @@ enum Commands {
     #[command(about = "Migrate a single subscription")]
     Single(SingleArgs),
     #[command(about = "Search all directories within a path for a given prefix")]
-    Bulk(BulkArgs),
+    Bulk(Box<BulkArgs>),
+    #[command(about = "Print aggregate statistics over all matching directories")]
+    Stats(StatsArgs),
+    #[command(about = "Run consistency checks without writing any files")]
+    Validate(ValidateArgs),
+    #[command(about = "Compare the subscriptions of two export trees")]
+    Compare(CompareArgs),
+    #[command(about = "Normalize existing subscription.yaml files")]
+    Fmt(FmtArgs),
+    #[command(about = "Check existing subscription.yaml files for common mistakes")]
+    Lint(LintArgs),
+    #[command(about = "Upgrade existing subscription.yaml files to the v2 schema")]
+    Upgrade(UpgradeArgs),
+    #[command(about = "Check the input, output and configuration of a bulk run")]
+    Doctor(DoctorArgs),
+    #[command(about = "Save what a bulk run would write to a plan file")]
+    Plan(PlanArgs),
+    #[command(about = "Write exactly what a plan file recorded")]
+    Apply(ApplyArgs),
+    #[command(about = "Migrate the subscriptions listed in a CSV inventory")]
+    ImportCsv(ImportCsvArgs),
+    #[command(about = "Migrate every application of one large subscribe.xml")]
+    File(FileArgs),
+    #[command(
+        hide = true,
+        about = "Check the binary against embedded example exports"
+    )]
+    Selftest(SelftestArgs),
 }
 
 #[derive(Args)]
 struct SingleArgs {
-    #[arg(long, short)]
+    #[arg(
+        long,
+        short,
+        help = "Directory holding the export, or a WSO2 export archive"
+    )]
     input_dir: PathBuf,
     #[arg(long, short)]
     output_dir: PathBuf,
-    #[arg(long, short, default_value = "false")]
-    force: bool,
+    #[command(flatten)]
+    write: WriteArgs,
+    #[command(flatten)]
+    convert: ConvertArgs,
+    #[command(flatten)]
+    checks: CheckArgs,
 }
 
 #[derive(Args)]
-struct BulkArgs {
+struct ImportCsvArgs {
+    #[arg(long, short, help = "CSV file with one subscription per row")]
+    input: PathBuf,
+    #[arg(long, short)]
+    output_dir: PathBuf,
+    #[arg(
+        long,
+        value_name = "COLUMN=HEADER",
+        value_delimiter = ',',
+        value_parser = parse_version_mapping,
+        help = "Header of the application, api, version or environment column, e.g. api=API Name"
+    )]
+    columns: Vec<(String, String)>,
+    #[command(flatten)]
+    write: WriteArgs,
+    #[command(flatten)]
+    convert: ConvertArgs,
+    #[command(flatten)]
+    checks: CheckArgs,
+}
+
+#[derive(Args)]
+struct FileArgs {
+    #[arg(long, short, help = "XML export holding many applications")]
+    input: PathBuf,
+    #[arg(long, short)]
+    output_dir: PathBuf,
+    #[arg(
+        long,
+        help = "Merge the applications with the same name into one document"
+    )]
+    unify: bool,
+    #[arg(
+        long,
+        value_name = "FILE",
+        help = "Write a JSON array of every failure to FILE"
+    )]
+    error_report: Option<PathBuf>,
+    #[command(flatten)]
+    write: WriteArgs,
+    #[command(flatten)]
+    convert: ConvertArgs,
+    #[command(flatten)]
+    checks: CheckArgs,
+}
+
+#[derive(Args)]
+struct ScanArgs {
     #[arg(long, short, default_value = ".")]
     path: PathBuf,
     #[arg(long, short)]
     name_prefix: String,
+    #[arg(long, help = "Also match directories starting with a dot")]
+    hidden: bool,
+    #[arg(
+        long,
+        help = "Match the prefix against the / separated path below --path, like team-a/app-, instead of the directory name"
+    )]
+    match_path: bool,
+    #[arg(
+        long,
+        help = "Parse matching directories that are symlinks to another matching directory again instead of skipping them"
+    )]
+    no_dedupe_sources: bool,
+}
+
+impl ScanArgs {
+    fn mode(&self) -> MatchMode {
+        match self.match_path {
+            true => MatchMode::Path,
+            false => MatchMode::Name,
+        }
+    }
+}
+
+#[derive(Args)]
+struct BulkArgs {
+    #[command(flatten)]
+    scan: ScanArgs,
+    #[arg(long, help = "Skip the first N of the sorted matching directories")]
+    offset: Option<usize>,
+    #[arg(long, help = "Process at most N of the sorted matching directories")]
+    limit: Option<usize>,
+    #[arg(
+        long,
+        conflicts_with_all = ["offset", "limit"],
+        help = "Process N randomly chosen matching directories"
+    )]
+    sample: Option<usize>,
+    #[arg(
+        long,
+        requires = "sample",
+        default_value = "0",
+        help = "Seed for --sample"
+    )]
+    seed: u64,
+    #[arg(
+        long,
+        value_parser = parse_since,
+        help = "Only migrate subscribe.xml files modified since an RFC 3339 timestamp or a duration like 24h"
+    )]
+    since: Option<SystemTime>,
+    #[cfg(feature = "git")]
+    #[arg(
+        long,
+        value_name = "REF",
+        num_args = 0..=1,
+        default_missing_value = "HEAD",
+        conflicts_with = "since",
+        help = "Only migrate exports changed in git since REF, HEAD by default"
+    )]
+    only_changed: Option<String>,
     #[arg(long, short, default_value = ".")]
     output_path: PathBuf,
-    #[arg(long, short)]
+    #[arg(
+        long,
+        short,
+        help = "Environments of the run, a warning lists applications that don't fit"
+    )]
     environments: Environment,
-    #[arg(long, short, default_value = "false")]
+    #[arg(
+        long,
+        help = "Fail instead of warning when applications don't fit --environments"
+    )]
+    forbid_unexpected_envs: bool,
+    #[arg(
+        long,
+        value_name = "FILE",
+        help = "Write a JSON report of the run to FILE"
+    )]
+    report: Option<PathBuf>,
+    #[arg(
+        long,
+        value_name = "PATH",
+        help = "Write gauges of the run in the Prometheus text format to PATH, e.g. for node-exporter"
+    )]
+    metrics_file: Option<PathBuf>,
+    #[arg(
+        long,
+        requires = "report",
+        help = "List every directory the scan saw in the report, with what happened to it"
+    )]
+    report_candidates: bool,
+    #[arg(
+        long,
+        value_name = "FILE",
+        help = "Write the subscribe.xml files each API of every application came from as JSON to FILE"
+    )]
+    provenance_report: Option<PathBuf>,
+    #[arg(
+        long,
+        value_name = "FILE",
+        help = "Write every failure as JSON to FILE, skipping directories that fail to parse"
+    )]
+    error_report: Option<PathBuf>,
+    #[arg(
+        long,
+        requires = "error_report",
+        help = "Only write the error report if something failed"
+    )]
+    error_report_only_on_failure: bool,
+    #[arg(long, help = "Succeed even if no directory is left to migrate")]
+    allow_empty: bool,
+    #[arg(
+        long,
+        value_name = "N",
+        help = "Fail before writing unless the run would write N applications"
+    )]
+    expect_applications: Option<usize>,
+    #[arg(
+        long,
+        value_name = "N",
+        help = "Fail before writing unless the applications come from N directories"
+    )]
+    expect_directories: Option<usize>,
+    #[arg(
+        long,
+        value_name = "PERCENT",
+        value_parser = parse_tolerance,
+        default_value = "0",
+        help = "How far --expect-applications and --expect-directories may be off, e.g. 5%"
+    )]
+    expect_tolerance: f64,
+    #[arg(
+        long,
+        help = "Also migrate the subscribe.xml directly in --path, merged with the matching directories"
+    )]
+    include_root: bool,
+    #[arg(
+        long,
+        requires = "include_root",
+        help = "Fail if there is no subscribe.xml directly in --path"
+    )]
+    require_root: bool,
+    #[command(flatten)]
+    write: WriteArgs,
+    #[command(flatten)]
+    convert: ConvertArgs,
+    #[command(flatten)]
+    checks: CheckArgs,
+}
+
+#[derive(Args)]
+struct WriteArgs {
+    #[arg(
+        long,
+        short,
+        default_value = "false",
+        help = "Same as --if-exists overwrite"
+    )]
     force: bool,
+    #[arg(
+        long,
+        value_enum,
+        default_value_t,
+        conflicts_with = "force",
+        help = "What to do about applications whose output directory already exists"
+    )]
+    if_exists: IfExists,
+    #[arg(
+        long,
+        value_name = "SECONDS",
+        help = "Wait up to SECONDS for another run to release the output lock"
+    )]
+    wait_lock: Option<u64>,
+    #[arg(long, help = "Write a manifest.json listing the generated files")]
+    manifest: bool,
+    #[arg(
+        long,
+        value_name = "DURATION",
+        value_parser = parse_duration,
+        help = "Warn about every file taking longer than DURATION to parse or write, e.g. 500ms"
+    )]
+    warn_slower_than: Option<Duration>,
+    #[arg(
+        long,
+        value_name = "MODE",
+        value_parser = parse_mode,
+        help = "Octal permissions of written files, e.g. 644 (Unix only)"
+    )]
+    file_mode: Option<u32>,
+    #[arg(
+        long,
+        value_name = "MODE",
+        value_parser = parse_mode,
+        help = "Octal permissions of created directories, e.g. 755 (Unix only)"
+    )]
+    dir_mode: Option<u32>,
+    #[arg(long, help = "Mark written files read-only to discourage hand edits")]
+    read_only: bool,
+    #[cfg(feature = "archive")]
+    #[arg(
+        long,
+        value_name = "FILE",
+        help = "Write the output into a .tar.gz or .zip archive instead of the output directory"
+    )]
+    archive: Option<ArchiveTarget>,
+    #[arg(
+        long,
+        help = "Write nothing, instead show a diff against every existing file in the output directory, or with an s3://bucket/prefix output check which objects would be uploaded"
+    )]
+    dry_run: bool,
+    #[arg(
+        long,
+        value_name = "TEMPLATE=DEST",
+        help = "Render TEMPLATE into DEST next to every subscription.yaml, filling in {name}, {description}, {apis} and {environments}"
+    )]
+    extra_file: Vec<ExtraFile>,
+    #[arg(
+        long,
+        help = "Write a README.md describing the application next to every subscription.yaml"
+    )]
+    write_readme: bool,
+    #[arg(long, help = "Create the output directory and its parents if missing")]
+    create_output_dir: bool,
+    #[arg(
+        long,
+        value_name = "ALGORITHM",
+        help = "Write a sha256sum compatible sidecar next to every subscription file"
+    )]
+    checksums: Option<ChecksumAlgorithm>,
+    #[arg(
+        long,
+        help = "Don't write index.yaml listing every generated subscription"
+    )]
+    no_index: bool,
+    #[arg(
+        long,
+        value_enum,
+        default_value_t,
+        help = "Newline style of the generated files"
+    )]
+    line_endings: LineEnding,
+    #[arg(
+        long,
+        help = "Print exactly one line per document to stdout, STATUS<TAB>APPLICATION<TAB>PATH, and everything else to stderr. This format is stable"
+    )]
+    porcelain: bool,
+    #[arg(
+        long,
+        value_name = "N",
+        default_value_t = DEFAULT_MAX_OUTPUT_FILES,
+        help = "Fail before writing anything when more than N files would be written, 0 for no limit"
+    )]
+    max_output_files: usize,
+    #[arg(
+        long,
+        value_name = "BYTES",
+        help = "Fail before writing anything when a subscription file would be larger than BYTES"
+    )]
+    max_output_size: Option<usize>,
+    #[arg(
+        long,
+        requires = "max_output_size",
+        conflicts_with_all = ["extra_file", "write_readme"],
+        help = "Split the APIs of files over --max-output-size into subscription-1.yaml, subscription-2.yaml and so on"
+    )]
+    split_large: bool,
+    #[command(flatten)]
+    metadata: MetadataArgs,
+}
+
+#[derive(Args)]
+struct MetadataArgs {
+    #[arg(
+        long,
+        help = "Add schemaVersion and a metadata block naming the tool to every document"
+    )]
+    emit_metadata: bool,
+    #[arg(
+        long,
+        help = "List the subscribe.xml files each document came from in its metadata block"
+    )]
+    emit_source_info: bool,
+    #[arg(
+        long,
+        help = "Leave machine specific details like source paths out of the output"
+    )]
+    reproducible: bool,
+}
+
+impl WriteArgs {
+    ///Prints `message` to stdout, or to stderr with `--porcelain` so
+    ///stdout only holds the porcelain lines.
+    fn say(&self, message: impl fmt::Display) {
+        match self.porcelain {
+            true => eprintln!("{}", message),
+            false => println!("{}", message),
+        }
+    }
+
+    fn lock(&self, output: &Path) -> Result<OutputLock> {
+        OutputLock::acquire(output, self.wait_lock.map(Duration::from_secs))
+    }
+
+    ///Validates the output directory, which isn't used when writing an
+    ///archive or to object storage.
+    fn prepare_output(&self, output: &Path) -> Result<()> {
+        match self.sink(output)? {
+            Sink::Directory(_) if self.dry_run => Ok(()),
+            Sink::Directory(_) => prepare_output_dir(output, self.create_output_dir),
+            Sink::Objects(_) => Ok(()),
+            #[cfg(feature = "archive")]
+            Sink::Archive(_) if self.dry_run => Err(anyhow::anyhow!(
+                "--dry-run needs an output directory or an s3://bucket/prefix output"
+            )),
+            #[cfg(feature = "archive")]
+            Sink::Archive(_) => Ok(()),
+        }
+    }
+
+    ///The archive given with `--archive`, the bucket of an `s3://` output
+    ///or the output directory.
+    fn sink(&self, output: &Path) -> Result<Sink> {
+        #[cfg(feature = "archive")]
+        if let Some(target) = &self.archive {
+            return Ok(Sink::Archive(target.clone()));
+        }
+        Ok(match ObjectUrl::parse(output)? {
+            Some(url) => Sink::Objects(url),
+            None => Sink::Directory(output.to_path_buf()),
+        })
+    }
+
+    fn policy(&self) -> WritePolicy {
+        WritePolicy {
+            force: self.force || self.if_exists == IfExists::Overwrite,
+            skip_existing: self.if_exists == IfExists::Skip,
+            permissions: self.permissions(),
+            line_ending: self.line_endings,
+            checksums: self.checksums,
+            manifest: self.manifest,
+            index: !self.no_index,
+            dry_run: self.dry_run,
+            extra_files: self.extra_file.clone(),
+            ..Default::default()
+        }
+    }
+
+    fn permissions(&self) -> Permissions {
+        let permissions = Permissions {
+            file_mode: self.file_mode,
+            dir_mode: self.dir_mode,
+            read_only: self.read_only,
+        };
+        permissions.warn_unsupported();
+        permissions
+    }
+}
+
+impl MetadataArgs {
+    ///The part of the prepared `provenance` [`MetadataArgs::stamp`] lists.
+    fn provenance<'a>(
+        &self,
+        provenance: &'a [ApplicationProvenance],
+    ) -> &'a [ApplicationProvenance] {
+        match self.emit_source_info && !self.reproducible {
+            true => provenance,
+            false => &[],
+        }
+    }
+
+    ///Adds the metadata block to every document when requested. With
+    ///`--emit-source-info` it lists the subscribe.xml files each application
+    ///and each of its API versions was parsed from, relative to the scanned
+    ///directory and sorted.
+    fn stamp(&self, documents: &mut [YamlApiSubscription], provenance: &[ApplicationProvenance]) {
+        if !self.emit_metadata && !self.emit_source_info {
+            return;
+        }
+        for document in documents {
+            let mut sources = Vec::new();
+            let mut apis = Vec::new();
+            if let Some(app) = provenance
+                .iter()
+                .find(|app| app.application == document.name())
+            {
+                let relative = |sources: &BTreeSet<PathBuf>| {
+                    sources
+                        .iter()
+                        .map(|source| relative_source(source))
+                        .collect::<BTreeSet<_>>()
+                };
+                sources = relative(&app.sources).into_iter().collect();
+                apis = app
+                    .apis
+                    .iter()
+                    .map(|api| ApiProvenance {
+                        sources: relative(&api.sources),
+                        ..api.clone()
+                    })
+                    .collect();
+            }
+            document.stamp(sources, apis);
+        }
+    }
+}
+
+#[derive(Args)]
+struct ConvertArgs {
+    #[arg(
+        long,
+        default_value = "all",
+        help = "How to handle an API subscribed with several versions"
+    )]
+    version_policy: VersionPolicy,
+    #[arg(long, help = "TOML or YAML file mapping old to new names")]
+    rename_map: Option<PathBuf>,
+    #[arg(
+        long,
+        default_value = "preserve",
+        help = "Case of the generated application names"
+    )]
+    name_case: NameCase,
+    #[arg(
+        long,
+        help = "Rename applications breaking the name rules to a compliant kebab case name"
+    )]
+    fix_names: bool,
+    #[arg(
+        long,
+        help = "Merge applications whose names differ only by case, under the name seen first"
+    )]
+    merge_case_insensitive: bool,
+    #[arg(
+        long,
+        help = "Write consumer keys and secrets found in the XML into the YAML"
+    )]
+    include_credentials: bool,
+    #[arg(
+        long,
+        help = "Write the XML comments directly above an application into its YAML"
+    )]
+    preserve_comments: bool,
+    #[arg(
+        long,
+        help = "Collapse runs of whitespace inside names into a single space"
+    )]
+    normalize_whitespace: bool,
+    #[arg(
+        long,
+        value_name = "FILE",
+        help = "TOML or YAML file mapping API names to versions per environment or default"
+    )]
+    override_versions: Option<PathBuf>,
+    #[arg(
+        long,
+        value_name = "FILE",
+        help = "Only migrate the applications named in FILE, one per line, checked after renaming"
+    )]
+    allow_list: Option<PathBuf>,
+    #[arg(
+        long,
+        value_name = "FILE",
+        help = "Never migrate the applications named in FILE, one per line, checked after renaming"
+    )]
+    deny_list: Option<PathBuf>,
+    #[arg(
+        long,
+        value_name = "TAG",
+        help = "Only migrate applications with one of these <tag> elements, repeatable"
+    )]
+    include_tag: Vec<String>,
+    #[arg(
+        long,
+        help = "Write the <tag> elements of every application as its labels"
+    )]
+    emit_labels: bool,
+    #[arg(
+        long,
+        help = "Keep a separate control plane block per environment group even when their URLs are the same"
+    )]
+    no_merge_env_blocks: bool,
+    #[arg(
+        long,
+        help = "Migrate applications whose environments are all outside the prod and allowed environments instead of skipping them"
+    )]
+    migrate_unmapped: bool,
+    #[arg(long, help = "Write the tokenValidity of every application")]
+    emit_token_validity: bool,
+    #[arg(
+        long,
+        value_enum,
+        default_value_t,
+        requires = "emit_token_validity",
+        help = "Write the tokenValidity as an integer, a duration like 1d or an ISO-8601 duration like PT24H"
+    )]
+    token_validity_format: TokenValidityFormat,
+    #[arg(
+        long,
+        value_name = "VALUE",
+        help = "Written for tokens that never expire [default: unlimited]"
+    )]
+    unlimited_token_validity: Option<String>,
+    #[arg(
+        long,
+        help = "Write a security block derived from the tokenType of every application"
+    )]
+    emit_security: bool,
+    #[arg(
+        long,
+        value_name = "URL",
+        default_value = PROD_PLANE_URL,
+        value_parser = parse_control_plane_url
+    )]
+    prod_plane_url: String,
+    #[arg(
+        long,
+        value_name = "URL",
+        default_value = NON_PROD_PLANE_URL,
+        value_parser = parse_control_plane_url
+    )]
+    non_prod_plane_url: String,
+    #[arg(
+        long,
+        value_delimiter = ',',
+        default_value = "prod",
+        help = "Environments listed under the prod control plane"
+    )]
+    prod_plane_envs: Vec<String>,
+    #[arg(
+        long,
+        value_name = "FROM=TO",
+        value_delimiter = ',',
+        value_parser = parse_version_mapping,
+        help = "Rename environments before they are sorted into control planes, e.g. production=prod"
+    )]
+    env_alias: Vec<(String, String)>,
+    #[arg(
+        long,
+        default_value = "{name}-subscription",
+        help = "Description of every application, {name} is replaced by its name"
+    )]
+    description_template: String,
+    #[arg(
+        long,
+        value_enum,
+        default_value_t,
+        help = "Key names of the generated YAML, v2 writes subscription and controlPlaneURL"
+    )]
+    key_style: KeyStyle,
+    #[arg(
+        long,
+        value_enum,
+        default_value_t,
+        help = "Key order of the generated YAML, subscription-first puts the application before its environments"
+    )]
+    field_order: FieldOrder,
+    #[arg(
+        long,
+        value_name = "NAME[=URL]",
+        help = "Add an environment to every application, under its own control plane URL or the prod or non-prod one"
+    )]
+    add_env: Vec<AddedEnvironment>,
+    #[arg(
+        long,
+        value_name = "NAME",
+        help = "Remove an environment, after aliasing, from every subscription"
+    )]
+    drop_env: Vec<String>,
+    #[arg(
+        long,
+        value_name = "N",
+        default_value_t = DEFAULT_MAX_APPLICATIONS,
+        help = "Fail when the exports hold more than N applications, 0 for no limit"
+    )]
+    max_applications: usize,
+    #[arg(
+        long,
+        value_name = "N",
+        default_value_t = DEFAULT_MAX_APIS_PER_APPLICATION,
+        help = "Fail when an application has more than N subscriptions, 0 for no limit"
+    )]
+    max_apis_per_application: usize,
+}
+
+impl ConvertArgs {
+    ///The conversion settings of these flags.
+    fn options(&self) -> ConvertOptions {
+        let mut options = ConvertOptions::default()
+            .prod_plane_url(&self.prod_plane_url)
+            .non_prod_plane_url(&self.non_prod_plane_url)
+            .prod_environments(self.prod_plane_envs.clone())
+            .description_template(&self.description_template)
+            .key_style(self.key_style)
+            .field_order(self.field_order)
+            .emit_labels(self.emit_labels)
+            .merge_env_blocks(!self.no_merge_env_blocks);
+        for (from, to) in &self.env_alias {
+            options = options.env_alias(from, to);
+        }
+        for environment in &self.add_env {
+            options = options.add_environment(environment.clone());
+        }
+        options
+    }
+
+    ///Lint rules that assume the default control planes and would flag
+    ///what these flags ask for.
+    fn unchecked_rules(&self) -> Vec<String> {
+        let defaults = ConvertOptions::default();
+        let mut rules = Vec::new();
+        let added_urls = self
+            .add_env
+            .iter()
+            .any(|env| env.control_plane_url.is_some());
+        if self.prod_plane_url != defaults.prod_plane_url
+            || self.non_prod_plane_url != defaults.non_prod_plane_url
+            || added_urls
+        {
+            rules.push(UNKNOWN_CONTROL_PLANE.to_string());
+        }
+        if self.prod_plane_envs != defaults.prod_environments || !self.env_alias.is_empty() {
+            rules.push(PROD_UNDER_NON_PROD.to_string());
+        }
+        rules
+    }
+
+    ///Sets the token validity and security block of the documents when
+    ///requested.
+    fn apply_token_settings(
+        &self,
+        documents: &mut [YamlApiSubscription],
+        applications: &[XmlApplication],
+        global: &GlobalArgs,
+    ) {
+        if self.emit_token_validity {
+            let unlimited = self
+                .unlimited_token_validity
+                .as_deref()
+                .or(global.settings.unlimited_token_validity.as_deref())
+                .unwrap_or("unlimited");
+            apply_token_validity(
+                documents,
+                applications,
+                unlimited,
+                self.token_validity_format,
+            );
+        }
+        if self.emit_security {
+            apply_security(documents, applications, &global.settings.token_type_map);
+        }
+    }
+}
+
+#[derive(Args)]
+struct CheckArgs {
+    #[arg(
+        long,
+        help = "Flag APIs subscribed in prod but in no pre-prod environment"
+    )]
+    check_env_parity: bool,
+    #[arg(
+        long,
+        help = "Flag API versions subscribed in prod while the other environments only have other versions"
+    )]
+    check_version_parity: bool,
+    #[arg(long, value_delimiter = ',', default_value = "prod")]
+    prod_envs: Vec<String>,
+    #[arg(long, value_delimiter = ',', default_value = "dev,test")]
+    pre_prod_envs: Vec<String>,
+    #[arg(
+        long,
+        value_name = "REGEX",
+        num_args = 0..=1,
+        default_missing_value = DEFAULT_VERSION_FORMAT,
+        value_parser = parse_version_format,
+        help = "Flag API versions not matching REGEX, checked with the default pattern under --strict"
+    )]
+    version_format: Option<Regex>,
+    #[arg(
+        long,
+        value_name = "FROM=TO",
+        value_delimiter = ',',
+        value_parser = parse_version_mapping,
+        help = "Rewrite API versions before validation, e.g. LATEST=2.0"
+    )]
+    version_map: Vec<(String, String)>,
+    #[arg(
+        long,
+        value_delimiter = ',',
+        help = "Flag environment names not in this list, after renaming"
+    )]
+    allowed_envs: Option<Vec<String>>,
+    #[arg(
+        long,
+        help = "Fail when merged applications disagree on their token settings"
+    )]
+    strict_token_conflicts: bool,
+    #[arg(
+        long,
+        help = "Check application names against the control plane rules: at most 60 of [A-Za-z0-9._-]"
+    )]
+    validate_names: bool,
+    #[arg(long, value_name = "N", help = "Flag application names longer than N")]
+    max_name_length: Option<usize>,
+    #[arg(
+        long,
+        value_name = "REGEX",
+        value_parser = parse_name_pattern,
+        help = "Flag application names not matching REGEX"
+    )]
+    name_pattern: Option<Regex>,
+    #[arg(
+        long,
+        value_name = "N",
+        help = "Warn about APIs subscribed by more than N distinct applications, counted after renaming"
+    )]
+    api_fanout_threshold: Option<usize>,
+    #[arg(long, help = "Treat validation warnings as errors")]
+    strict: bool,
+}
+
+impl CheckArgs {
+    ///Environments an application needs at least one of to be migrated
+    ///without `--migrate-unmapped`: the prod environments and the allowed
+    ///ones, or the pre-prod ones if no allowed environments are set.
+    fn known_envs(&self, convert: &ConvertArgs, global: &GlobalArgs) -> Vec<String> {
+        let allowed = self
+            .allowed_envs
+            .as_ref()
+            .or(global.settings.allowed_envs.as_ref())
+            .unwrap_or(&self.pre_prod_envs);
+        let mut known = convert.prod_plane_envs.clone();
+        known.extend(self.prod_envs.iter().chain(allowed).cloned());
+        known
+    }
+
+    ///The explicit name limits, falling back to the control plane rules with
+    ///`--validate-names`.
+    fn name_rules(&self) -> NameRules {
+        let mut rules = NameRules {
+            max_length: self.max_name_length,
+            pattern: self.name_pattern.clone(),
+        };
+        if self.validate_names {
+            rules.max_length.get_or_insert(PLATFORM_MAX_NAME_LENGTH);
+            if rules.pattern.is_none() {
+                rules.pattern = parse_name_pattern(PLATFORM_NAME_PATTERN).ok();
+            }
+        }
+        rules
+    }
+}
+
+#[derive(Args)]
+struct ValidateArgs {
+    #[command(flatten)]
+    scan: ScanArgs,
+    #[command(flatten)]
+    checks: CheckArgs,
+}
+
+#[derive(Args)]
+struct StatsArgs {
+    #[command(flatten)]
+    scan: ScanArgs,
+    #[arg(long, short, default_value = "table")]
+    format: OutputFormat,
+    #[arg(long, short, help = "Only show the N most subscribed APIs")]
+    top: Option<usize>,
+    #[arg(
+        long,
+        value_name = "N",
+        help = "List the APIs subscribed by more than N distinct applications"
+    )]
+    api_fanout_threshold: Option<usize>,
+}
+
+#[derive(Args)]
+struct CompareArgs {
+    #[arg(long, help = "Export tree to compare from")]
+    left: PathBuf,
+    #[arg(long, help = "Export tree to compare to")]
+    right: PathBuf,
+    #[arg(long, short)]
+    name_prefix: String,
+    #[arg(long, help = "Also match directories starting with a dot")]
+    hidden: bool,
+    #[arg(
+        long,
+        help = "Match the prefix against the / separated path below each tree instead of the directory name"
+    )]
+    match_path: bool,
+    #[arg(long, short, default_value = "table")]
+    format: OutputFormat,
+}
+
+#[derive(Args)]
+struct FmtArgs {
+    #[arg(
+        long,
+        short,
+        default_value = ".",
+        help = "Directory searched for subscription.yaml files"
+    )]
+    path: PathBuf,
+    #[arg(
+        long,
+        short,
+        help = "Write the formatted files below this directory instead of in place"
+    )]
+    output_path: Option<PathBuf>,
+    #[arg(
+        long,
+        help = "Only report files that would change and fail if there are any"
+    )]
+    check: bool,
+    #[arg(
+        long,
+        value_enum,
+        default_value_t,
+        help = "Newline style of rewritten files"
+    )]
+    line_endings: LineEnding,
+}
+
+#[derive(Args)]
+struct LintArgs {
+    #[arg(
+        long,
+        short,
+        default_value = ".",
+        help = "Directory searched for subscription.yaml files"
+    )]
+    path: PathBuf,
+    #[arg(long, value_parser = RULES, help = "Rule to skip, can be repeated")]
+    disable: Vec<String>,
+    #[arg(
+        long,
+        value_name = "ALGORITHM",
+        help = "Report files that no longer match their checksum sidecar"
+    )]
+    verify_checksums: Option<ChecksumAlgorithm>,
+}
+
+#[derive(Args)]
+struct UpgradeArgs {
+    #[arg(
+        long,
+        short,
+        default_value = ".",
+        help = "Directory searched for subscription.yaml files"
+    )]
+    path: PathBuf,
+    #[arg(long, help = "Print a diff per file instead of rewriting it")]
+    dry_run: bool,
+}
+
+#[derive(Args)]
+struct DoctorArgs {
+    #[command(flatten)]
+    scan: ScanArgs,
+    #[arg(long, short, default_value = ".")]
+    output_path: PathBuf,
+    #[arg(long, help = "TOML or YAML file mapping old to new names")]
+    rename_map: Option<PathBuf>,
+    #[arg(
+        long,
+        default_value = "10",
+        help = "Parse N randomly chosen matching directories"
+    )]
+    sample: usize,
+    #[arg(long, value_name = "URL", help = "Control plane URL to check")]
+    prod_plane_url: Option<String>,
+    #[arg(long, value_name = "URL", help = "Control plane URL to check")]
+    non_prod_plane_url: Option<String>,
+    #[arg(
+        long,
+        value_name = "NAME=URL",
+        help = "Added environment whose control plane URL to check"
+    )]
+    add_env: Vec<String>,
+}
+
+#[derive(Args)]
+struct SelftestArgs {
+    #[arg(
+        long,
+        value_name = "DIR",
+        help = "Also write the embedded exports and expected YAML to DIR"
+    )]
+    write_fixtures: Option<PathBuf>,
+}
+
+#[derive(Args)]
+struct PlanArgs {
+    #[command(flatten)]
+    scan: ScanArgs,
+    #[arg(long, short, default_value = ".")]
+    output_path: PathBuf,
+    #[arg(long, value_name = "FILE", help = "Where to save the plan")]
+    plan: PathBuf,
+    #[arg(
+        long,
+        help = "Remove generated directories no application maps to anymore"
+    )]
+    prune: bool,
+    #[arg(
+        long,
+        value_name = "FILE",
+        help = "Write the subscriptions of the existing output missing from the exports as JSON"
+    )]
+    decommission_report: Option<PathBuf>,
+    #[arg(
+        long,
+        value_name = "TEMPLATE=DEST",
+        help = "Render TEMPLATE into DEST next to every subscription.yaml, filling in {name}, {description}, {apis} and {environments}"
+    )]
+    extra_file: Vec<ExtraFile>,
+    #[arg(
+        long,
+        help = "Write a README.md describing the application next to every subscription.yaml"
+    )]
+    write_readme: bool,
+    #[arg(long, short, default_value = "table")]
+    format: OutputFormat,
+    #[command(flatten)]
+    metadata: MetadataArgs,
+    #[command(flatten)]
+    convert: ConvertArgs,
+    #[command(flatten)]
+    checks: CheckArgs,
+}
+
+#[derive(Args)]
+struct ApplyArgs {
+    #[arg(long, value_name = "FILE", help = "Plan saved by the plan subcommand")]
+    plan: PathBuf,
+    #[arg(
+        long,
+        value_name = "SECONDS",
+        help = "Wait up to SECONDS for another run to release the output lock"
+    )]
+    wait_lock: Option<u64>,
+}
+
+#[derive(Copy, Clone, Default, PartialEq, Eq, ValueEnum)]
+enum IfExists {
+    ///List every existing directory and write nothing
+    #[default]
+    Error,
+    ///Write the other applications and list the skipped ones
+    Skip,
+    ///Replace the files of existing directories, same as --force
+    Overwrite,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, ValueEnum)]
+enum OutputFormat {
+    Table,
+    Json,
 }
 
 #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
@@ -54,54 +1348,1491 @@ enum Environment {
     Prod,
 }
 
+impl Environment {
+    ///Whether the run is for the prod environments, `None` for all.
+    fn is_prod(self) -> Option<bool> {
+        match self {
+            Environment::All => None,
+            Environment::Dev | Environment::Test => Some(false),
+            Environment::Prod => Some(true),
+        }
+    }
+}
+
+///Warns about the applications that don't fit `--environments`, or fails
+///with `--forbid-unexpected-envs`. They are what is left after the drop,
+///allow, deny and unmapped filters, so environments those removed aren't
+///reported.
+fn check_unexpected_environments(
+    unexpected: &[UnexpectedEnvironments],
+    environments: Environment,
+    forbid: bool,
+) -> Result<()> {
+    if unexpected.is_empty() {
+        return Ok(());
+    }
+    let found = match environments.is_prod() {
+        Some(true) => "only non-prod environments",
+        _ => "prod environments",
+    };
+    let flag = environments
+        .to_possible_value()
+        .map(|value| value.get_name().to_string())
+        .unwrap_or_default();
+    let message = format!(
+        "{} application(s) left after filtering have {}, but the run is for --environments {}:",
+        unexpected.len(),
+        found,
+        flag
+    );
+    if forbid {
+        let apps = unexpected.iter().map(ToString::to_string);
+        anyhow::bail!("{} {}", message, apps.collect::<Vec<_>>().join(", "));
+    }
+    warn(message);
+    for app in unexpected {
+        warn(format_args!("  {}", app));
+    }
+    Ok(())
+}
+
+///Exit code of a run interrupted with Ctrl-C
+const EXIT_INTERRUPTED: i32 = 130;
+///Exit code of a bulk run without directories to migrate
+const EXIT_NOTHING_TO_MIGRATE: i32 = 3;
+///Exit code of a run that wrote everything but the applications skipped
+///by `--if-exists skip`
+const EXIT_SKIPPED_EXISTING: i32 = 4;
+
+///Every option can also be set with an environment variable named after
+///it with this prefix, `--output-path` with `MIGRATOR_OUTPUT_PATH`
+const ENV_PREFIX: &str = "MIGRATOR_";
+
+///The environment variable of the option `long`.
+fn env_var(long: &str) -> String {
+    format!("{}{}", ENV_PREFIX, long.to_uppercase().replace('-', "_"))
+}
+
+///`command` with an environment variable for every option of it and its
+///subcommands. Flags accept `1`, `true`, `yes` and `on` and their
+///negations.
+fn with_env_vars(command: clap::Command) -> clap::Command {
+    let subcommands = command
+        .get_subcommands()
+        .map(|subcommand| subcommand.get_name().to_string())
+        .collect::<Vec<_>>();
+    let command = subcommands.iter().fold(command, |command, name| {
+        command.mut_subcommand(name, with_env_vars)
+    });
+    command.mut_args(|arg| {
+        let Some(long) = arg
+            .get_long()
+            .filter(|long| !matches!(*long, "help" | "version"))
+            .filter(|_| arg.get_id() != "version_json")
+        else {
+            return arg;
+        };
+        let env = env_var(long);
+        match arg.get_action() {
+            ArgAction::SetTrue => arg.env(env).value_parser(BoolishValueParser::new()),
+            _ => arg.env(env),
+        }
+    })
+}
+
+///Prints every option of the invoked subcommand with its value and its
+///source. Options the config file backs show its value when neither a flag
+///nor the environment set them.
+fn print_config(command: &mut clap::Command, matches: &ArgMatches, settings: &Config) {
+    command.build();
+    let Some((name, matches)) = matches.subcommand() else {
+        return;
+    };
+    let Some(subcommand) = command.find_subcommand(name) else {
+        return;
+    };
+    let config = |id: &str| match id {
+        "allowed_envs" => settings.allowed_envs.as_ref().map(|envs| envs.join(",")),
+        "unlimited_token_validity" => settings.unlimited_token_validity.clone(),
+        _ => None,
+    };
+    for arg in subcommand.get_arguments() {
+        let id = arg.get_id().as_str();
+        let Some(long) = arg
+            .get_long()
+            .filter(|long| !matches!(*long, "help" | "print-config"))
+        else {
+            continue;
+        };
+        let value = || {
+            matches
+                .get_raw(id)
+                .into_iter()
+                .flatten()
+                .map(|value| value.to_string_lossy().into_owned())
+                .collect::<Vec<_>>()
+                .join(",")
+        };
+        let (value, source) = match matches.value_source(id) {
+            Some(ValueSource::CommandLine) => (value(), "flag".to_string()),
+            Some(ValueSource::EnvVariable) => (value(), format!("env {}", env_var(long))),
+            Some(ValueSource::DefaultValue) => match config(id) {
+                Some(value) => (value, "config".to_string()),
+                None => (value(), "default".to_string()),
+            },
+            _ => match config(id) {
+                Some(value) => (value, "config".to_string()),
+                None => continue,
+            },
+        };
+        println!("--{} = {} ({})", long, value, source);
+    }
+}
+
 fn main() -> Result<()> {
-    let cli = Cli::parse();
+    let mut command = with_env_vars(Cli::command());
+    let matches = command.get_matches_mut();
+    let mut cli = Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    cli.global.plain = is_plain(cli.global.plain, |name| std::env::var(name).ok());
+    cli.global.color.plain(cli.global.plain).apply();
+    if cli.version {
+        match cli.json {
+            true => print_version_json(),
+            false => print_version(),
+        }
+        return Ok(());
+    }
+    let Some(subcommand) = cli.command else {
+        command
+            .error(ErrorKind::MissingSubcommand, "A subcommand is required")
+            .exit();
+    };
+    // doctor reports a broken config file instead of failing on it
+    if let (Some(path), false) = (
+        &cli.global.config,
+        matches!(subcommand, Commands::Doctor(_)),
+    ) {
+        cli.global.settings = Config::load(path)?;
+    }
+    if cli.global.print_config {
+        print_config(&mut command, &matches, &cli.global.settings);
+        return Ok(());
+    }
+
+    // outside a write Ctrl-C exits at once, during one the first Ctrl-C lets
+    // it finish and a second one exits
+    let cancel = CancellationToken::default();
+    let handler_token = cancel.clone();
+    ctrlc::set_handler(move || {
+        if handler_token.is_cancelled() || !handler_token.is_writing() {
+            subscription_migrator::lock::release_all();
+            std::process::exit(EXIT_INTERRUPTED);
+        }
+        eprintln!("Interrupted, finishing the current write. Press Ctrl-C again to abort.");
+        handler_token.cancel();
+    })?;
+
+    let result = match subcommand {
+        Commands::Single(args) => migrate_single(args, &cli.global, &cancel),
+        Commands::Bulk(args) => migrate_bulk(*args, &cli.global, &cancel),
+        Commands::Stats(args) => print_stats(args, &cli.global),
+        Commands::Validate(args) => validate(args, &cli.global),
+        Commands::Compare(args) => compare(args, &cli.global),
+        Commands::Fmt(args) => format(args, &cli.global),
+        Commands::Lint(args) => lint(args),
+        Commands::Upgrade(args) => upgrade(args),
+        Commands::Doctor(args) => doctor(args, &cli.global),
+        Commands::Plan(args) => plan(args, &cli.global),
+        Commands::Apply(args) => apply(args, &cancel),
+        Commands::ImportCsv(args) => import_csv(args, &cli.global, &cancel),
+        Commands::File(args) => migrate_file(args, &cli.global, &cancel),
+        Commands::Selftest(args) => selftest(args),
+    };
 
-    match cli.command {
-        Commands::Single(args) => migrate_single(args),
-        Commands::Bulk(args) => migrate_bulk(args),
+    if let Some(cancelled) = result
+        .as_ref()
+        .err()
+        .and_then(|e| e.downcast_ref::<Cancelled>())
+    {
+        eprintln!("{}. Completed before the interruption:", cancelled);
+        for file in &cancelled.files_written {
+            eprintln!("  {:?}", file);
+        }
+        std::process::exit(EXIT_INTERRUPTED);
     }
+    if let Some(nothing) = result
+        .as_ref()
+        .err()
+        .and_then(|e| e.downcast_ref::<NothingToMigrate>())
+    {
+        anstream::eprintln!("{} {}", paint(ERROR, "error:"), nothing);
+        std::process::exit(EXIT_NOTHING_TO_MIGRATE);
+    }
+    if let Some(skipped) = result
+        .as_ref()
+        .err()
+        .and_then(|e| e.downcast_ref::<SkippedExisting>())
+    {
+        warn(skipped);
+        std::process::exit(EXIT_SKIPPED_EXISTING);
+    }
+    result
 }
 
-fn migrate_bulk(args: BulkArgs) -> Result<()> {
-    let directories = std::fs::read_dir(&args.path)?;
-    let matching_paths = directories
-        .into_iter()
-        .filter_map(|entry| {
-            let entry = entry.as_ref().unwrap();
-            let path = entry.path();
-            let is_matching = path.is_dir()
-                && path
-                    .file_name()
-                    .unwrap()
-                    .to_str()
-                    .unwrap()
-                    .starts_with(&args.name_prefix);
-            if is_matching {
-                Some(path)
-            } else {
-                None
+///Runs the checks of `args`. The token settings and names are checked on
+///`sources`, one application per source, the subscriptions on
+///`applications`, which may already be merged by name.
+fn check_findings(
+    sources: &[XmlApplication],
+    applications: &[XmlApplication],
+    args: &CheckArgs,
+    options: &ConvertOptions,
+    global: &GlobalArgs,
+) -> Vec<Finding> {
+    let mut findings = check_token_conflicts(sources, args.strict_token_conflicts);
+    findings.extend(check_case_collisions(sources));
+    findings.extend(check_token_validity(sources));
+    if let Some(allowed) = args
+        .allowed_envs
+        .as_ref()
+        .or(global.settings.allowed_envs.as_ref())
+    {
+        findings.extend(check_allowed_envs(applications, allowed, options));
+    }
+    let name_rules = args.name_rules();
+    if name_rules.is_enabled() {
+        findings.extend(check_names(sources, &name_rules));
+    }
+    if args.check_env_parity {
+        findings.extend(check_env_parity(
+            applications,
+            &args.prod_envs,
+            &args.pre_prod_envs,
+        ));
+    }
+    if args.check_version_parity {
+        findings.extend(check_version_parity(applications, &args.prod_envs));
+    }
+    let default_format;
+    let version_format = match &args.version_format {
+        Some(format) => Some(format),
+        None if args.strict => {
+            default_format = parse_version_format(DEFAULT_VERSION_FORMAT)
+                .expect("the default version format is valid");
+            Some(&default_format)
+        }
+        None => None,
+    };
+    if let Some(format) = version_format {
+        findings.extend(check_version_format(applications, format));
+    }
+    findings
+}
+
+///What [`Preparer`] changed, for the report.
+#[derive(Debug, Default)]
+struct Preparation {
+    name_fixes: Vec<NameFix>,
+    version_overrides: Vec<AppliedOverride>,
+    ///Applications left out by the allow and deny lists and `--include-tag`
+    skipped: Vec<SkippedApplication>,
+    ///Applications with only unknown environments
+    unmapped: Vec<UnmappedApplication>,
+    ///APIs over `--api-fanout-threshold`
+    api_fanout: Vec<ApiFanout>,
+    ///What `--drop-env` removed
+    dropped_environments: DroppedEnvironments,
+    ///Warnings of the checks, errors fail the preparation
+    findings: Vec<Finding>,
+}
+
+///The applications [`Preparer::finish`] made ready to convert.
+#[derive(Debug)]
+struct Prepared {
+    applications: Vec<XmlApplication>,
+    ///Every prepared application as parsed from its source, without its
+    ///subscriptions, credentials and comments
+    sources: Vec<XmlApplication>,
+    provenance: Vec<ApplicationProvenance>,
+    preparation: Preparation,
+}
+
+///The sources the applications named `name` were parsed from.
+fn sources_of(provenance: &[ApplicationProvenance], name: &str) -> BTreeSet<PathBuf> {
+    provenance
+        .binary_search_by(|app| app.application.as_str().cmp(name))
+        .map(|index| provenance[index].sources.clone())
+        .unwrap_or_default()
+}
+
+///Applies the conversion settings to the parsed applications and runs the
+///requested checks, failing before anything is written. The applications
+///are added a source at a time, so a bulk run can merge each directory
+///right after parsing it and only hold the merged applications, which
+///[`Preparer::finish`] then checks as a whole.
+struct Preparer<'a> {
+    convert: &'a ConvertArgs,
+    checks: &'a CheckArgs,
+    global: &'a GlobalArgs,
+    options: ConvertOptions,
+    limits: Limits,
+    ///Applications added so far
+    read: usize,
+    name_rules: NameRules,
+    overrides: Option<VersionOverrides>,
+    override_report: OverrideReport,
+    rename_map: Option<RenameMap>,
+    rename_report: RenameReport,
+    name_casing: NameCasing,
+    case_merge: CaseMerge,
+    ///The allow and deny lists, which only need the name of an application
+    selection: Selection,
+    selection_report: SelectionReport,
+    dropped_environments: DroppedEnvironments,
+    name_fixes: Vec<NameFix>,
+    sources: Vec<XmlApplication>,
+    provenance: ProvenanceTracker,
+}
+
+impl<'a> Preparer<'a> {
+    fn new(
+        convert: &'a ConvertArgs,
+        checks: &'a CheckArgs,
+        global: &'a GlobalArgs,
+    ) -> Result<Self> {
+        let overrides = convert
+            .override_versions
+            .as_deref()
+            .map(VersionOverrides::from_file)
+            .transpose()?;
+        let rename_map = convert
+            .rename_map
+            .as_deref()
+            .map(RenameMap::from_file)
+            .transpose()?;
+        let selection =
+            Selection::load(convert.allow_list.as_deref(), convert.deny_list.as_deref())?;
+        // applied to no applications, every entry is unmatched until one
+        // of the sources matches it
+        let override_report = match &overrides {
+            Some(overrides) => overrides.apply(&mut [])?,
+            None => OverrideReport::default(),
+        };
+        let rename_report = match &rename_map {
+            Some(rename_map) => rename_map.apply(&mut []),
+            None => RenameReport::default(),
+        };
+        let selection_report = selection.apply(&mut Vec::new());
+        Ok(Preparer {
+            convert,
+            checks,
+            global,
+            options: convert.options(),
+            limits: Limits {
+                max_applications: convert.max_applications,
+                max_apis_per_application: convert.max_apis_per_application,
+                ..Default::default()
+            },
+            read: 0,
+            name_rules: checks.name_rules(),
+            overrides,
+            override_report,
+            rename_map,
+            rename_report,
+            name_casing: NameCasing::new(convert.name_case),
+            case_merge: CaseMerge::default(),
+            selection,
+            selection_report,
+            dropped_environments: DroppedEnvironments::default(),
+            name_fixes: Vec::new(),
+            sources: Vec::new(),
+            provenance: ProvenanceTracker::default(),
+        })
+    }
+
+    ///Prepares the applications of the next source, everything that
+    ///doesn't need the applications of the other sources.
+    fn add(&mut self, applications: &mut Vec<XmlApplication>) -> Result<()> {
+        let convert = self.convert;
+        self.limits
+            .check_more_applications(self.read, applications)?;
+        self.read += applications.len();
+        for app in applications.iter_mut() {
+            if !convert.include_credentials {
+                app.credentials = None;
+            }
+            if !convert.preserve_comments {
+                app.comments.clear();
+            }
+        }
+        if convert.normalize_whitespace {
+            normalize_whitespace(applications);
+        }
+        apply_version_map(applications, &self.checks.version_map);
+        if let Some(overrides) = &self.overrides {
+            self.override_report.merge(overrides.apply(applications)?);
+        }
+        if let Some(rename_map) = &self.rename_map {
+            self.rename_report.merge(rename_map.apply(applications));
+        }
+        self.name_casing.apply(applications);
+
+        if convert.fix_names {
+            let fixes = fix_names(applications, &self.name_rules);
+            for fix in &fixes {
+                eprintln!(
+                    "Fixed application name {:?} to {:?} in {:?}",
+                    fix.from, fix.to, fix.source
+                );
+            }
+            self.name_fixes.extend(fixes);
+        }
+
+        if convert.merge_case_insensitive {
+            for merge in self.case_merge.apply(applications) {
+                if self.global.verbose {
+                    eprintln!(
+                        "Merged application {:?} into {:?} in {:?}",
+                        merge.from, merge.to, merge.source
+                    );
+                }
+            }
+        }
+
+        self.selection_report
+            .merge(self.selection.apply(applications));
+        self.dropped_environments.merge(drop_environments(
+            applications,
+            &convert.drop_env,
+            &self.options,
+        ));
+
+        self.sources
+            .extend(applications.iter().map(|app| XmlApplication {
+                name: app.name.clone(),
+                token_type: app.token_type.clone(),
+                apis: Vec::new(),
+                token_validity: app.token_validity,
+                source: app.source.clone(),
+                original_name: app.original_name.clone(),
+                credentials: None,
+                comments: Vec::new(),
+                duplicate_subscriptions: app.duplicate_subscriptions,
+                description: app.description.clone(),
+                tags: app.tags.clone(),
+            }));
+        self.provenance.add(applications);
+        Ok(())
+    }
+
+    ///Prepares the `applications` of every added source, merged by name in
+    ///a bulk run, with what needs all of them, and runs the checks.
+    fn finish(self, mut applications: Vec<XmlApplication>) -> Result<Prepared> {
+        let Preparer {
+            convert,
+            checks,
+            global,
+            options,
+            override_report,
+            rename_report,
+            name_casing,
+            mut selection_report,
+            mut dropped_environments,
+            name_fixes,
+            mut sources,
+            provenance,
+            ..
+        } = self;
+        let mut provenance = provenance.finish();
+        let mut preparation = Preparation {
+            name_fixes,
+            ..Default::default()
+        };
+        eprint!("{}", override_report);
+        preparation.version_overrides = override_report.applied;
+        eprint!("{}", rename_report);
+
+        let mut findings = name_casing.finish();
+        if global.verbose {
+            for (name, originals) in original_names(&sources) {
+                for original in originals.iter().filter(|original| **original != name) {
+                    eprintln!("Application {:?} is named {:?}", original, name);
+                }
+            }
+        }
+
+        // the tags of every same-named application count, so they are only
+        // known once all of them are added
+        let mut removed = BTreeSet::new();
+        let tags = Selection::default().include_tags(convert.include_tag.iter().cloned());
+        for skip in tags.apply(&mut applications).skipped {
+            removed.insert(skip.name);
+        }
+        selection_report.skipped.extend(
+            sources
+                .iter()
+                .filter(|app| removed.contains(&app.name))
+                .map(|app| SkippedApplication {
+                    name: app.name.clone(),
+                    source: app.source.clone(),
+                    reason: SkipReason::Untagged,
+                }),
+        );
+        for (list, name) in &selection_report.unmatched {
+            warn(format_args!(
+                "{:?} in {:?} matches no application",
+                name, list
+            ));
+        }
+        for skip in &selection_report.skipped {
+            if skip.reason == SkipReason::Denied || global.verbose {
+                eprintln!("Skipped application {:?}: {}", skip.name, skip.reason);
+            }
+        }
+        preparation.skipped = selection_report.skipped;
+
+        dropped_environments
+            .emptied
+            .retain(|app| !removed.contains(&app.name));
+        for app in &dropped_environments.emptied {
+            warn(format_args!(
+                "Application {:?} in {:?} has no subscriptions left after --drop-env",
+                app.name, app.source
+            ));
+        }
+        preparation.dropped_environments = dropped_environments;
+
+        let known = checks.known_envs(convert, global);
+        preparation.unmapped = unmapped_only(&applications, &known, &options);
+        for app in &mut preparation.unmapped {
+            app.sources = sources_of(&provenance, &app.name);
+            let environments = app.environments.iter().cloned().collect::<Vec<_>>();
+            match convert.migrate_unmapped {
+                true => warn(format_args!(
+                    "Application {:?} only has unmapped environments [{}], migrating it under the non-prod control plane",
+                    app.name,
+                    environments.join(", ")
+                )),
+                false => warn(format_args!(
+                    "Skipped application {:?}: it only has unmapped environments [{}], use --migrate-unmapped to migrate it",
+                    app.name,
+                    environments.join(", ")
+                )),
             }
+        }
+        if !convert.migrate_unmapped {
+            removed.extend(preparation.unmapped.iter().map(|app| app.name.clone()));
+            applications.retain(|app| !removed.contains(&app.name));
+        }
+        sources.retain(|app| !removed.contains(&app.name));
+        provenance.retain(|app| !removed.contains(&app.application));
+
+        if let Some(threshold) = checks.api_fanout_threshold {
+            preparation.api_fanout = api_fanout(&applications, threshold);
+            for api in &preparation.api_fanout {
+                warn(api);
+            }
+        }
+
+        if convert.emit_security {
+            findings.extend(check_token_types(&sources, &global.settings.token_type_map));
+        }
+        if global.verbose && !convert.add_env.is_empty() {
+            report_added_environments(&applications, convert);
+        }
+
+        findings.extend(apply_version_policy_with(
+            &mut applications,
+            &mut provenance,
+            convert.version_policy,
+        ));
+        findings.extend(check_findings(
+            &sources,
+            &applications,
+            checks,
+            &options,
+            global,
+        ));
+        report_findings(&mut findings, checks.strict)?;
+        preparation.findings = findings;
+        Ok(Prepared {
+            applications,
+            sources,
+            provenance,
+            preparation,
         })
-        .collect::<Vec<PathBuf>>();
+    }
+}
+
+///Prints which applications get the `--add-env` environments and which
+///already have them.
+fn report_added_environments(applications: &[XmlApplication], convert: &ConvertArgs) {
+    let options = convert.options();
+    let mut environments = BTreeMap::<&str, BTreeSet<&str>>::new();
+    for app in applications {
+        let envs = app.apis.iter().flat_map(|sub| &sub.env);
+        environments
+            .entry(&app.name)
+            .or_default()
+            .extend(envs.map(|env| options.alias(env)));
+    }
+    for (name, envs) in environments {
+        for added in &convert.add_env {
+            match envs.contains(added.name.as_str()) {
+                true => eprintln!(
+                    "Application {:?} already has environment {:?}, not adding it",
+                    name, added.name
+                ),
+                false => eprintln!("Added environment {:?} to {:?}", added.name, name),
+            }
+        }
+    }
+}
+
+///Prints the duplicate subscriptions dropped while parsing.
+fn report_warnings(parsed: &ParseOutcome, global: &GlobalArgs) {
+    if !global.verbose {
+        return;
+    }
+    for warning in parsed.warnings() {
+        eprintln!("{}", warning);
+    }
+    if parsed.cache_hits > 0 {
+        eprintln!(
+            "Reused {} parsed export(s) from the cache",
+            parsed.cache_hits
+        );
+    }
+}
+
+///The applications of the exports of `paths`.
+fn parse_directories(paths: &[PathBuf], global: &GlobalArgs) -> Result<Vec<XmlApplication>> {
+    let sources = paths
+        .iter()
+        .map(|path| global.source(path.clone()))
+        .collect::<Vec<_>>();
+    let parsed = pipeline::parse(&sources, &mut Timings::default())?;
+    report_warnings(&parsed, global);
+    Ok(parsed.applications)
+}
+
+fn find_matching_directories(args: &ScanArgs, global: &GlobalArgs) -> Result<Vec<PathBuf>> {
+    Ok(scan(args, None, global)?.matched)
+}
+
+///Scans for matching directories, leaving out those belonging to `output`.
+fn scan(args: &ScanArgs, output: Option<&Path>, global: &GlobalArgs) -> Result<Scan> {
+    let matcher = Matcher {
+        prefix: args.name_prefix.clone(),
+        include_hidden: args.hidden,
+        mode: args.mode(),
+        output: output.map(Path::to_path_buf),
+        keep_aliases: args.no_dedupe_sources,
+    };
+    let scan = pipeline::scan(std::slice::from_ref(&args.path), &matcher)?;
+    if global.verbose {
+        eprintln!("Scanned {:?}: {}", args.path, scan);
+        for (alias, kept) in &scan.aliases {
+            eprintln!("Skipped {:?}, the same directory as {:?}", alias, kept);
+        }
+        if args.match_path {
+            for directory in &scan.matched {
+                let relative = directory.strip_prefix(&args.path).unwrap_or(directory);
+                let relative = relative
+                    .components()
+                    .map(|component| component.as_os_str().to_string_lossy())
+                    .collect::<Vec<_>>();
+                eprintln!("Matched {:?}", relative.join("/"));
+            }
+        }
+    }
+    Ok(scan)
+}
+
+///The README of `--write-readme`, listing the sources of each application
+///like `--emit-source-info` does. Stamped with the time of the run unless
+///`reproducible`.
+fn readme(applications: &[XmlApplication], reproducible: bool) -> ExtraFile {
+    let mut sources = BTreeMap::<String, BTreeSet<PathBuf>>::new();
+    for app in applications {
+        sources
+            .entry(app.name.clone())
+            .or_default()
+            .insert(relative_source(&app.source));
+    }
+    let generated_at = (!reproducible)
+        .then(|| chrono::DateTime::<chrono::Utc>::from(SystemTime::now()).to_rfc3339());
+    ExtraFile::readme(Readme {
+        sources,
+        generated_at,
+    })
+}
+
+///The subscribe.xml path below the scanned directory, e.g.
+///`app-a/subscribe.xml`. Matched directories sit directly below it.
+fn relative_source(source: &Path) -> PathBuf {
+    let file = source.file_name().unwrap_or_default();
+    match source.parent().and_then(Path::file_name) {
+        Some(directory) => Path::new(directory).join(file),
+        None => PathBuf::from(file),
+    }
+}
+
+///Checks and lints the generated documents before they are written.
+fn self_check(
+    documents: &[YamlApiSubscription],
+    checks: &CheckArgs,
+    convert: &ConvertArgs,
+) -> Result<()> {
+    for document in documents {
+        check_environment_blocks(document)?;
+    }
+    let disabled = convert.unchecked_rules();
+    let mut findings = documents
+        .iter()
+        .flat_map(|document| lint_document(document, None, &disabled))
+        .collect::<Vec<_>>();
+    report_findings(&mut findings, checks.strict)
+}
+
+///Writes the documents and, if enabled, the manifest below `output` or into
+///the archive given with `--archive`. With `--dry-run` a directory output
+///is only previewed.
+fn write_output(
+    write: &WriteArgs,
+    output: &Path,
+    documents: &[YamlApiSubscription],
+    applications: &[XmlApplication],
+    cancel: &CancellationToken,
+    timings: &mut Timings,
+    global: &GlobalArgs,
+) -> Result<WriteOutcome> {
+    let mut policy = WritePolicy {
+        retry: global.retry_policy(),
+        ..write.policy()
+    };
+    if write.write_readme {
+        let readme = readme(applications, write.metadata.reproducible);
+        policy.extra_files.push(readme);
+    }
+    let sink = write.sink(output)?;
+    let documents = match write.max_output_size {
+        Some(max) => {
+            let fitted = fit_documents(documents, max, write.split_large, policy.line_ending)?;
+            report_split_documents(&fitted, write);
+            Cow::Owned(fitted)
+        }
+        None => Cow::Borrowed(documents),
+    };
+    let documents = documents.as_ref();
+    let limits = Limits {
+        max_output_files: write.max_output_files,
+        ..Default::default()
+    };
+    limits.check_output_files(policy.file_count(documents.len()))?;
+    if let (Sink::Directory(directory), true) = (&sink, policy.dry_run) {
+        let previews = pipeline::preview(documents, directory, policy.line_ending)?;
+        match write.porcelain {
+            true => write_preview(&mut anstream::stderr(), &previews)?,
+            false => write_preview(&mut anstream::stdout(), &previews)?,
+        }
+        write.say(format_args!(
+            "Dry run, nothing was written to {}",
+            directory.display()
+        ));
+        return Ok(WriteOutcome::default());
+    }
+    let _lock = match &sink {
+        Sink::Directory(_) => Some(write.lock(output)?),
+        _ => None,
+    };
+    let outcome = pipeline::write(documents, applications, &sink, &policy, cancel, timings)?;
+    if let Some((archive, entries)) = &outcome.archive {
+        for file in entries {
+            write.say(format_args!("File archived: {}", file.display()));
+        }
+        write.say(format_args!("Archive written: {}", archive.display()));
+        return Ok(outcome);
+    }
+    let mut files = outcome.files.clone();
+    files.extend(outcome.skipped_existing.iter().map(|conflict| WrittenFile {
+        application: conflict.application.clone(),
+        path: conflict.directory.clone(),
+        status: WriteStatus::Skipped(format!("{}, --if-exists skip", conflict)),
+    }));
+    if write.porcelain {
+        print_porcelain(&files)?;
+    } else {
+        print_grouped(&files, applications);
+    }
+    if global.verbose {
+        for directory in &outcome.created_directories {
+            eprintln!("Created directory {:?}", directory);
+        }
+        for directory in &outcome.reused_directories {
+            eprintln!("Reused existing directory {:?}", directory);
+        }
+    }
+    if !outcome.created_directories.is_empty() || !outcome.reused_directories.is_empty() {
+        write.say(format_args!(
+            "Created {} directories, reused {} existing ones",
+            outcome.created_directories.len(),
+            outcome.reused_directories.len()
+        ));
+    }
+    if outcome.not_written() > 0 {
+        let files = outcome
+            .files
+            .into_iter()
+            .filter(|file| !file.status.is_written())
+            .collect();
+        return Err(NotWritten { files }.into());
+    }
+    if !outcome.skipped_existing.is_empty() {
+        write.say(format_args!(
+            "Skipped {} application(s) whose directory already exists",
+            outcome.skipped_existing.len()
+        ));
+    }
+    if let (Sink::Objects(url), true) = (&sink, policy.dry_run) {
+        write.say(format_args!("Dry run, nothing was uploaded to {}", url));
+    }
+    if let Some(path) = &outcome.manifest {
+        write.say(format_args!("Manifest written: {}", path.display()));
+    }
+    if let Some(path) = &outcome.index {
+        write.say(format_args!("Index written: {}", path.display()));
+    }
+    Ok(outcome)
+}
+
+///Names every application split by `--split-large` and its number of files.
+fn report_split_documents(documents: &[YamlApiSubscription], write: &WriteArgs) {
+    let mut parts = BTreeMap::<&str, usize>::new();
+    for document in documents.iter().filter(|document| document.part.is_some()) {
+        *parts.entry(document.name()).or_default() += 1;
+    }
+    for (name, count) in parts {
+        write.say(format_args!(
+            "Split application {:?} into {} files to stay within --max-output-size",
+            name, count
+        ));
+    }
+}
+
+///Parses, prepares and merges the applications of the matched directories
+///into the documents a bulk run writes, see [`prepare_directories`] and
+///[`convert_prepared`].
+fn convert_directories(
+    paths: &[PathBuf],
+    convert: &ConvertArgs,
+    checks: &CheckArgs,
+    metadata: &MetadataArgs,
+    report: &mut MigrationReport,
+    errors: Option<&mut ErrorReport>,
+    global: &GlobalArgs,
+) -> Result<(Vec<XmlApplication>, Vec<YamlApiSubscription>)> {
+    let applications = prepare_directories(paths, convert, checks, report, errors, global)?;
+    convert_prepared(applications, true, convert, checks, metadata, global)
+}
+
+///Parses and prepares the applications of the matched directories, merging
+///each directory into the applications of the ones before it so that its
+///parse output is dropped right away. Unknown XML elements and parse
+///timings are added to `report`. With `errors` directories that fail to
+///parse are recorded there and left out instead of failing.
+fn prepare_directories(
+    paths: &[PathBuf],
+    convert: &ConvertArgs,
+    checks: &CheckArgs,
+    report: &mut MigrationReport,
+    mut errors: Option<&mut ErrorReport>,
+    global: &GlobalArgs,
+) -> Result<Prepared> {
+    let mut sources = paths
+        .iter()
+        .map(|path| global.source(path.clone()))
+        .collect::<Vec<_>>();
+    // merged in source order, the first source keeps its settings
+    sources.sort_by(|a, b| a.file.cmp(&b.file));
+    let mut preparer = Preparer::new(convert, checks, global)?;
+    let mut accumulator = ApplicationAccumulator::new(convert.options());
+    let mut cache_hits = 0;
+    for source in &sources {
+        let source = std::slice::from_ref(source);
+        let mut parsed = match errors.as_deref_mut() {
+            Some(errors) => {
+                let mut parsed = pipeline::parse_skipping_failures(source, &mut report.timings);
+                for (directory, e) in parsed.failures.drain(..) {
+                    eprintln!("Failed to parse {:?}: {:#}", directory, e);
+                    errors.record(Phase::Parse, &directory, &e);
+                    if let Some(candidates) = &mut report.candidates {
+                        let outcome = CandidateOutcome::parse_failed(&e);
+                        candidates.classify(&directory, outcome);
+                    }
+                }
+                parsed
+            }
+            None => pipeline::parse(source, &mut report.timings)?,
+        };
+        cache_hits += std::mem::take(&mut parsed.cache_hits);
+        report_warnings(&parsed, global);
+        let ParseOutcome {
+            mut applications,
+            unknown_elements,
+            directory_overrides,
+            ..
+        } = parsed;
+        for applied in &directory_overrides {
+            eprint!("{}", applied);
+        }
+        report.directory_overrides.extend(directory_overrides);
+        report.unknown_elements.merge(unknown_elements);
+        preparer.add(&mut applications)?;
+        for app in applications {
+            accumulator.add(app);
+        }
+    }
+    report_warnings(
+        &ParseOutcome {
+            cache_hits,
+            ..Default::default()
+        },
+        global,
+    );
+    if global.verbose {
+        eprint!("{}", report.unknown_elements);
+    }
+    let mut prepared = preparer.finish(accumulator.into_applications())?;
+    let preparation = std::mem::take(&mut prepared.preparation);
+    report.name_fixes = preparation.name_fixes;
+    report.version_overrides = preparation.version_overrides;
+    report.skipped_by_policy = preparation.skipped;
+    report.unmapped_only = preparation.unmapped;
+    report.api_fanout = preparation.api_fanout;
+    report.findings = preparation.findings;
+    if !convert.drop_env.is_empty() {
+        report.dropped_environments = Some(preparation.dropped_environments);
+    }
+    if global.verbose {
+        for app in &prepared.provenance {
+            if app.sources.len() > 1 {
+                eprint!("{}", app);
+            }
+        }
+    }
+    Ok(prepared)
+}
+
+///Converts prepared applications into documents, merging the same-named
+///ones with `merge`. Only the documents hold the subscriptions from here
+///on, the applications come back as parsed from their sources.
+fn convert_prepared(
+    prepared: Prepared,
+    merge: bool,
+    convert: &ConvertArgs,
+    checks: &CheckArgs,
+    metadata: &MetadataArgs,
+    global: &GlobalArgs,
+) -> Result<(Vec<XmlApplication>, Vec<YamlApiSubscription>)> {
+    let options = convert
+        .options()
+        .merge_by_name(merge)
+        .split_by_subscription(!merge);
+    let mut documents = convert_applications(prepared.applications, &options);
+    convert.apply_token_settings(&mut documents, &prepared.sources, global);
+    metadata.stamp(&mut documents, metadata.provenance(&prepared.provenance));
+    self_check(&documents, checks, convert)?;
+    Ok((prepared.sources, documents))
+}
 
-    let mut staged_applications = Vec::new();
-    for mut path in matching_paths {
-        path = path.join("subscribe.xml");
-        let file = std::fs::File::open(path)?;
-        let applications = parse_xml_file(&file)?;
-        staged_applications.extend(applications);
+fn migrate_bulk(args: BulkArgs, global: &GlobalArgs, cancel: &CancellationToken) -> Result<()> {
+    let mut errors = ErrorReport::default();
+    let result = run_bulk(&args, global, cancel, &mut errors);
+    if let Some(path) = &args.error_report {
+        if !errors.is_empty() || !args.error_report_only_on_failure {
+            errors.write(path)?;
+            eprintln!("Error report written: {:?}", path);
+        }
+    }
+    result
+}
+
+///The bulk run, recording every failure in `errors`.
+fn run_bulk(
+    args: &BulkArgs,
+    global: &GlobalArgs,
+    cancel: &CancellationToken,
+    errors: &mut ErrorReport,
+) -> Result<()> {
+    let input = args.scan.path.as_path();
+    errors.check(
+        Phase::Write,
+        &args.output_path,
+        args.write.prepare_output(&args.output_path),
+    )?;
+    let output = matches!(args.write.sink(&args.output_path)?, Sink::Directory(_))
+        .then_some(args.output_path.as_path());
+    let scan = errors.check(Phase::Scan, input, scan(&args.scan, output, global))?;
+    let root = args.include_root.then(|| input.join("subscribe.xml"));
+    let root = root.filter(|file| file.is_file());
+    if args.require_root && root.is_none() {
+        let e = anyhow::anyhow!("--require-root: there is no subscribe.xml in {:?}", input);
+        return errors.check(Phase::Scan, input, Err(e));
+    }
+    if scan.matched.is_empty() && root.is_none() && !args.allow_empty {
+        let prefix = &args.scan.name_prefix;
+        let e = NothingToMigrate::no_matches(input, prefix, &scan).into();
+        return errors.check(Phase::Scan, input, Err(e));
+    }
+    let mut candidates = args.report_candidates.then_some(scan.candidates);
+    let matching_paths = scan.matched;
+    let total = matching_paths.len();
+    let slice = match args.sample {
+        Some(size) => Slice::Sample {
+            size,
+            seed: args.seed,
+        },
+        None => Slice::Range {
+            offset: args.offset.unwrap_or_default(),
+            limit: args.limit,
+        },
+    };
+    let mut matching_paths = match &mut candidates {
+        Some(candidates) => {
+            let selected = slice.select(matching_paths.clone());
+            for path in matching_paths
+                .iter()
+                .filter(|path| !selected.contains(path))
+            {
+                candidates.classify(path, CandidateOutcome::FilteredBySlice);
+            }
+            selected
+        }
+        None => slice.select(matching_paths),
+    };
+    let selected = matching_paths.len();
+    // the root is not one of the matches and always part of the run
+    if root.is_some() {
+        matching_paths.insert(0, input.to_path_buf());
+    }
+    let mut up_to_date = Vec::new();
+    if let Some(since) = args.since {
+        (matching_paths, up_to_date) = partition_modified_since(matching_paths, since);
+        if let Some(candidates) = &mut candidates {
+            for path in &up_to_date {
+                candidates.classify(path, CandidateOutcome::FilteredBySince);
+            }
+        }
+        if global.verbose {
+            for path in &up_to_date {
+                eprintln!("Up to date: {:?}", path);
+            }
+        }
+    }
+    #[cfg(feature = "git")]
+    let deleted_exports = match &args.only_changed {
+        Some(reference) => {
+            let changes = ChangedExports::since(input, reference)?;
+            (matching_paths, up_to_date) = matching_paths
+                .into_iter()
+                .partition(|path| changes.contains(&subscribe_file(path)));
+            if let Some(candidates) = &mut candidates {
+                for path in &up_to_date {
+                    candidates.classify(path, CandidateOutcome::UnchangedInGit);
+                }
+            }
+            if global.verbose {
+                for path in &up_to_date {
+                    eprintln!("Unchanged since {}: {:?}", reference, path);
+                }
+            }
+            changes.deleted
+        }
+        None => Vec::new(),
+    };
+    if matching_paths.is_empty() && total > 0 && !args.allow_empty {
+        let e = NothingToMigrate::AllFiltered {
+            path: args.scan.path.clone(),
+            matched: total,
+        }
+        .into();
+        return errors.check(Phase::Scan, input, Err(e));
+    }
+    let mut report = MigrationReport::new(Timings::new(
+        global.verbose || args.report.is_some(),
+        args.write.warn_slower_than,
+    ));
+    report.candidates = candidates;
+    #[cfg(feature = "git")]
+    {
+        report.deleted_exports = deleted_exports;
+    }
+    let skip_failures = args.error_report.is_some().then_some(&mut *errors);
+    let prepared = prepare_directories(
+        &matching_paths,
+        &args.convert,
+        &args.checks,
+        &mut report,
+        skip_failures,
+        global,
+    );
+    let prepared = errors.check(Phase::Convert, input, prepared)?;
+    if let Some(candidates) = &mut report.candidates {
+        candidates.finish();
+    }
+    let provenance_report = args.provenance_report.as_ref().map(|_| ProvenanceReport {
+        root: root.clone(),
+        applications: prepared.provenance.clone(),
+    });
+    // needs the subscriptions, which only the documents keep
+    if let Some(prod) = args.environments.is_prod() {
+        report.unexpected_environments =
+            unexpected_environments(&prepared.applications, prod, &args.convert.options());
+        for app in &mut report.unexpected_environments {
+            app.sources = sources_of(&prepared.provenance, &app.name);
+        }
+    }
+    let converted = convert_prepared(
+        prepared,
+        true,
+        &args.convert,
+        &args.checks,
+        &args.write.metadata,
+        global,
+    );
+    let (staged_applications, yaml_applications) =
+        errors.check(Phase::Convert, input, converted)?;
+    if let Some(root) = &root {
+        report.root_applications = staged_applications
+            .iter()
+            .filter(|app| &app.source == root)
+            .map(|app| app.name.clone())
+            .collect();
+        if global.verbose {
+            for name in &report.root_applications {
+                eprintln!("Application {:?} comes from the root {:?}", name, root);
+            }
+        }
+    }
+    if args.environments.is_prod().is_some() {
+        let checked = check_unexpected_environments(
+            &report.unexpected_environments,
+            args.environments,
+            args.forbid_unexpected_envs,
+        );
+        errors.check(Phase::Convert, input, checked)?;
+    }
+    let directories = staged_applications
+        .iter()
+        .map(|app| &app.source)
+        .collect::<BTreeSet<_>>();
+    let expected = |expected: Option<usize>, actual: usize| {
+        expected.map(|expected| ExpectedCount {
+            expected,
+            actual,
+            tolerance: args.expect_tolerance,
+        })
+    };
+    report.expectations = Expectations {
+        applications: expected(args.expect_applications, yaml_applications.len()),
+        directories: expected(args.expect_directories, directories.len()),
+    };
+    if let (Err(_), Some(path)) = (report.expectations.check(), &args.report) {
+        errors.check(Phase::Write, path, report.write(path))?;
+    }
+    errors.check(Phase::Convert, input, report.expectations.check())?;
+    let parse_failures = errors.0.len();
+    let written = write_output(
+        &args.write,
+        &args.output_path,
+        &yaml_applications,
+        &staged_applications,
+        cancel,
+        &mut report.timings,
+        global,
+    );
+    let outcome = errors.check(Phase::Write, &args.output_path, written)?;
+    if global.verbose {
+        eprint!("{}", report.timings.slowest(SLOWEST_SHOWN));
+    }
+    if let Some(path) = &args.metrics_file {
+        let status = |matches: fn(&WriteStatus) -> bool| {
+            outcome
+                .files
+                .iter()
+                .filter(|file| matches(&file.status))
+                .count()
+        };
+        let mut metrics = RunMetrics {
+            files_written: status(|status| {
+                matches!(status, WriteStatus::Created | WriteStatus::Overwritten)
+            }),
+            files_unchanged: status(|status| matches!(status, WriteStatus::Unchanged)),
+            failures: parse_failures + status(|status| matches!(status, WriteStatus::Failed(_))),
+            timestamp: SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .map_or(0, |since| since.as_secs()),
+            ..Default::default()
+        };
+        metrics.count_documents(&yaml_applications);
+        let written = write_atomic(path, metrics.render().as_bytes());
+        errors.check(Phase::Write, path, written)?;
+    }
+    if let Some(path) = &args.report {
+        report.files_written = outcome.written_paths();
+        errors.check(Phase::Write, path, report.write(path))?;
+        args.write
+            .say(format_args!("Report written: {}", path.display()));
+    }
+    if let (Some(path), Some(report)) = (&args.provenance_report, provenance_report) {
+        errors.check(Phase::Write, path, report.write(path))?;
+        args.write.say(format_args!(
+            "Provenance report written: {}",
+            path.display()
+        ));
+    }
+    args.write.say(slice.describe(selected, total));
+    if let Some(root) = &root {
+        args.write.say(format_args!(
+            "Included {} application(s) from the root {}",
+            report.root_applications.len(),
+            root.display()
+        ));
+    }
+    if !report.skipped_by_policy.is_empty() {
+        args.write.say(format_args!(
+            "Skipped {} application(s) by the allow and deny lists and --include-tag",
+            report.skipped_by_policy.len()
+        ));
+    }
+    if let Some(dropped) = &report.dropped_environments {
+        args.write.say(format_args!(
+            "Dropped {} environment reference(s) by --drop-env, removing {} subscription(s)",
+            dropped.references, dropped.subscriptions
+        ));
+    }
+    for (name, count) in report.expectations.counts() {
+        args.write.say(format_args!("{}: {}", name, count));
+    }
+    if args.since.is_some() {
+        args.write.say(format_args!(
+            "Skipped {} up-to-date directories not modified since the cutoff",
+            up_to_date.len()
+        ));
+    }
+    #[cfg(feature = "git")]
+    if let Some(reference) = &args.only_changed {
+        args.write.say(format_args!(
+            "Skipped {} directories unchanged since {}",
+            up_to_date.len(),
+            reference
+        ));
+        for export in &report.deleted_exports {
+            warn(format_args!(
+                "{:?} was deleted since {}, its applications may need decommissioning",
+                export, reference
+            ));
+        }
     }
-    let yaml_applications = unify_applilcations(&staged_applications);
-    let files_written = write_to_file(&yaml_applications, args.output_path, args.force)?;
-    for file in files_written {
-        println!("File written: {:?}", file);
+    if parse_failures > 0 {
+        return Err(anyhow::anyhow!(
+            "{} directories failed to parse",
+            parse_failures
+        ));
     }
+    let skipped = SkippedExisting::check(&outcome.skipped_existing);
+    errors.check(Phase::Write, &args.output_path, skipped)?;
 
     Ok(())
 }
 
-fn migrate_single(args: SingleArgs) -> Result<()> {
+///Prepares the applications of a single file and converts them into one
+///document each, like [`convert_directories`] but only merging the
+///same-named ones with `merge`.
+fn convert_file(
+    mut applications: Vec<XmlApplication>,
+    merge: bool,
+    convert: &ConvertArgs,
+    checks: &CheckArgs,
+    metadata: &MetadataArgs,
+    global: &GlobalArgs,
+) -> Result<(Vec<XmlApplication>, Vec<YamlApiSubscription>)> {
+    let mut preparer = Preparer::new(convert, checks, global)?;
+    preparer.add(&mut applications)?;
+    let prepared = preparer.finish(applications)?;
+    convert_prepared(prepared, merge, convert, checks, metadata, global)
+}
+
+fn import_csv(args: ImportCsvArgs, global: &GlobalArgs, cancel: &CancellationToken) -> Result<()> {
+    let columns = CsvColumns::with_overrides(&args.columns)?;
+    args.write.prepare_output(&args.output_dir)?;
+
+    let mut timings = Timings::new(global.verbose, args.write.warn_slower_than);
+    let mut applications = timings.time_parse(&args.input, || {
+        let file = std::fs::File::open(&args.input)
+            .with_context(|| format!("Failed to open {:?}", args.input))?;
+        parse_csv_file(file, &columns)
+    })?;
+    for app in &mut applications {
+        app.source.clone_from(&args.input);
+    }
+    let parsed = ParseOutcome {
+        applications,
+        ..Default::default()
+    };
+    report_warnings(&parsed, global);
+    let (applications, documents) = convert_file(
+        parsed.applications,
+        false,
+        &args.convert,
+        &args.checks,
+        &args.write.metadata,
+        global,
+    )?;
+
+    let outcome = write_output(
+        &args.write,
+        &args.output_dir,
+        &documents,
+        &applications,
+        cancel,
+        &mut timings,
+        global,
+    )?;
+    if global.verbose {
+        eprint!("{}", timings.slowest(SLOWEST_SHOWN));
+    }
+    SkippedExisting::check(&outcome.skipped_existing)
+}
+
+///How many applications `file --verbose` reads between two progress lines
+const PROGRESS_EVERY: usize = 100;
+
+fn migrate_file(args: FileArgs, global: &GlobalArgs, cancel: &CancellationToken) -> Result<()> {
+    let mut errors = ErrorReport::default();
+    let result = run_file(&args, global, cancel, &mut errors);
+    if let Some(path) = &args.error_report {
+        errors.write(path)?;
+        eprintln!("Error report written: {:?}", path);
+    }
+    result
+}
+
+///Migrates the applications of one export like a bulk run migrates
+///directories: an application that fails to parse is recorded in `errors`
+///and the others are still written.
+fn run_file(
+    args: &FileArgs,
+    global: &GlobalArgs,
+    cancel: &CancellationToken,
+    errors: &mut ErrorReport,
+) -> Result<()> {
+    let input = args.input.as_path();
+    let directory = input.parent().unwrap_or(Path::new("."));
+    UnreadableExport::check(directory, input)?;
+    args.write.prepare_output(&args.output_dir)?;
+
+    let mut timings = Timings::new(global.verbose, args.write.warn_slower_than);
+    let mut applications = Vec::new();
+    // with --unify same-named applications are merged as they are read,
+    // keeping only their token settings for the conflict check
+    let mut accumulator = args
+        .unify
+        .then(|| ApplicationAccumulator::new(args.convert.options()));
+    let mut token_settings = Vec::new();
+    let mut read = 0;
+    timings.time_parse(input, || -> Result<()> {
+        let file = global
+            .retry_policy()
+            .open(input)
+            .with_context(|| format!("Failed to open {:?}", input))?;
+        for application in ApplicationIter::new(file) {
+            read += 1;
+            match application {
+                Ok(mut app) => {
+                    app.source = input.to_path_buf();
+                    match &mut accumulator {
+                        Some(accumulator) => {
+                            token_settings.push(XmlApplication {
+                                name: app.name.clone(),
+                                token_type: app.token_type.clone(),
+                                token_validity: app.token_validity,
+                                source: app.source.clone(),
+                                ..Default::default()
+                            });
+                            accumulator.add(app);
+                        }
+                        None => applications.push(app),
+                    }
+                }
+                Err(e) => {
+                    eprintln!(
+                        "Failed to parse application {} of {:?}: {:#}",
+                        read, input, e
+                    );
+                    errors.record_application(input, read, &e);
+                }
+            }
+            if global.verbose && read % PROGRESS_EVERY == 0 {
+                eprintln!("Read {} applications", read);
+            }
+        }
+        Ok(())
+    })?;
+    let parse_failures = errors.0.len();
+    if let Some(accumulator) = accumulator {
+        applications = accumulator.into_applications();
+        let mut conflicts =
+            check_token_conflicts(&token_settings, args.checks.strict_token_conflicts);
+        let checked = report_findings(&mut conflicts, args.checks.strict);
+        errors.check(Phase::Convert, input, checked)?;
+    }
+    let parsed = ParseOutcome {
+        applications,
+        ..Default::default()
+    };
+    report_warnings(&parsed, global);
+    let converted = convert_file(
+        parsed.applications,
+        args.unify,
+        &args.convert,
+        &args.checks,
+        &args.write.metadata,
+        global,
+    );
+    let (applications, documents) = errors.check(Phase::Convert, input, converted)?;
+
+    let written = write_output(
+        &args.write,
+        &args.output_dir,
+        &documents,
+        &applications,
+        cancel,
+        &mut timings,
+        global,
+    );
+    let outcome = errors.check(Phase::Write, &args.output_dir, written)?;
+    if global.verbose {
+        eprint!("{}", timings.slowest(SLOWEST_SHOWN));
+    }
+    args.write.say(format_args!(
+        "Migrated {} of {} application(s) read from {}",
+        applications.len(),
+        read,
+        input.display()
+    ));
+    if parse_failures > 0 {
+        return Err(anyhow::anyhow!(
+            "{} application(s) failed to parse",
+            parse_failures
+        ));
+    }
+    let skipped = SkippedExisting::check(&outcome.skipped_existing);
+    errors.check(Phase::Write, &args.output_dir, skipped)
+}
+
+fn migrate_single(args: SingleArgs, global: &GlobalArgs, cancel: &CancellationToken) -> Result<()> {
     let directory = args.input_dir;
 
     if !directory.exists() {
@@ -109,27 +2840,344 @@ fn migrate_single(args: SingleArgs) -> Result<()> {
         return Err(anyhow::anyhow!("Directory {:?} does not exist", directory));
     }
 
-    let file_path = directory.join("subscribe.xml");
+    let file_path = SourceDir::new(directory.clone()).file;
+    UnreadableExport::check(&directory, &file_path)?;
+
+    args.write.prepare_output(&args.output_dir)?;
+
+    let mut timings = Timings::new(global.verbose, args.write.warn_slower_than);
+    let parsed = pipeline::parse(&[global.source(directory)], &mut timings)?;
+    report_warnings(&parsed, global);
+    if global.verbose {
+        eprint!("{}", parsed.unknown_elements);
+    }
+    for applied in &parsed.directory_overrides {
+        eprint!("{}", applied);
+    }
+    let (xml_applications, yaml_applications) = convert_file(
+        parsed.applications,
+        false,
+        &args.convert,
+        &args.checks,
+        &args.write.metadata,
+        global,
+    )?;
+
+    let outcome = write_output(
+        &args.write,
+        &args.output_dir,
+        &yaml_applications,
+        &xml_applications,
+        cancel,
+        &mut timings,
+        global,
+    )?;
+    if global.verbose {
+        eprint!("{}", timings.slowest(SLOWEST_SHOWN));
+    }
+
+    SkippedExisting::check(&outcome.skipped_existing)
+}
+
+fn print_stats(args: StatsArgs, global: &GlobalArgs) -> Result<()> {
+    let matching_paths = find_matching_directories(&args.scan, global)?;
+    let sources = matching_paths
+        .into_iter()
+        .map(|path| global.source(path))
+        .collect::<Vec<_>>();
+    let parsed = pipeline::parse(&sources, &mut Timings::default())?;
+    report_warnings(&parsed, global);
+    let applications = parsed.applications;
+    let stats = Stats::compute(&applications, args.top, args.api_fanout_threshold);
+
+    match args.format {
+        OutputFormat::Table => print!("{}", stats),
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&stats)?),
+    }
+
+    Ok(())
+}
+
+fn validate(args: ValidateArgs, global: &GlobalArgs) -> Result<()> {
+    let matching_paths = find_matching_directories(&args.scan, global)?;
+    let mut applications = parse_directories(&matching_paths, global)?;
+    apply_version_map(&mut applications, &args.checks.version_map);
+    report_findings(
+        &mut check_findings(
+            &applications,
+            &applications,
+            &args.checks,
+            &ConvertOptions::default(),
+            global,
+        ),
+        args.checks.strict,
+    )?;
+    println!(
+        "Validated {} application(s) from {} directories",
+        applications.len(),
+        matching_paths.len()
+    );
+    Ok(())
+}
+
+fn compare(args: CompareArgs, global: &GlobalArgs) -> Result<()> {
+    let mut sides = Vec::new();
+    for path in [&args.left, &args.right] {
+        let scan = ScanArgs {
+            path: path.clone(),
+            name_prefix: args.name_prefix.clone(),
+            hidden: args.hidden,
+            match_path: args.match_path,
+            no_dedupe_sources: false,
+        };
+        let matching_paths = find_matching_directories(&scan, global)?;
+        sides.push(parse_directories(&matching_paths, global)?);
+    }
+    let comparison = Comparison::new(&sides[0], &sides[1]);
+
+    match args.format {
+        OutputFormat::Table => print!("{}", comparison),
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&comparison)?),
+    }
+
+    if !comparison.is_identical() {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+fn format(args: FmtArgs, global: &GlobalArgs) -> Result<()> {
+    let files = find_files_named(&args.path, SUBSCRIPTION_FILE)?;
+    let outcome = format_files(
+        &args.path,
+        &files,
+        args.output_path.as_deref(),
+        args.check,
+        args.line_endings,
+    );
+
+    let verb = if args.check {
+        "Would reformat"
+    } else {
+        "Reformatted"
+    };
+    for file in &outcome.changed {
+        println!("{}: {}", verb, file.display());
+    }
+    if global.verbose {
+        for file in &outcome.unchanged {
+            eprintln!("Unchanged: {:?}", file);
+        }
+    }
+    for (file, error) in &outcome.failed {
+        eprintln!("Failed: {:?}: {:#}", file, error);
+    }
 
-    if !file_path.exists() {
+    if !outcome.failed.is_empty() {
         return Err(anyhow::anyhow!(
-            "subscribe.xml does not exist in the directory {:?}",
-            directory
+            "{} file(s) could not be formatted",
+            outcome.failed.len()
         ));
     }
+    if args.check && !outcome.changed.is_empty() {
+        return Err(anyhow::anyhow!(
+            "{} file(s) are not formatted",
+            outcome.changed.len()
+        ));
+    }
+    Ok(())
+}
 
-    let file = std::fs::File::open(file_path)?;
+fn lint(args: LintArgs) -> Result<()> {
+    let files = find_files_named(&args.path, SUBSCRIPTION_FILE)?;
+    let mut findings = files
+        .iter()
+        .flat_map(|file| {
+            let drift = args
+                .verify_checksums
+                .and_then(|algorithm| verify_sidecar(algorithm, file));
+            lint_file(file, &args.disable).into_iter().chain(drift)
+        })
+        .collect::<Vec<_>>();
+    report_findings(&mut findings, false)?;
+    println!("Linted {} file(s)", files.len());
+    Ok(())
+}
 
-    let xml_applications = parse_xml_file(&file)?;
-    let yaml_applications = xml_applications
-        .into_iter()
-        .map(|app| app.into())
-        .collect::<Vec<YamlApiSubscription>>();
+fn upgrade(args: UpgradeArgs) -> Result<()> {
+    let files = find_files_named(&args.path, SUBSCRIPTION_FILE)?;
+    let mut failed = 0;
+    for file in &files {
+        let result = std::fs::read_to_string(file)
+            .map_err(anyhow::Error::from)
+            .and_then(|content| Ok((upgrade_document(&content)?, content)));
+        match result {
+            Ok((Upgrade::Current, _)) => println!("Current: {}", file.display()),
+            Ok((Upgrade::Upgraded { yaml }, content)) if args.dry_run => {
+                let name = file.display().to_string();
+                print!(
+                    "{}",
+                    similar::TextDiff::from_lines(&*redact_yaml(&content), &*redact_yaml(&yaml))
+                        .unified_diff()
+                        .header(&name, &name)
+                );
+            }
+            Ok((Upgrade::Upgraded { yaml }, _)) => {
+                write_atomic(file, yaml.as_bytes())?;
+                println!("Upgraded: {}", file.display());
+            }
+            Err(e) => {
+                eprintln!("Failed: {:?}: {:#}", file, e);
+                failed += 1;
+            }
+        }
+    }
 
-    let files_written = write_to_file(&yaml_applications, args.output_dir, args.force)?;
-    for file in files_written {
-        println!("File written: {:?}", file);
+    if failed > 0 {
+        return Err(anyhow::anyhow!("{} file(s) could not be upgraded", failed));
     }
+    Ok(())
+}
 
+fn doctor(args: DoctorArgs, global: &GlobalArgs) -> Result<()> {
+    let mut control_plane_urls = Vec::new();
+    for (option, url) in [
+        ("--prod-plane-url", &args.prod_plane_url),
+        ("--non-prod-plane-url", &args.non_prod_plane_url),
+    ] {
+        if let Some(url) = url {
+            control_plane_urls.push((option.to_string(), url.clone()));
+        }
+    }
+    for env in &args.add_env {
+        if let Some((name, url)) = env.split_once('=') {
+            control_plane_urls.push((format!("--add-env {}", name.trim()), url.trim().to_string()));
+        }
+    }
+    let checks = diagnose(&Setup {
+        input: &args.scan.path,
+        name_prefix: &args.scan.name_prefix,
+        include_hidden: args.scan.hidden,
+        mode: args.scan.mode(),
+        output: &args.output_path,
+        rename_map: args.rename_map.as_deref(),
+        config: global.config.as_deref(),
+        sample: args.sample,
+        control_plane_urls: &control_plane_urls,
+    });
+    for check in &checks {
+        println!("{}", check);
+    }
+
+    let worst = checks
+        .iter()
+        .map(|check| check.status)
+        .max()
+        .unwrap_or(Status::Pass);
+    if worst != Status::Pass {
+        std::process::exit(worst.exit_code());
+    }
+    Ok(())
+}
+
+fn selftest(args: SelftestArgs) -> Result<()> {
+    if let Some(directory) = &args.write_fixtures {
+        selftest::write_fixtures(directory)?;
+        println!("Wrote the self-test fixtures to {}", directory.display());
+    }
+    let mut failed = 0;
+    for case in selftest::CASES {
+        match case.check() {
+            Outcome::Pass => println!("[pass] {}", case.name),
+            Outcome::Mismatch(diff) => {
+                println!("[fail] {}\n{}", case.name, diff);
+                failed += 1;
+            }
+            Outcome::Error(e) => {
+                println!("[fail] {}: {:#}", case.name, e);
+                failed += 1;
+            }
+        }
+    }
+    if failed > 0 {
+        return Err(anyhow::anyhow!(
+            "{} of {} self-test case(s) failed",
+            failed,
+            selftest::CASES.len()
+        ));
+    }
+    Ok(())
+}
+
+fn plan(args: PlanArgs, global: &GlobalArgs) -> Result<()> {
+    // the plan file holds the content of every document until it's applied
+    if args.convert.include_credentials {
+        anyhow::bail!(
+            "--include-credentials can't be used with plan, the plan file would hold the secrets in clear text. Migrate with bulk instead"
+        );
+    }
+    let matching_paths = scan(&args.scan, Some(&args.output_path), global)?.matched;
+    let (applications, documents) = convert_directories(
+        &matching_paths,
+        &args.convert,
+        &args.checks,
+        &args.metadata,
+        &mut MigrationReport::default(),
+        None,
+        global,
+    )?;
+    let sources = applications
+        .iter()
+        .map(|app| app.source.clone())
+        .collect::<Vec<_>>();
+    let mut extra_files = args.extra_file.clone();
+    if args.write_readme {
+        extra_files.push(readme(&applications, args.metadata.reproducible));
+    }
+    let plan = Plan::new(
+        &args.output_path,
+        &documents,
+        &extra_files,
+        &sources,
+        args.prune,
+    )?;
+    plan.save(&args.plan)?;
+    if let Some(path) = &args.decommission_report {
+        let decommission = Decommission::new(&args.output_path, &documents)?;
+        decommission.write(path)?;
+        eprintln!(
+            "Decommission report written: {:?} ({} subscription(s), {} application(s) removed)",
+            path,
+            decommission.count(),
+            decommission.removed_applications.len()
+        );
+    }
+
+    match args.format {
+        OutputFormat::Table => print!("{}", plan),
+        OutputFormat::Json => println!("{}", plan.to_report_json()?),
+    }
+    eprintln!("Plan saved: {:?}", args.plan);
+    Ok(())
+}
+
+fn apply(args: ApplyArgs, cancel: &CancellationToken) -> Result<()> {
+    let plan = Plan::load(&args.plan)?;
+    let _lock = OutputLock::acquire(&plan.output, args.wait_lock.map(Duration::from_secs))?;
+    plan.apply(cancel)?;
+    for file in &plan.files {
+        match file.action {
+            Action::Create | Action::Change => println!("File written: {}", file.path.display()),
+            Action::Prune => println!("File removed: {}", file.path.display()),
+            Action::Unchanged => {}
+        }
+    }
+    println!(
+        "Applied {}: {} created, {} changed, {} pruned",
+        args.plan.display(),
+        plan.count(Action::Create),
+        plan.count(Action::Change),
+        plan.count(Action::Prune)
+    );
     Ok(())
 }
@@ -0,0 +1,80 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::{Path, PathBuf},
+};
+
+use anyhow::Result;
+use serde::Serialize;
+
+use crate::{line_ending::LineEnding, migrate::YamlApiSubscription};
+
+pub const MANIFEST_FILE: &str = "manifest.json";
+
+///Lists every file written by a run, relative to the output path, with its
+///digest when checksums are enabled, and the directories the run created.
+#[derive(Debug, Serialize)]
+pub struct Manifest {
+    applications: Vec<ManifestEntry>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    created_directories: Vec<PathBuf>,
+}
+
+#[derive(Debug, Serialize)]
+struct ManifestEntry {
+    name: String,
+    original_names: Vec<String>,
+    path: PathBuf,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sha256: Option<String>,
+}
+
+impl Manifest {
+    pub fn new(
+        base_path: &Path,
+        documents: &[YamlApiSubscription],
+        files_written: &[PathBuf],
+        original_names: &BTreeMap<String, BTreeSet<String>>,
+        checksums: &[String],
+    ) -> Self {
+        let mut applications = documents
+            .iter()
+            .zip(files_written)
+            .enumerate()
+            .map(|(i, (doc, path))| ManifestEntry {
+                name: doc.name().to_string(),
+                original_names: original_names
+                    .get(doc.name())
+                    .map(|names| names.iter().cloned().collect())
+                    .unwrap_or_default(),
+                path: path.strip_prefix(base_path).unwrap_or(path).to_path_buf(),
+                sha256: checksums.get(i).cloned(),
+            })
+            .collect::<Vec<_>>();
+        applications.sort_by(|a, b| a.name.cmp(&b.name));
+        Manifest {
+            applications,
+            created_directories: Vec::new(),
+        }
+    }
+
+    ///Lists the directories below `base_path` the run created, so they can
+    ///be told from the ones that existed before.
+    pub fn created_directories(mut self, base_path: &Path, directories: &[PathBuf]) -> Self {
+        self.created_directories = directories
+            .iter()
+            .map(|dir| dir.strip_prefix(base_path).unwrap_or(dir).to_path_buf())
+            .collect();
+        self.created_directories.sort();
+        self
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    pub fn write(&self, base_path: &Path, line_ending: LineEnding) -> Result<PathBuf> {
+        let path = base_path.join(MANIFEST_FILE);
+        std::fs::write(&path, line_ending.apply(&self.to_json()?).as_bytes())?;
+        Ok(path)
+    }
+}
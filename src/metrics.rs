@@ -0,0 +1,150 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Write,
+};
+
+use crate::migrate::YamlApiSubscription;
+
+///A gauge of the metrics file, with the help text Prometheus shows for it.
+struct Gauge {
+    name: &'static str,
+    help: &'static str,
+}
+
+///Documents the run wrote or would have written
+const APPLICATIONS: Gauge = Gauge {
+    name: "subscription_migrator_applications_total",
+    help: "Applications migrated by the last run",
+};
+///Distinct API name and version pairs over all documents
+const APIS: Gauge = Gauge {
+    name: "subscription_migrator_apis_total",
+    help: "Distinct API versions subscribed by the migrated applications",
+};
+///Created or overwritten files
+const FILES_WRITTEN: Gauge = Gauge {
+    name: "subscription_migrator_files_written_total",
+    help: "Files the last run created or overwrote",
+};
+///Files that already held the same content
+const FILES_UNCHANGED: Gauge = Gauge {
+    name: "subscription_migrator_files_unchanged_total",
+    help: "Files the last run left alone because they were up to date",
+};
+///Directories that failed to parse and files that failed to be written
+const FAILURES: Gauge = Gauge {
+    name: "subscription_migrator_failures_total",
+    help: "Failures of the last run",
+};
+///Subscribed API versions per environment, labelled with `environment`
+const SUBSCRIPTIONS: Gauge = Gauge {
+    name: "subscription_migrator_subscriptions",
+    help: "API subscriptions of the migrated applications per environment",
+};
+///When the run ended
+const TIMESTAMP: Gauge = Gauge {
+    name: "subscription_migrator_last_run_timestamp_seconds",
+    help: "Unix time the last run finished at",
+};
+
+///Counts of a bulk run, written in the Prometheus text format with
+///`--metrics-file` for the node-exporter textfile collector.
+///
+///```
+///use subscription_migrator::metrics::RunMetrics;
+///use subscription_migrator::migrate::{parse_xml_file, unify_applilcations};
+///
+///let xml = r#"<applications><application name="checkout">
+///    <subscription apiName="orders" apiVersion="1" environment="prod"/>
+///    <subscription apiName="users" apiVersion="2" environment="dev"/>
+///</application></applications>"#;
+///let documents = unify_applilcations(&parse_xml_file(xml.as_bytes())?.0);
+///let mut metrics = RunMetrics { files_written: 1, timestamp: 1_760_000_000, ..Default::default() };
+///metrics.count_documents(&documents);
+///
+///let rendered = metrics.render();
+///assert!(rendered.contains("\nsubscription_migrator_applications_total 1\n"));
+///assert!(rendered.contains("\nsubscription_migrator_apis_total 2\n"));
+///assert!(rendered.contains("\nsubscription_migrator_subscriptions{environment=\"prod\"} 2\n"));
+///assert!(rendered.contains("# TYPE subscription_migrator_files_written_total gauge\n"));
+///assert!(rendered.ends_with("subscription_migrator_last_run_timestamp_seconds 1760000000\n"));
+///# anyhow::Ok(())
+///```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RunMetrics {
+    pub applications: usize,
+    pub apis: usize,
+    pub files_written: usize,
+    pub files_unchanged: usize,
+    pub failures: usize,
+    pub subscriptions_by_environment: BTreeMap<String, usize>,
+    ///Unix time in seconds
+    pub timestamp: u64,
+}
+
+impl RunMetrics {
+    ///Counts the applications, APIs and subscriptions per environment of
+    ///`documents`.
+    pub fn count_documents(&mut self, documents: &[YamlApiSubscription]) {
+        self.applications = documents.len();
+        let mut apis = BTreeSet::new();
+        for document in documents {
+            let application = &document.subscription.application;
+            apis.extend(application.apis.iter().map(|api| (&api.name, &api.version)));
+            let environments = document
+                .environments
+                .iter()
+                .flat_map(|block| &block.environments)
+                .map(|environment| &environment.name)
+                .collect::<BTreeSet<_>>();
+            for environment in environments {
+                *self
+                    .subscriptions_by_environment
+                    .entry(environment.clone())
+                    .or_default() += application.apis.len();
+            }
+        }
+        self.apis = apis.len();
+    }
+
+    ///The metrics in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let mut text = String::new();
+        for (gauge, value) in [
+            (APPLICATIONS, self.applications),
+            (APIS, self.apis),
+            (FILES_WRITTEN, self.files_written),
+            (FILES_UNCHANGED, self.files_unchanged),
+            (FAILURES, self.failures),
+        ] {
+            header(&mut text, &gauge);
+            writeln!(text, "{} {}", gauge.name, value).unwrap();
+        }
+        header(&mut text, &SUBSCRIPTIONS);
+        for (environment, count) in &self.subscriptions_by_environment {
+            let label = label_value(environment);
+            writeln!(
+                text,
+                "{}{{environment=\"{}\"}} {}",
+                SUBSCRIPTIONS.name, label, count
+            )
+            .unwrap();
+        }
+        header(&mut text, &TIMESTAMP);
+        writeln!(text, "{} {}", TIMESTAMP.name, self.timestamp).unwrap();
+        text
+    }
+}
+
+fn header(text: &mut String, gauge: &Gauge) {
+    writeln!(text, "# HELP {} {}", gauge.name, gauge.help).unwrap();
+    writeln!(text, "# TYPE {} gauge", gauge.name).unwrap();
+}
+
+///`value` escaped for a label, as the exposition format requires.
+fn label_value(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
@@ -1,211 +1,759 @@
 use std::{
-    collections::{HashMap, HashSet},
+    collections::{BTreeMap, HashSet},
+    fmt,
     io::Read,
-    path::PathBuf,
+    path::{Component, Path, PathBuf},
+    time::{Duration, Instant},
 };
 
 use anyhow::Result;
-use serde::Serialize;
-use xml::{reader::XmlEvent, EventReader};
+use serde::{Deserialize, Serialize};
+use xml::{reader::XmlEvent, writer, EmitterConfig, ParserConfig};
 
-#[derive(Debug, Default, Clone)]
-pub(crate) struct XmlApplication {
-    name: String,
+#[cfg(feature = "cli")]
+use crate::{
+    cancel::{CancellationToken, Cancelled},
+    pipeline::{WriteOutcome, WritePolicy},
+    timing::Timings,
+};
+use crate::{
+    convert::{convert_application, ApplicationAccumulator, ConvertOptions},
+    field_order::FieldOrder,
+    formatting::SUBSCRIPTION_FILE,
+    inventory::ElementTracker,
+    key_style::KeyStyle,
+    provenance::ApiProvenance,
+    secret::{Credentials, Secret},
+    token::{YamlSecurity, YamlTokenValidity},
+    versions::compare_versions,
+    yaml_quoting::quote_yaml_11_scalars,
+};
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct XmlApplication {
+    pub name: String,
     ///Maybe not needed
-    token_type: String,
-    apis: Vec<XmlSubscription>,
+    pub token_type: String,
+    pub apis: Vec<XmlSubscription>,
     ///TODO
-    token_validity: i32,
+    pub token_validity: i32,
+    ///The subscribe.xml the application was parsed from
+    pub source: PathBuf,
+    ///The name as found in the XML, before any renaming
+    pub original_name: String,
+    ///Consumer key and secret, if the export contains them
+    pub credentials: Option<Credentials>,
+    ///Text of the XML comments directly preceding the application
+    pub comments: Vec<String>,
+    ///Exact duplicate subscriptions dropped while parsing
+    pub duplicate_subscriptions: usize,
+    ///Set by a directory override, replaces the description template
+    pub description: Option<String>,
+    ///Text of the `<tag>` elements, sorted and without duplicates
+    pub tags: Vec<String>,
+}
+
+impl XmlApplication {
+    ///Sets the subscriptions, dropping exact duplicates and counting them.
+    pub fn with_subscriptions(mut self, subscriptions: Vec<XmlSubscription>) -> Self {
+        let parsed = subscriptions.len();
+        let mut seen = HashSet::new();
+        self.apis = subscriptions
+            .into_iter()
+            .filter(|sub| seen.insert(sub.clone()))
+            .collect();
+        self.duplicate_subscriptions = parsed - self.apis.len();
+        self
+    }
 }
 
-#[derive(Debug, Clone, Default, PartialEq)]
-pub(crate) struct XmlSubscription {
-    api_name: String,
-    api_version: String,
-    env: Vec<String>,
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct XmlSubscription {
+    pub api_name: String,
+    pub api_version: String,
+    pub env: Vec<String>,
 }
 
-#[derive(Debug, Serialize)]
-pub(crate) struct YamlApiSubscription {
-    environments: Vec<YamlEnvironment>,
-    #[serde(rename = "subscriptions")]
-    subscription: YamlSubscription,
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct YamlApiSubscription {
+    ///Omitted documents are schema version 1
+    #[serde(
+        rename = "schemaVersion",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub schema_version: Option<u32>,
+    ///Always written, as `[]` when empty, consumers validating the schema
+    ///require the key
+    pub environments: Vec<YamlEnvironment>,
+    #[serde(rename = "subscriptions", alias = "subscription")]
+    pub subscription: YamlSubscription,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<YamlMetadata>,
+    ///Written as `#` lines above the document, serde_yaml can't emit comments
+    #[serde(skip)]
+    pub comments: Vec<String>,
+    ///Applied by [`Self::to_yaml`], detected by [`Self::from_yaml`]
+    #[serde(skip)]
+    pub key_style: KeyStyle,
+    ///Applied and detected like [`Self::key_style`]
+    #[serde(skip)]
+    pub field_order: FieldOrder,
+    ///Number of the part when split by `--split-large`, written as
+    ///`subscription-<part>.yaml`
+    #[serde(skip)]
+    pub part: Option<usize>,
 }
 
-#[derive(Debug, Serialize)]
-struct YamlEnvironment {
-    #[serde(rename = "controlPlaneUrl")]
-    control_plane_url: String,
+///Describes what produced a document. Not part of its content, comparisons
+///ignore it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "camelCase")]
+pub struct YamlMetadata {
+    pub managed_by: String,
+    pub tool_version: String,
+    ///The subscribe.xml files the document was generated from
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sources: Vec<PathBuf>,
+    ///The subscribe.xml files each API version came from
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub apis: Vec<ApiProvenance>,
+}
+
+pub const MANAGED_BY: &str = "subscription-migrator";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct YamlEnvironment {
+    #[serde(rename = "controlPlaneUrl", alias = "controlPlaneURL")]
+    pub control_plane_url: String,
     #[serde(rename = "environment")]
-    environments: Vec<YamlEnvironmentName>,
+    pub environments: Vec<YamlEnvironmentName>,
 }
 
-#[derive(Debug, Serialize)]
-struct YamlEnvironmentName {
-    name: String,
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct YamlEnvironmentName {
+    pub name: String,
 }
 
-#[derive(Debug, Serialize)]
-struct YamlSubscription {
-    application: YamlApplication,
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct YamlSubscription {
+    pub application: YamlApplication,
 }
 
-#[derive(Debug, Serialize)]
-struct YamlApplication {
-    name: String,
-    description: String,
-    apis: Vec<YamlApi>,
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct YamlApplication {
+    pub name: String,
+    pub description: String,
+    ///Always written like [`YamlApiSubscription::environments`]
+    pub apis: Vec<YamlApi>,
+    #[serde(
+        rename = "tokenValidity",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub token_validity: Option<YamlTokenValidity>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub security: Option<YamlSecurity>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub credentials: Option<Credentials>,
+    ///The tags of the application, with `--emit-labels`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub labels: Vec<String>,
 }
 
-#[derive(Debug, Serialize)]
-struct YamlApi {
-    name: String,
-    version: String,
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct YamlApi {
+    pub name: String,
+    pub version: String,
+}
+
+impl YamlApiSubscription {
+    pub fn name(&self) -> &str {
+        &self.subscription.application.name
+    }
+
+    ///Name of the file the document is written to in its application
+    ///directory.
+    pub fn file_name(&self) -> String {
+        match self.part {
+            Some(part) => format!("subscription-{}.yaml", part),
+            None => SUBSCRIPTION_FILE.to_string(),
+        }
+    }
+
+    ///Sorts and dedupes environment blocks, environment names and APIs so
+    ///equal content always serializes to the same bytes.
+    pub fn canonicalize(&mut self) {
+        self.environments
+            .sort_by(|a, b| a.control_plane_url.cmp(&b.control_plane_url));
+        for env in &mut self.environments {
+            env.environments.sort_by(|a, b| a.name.cmp(&b.name));
+            env.environments.dedup();
+        }
+        let apis = &mut self.subscription.application.apis;
+        apis.sort_by(|a, b| {
+            a.name
+                .cmp(&b.name)
+                .then_with(|| compare_versions(&a.version, &b.version))
+                // versions equal as numbers, like v3 and v3.0, in a fixed order
+                .then_with(|| a.version.cmp(&b.version))
+        });
+        apis.dedup();
+    }
+
+    ///Declares the schema version and adds the metadata block. `sources`
+    ///and `apis` are left out when empty.
+    pub fn stamp(&mut self, sources: Vec<PathBuf>, apis: Vec<ApiProvenance>) {
+        self.schema_version = Some(1);
+        self.metadata = Some(YamlMetadata {
+            managed_by: MANAGED_BY.to_string(),
+            tool_version: env!("CARGO_PKG_VERSION").to_string(),
+            sources,
+            apis,
+        });
+    }
+
+    ///Compares the subscriptions of two documents, ignoring the schema
+    ///version, the metadata block, ordering, the key style and order and the
+    ///format of the token validity.
+    pub fn same_content(&self, other: &YamlApiSubscription) -> bool {
+        let strip = |document: &YamlApiSubscription| {
+            let mut document = document.clone();
+            document.schema_version = None;
+            document.metadata = None;
+            document.key_style = KeyStyle::default();
+            document.field_order = FieldOrder::default();
+            document.part = None;
+            let validity = &mut document.subscription.application.token_validity;
+            if let Some(seconds) = validity.as_ref().and_then(YamlTokenValidity::seconds) {
+                *validity = Some(YamlTokenValidity::Seconds(seconds));
+            }
+            document.canonicalize();
+            document
+        };
+        strip(self) == strip(other)
+    }
+
+    ///Serializes the canonical form of the document with its key style,
+    ///used for every YAML file this tool writes. Every line of a comment is
+    ///prefixed with `#` and comments are separated by an empty line.
+    pub fn to_yaml(&self) -> Result<String> {
+        let mut document = self.clone();
+        document.canonicalize();
+        let mut yaml = String::new();
+        for comment in &document.comments {
+            for line in comment.split('\n') {
+                yaml.push('#');
+                yaml.push_str(line);
+                yaml.push('\n');
+            }
+            yaml.push('\n');
+        }
+        let value = document.key_style.apply(serde_yaml::to_value(&document)?);
+        let value = document.field_order.apply(value);
+        yaml.push_str(&quote_yaml_11_scalars(&serde_yaml::to_string(&value)?));
+        Ok(yaml)
+    }
+
+    ///Deserializes a document of either key style, reading back the
+    ///comments [`Self::to_yaml`] writes above it.
+    pub fn from_yaml(content: &str) -> Result<Self> {
+        let value: serde_yaml::Value = serde_yaml::from_str(content)?;
+        let key_style = KeyStyle::detect(&value);
+        let field_order = FieldOrder::detect(&value);
+        let mut document: YamlApiSubscription = serde_yaml::from_value(value)?;
+        document.key_style = key_style;
+        document.field_order = field_order;
+        let mut comment: Option<String> = None;
+        for line in content.lines() {
+            if let Some(text) = line.strip_prefix('#') {
+                match &mut comment {
+                    Some(comment) => {
+                        comment.push('\n');
+                        comment.push_str(text);
+                    }
+                    None => comment = Some(text.to_string()),
+                }
+            } else if line.is_empty() {
+                document.comments.extend(comment.take());
+            } else {
+                break;
+            }
+        }
+        document.comments.extend(comment);
+        Ok(document)
+    }
 }
 
-const PROD_PLANE_URL: &str = "https://prod.control-plane.com";
-const NON_PROD_PLANE_URL: &str = "https://non-prod.control-plane.com";
+pub const PROD_PLANE_URL: &str = "https://prod.control-plane.com";
+pub const NON_PROD_PLANE_URL: &str = "https://non-prod.control-plane.com";
 
 impl From<XmlApplication> for YamlApiSubscription {
     fn from(app: XmlApplication) -> Self {
-        let mut environments = Vec::new();
-        let non_prod_envs: HashSet<String> = app
-            .apis
-            .iter()
-            .filter(|sub| sub.env.iter().any(|env| env != "prod"))
-            .flat_map(|sub| sub.env.clone())
-            .collect();
+        convert_application(app, &ConvertOptions::default())
+    }
+}
 
-        let prod_envs: HashSet<String> = app
-            .apis
-            .iter()
-            .filter(|sub| sub.env.iter().any(|env| env == "prod"))
-            .flat_map(|sub| sub.env.clone())
-            .collect();
+///Parses all applications of a subscribe.xml, along with the number of
+///times each element the parser doesn't know occurs. The `source` of the
+///returned applications is left empty for the caller to fill in.
+pub fn parse_xml_file(file: impl Read) -> Result<(Vec<XmlApplication>, BTreeMap<String, usize>)> {
+    parse_xml_file_until(file, None)
+}
 
-        let yaml_prod_names = prod_envs
-            .iter()
-            .map(|env| YamlEnvironmentName { name: env.clone() })
-            .collect::<Vec<_>>();
+///Like [`parse_xml_file`], but gives up with [`TimedOut`] once `deadline`
+///has passed.
+///
+///```
+///use std::time::Duration;
+///use subscription_migrator::migrate::{parse_xml_file_until, Deadline, TimedOut};
+///
+///let xml = format!(
+///    "<applications>{}</applications>",
+///    "<noise/>".repeat(100_000)
+///);
+///let e = parse_xml_file_until(xml.as_bytes(), Some(Deadline::after(Duration::ZERO))).unwrap_err();
+///assert!(e.is::<TimedOut>());
+///
+///let deadline = Deadline::after(Duration::from_secs(60));
+///assert!(parse_xml_file_until(xml.as_bytes(), Some(deadline)).is_ok());
+///```
+pub fn parse_xml_file_until(
+    file: impl Read,
+    deadline: Option<Deadline>,
+) -> Result<(Vec<XmlApplication>, BTreeMap<String, usize>)> {
+    let mut iter = ApplicationIter::new(file);
+    iter.deadline = deadline;
+    let applications = iter.by_ref().collect::<Result<Vec<_>>>()?;
+    Ok((applications, iter.into_unknown_elements()))
+}
 
-        let yaml_non_prod_names = non_prod_envs
-            .iter()
-            .map(|env| YamlEnvironmentName { name: env.clone() });
+///Writes the applications as a subscribe.xml that [`parse_xml_file`] reads
+///back into the same applications. A subscription with several
+///environments becomes one element per environment, one without any has
+///no `environment` attribute.
+///
+///```
+///use subscription_migrator::migrate::{parse_xml_file, to_subscribe_xml, XmlApplication, XmlSubscription};
+///
+///let apps = vec![XmlApplication {
+///    name: "billing & co".to_string(),
+///    original_name: "billing & co".to_string(),
+///    token_type: "JWT".to_string(),
+///    apis: vec![XmlSubscription {
+///        api_name: "invoices".to_string(),
+///        api_version: "v1".to_string(),
+///        env: vec!["dev".to_string()],
+///    }],
+///    ..Default::default()
+///}];
+///let xml = to_subscribe_xml(&apps);
+///assert!(xml.contains(r#"name="billing &amp; co""#));
+///assert_eq!(parse_xml_file(xml.as_bytes())?.0, apps);
+///# anyhow::Ok(())
+///```
+pub fn to_subscribe_xml(applications: &[XmlApplication]) -> String {
+    let mut xml = Vec::new();
+    let mut events = EmitterConfig::new()
+        .perform_indent(true)
+        .create_writer(&mut xml);
+    let mut emit = |event: writer::XmlEvent| {
+        events
+            .write(event)
+            .expect("writing XML into memory can't fail");
+    };
+    emit(writer::XmlEvent::start_element("applications").into());
+    for app in applications {
+        for comment in &app.comments {
+            emit(writer::XmlEvent::comment(comment));
+        }
+        let token_validity = app.token_validity.to_string();
+        let mut element = writer::XmlEvent::start_element("application").attr("name", &app.name);
+        if !app.token_type.is_empty() {
+            element = element.attr("tokenType", &app.token_type);
+        }
+        if app.token_validity != 0 {
+            element = element.attr("tokenValidity", &token_validity);
+        }
+        emit(element.into());
+        for tag in &app.tags {
+            emit(writer::XmlEvent::start_element("tag").into());
+            emit(writer::XmlEvent::characters(tag));
+            emit(writer::XmlEvent::end_element().into());
+        }
+        for sub in &app.apis {
+            let element = || {
+                writer::XmlEvent::start_element("subscription")
+                    .attr("apiName", &sub.api_name)
+                    .attr("apiVersion", &sub.api_version)
+            };
+            if sub.env.is_empty() {
+                emit(element().into());
+                emit(writer::XmlEvent::end_element().into());
+            }
+            for env in &sub.env {
+                emit(element().attr("environment", env).into());
+                emit(writer::XmlEvent::end_element().into());
+            }
+        }
+        if let Some(credentials) = &app.credentials {
+            for (element, secret) in [
+                ("consumerKey", &credentials.consumer_key),
+                ("consumerSecret", &credentials.consumer_secret),
+            ] {
+                emit(writer::XmlEvent::start_element(element).into());
+                emit(writer::XmlEvent::characters(secret.expose()));
+                emit(writer::XmlEvent::end_element().into());
+            }
+        }
+        emit(writer::XmlEvent::end_element().into());
+    }
+    emit(writer::XmlEvent::end_element().into());
+    String::from_utf8(xml).expect("the XML writer only writes UTF-8")
+}
 
-        let yaml_env_non_prod = YamlEnvironment {
-            control_plane_url: NON_PROD_PLANE_URL.to_string(),
-            environments: yaml_non_prod_names.collect(),
-        };
+///Streams the applications of a subscribe.xml, yielding each one as soon as
+///its end element is read. Only the application being read is held in
+///memory.
+///
+///An application with invalid attributes is yielded as an error and parsing
+///continues with the next one. Malformed XML ends the iteration after its
+///error. Pass `&mut reader` to keep using a reader afterwards.
+///
+///```
+///use subscription_migrator::migrate::ApplicationIter;
+///
+///let xml = r#"<applications>
+///    <application name="billing">
+///        <subscription apiName="invoices" apiVersion="v1" environment="prod"/>
+///    </application>
+///    <application name="reporting">
+///        <subscription apiName="invoices" apiVersion="v1" environment="dev"/>
+///        <subscription apiName="exports" apiVersion="v2" environment="prod"/>
+///    </application>
+///</applications>"#;
+///
+///let prod_only = ApplicationIter::new(xml.as_bytes())
+///    .filter_map(Result::ok)
+///    .filter(|app| app.apis.iter().flat_map(|sub| &sub.env).all(|env| env == "prod"))
+///    .count();
+///assert_eq!(prod_only, 1);
+///```
+pub struct ApplicationIter<R: Read> {
+    events: xml::reader::Events<R>,
+    elements: ElementTracker,
+    ///Comments seen since the last element outside of an application
+    comments: Vec<String>,
+    ///The application being read, or why it can't be
+    application: Option<Result<XmlApplication>>,
+    ///One entry per enclosing <applications>, each layered on its parent
+    defaults: Vec<Defaults>,
+    subscriptions: Vec<XmlSubscription>,
+    ///The credential element whose text is being read
+    credential: Option<String>,
+    ///Text of the `<tag>` element being read
+    tag: Option<String>,
+    ///Set after a syntax error, the reader can't recover from those
+    failed: bool,
+    deadline: Option<Deadline>,
+    ///XML events read so far, the deadline is checked every
+    ///`DEADLINE_CHECK_EVENTS` of them
+    events_read: u64,
+}
 
-        let yaml_env_prod = YamlEnvironment {
-            control_plane_url: PROD_PLANE_URL.to_string(),
-            environments: yaml_prod_names,
-        };
+///How many XML events are read between two looks at the clock
+const DEADLINE_CHECK_EVENTS: u64 = 256;
+
+///The point in time a parse has to be done by.
+#[derive(Debug, Clone, Copy)]
+pub struct Deadline {
+    at: Instant,
+    timeout: Duration,
+}
 
-        if !non_prod_envs.is_empty() {
-            environments.push(yaml_env_non_prod);
+impl Deadline {
+    ///`timeout` from now.
+    pub fn after(timeout: Duration) -> Self {
+        Deadline {
+            at: Instant::now() + timeout,
+            timeout,
         }
-        if !prod_envs.is_empty() {
-            environments.push(yaml_env_prod);
+    }
+
+    fn check(&self) -> Result<(), TimedOut> {
+        match Instant::now() >= self.at {
+            true => Err(TimedOut(self.timeout)),
+            false => Ok(()),
         }
+    }
+}
 
-        let apis = app
-            .apis
-            .iter()
-            .map(|sub| YamlApi {
-                name: sub.api_name.clone(),
-                version: sub.api_version.clone(),
-            })
-            .collect::<Vec<_>>();
+///A parse that ran past its [`Deadline`], with the timeout it was given.
+#[derive(Debug)]
+pub struct TimedOut(pub Duration);
 
-        let description = format!("{}-subscription", app.name);
+impl fmt::Display for TimedOut {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "parsing timed out after {:?}", self.0)
+    }
+}
 
-        let app = YamlApplication {
-            name: app.name,
-            description,
-            apis,
-        };
+impl std::error::Error for TimedOut {}
+
+impl<R: Read> ApplicationIter<R> {
+    pub fn new(reader: R) -> Self {
+        let events = ParserConfig::new()
+            .ignore_comments(false)
+            .create_reader(reader)
+            .into_iter();
+        ApplicationIter {
+            events,
+            elements: ElementTracker::default(),
+            comments: Vec::new(),
+            application: None,
+            defaults: vec![Defaults::default()],
+            subscriptions: Vec::new(),
+            credential: None,
+            tag: None,
+            failed: false,
+            deadline: None,
+            events_read: 0,
+        }
+    }
 
-        let subscription = YamlSubscription { application: app };
+    ///Ends the iteration with a [`TimedOut`] error once `deadline` has
+    ///passed.
+    pub fn deadline(mut self, deadline: Deadline) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
 
-        YamlApiSubscription {
-            environments,
-            subscription,
+    ///The number of times each element the parser doesn't know occurred in
+    ///what has been read so far.
+    pub fn into_unknown_elements(self) -> BTreeMap<String, usize> {
+        self.elements.unknown
+    }
+
+    fn defaults(&self) -> &Defaults {
+        self.defaults.last().unwrap()
+    }
+
+    fn start_element(&mut self, name: String, attributes: &[xml::attribute::OwnedAttribute]) {
+        self.elements.start(&name);
+        if name == "applications" {
+            // a broken wrapper default only surfaces for the applications
+            // that use it
+            let layered = self.defaults().layer(attributes);
+            self.defaults.push(layered);
+        }
+        if name == "application" {
+            let comments = std::mem::take(&mut self.comments);
+            let parsed = parse_application(attributes, self.defaults());
+            self.application = Some(parsed.map(|app| XmlApplication { comments, ..app }));
+        } else if self.application.is_none() {
+            self.comments.clear();
+        }
+        if name == "subscription" {
+            let sub = parse_subscription(attributes, self.defaults());
+            self.subscriptions.push(sub);
+        }
+        if name == "tag" && self.application.is_some() {
+            self.tag = Some(String::new());
+        }
+        if matches!(name.as_str(), "consumerKey" | "consumerSecret") {
+            self.credential = Some(name);
         }
     }
-}
 
-pub(crate) fn parse_xml_file(file: impl Read) -> Result<Vec<XmlApplication>> {
-    let parser = EventReader::new(file);
-    let mut app = XmlApplication::default();
-    let mut applications = Vec::new();
-    let mut subscriptions = Vec::new();
+    fn characters(&mut self, text: &str) {
+        if let Some(tag) = &mut self.tag {
+            tag.push_str(text);
+            return;
+        }
+        let (Some(element), Some(Ok(app))) = (&self.credential, &mut self.application) else {
+            return;
+        };
+        let credentials = app.credentials.get_or_insert_with(Default::default);
+        let value = Secret::new(text.trim().to_string());
+        if element == "consumerKey" {
+            credentials.consumer_key = value;
+        } else {
+            credentials.consumer_secret = value;
+        }
+    }
 
-    for event in parser {
-        match event {
-            Ok(XmlEvent::StartElement {
-                name, attributes, ..
-            }) => {
-                if name.local_name.as_str() == "application" {
-                    app = parse_application(&attributes);
-                }
-                if name.local_name.as_str() == "subscription" {
-                    let sub = parse_subscription(&attributes);
-                    subscriptions.push(sub);
+    ///The finished application when `name` closes one.
+    fn end_element(&mut self, name: &str) -> Option<Result<XmlApplication>> {
+        self.elements.end();
+        match name {
+            "consumerKey" | "consumerSecret" => self.credential = None,
+            "tag" => {
+                let tag = self.tag.take().unwrap_or_default();
+                if let (false, Some(Ok(app))) = (tag.trim().is_empty(), &mut self.application) {
+                    app.tags.push(tag.trim().to_string());
                 }
             }
-            Ok(XmlEvent::EndElement { name }) => {
-                if name.local_name.as_str() == "application" {
-                    app.apis.clone_from(&subscriptions);
-                    applications.push(app.clone());
-                    subscriptions.clear();
-                }
+            "applications" => {
+                self.defaults.pop();
             }
-            Err(e) => {
-                return Err(anyhow::anyhow!("Error: {:?}", e));
+            "application" => {
+                let application = self.application.take()?;
+                let subscriptions = std::mem::take(&mut self.subscriptions);
+                return Some(application.map(|mut app| {
+                    app.tags.sort();
+                    app.tags.dedup();
+                    app.with_subscriptions(subscriptions)
+                }));
             }
             _ => {}
         }
+        None
+    }
+}
+
+impl<R: Read> Iterator for ApplicationIter<R> {
+    type Item = Result<XmlApplication>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        while let Some(event) = self.events.next() {
+            if let Some(deadline) = &self.deadline {
+                self.events_read += 1;
+                if self.events_read.is_multiple_of(DEADLINE_CHECK_EVENTS) {
+                    if let Err(e) = deadline.check() {
+                        self.failed = true;
+                        return Some(Err(e.into()));
+                    }
+                }
+            }
+            match event {
+                Ok(XmlEvent::StartElement {
+                    name, attributes, ..
+                }) => self.start_element(name.local_name, &attributes),
+                Ok(XmlEvent::Comment(text)) if self.application.is_none() => {
+                    self.comments.push(text)
+                }
+                Ok(XmlEvent::Characters(text)) => self.characters(&text),
+                Ok(XmlEvent::EndElement { name }) => {
+                    if let Some(app) = self.end_element(&name.local_name) {
+                        return Some(app);
+                    }
+                }
+                Err(e) => {
+                    self.failed = true;
+                    return Some(Err(anyhow::anyhow!("Error: {:?}", e)));
+                }
+                _ => {}
+            }
+        }
+        None
     }
+}
+
+///Attributes of the enclosing `<applications>` elements that every
+///application inherits unless it sets them itself.
+#[derive(Debug, Clone, Default)]
+struct Defaults {
+    token_type: Option<String>,
+    ///The unparsable value if the wrapper's is invalid
+    token_validity: Option<Result<i32, String>>,
+    ///Used for subscriptions without an environment
+    environment: Option<String>,
+}
 
-    Ok(applications)
+impl Defaults {
+    ///The defaults of a nested wrapper, overriding the ones set by its
+    ///attributes.
+    fn layer(&self, attributes: &[xml::attribute::OwnedAttribute]) -> Defaults {
+        let mut defaults = self.clone();
+        for attr in attributes {
+            let value = attr.value.trim();
+            if value.is_empty() {
+                continue;
+            }
+            match attr.name.local_name.as_str() {
+                "tokenType" => defaults.token_type = Some(value.to_string()),
+                "tokenValidity" => {
+                    defaults.token_validity = Some(value.parse().map_err(|_| value.to_string()))
+                }
+                "defaultEnvironment" => defaults.environment = Some(value.to_string()),
+                _ => {}
+            }
+        }
+        defaults
+    }
 }
 
-fn parse_application(attributes: &[xml::attribute::OwnedAttribute]) -> XmlApplication {
+fn parse_application(
+    attributes: &[xml::attribute::OwnedAttribute],
+    defaults: &Defaults,
+) -> Result<XmlApplication> {
     let mut name = String::new();
-    let mut token_type = String::new();
-    let mut token_validity = 0;
+    let mut token_type = defaults.token_type.clone().unwrap_or_default();
+    let mut token_validity = defaults.token_validity.clone().unwrap_or(Ok(0));
 
     for attr in attributes {
+        let value = attr.value.trim();
+        if value.is_empty() {
+            continue;
+        }
         match attr.name.local_name.as_str() {
-            "name" => name.clone_from(&attr.value),
-            "tokenType" => token_type.clone_from(&attr.value),
-            "tokenValidity" => token_validity = attr.value.parse().unwrap(),
+            "name" => name = value.to_string(),
+            "tokenType" => token_type = value.to_string(),
+            "tokenValidity" => token_validity = value.parse().map_err(|_| value.to_string()),
             _ => {}
         }
     }
+    let token_validity = token_validity.map_err(|value| {
+        anyhow::anyhow!("Application {name:?} has an invalid tokenValidity {value:?}")
+    })?;
 
-    XmlApplication {
+    Ok(XmlApplication {
+        original_name: name.clone(),
         name,
         token_type,
         apis: Vec::new(),
         token_validity,
-    }
+        source: PathBuf::new(),
+        credentials: None,
+        comments: Vec::new(),
+        duplicate_subscriptions: 0,
+        description: None,
+        tags: Vec::new(),
+    })
 }
 
-fn parse_subscription(attributes: &[xml::attribute::OwnedAttribute]) -> XmlSubscription {
+fn parse_subscription(
+    attributes: &[xml::attribute::OwnedAttribute],
+    defaults: &Defaults,
+) -> XmlSubscription {
     let mut api_name = String::new();
     let mut api_version = String::new();
     let mut env = Vec::new();
 
     for attr in attributes {
+        let value = attr.value.trim();
+        if value.is_empty() {
+            continue;
+        }
         match attr.name.local_name.as_str() {
-            "apiName" => api_name.clone_from(&attr.value),
-            "apiVersion" => api_version.clone_from(&attr.value),
-            "environment" => env.push(attr.value.clone()),
+            "apiName" => api_name = value.to_string(),
+            "apiVersion" => api_version = value.to_string(),
+            "environment" => env.push(value.to_string()),
             _ => {}
         }
     }
+    if env.is_empty() {
+        env.extend(defaults.environment.clone());
+    }
 
     XmlSubscription {
         api_name,
@@ -214,130 +762,521 @@ fn parse_subscription(attributes: &[xml::attribute::OwnedAttribute]) -> XmlSubsc
     }
 }
 
+///What happened to the file of an application.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WriteStatus {
+    Created,
+    Overwritten,
+    ///Already held the same content
+    Unchanged,
+    ///Not written, with the reason
+    Skipped(String),
+    Failed(String),
+}
+
+impl WriteStatus {
+    pub fn label(&self) -> &'static str {
+        match self {
+            WriteStatus::Created => "created",
+            WriteStatus::Overwritten => "overwritten",
+            WriteStatus::Unchanged => "unchanged",
+            WriteStatus::Skipped(_) => "skipped",
+            WriteStatus::Failed(_) => "failed",
+        }
+    }
+
+    ///Whether the file on disk holds the document afterwards.
+    pub fn is_written(&self) -> bool {
+        matches!(
+            self,
+            WriteStatus::Created | WriteStatus::Overwritten | WriteStatus::Unchanged
+        )
+    }
+}
+
+///An application whose output directory already exists.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Conflict {
+    pub application: String,
+    pub directory: PathBuf,
+}
+
+impl fmt::Display for Conflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "directory {:?} of application {:?} already exists",
+            self.directory, self.application
+        )
+    }
+}
+
+///The documents whose directory below `base_path` already exists, one
+///conflict per directory even for the parts of a split document.
+///
+///```
+///use subscription_migrator::convert::{convert_application, ConvertOptions};
+///use subscription_migrator::migrate::{find_conflicts, XmlApplication};
+///
+///let output = std::env::temp_dir().join(format!("find-conflicts-{}", std::process::id()));
+///std::fs::create_dir_all(output.join("billing-subscription"))?;
+///let documents = ["billing", "checkout"].map(|name| {
+///    let app = XmlApplication {
+///        name: name.to_string(),
+///        ..Default::default()
+///    };
+///    convert_application(app, &ConvertOptions::default())
+///});
+///
+///let conflicts = find_conflicts(&documents, &output);
+///assert_eq!(conflicts.len(), 1);
+///assert_eq!(
+///    conflicts[0].to_string(),
+///    format!("directory {:?} of application \"billing\" already exists", output.join("billing-subscription"))
+///);
+///# std::fs::remove_dir_all(&output)?;
+///# anyhow::Ok(())
+///```
+#[cfg(feature = "cli")]
+pub fn find_conflicts(documents: &[YamlApiSubscription], base_path: &Path) -> Vec<Conflict> {
+    let mut conflicts = Vec::<Conflict>::new();
+    for document in documents {
+        let path = subscription_path(base_path, document);
+        let directory = path.parent().unwrap_or(base_path);
+        if directory.exists() && !conflicts.iter().any(|c| c.directory == directory) {
+            conflicts.push(Conflict {
+                application: document.name().to_string(),
+                directory: directory.to_path_buf(),
+            });
+        }
+    }
+    conflicts
+}
+
+#[derive(Debug, Clone)]
+pub struct WrittenFile {
+    pub application: String,
+    pub path: PathBuf,
+    pub status: WriteStatus,
+}
+
+///Writes every document below `base_path`. The cancellation token is checked
+///before each application; once cancelled no further files are written and
+///[`Cancelled`] is returned with the completed files. An application whose
+///directory exists without `force` is skipped, one that can't be written
+///fails, and both don't stop the others. The outcome tells the directories
+///this call created from the existing ones it wrote into. A created
+///directory is removed again if its file could not be written, an existing
+///one never is. An existing file is left untouched when it only differs in
+///its metadata, so a new tool version alone doesn't rewrite every file.
+///
+///```
+///use subscription_migrator::cancel::CancellationToken;
+///use subscription_migrator::convert::{convert_application, ConvertOptions};
+///use subscription_migrator::migrate::{write_to_file, XmlApplication};
+///use subscription_migrator::pipeline::WritePolicy;
+///use subscription_migrator::timing::Timings;
+///
+///let output = std::env::temp_dir().join(format!("write-to-file-{}", std::process::id()));
+///std::fs::create_dir_all(output.join("billing-subscription"))?;
+///let documents = ["billing", "checkout"].map(|name| {
+///    let app = XmlApplication {
+///        name: name.to_string(),
+///        ..Default::default()
+///    };
+///    convert_application(app, &ConvertOptions::default())
+///});
+///let policy = WritePolicy {
+///    force: true,
+///    ..Default::default()
+///};
+///let cancel = CancellationToken::default();
+///
+///let outcome = write_to_file(&documents, output.clone(), &policy, &cancel, &mut Timings::default())?;
+///assert_eq!(outcome.written_paths().len(), 2);
+///assert_eq!(outcome.created_directories, [output.join("checkout-subscription")]);
+///assert_eq!(outcome.reused_directories, [output.join("billing-subscription")]);
+///# std::fs::remove_dir_all(&output)?;
+///# anyhow::Ok(())
+///```
+#[cfg(feature = "cli")]
 pub fn write_to_file(
     applications: &[YamlApiSubscription],
     base_path: PathBuf,
-    force: bool,
-) -> Result<Vec<PathBuf>> {
-    let mut files_written = Vec::new();
+    policy: &WritePolicy,
+    cancel: &CancellationToken,
+    timings: &mut Timings,
+) -> Result<WriteOutcome> {
+    let mut outcome = WriteOutcome::default();
     for app in applications {
-        let dir_name = format!("{}-{}", app.subscription.application.name, "subscription");
-        let mut project_path = base_path.join(dir_name);
-
-        if project_path.exists() && !force {
-            return Err(anyhow::anyhow!("Directory already exists"));
+        if cancel.is_cancelled() {
+            let files_written = outcome.written_paths();
+            return Err(Cancelled { files_written }.into());
         }
 
-        std::fs::create_dir_all(&project_path)?;
+        let file_path = subscription_path(&base_path, app);
+        let project_path = file_path.parent().unwrap_or(&base_path).to_path_buf();
+
+        // the parts of a split document share the directory of the first
+        let own_directory = outcome.created_directories.contains(&project_path);
+        let status = if let Err(e) = ensure_inside(&base_path, &file_path) {
+            WriteStatus::Failed(e.to_string())
+        } else if project_path.exists() && !policy.force && !own_directory {
+            let conflict = Conflict {
+                application: app.name().to_string(),
+                directory: project_path,
+            };
+            WriteStatus::Skipped(format!("{}, use --force", conflict))
+        } else {
+            let existed = project_path.exists();
+            let result = write_document(app, &base_path, &file_path, timings, policy);
+            let known = own_directory || outcome.reused_directories.contains(&project_path);
+            match (result, existed) {
+                (Ok(status), _) if known => status,
+                (Ok(status), true) => {
+                    outcome.reused_directories.push(project_path);
+                    status
+                }
+                (Ok(status), false) => {
+                    outcome.created_directories.push(project_path);
+                    status
+                }
+                (Err(e), existed) => {
+                    if !existed {
+                        let _ = std::fs::remove_dir_all(&project_path);
+                    }
+                    WriteStatus::Failed(format!("{:#}", e))
+                }
+            }
+        };
+        outcome.files.push(WrittenFile {
+            application: app.name().to_string(),
+            path: file_path,
+            status,
+        });
+    }
+    Ok(outcome)
+}
 
-        project_path = project_path.join("subscription.yaml");
+#[cfg(feature = "cli")]
+fn write_document(
+    app: &YamlApiSubscription,
+    base_path: &Path,
+    file_path: &Path,
+    timings: &mut Timings,
+    policy: &WritePolicy,
+) -> Result<WriteStatus> {
+    let project_path = file_path.parent().unwrap_or(base_path);
+    let permissions = &policy.permissions;
+    std::fs::create_dir_all(project_path)?;
+    permissions.apply_to_dir(project_path)?;
 
-        std::fs::write(project_path.clone(), serde_yaml::to_string(&app)?)?;
-        files_written.push(project_path);
+    let status = if is_unchanged(file_path, app) {
+        permissions.apply_to_file(file_path)?;
+        WriteStatus::Unchanged
+    } else {
+        let existed = file_path.exists();
+        timings.time_write(file_path, || {
+            let content = app.to_yaml()?;
+            write_file(file_path, &content, policy)
+        })?;
+        permissions.apply_to_file(file_path)?;
+        match existed {
+            true => WriteStatus::Overwritten,
+            false => WriteStatus::Created,
+        }
+    };
+
+    let mut extra_written = false;
+    for extra in &policy.extra_files {
+        let path = extra.path(file_path);
+        ensure_inside(base_path, &path)?;
+        let content = extra.render(app)?;
+        let expected = policy.line_ending.apply(&content);
+        let current = std::fs::read_to_string(&path)
+            .is_ok_and(|existing| extra.is_current(&existing, &expected));
+        if !current {
+            write_file(&path, &content, policy)?;
+            extra_written = true;
+        }
+        permissions.apply_to_file(&path)?;
     }
-    Ok(files_written)
+    Ok(match status {
+        WriteStatus::Unchanged if extra_written => WriteStatus::Overwritten,
+        status => status,
+    })
 }
 
-pub fn unify_applilcations(applications: &[XmlApplication]) -> Vec<YamlApiSubscription> {
-    let mut app_map = HashMap::new();
+///Writes `content` with the line endings and retries of `policy`.
+#[cfg(feature = "cli")]
+fn write_file(path: &Path, content: &str, policy: &WritePolicy) -> Result<()> {
+    let content = policy.line_ending.apply(content);
+    policy.retry.run(format_args!("writing {:?}", path), || {
+        write_atomic(path, content.as_bytes())
+    })
+}
 
-    for app in applications {
-        app_map
-            .entry(app.name.clone())
-            .or_insert_with(|| XmlApplication {
-                name: app.name.clone(),
-                token_type: app.token_type.clone(),
-                token_validity: app.token_validity,
-                apis: Vec::new(),
-            })
-            .apis
-            .extend(app.apis.clone());
+///Where the document of an application is written below `base_path`.
+pub fn subscription_path(base_path: &Path, document: &YamlApiSubscription) -> PathBuf {
+    base_path
+        .join(format!("{}-subscription", document.name()))
+        .join(document.file_name())
+}
+
+///Returned instead of writing a file that an application name, e.g. one
+///with `..` or a leading `/`, would put outside the output directory.
+#[derive(Debug)]
+pub struct OutsideOutput {
+    pub path: PathBuf,
+    pub output: PathBuf,
+}
+
+impl fmt::Display for OutsideOutput {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Refusing to write {:?}, it is outside the output directory {:?}. \
+             The application name may be crafted to escape it, check the export",
+            self.path, self.output
+        )
     }
+}
 
-    let mut yaml_api_subs = Vec::new();
+impl std::error::Error for OutsideOutput {}
 
-    for app in app_map.values() {
-        let mut yaml_apis = Vec::new();
-        let mut env_set = HashSet::new();
-        let mut name_set = HashSet::new();
-        let mut version_map = HashMap::new();
-        for sub in &app.apis {
-            name_set.insert(sub.api_name.clone());
-            version_map
-                .entry(sub.api_name.clone())
-                .or_insert_with(HashSet::new)
-                .insert(sub.api_version.clone());
-            for env in &sub.env {
-                env_set.insert(env.clone());
+///Fails unless `path` is below `base`. The base is canonicalized and `path`,
+///which may not exist yet, is resolved lexically against it. An existing
+///parent of `path` is canonicalized as well, so a symlink can't lead out.
+///
+///```
+///use std::path::Path;
+///use subscription_migrator::migrate::ensure_inside;
+///
+///let output = std::env::temp_dir();
+///assert!(ensure_inside(&output, &output.join("billing-subscription/subscription.yaml")).is_ok());
+///assert!(ensure_inside(&output, &output.join("../../etc/app-subscription/subscription.yaml")).is_err());
+///assert!(ensure_inside(&output, &output.join("a/../../escaped")).is_err());
+///assert!(ensure_inside(&output, &output.join("/etc/app-subscription")).is_err());
+///assert!(ensure_inside(&output, &output).is_err());
+///#[cfg(windows)]
+///assert!(ensure_inside(&output, &output.join(r"..\..\app-subscription")).is_err());
+///#[cfg(unix)]
+///assert!(ensure_inside(&output, &output.join(r"..\..\app-subscription")).is_ok());
+///```
+pub fn ensure_inside(base: &Path, path: &Path) -> std::result::Result<(), OutsideOutput> {
+    let outside = || OutsideOutput {
+        path: path.to_path_buf(),
+        output: base.to_path_buf(),
+    };
+    let canonical_base = base.canonicalize().unwrap_or_else(|_| normalize(base));
+    let relative = path.strip_prefix(base).map_err(|_| outside())?;
+    let target = normalize(&canonical_base.join(relative));
+    if !target.starts_with(&canonical_base) || target == canonical_base {
+        return Err(outside());
+    }
+    let parent = path.parent().and_then(|parent| parent.canonicalize().ok());
+    if parent.is_some_and(|parent| !parent.starts_with(&canonical_base)) {
+        return Err(outside());
+    }
+    Ok(())
+}
+
+///`path` with `.` and `..` resolved without looking at the file system.
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::ParentDir => {
+                normalized.pop();
             }
+            Component::CurDir => {}
+            component => normalized.push(component),
         }
+    }
+    normalized
+}
 
-        for name in name_set {
-            for version in version_map.get(&name).unwrap() {
-                let yaml_api = YamlApi {
-                    name: name.clone(),
-                    version: version.clone(),
-                };
-                yaml_apis.push(yaml_api);
-            }
+///Whether `path` already holds `document` apart from its metadata. Adding
+///or removing the metadata block still counts as a change.
+#[cfg(feature = "cli")]
+pub fn is_unchanged(path: &Path, document: &YamlApiSubscription) -> bool {
+    std::fs::read_to_string(path)
+        .ok()
+        .and_then(|content| YamlApiSubscription::from_yaml(&content).ok())
+        .is_some_and(|existing| {
+            existing.metadata.is_some() == document.metadata.is_some()
+                && existing.same_content(document)
+        })
+}
+
+///Name of the empty file written to check that a directory is writable
+#[cfg(feature = "cli")]
+const PROBE_FILE: &str = ".migrator-probe";
+
+///Writes and removes a probe file in `directory`.
+#[cfg(feature = "cli")]
+pub fn probe_writable(directory: &Path) -> Result<()> {
+    let probe = directory.join(PROBE_FILE);
+    write_atomic(&probe, b"")?;
+    std::fs::remove_file(&probe)?;
+    Ok(())
+}
+
+///Checks that `output` is a writable directory before any work is done,
+///creating it and its parents first with `create`.
+#[cfg(feature = "cli")]
+pub fn prepare_output_dir(output: &Path, create: bool) -> Result<()> {
+    if output.is_file() {
+        anyhow::bail!("Output path {:?} is a file, not a directory", output);
+    }
+    if !output.exists() {
+        if !create {
+            anyhow::bail!(
+                "Output directory {:?} does not exist, create it or pass --create-output-dir",
+                output
+            );
         }
-        let yaml_app = YamlApplication {
-            name: app.name.clone(),
-            description: format!("{}-subscription", app.name),
-            apis: yaml_apis,
-        };
+        std::fs::create_dir_all(output).map_err(|e| {
+            anyhow::anyhow!("Failed to create output directory {:?}: {}", output, e)
+        })?;
+    }
+    probe_writable(output)
+        .map_err(|e| anyhow::anyhow!("Output directory {:?} is not writable: {:#}", output, e))
+}
 
-        let yaml_sub = YamlSubscription {
-            application: yaml_app,
-        };
+///Writes `contents` next to `path` and renames it into place, so readers and
+///interrupted runs never see a partially written file.
+#[cfg(feature = "cli")]
+pub fn write_atomic(path: &Path, contents: &[u8]) -> Result<()> {
+    let mut tmp_name = path.file_name().unwrap_or_default().to_os_string();
+    tmp_name.push(".tmp");
+    let tmp_path = path.with_file_name(tmp_name);
 
-        let mut environments = Vec::new();
+    if let Err(e) = std::fs::write(&tmp_path, contents) {
+        let _ = std::fs::remove_file(&tmp_path);
+        return Err(e.into());
+    }
+    std::fs::rename(&tmp_path, path)?;
+    Ok(())
+}
 
-        let non_prod_envs: HashSet<String> = env_set
-            .iter()
-            .filter(|env| env.as_str() != "prod")
-            .cloned()
-            .collect();
+///Merges applications with the same name. Token settings and other
+///per-application values come from the lexicographically first source.
+pub fn unify_applilcations(applications: &[XmlApplication]) -> Vec<YamlApiSubscription> {
+    let mut ordered = applications.iter().collect::<Vec<_>>();
+    ordered.sort_by(|a, b| a.source.cmp(&b.source));
+    let mut accumulator = ApplicationAccumulator::default();
+    for app in ordered {
+        accumulator.add(app.clone());
+    }
+    accumulator.finish()
+}
 
-        let prod_envs: HashSet<String> = env_set
-            .iter()
-            .filter(|env| env.as_str() == "prod")
-            .cloned()
-            .collect();
+#[cfg(all(test, feature = "cli"))]
+mod tests {
+    use assert_fs::TempDir;
 
-        let yaml_non_prod_names = non_prod_envs
-            .iter()
-            .map(|env| YamlEnvironmentName { name: env.clone() });
+    use super::*;
+
+    const NAMES: [&str; 4] = ["billing", "checkout", "ledger", "shop"];
 
-        let yaml_prod_names = prod_envs
+    fn documents() -> Vec<YamlApiSubscription> {
+        NAMES
             .iter()
-            .map(|env| YamlEnvironmentName { name: env.clone() });
+            .map(|name| {
+                let app = XmlApplication {
+                    name: name.to_string(),
+                    ..Default::default()
+                };
+                convert_application(app, &ConvertOptions::default())
+            })
+            .collect()
+    }
 
-        let yaml_env_non_prod = YamlEnvironment {
-            control_plane_url: NON_PROD_PLANE_URL.to_string(),
-            environments: yaml_non_prod_names.collect(),
-        };
+    fn cancelled(result: Result<WriteOutcome>) -> Vec<PathBuf> {
+        let e = result.expect_err("the write is cancelled");
+        e.downcast::<Cancelled>()
+            .expect("the error is Cancelled")
+            .files_written
+    }
 
-        let yaml_env_prod = YamlEnvironment {
-            control_plane_url: PROD_PLANE_URL.to_string(),
-            environments: yaml_prod_names.collect(),
-        };
+    ///The `*-subscription` directories below `output`, sorted.
+    fn directories(output: &Path) -> Vec<PathBuf> {
+        let mut directories = std::fs::read_dir(output)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect::<Vec<_>>();
+        directories.sort();
+        directories
+    }
 
-        if !non_prod_envs.is_empty() {
-            environments.push(yaml_env_non_prod);
-        }
+    #[test]
+    fn cancelling_lists_the_files_written_and_writes_nothing_else() {
+        let temp = TempDir::new().unwrap();
+        let documents = documents();
+        let cancel = CancellationToken::cancel_after(2);
+
+        let result = write_to_file(
+            &documents,
+            temp.to_path_buf(),
+            &WritePolicy::default(),
+            &cancel,
+            &mut Timings::default(),
+        );
 
-        if !prod_envs.is_empty() {
-            environments.push(yaml_env_prod);
+        let written = documents[..2]
+            .iter()
+            .map(|document| subscription_path(temp.path(), document))
+            .collect::<Vec<_>>();
+        assert_eq!(cancelled(result), written);
+        let expected = written
+            .iter()
+            .map(|file| file.parent().unwrap().to_path_buf())
+            .collect::<Vec<_>>();
+        assert_eq!(directories(temp.path()), expected);
+        for file in &written {
+            assert!(file.is_file(), "{:?} is written", file);
         }
+    }
 
-        let yaml_api_sub = YamlApiSubscription {
-            environments,
-            subscription: yaml_sub,
+    #[test]
+    fn cancelling_with_force_leaves_the_later_files_as_they_were() {
+        let temp = TempDir::new().unwrap();
+        let documents = documents();
+        let files = documents
+            .iter()
+            .map(|document| subscription_path(temp.path(), document))
+            .collect::<Vec<_>>();
+        for file in &files {
+            std::fs::create_dir_all(file.parent().unwrap()).unwrap();
+            std::fs::write(file, "old: true\n").unwrap();
+        }
+        let policy = WritePolicy {
+            force: true,
+            ..Default::default()
         };
+        let cancel = CancellationToken::cancel_after(3);
 
-        yaml_api_subs.push(yaml_api_sub);
-    }
+        let result = write_to_file(
+            &documents,
+            temp.to_path_buf(),
+            &policy,
+            &cancel,
+            &mut Timings::default(),
+        );
 
-    yaml_api_subs
+        assert_eq!(cancelled(result), files[..3]);
+        let expected = files
+            .iter()
+            .map(|file| file.parent().unwrap().to_path_buf())
+            .collect::<Vec<_>>();
+        assert_eq!(directories(temp.path()), expected);
+        for (file, document) in files.iter().zip(&documents) {
+            let content = std::fs::read_to_string(file).unwrap();
+            match files[..3].contains(file) {
+                true => assert_eq!(content, document.to_yaml().unwrap()),
+                false => assert_eq!(content, "old: true\n"),
+            }
+        }
+    }
 }
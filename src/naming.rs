@@ -0,0 +1,509 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::PathBuf,
+};
+
+use regex::Regex;
+use serde::Serialize;
+
+use crate::{
+    migrate::XmlApplication,
+    validate::{Finding, Severity},
+};
+
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum NameCase {
+    ///`MyApp` becomes `my-app`
+    Kebab,
+    ///`MyApp` becomes `my_app`
+    Snake,
+    ///`MyApp` becomes `myapp`
+    Lower,
+    ///Keep the name as is
+    #[default]
+    Preserve,
+}
+
+impl NameCase {
+    pub fn apply(self, name: &str) -> String {
+        match self {
+            NameCase::Kebab => split_words(name).join("-"),
+            NameCase::Snake => split_words(name).join("_"),
+            NameCase::Lower => name.to_lowercase(),
+            NameCase::Preserve => name.to_string(),
+        }
+    }
+}
+
+///Splits a name into lowercase words on non-alphanumeric characters and on
+///camel case boundaries, keeping acronyms together (`HTTPServer` is
+///`http`, `server`).
+fn split_words(name: &str) -> Vec<String> {
+    let chars = name.chars().collect::<Vec<_>>();
+    let mut words = Vec::new();
+    let mut current = String::new();
+
+    for (i, &c) in chars.iter().enumerate() {
+        if !c.is_alphanumeric() {
+            if !current.is_empty() {
+                words.push(std::mem::take(&mut current));
+            }
+            continue;
+        }
+
+        if c.is_uppercase() && !current.is_empty() {
+            let prev = chars[i - 1];
+            let next_is_lower = chars.get(i + 1).is_some_and(|next| next.is_lowercase());
+            if prev.is_lowercase() || prev.is_numeric() || (prev.is_uppercase() && next_is_lower) {
+                words.push(std::mem::take(&mut current));
+            }
+        }
+        current.extend(c.to_lowercase());
+    }
+
+    if !current.is_empty() {
+        words.push(current);
+    }
+    words
+}
+
+///Replaces every run of whitespace inside `name` with a single space.
+pub fn collapse_whitespace(name: &str) -> String {
+    name.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+///Collapses whitespace in the application, API and environment names.
+pub fn normalize_whitespace(applications: &mut [XmlApplication]) {
+    for app in applications {
+        app.name = collapse_whitespace(&app.name);
+        for sub in &mut app.apis {
+            sub.api_name = collapse_whitespace(&sub.api_name);
+            for env in &mut sub.env {
+                *env = collapse_whitespace(env);
+            }
+        }
+    }
+}
+
+///Applies the name case to every application and reports names that only
+///collide after the transformation.
+pub fn apply_name_case(applications: &mut [XmlApplication], case: NameCase) -> Vec<Finding> {
+    let mut casing = NameCasing::new(case);
+    casing.apply(applications);
+    casing.finish()
+}
+
+///Applies a name case to applications added a source at a time, reporting
+///the collisions across all of them at the end like [`apply_name_case`].
+#[derive(Debug)]
+pub struct NameCasing {
+    case: NameCase,
+    ///Transformed name with the original names and the first source of each
+    collisions: BTreeMap<String, BTreeMap<String, PathBuf>>,
+}
+
+impl NameCasing {
+    pub fn new(case: NameCase) -> Self {
+        NameCasing {
+            case,
+            collisions: BTreeMap::new(),
+        }
+    }
+
+    pub fn apply(&mut self, applications: &mut [XmlApplication]) {
+        if self.case == NameCase::Preserve {
+            return;
+        }
+        for app in applications {
+            let name = self.case.apply(&app.name);
+            self.collisions
+                .entry(name.clone())
+                .or_default()
+                .entry(std::mem::replace(&mut app.name, name))
+                .or_insert_with(|| app.source.clone());
+        }
+    }
+
+    ///The names that collide after the transformation.
+    pub fn finish(self) -> Vec<Finding> {
+        self.collisions
+            .into_iter()
+            .filter(|(_, names)| names.len() > 1)
+            .map(|(name, names)| {
+                let listing = names
+                    .iter()
+                    .map(|(original, source)| format!("{} ({})", original, source.display()))
+                    .collect::<Vec<_>>();
+                Finding {
+                    severity: Severity::Warning,
+                    rule: "name-collision",
+                    application: name,
+                    api: None,
+                    version: None,
+                    source: names.into_values().next().unwrap(),
+                    message: format!("names collide after case change: {}", listing.join(", ")),
+                }
+            })
+            .collect()
+    }
+}
+
+///Maximum length of application names on the new control plane
+pub const PLATFORM_MAX_NAME_LENGTH: usize = 60;
+///Characters the new control plane allows in application names
+pub const PLATFORM_NAME_PATTERN: &str = "[A-Za-z0-9._-]+";
+
+pub const INVALID_NAME: &str = "invalid-name";
+pub const CASE_COLLISION: &str = "case-collision";
+
+///Compiles a `--name-pattern`, anchored to match whole names.
+pub fn parse_name_pattern(pattern: &str) -> Result<Regex, String> {
+    Regex::new(&format!("^(?:{})$", pattern)).map_err(|e| e.to_string())
+}
+
+///Limits application names must stay within. Both are unset by default, so
+///every name passes.
+#[derive(Debug, Clone, Default)]
+pub struct NameRules {
+    pub max_length: Option<usize>,
+    ///Anchored pattern the whole name must match
+    pub pattern: Option<Regex>,
+}
+
+impl NameRules {
+    pub fn is_enabled(&self) -> bool {
+        self.max_length.is_some() || self.pattern.is_some()
+    }
+
+    ///Why `name` breaks the rules, `None` if it doesn't.
+    fn violation(&self, name: &str) -> Option<String> {
+        let length = name.chars().count();
+        if let Some(max) = self.max_length.filter(|max| length > *max) {
+            return Some(format!(
+                "name is {} characters long, at most {} are allowed",
+                length, max
+            ));
+        }
+        if let Some(pattern) = self
+            .pattern
+            .as_ref()
+            .filter(|pattern| !pattern.is_match(name))
+        {
+            return Some(format!("name doesn't match {}", pattern.as_str()));
+        }
+        None
+    }
+
+    ///Brings `name` within the rules: kebab case first, then characters the
+    ///pattern rejects become `-`, repeated and outer dashes are dropped and the
+    ///name is cut to the maximum length.
+    fn fix(&self, name: &str) -> String {
+        let mut fixed = NameCase::Kebab.apply(name);
+        if let Some(pattern) = &self.pattern {
+            fixed = fixed
+                .chars()
+                .map(|c| match pattern.is_match(c.encode_utf8(&mut [0; 4])) {
+                    true => c,
+                    false => '-',
+                })
+                .collect();
+        }
+        let tidy = |name: &str| {
+            let parts = name.split('-').filter(|part| !part.is_empty());
+            parts.collect::<Vec<_>>().join("-")
+        };
+        fixed = tidy(&fixed);
+        if let Some(max) = self.max_length {
+            fixed = tidy(&fixed.chars().take(max).collect::<String>());
+        }
+        fixed
+    }
+}
+
+///Reports every application whose name breaks the rules. The original name
+///is mentioned when the name was changed by renaming or a case transform.
+pub fn check_names(applications: &[XmlApplication], rules: &NameRules) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    let mut seen = BTreeSet::new();
+    for app in applications {
+        let Some(message) = rules.violation(&app.name) else {
+            continue;
+        };
+        if !seen.insert((&app.name, &app.source)) {
+            continue;
+        }
+        let message = match app.original_name != app.name {
+            true => format!("{} (was {:?})", message, app.original_name),
+            false => message,
+        };
+        findings.push(Finding {
+            severity: Severity::Error,
+            rule: INVALID_NAME,
+            application: app.name.clone(),
+            api: None,
+            version: None,
+            source: app.source.clone(),
+            message,
+        });
+    }
+    findings
+}
+
+///A name changed by `--fix-names`.
+#[derive(Debug, Clone, Serialize)]
+pub struct NameFix {
+    pub source: PathBuf,
+    pub from: String,
+    pub to: String,
+}
+
+///Renames every application breaking the rules to its fixed name. Names
+///that still break them are left to [`check_names`].
+pub fn fix_names(applications: &mut [XmlApplication], rules: &NameRules) -> Vec<NameFix> {
+    let mut fixes = Vec::new();
+    for app in applications {
+        if rules.violation(&app.name).is_none() {
+            continue;
+        }
+        let fixed = rules.fix(&app.name);
+        if fixed.is_empty() || fixed == app.name {
+            continue;
+        }
+        fixes.push(NameFix {
+            source: app.source.clone(),
+            from: std::mem::replace(&mut app.name, fixed.clone()),
+            to: fixed,
+        });
+    }
+    fixes
+}
+
+///Reports application names that differ only by case. Their directories
+///collide on case-insensitive filesystems, where the second one is written
+///into the directory of the first.
+///
+///```
+///use subscription_migrator::naming::{check_case_collisions, merge_case_insensitive};
+///use subscription_migrator::testing::app;
+///
+///let mut applications = [
+///    app("Checkout", &[("orders", "1", "dev")]),
+///    app("billing", &[("invoices", "1", "dev")]),
+///    app("checkout", &[("users", "1", "prod")]),
+///    app("CHECKOUT", &[("orders", "1", "prod")]),
+///];
+///for (i, app) in applications.iter_mut().enumerate() {
+///    app.source = format!("export-{}/subscribe.xml", i).into();
+///}
+///
+///let findings = check_case_collisions(&applications);
+///assert_eq!(findings.len(), 1);
+///assert_eq!(findings[0].application, "Checkout");
+///assert!(findings[0].message.ends_with(
+///    "CHECKOUT (export-3/subscribe.xml), Checkout (export-0/subscribe.xml), checkout (export-2/subscribe.xml)"
+///));
+///
+///let merged = merge_case_insensitive(&mut applications);
+///assert_eq!(merged.len(), 2);
+///assert_eq!((merged[0].from.as_str(), merged[0].to.as_str()), ("checkout", "Checkout"));
+///let names = applications.iter().map(|app| app.name.as_str()).collect::<Vec<_>>();
+///assert_eq!(names, ["Checkout", "billing", "Checkout", "Checkout"]);
+///assert!(check_case_collisions(&applications).is_empty());
+///```
+pub fn check_case_collisions(applications: &[XmlApplication]) -> Vec<Finding> {
+    let mut by_key = BTreeMap::<String, (&str, BTreeMap<&str, &std::path::Path>)>::new();
+    for app in applications {
+        by_key
+            .entry(app.name.to_lowercase())
+            .or_insert_with(|| (&app.name, BTreeMap::new()))
+            .1
+            .entry(&app.name)
+            .or_insert(&app.source);
+    }
+    by_key
+        .into_values()
+        .filter(|(_, names)| names.len() > 1)
+        .map(|(first, names)| {
+            let listing = names
+                .iter()
+                .map(|(name, source)| format!("{} ({})", name, source.display()))
+                .collect::<Vec<_>>();
+            Finding {
+                severity: Severity::Warning,
+                rule: CASE_COLLISION,
+                application: first.to_string(),
+                api: None,
+                version: None,
+                source: names[first].to_path_buf(),
+                message: format!(
+                    "names differ only by case and collide on case-insensitive filesystems, pass --merge-case-insensitive to merge them: {}",
+                    listing.join(", ")
+                ),
+            }
+        })
+        .collect()
+}
+
+///Renames applications whose names differ only by case to the casing seen
+///first, so they are merged into one document.
+pub fn merge_case_insensitive(applications: &mut [XmlApplication]) -> Vec<NameFix> {
+    CaseMerge::default().apply(applications)
+}
+
+///Merges names that differ only by case like [`merge_case_insensitive`],
+///for applications added a source at a time. The casing seen first in any
+///of them is kept.
+#[derive(Debug, Default)]
+pub struct CaseMerge {
+    ///Lowercased name with the casing seen first
+    first: BTreeMap<String, String>,
+}
+
+impl CaseMerge {
+    pub fn apply(&mut self, applications: &mut [XmlApplication]) -> Vec<NameFix> {
+        let mut merged = Vec::new();
+        for app in applications {
+            let name = self
+                .first
+                .entry(app.name.to_lowercase())
+                .or_insert_with(|| app.name.clone());
+            if *name != app.name {
+                merged.push(NameFix {
+                    source: app.source.clone(),
+                    from: std::mem::replace(&mut app.name, name.clone()),
+                    to: name.clone(),
+                });
+            }
+        }
+        merged
+    }
+}
+
+///Original names of each application, keyed by its final name.
+pub fn original_names(applications: &[XmlApplication]) -> BTreeMap<String, BTreeSet<String>> {
+    let mut names: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+    for app in applications {
+        names
+            .entry(app.name.clone())
+            .or_default()
+            .insert(app.original_name.clone());
+    }
+    names
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn named(names: &[&str]) -> Vec<XmlApplication> {
+        names
+            .iter()
+            .enumerate()
+            .map(|(i, name)| XmlApplication {
+                name: name.to_string(),
+                original_name: name.to_string(),
+                source: PathBuf::from(format!("export-{}/subscribe.xml", i)),
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    fn names(applications: &[XmlApplication]) -> Vec<&str> {
+        applications.iter().map(|app| app.name.as_str()).collect()
+    }
+
+    #[test]
+    fn distinct_names_do_not_collide() {
+        let applications = named(&["checkout", "check-out", "checkout2", "billing"]);
+        assert!(check_case_collisions(&applications).is_empty());
+    }
+
+    #[test]
+    fn exact_duplicates_do_not_collide() {
+        let applications = named(&["checkout", "checkout"]);
+        assert!(check_case_collisions(&applications).is_empty());
+    }
+
+    #[test]
+    fn collision_lists_every_casing_with_its_first_source() {
+        let applications = named(&["Checkout", "checkout", "Checkout", "CheckOut"]);
+        let findings = check_case_collisions(&applications);
+        assert_eq!(findings.len(), 1);
+        let finding = &findings[0];
+        assert_eq!(finding.severity, Severity::Warning);
+        assert_eq!(finding.rule, CASE_COLLISION);
+        assert_eq!(finding.application, "Checkout");
+        assert_eq!(finding.source, PathBuf::from("export-0/subscribe.xml"));
+        assert!(finding.message.ends_with(
+            "CheckOut (export-3/subscribe.xml), Checkout (export-0/subscribe.xml), checkout (export-1/subscribe.xml)"
+        ));
+    }
+
+    #[test]
+    fn each_group_is_reported_once() {
+        let applications = named(&["A", "b", "a", "B", "c"]);
+        let findings = check_case_collisions(&applications);
+        let reported = findings
+            .iter()
+            .map(|finding| finding.application.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(reported, ["A", "b"]);
+    }
+
+    #[test]
+    fn non_ascii_names_collide_by_case() {
+        let applications = named(&["Bücher", "BÜCHER"]);
+        assert_eq!(check_case_collisions(&applications).len(), 1);
+    }
+
+    #[test]
+    fn merge_keeps_the_first_casing() {
+        let mut applications = named(&["checkout", "Checkout", "billing", "CHECKOUT"]);
+        let merged = merge_case_insensitive(&mut applications);
+        assert_eq!(
+            names(&applications),
+            ["checkout", "checkout", "billing", "checkout"]
+        );
+        let renames = merged
+            .iter()
+            .map(|fix| {
+                (
+                    fix.from.as_str(),
+                    fix.to.as_str(),
+                    fix.source.to_str().unwrap(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            renames,
+            [
+                ("Checkout", "checkout", "export-1/subscribe.xml"),
+                ("CHECKOUT", "checkout", "export-3/subscribe.xml"),
+            ]
+        );
+        assert!(check_case_collisions(&applications).is_empty());
+    }
+
+    #[test]
+    fn merge_leaves_distinct_names_alone() {
+        let mut applications = named(&["checkout", "billing", "checkout"]);
+        assert!(merge_case_insensitive(&mut applications).is_empty());
+        assert_eq!(names(&applications), ["checkout", "billing", "checkout"]);
+    }
+
+    #[test]
+    fn merge_keeps_the_original_names() {
+        let mut applications = named(&["Checkout", "checkout"]);
+        merge_case_insensitive(&mut applications);
+        let originals = original_names(&applications);
+        assert_eq!(originals.len(), 1);
+        assert_eq!(
+            originals["Checkout"]
+                .iter()
+                .map(String::as_str)
+                .collect::<Vec<_>>(),
+            ["Checkout", "checkout"]
+        );
+    }
+}
@@ -0,0 +1,229 @@
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Result};
+
+use crate::{
+    cancel::{CancellationToken, Cancelled},
+    line_ending::LineEnding,
+    migrate::{WriteStatus, WrittenFile, YamlApiSubscription},
+};
+
+const S3_SCHEME: &str = "s3://";
+
+///An `s3://bucket/prefix` output location.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ObjectUrl {
+    pub bucket: String,
+    ///Without leading or trailing slashes, may be empty
+    pub prefix: String,
+}
+
+impl ObjectUrl {
+    ///The location `output` names, `None` if it is a local path.
+    ///
+    ///```
+    ///use std::path::Path;
+    ///use subscription_migrator::objects::ObjectUrl;
+    ///
+    ///let url = ObjectUrl::parse(Path::new("s3://exports/subscriptions/"))?.unwrap();
+    ///assert_eq!(url.bucket, "exports");
+    ///assert_eq!(
+    ///    url.key("billing", "subscription.yaml"),
+    ///    "subscriptions/billing-subscription/subscription.yaml"
+    ///);
+    ///assert!(ObjectUrl::parse(Path::new("out/s3"))?.is_none());
+    ///assert!(ObjectUrl::parse(Path::new("s3:///prefix")).is_err());
+    ///# anyhow::Ok(())
+    ///```
+    pub fn parse(output: &Path) -> Result<Option<Self>> {
+        let Some(location) = output.to_str().and_then(|s| s.strip_prefix(S3_SCHEME)) else {
+            return Ok(None);
+        };
+        let (bucket, prefix) = location.split_once('/').unwrap_or((location, ""));
+        if bucket.is_empty() {
+            bail!("{:?} names no bucket", output);
+        }
+        Ok(Some(ObjectUrl {
+            bucket: bucket.to_string(),
+            prefix: prefix.trim_matches('/').to_string(),
+        }))
+    }
+
+    ///Key of the document `file` of application `name`, laid out like the
+    ///directories of a local output.
+    pub fn key(&self, name: &str, file: &str) -> String {
+        let key = format!("{}-subscription/{}", name, file);
+        match self.prefix.as_str() {
+            "" => key,
+            prefix => format!("{}/{}", prefix, key),
+        }
+    }
+}
+
+impl fmt::Display for ObjectUrl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}/{}", S3_SCHEME, self.bucket, self.prefix)
+    }
+}
+
+///A bucket documents can be uploaded to.
+pub trait ObjectStore {
+    fn exists(&self, key: &str) -> Result<bool>;
+    fn put(&self, key: &str, content: Vec<u8>) -> Result<()>;
+}
+
+///Uploads every document below `url`. Like [`write_to_file`], existing
+///objects are only replaced with `force` and a document that fails is
+///reported without stopping the others. With `dry_run` nothing is uploaded,
+///the statuses tell what would happen.
+///
+///```
+///use std::{cell::RefCell, collections::BTreeMap, path::Path};
+///use subscription_migrator::cancel::CancellationToken;
+///use subscription_migrator::line_ending::LineEnding;
+///use subscription_migrator::migrate::{parse_xml_file, unify_applilcations, WriteStatus};
+///use subscription_migrator::objects::{write_objects, ObjectStore, ObjectUrl};
+///
+///#[derive(Default)]
+///struct Memory(RefCell<BTreeMap<String, Vec<u8>>>);
+///impl ObjectStore for Memory {
+///    fn exists(&self, key: &str) -> anyhow::Result<bool> {
+///        Ok(self.0.borrow().contains_key(key))
+///    }
+///    fn put(&self, key: &str, content: Vec<u8>) -> anyhow::Result<()> {
+///        self.0.borrow_mut().insert(key.to_string(), content);
+///        Ok(())
+///    }
+///}
+///
+///let xml = r#"<applications><application name="billing">
+///    <subscription apiName="invoices" apiVersion="v1" environment="dev"/>
+///</application></applications>"#;
+///let documents = unify_applilcations(&parse_xml_file(xml.as_bytes())?.0);
+///let url = ObjectUrl::parse(Path::new("s3://exports/yaml"))?.unwrap();
+///let store = Memory::default();
+///let write = |force| {
+///    let files = write_objects(&documents, &url, &store, force, false, LineEnding::Lf, &CancellationToken::default());
+///    files.map(|files| files[0].status.clone())
+///};
+///
+///assert_eq!(write(false)?, WriteStatus::Created);
+///assert!(store.0.borrow().contains_key("yaml/billing-subscription/subscription.yaml"));
+///assert!(matches!(write(false)?, WriteStatus::Skipped(_)));
+///assert_eq!(write(true)?, WriteStatus::Overwritten);
+///# anyhow::Ok(())
+///```
+///
+///[`write_to_file`]: crate::migrate::write_to_file
+pub fn write_objects(
+    documents: &[YamlApiSubscription],
+    url: &ObjectUrl,
+    store: &dyn ObjectStore,
+    force: bool,
+    dry_run: bool,
+    line_ending: LineEnding,
+    cancel: &CancellationToken,
+) -> Result<Vec<WrittenFile>> {
+    let mut written = Vec::<WrittenFile>::new();
+    for document in documents {
+        if cancel.is_cancelled() {
+            let files_written = written
+                .into_iter()
+                .filter(|file| file.status.is_written())
+                .map(|file| file.path)
+                .collect();
+            return Err(Cancelled { files_written }.into());
+        }
+        let key = url.key(document.name(), &document.file_name());
+        let result = (|| {
+            let existed = store.exists(&key)?;
+            if existed && !force {
+                return Ok(WriteStatus::Skipped(
+                    "object already exists, use --force".to_string(),
+                ));
+            }
+            if !dry_run {
+                let content = document.to_yaml()?;
+                store.put(&key, line_ending.apply(&content).as_bytes().to_vec())?;
+            }
+            Ok::<_, anyhow::Error>(match existed {
+                true => WriteStatus::Overwritten,
+                false => WriteStatus::Created,
+            })
+        })();
+        let status = result.unwrap_or_else(|e| WriteStatus::Failed(format!("{:#}", e)));
+        written.push(WrittenFile {
+            application: document.name().to_string(),
+            path: PathBuf::from(format!("{}{}/{}", S3_SCHEME, url.bucket, key)),
+            status,
+        });
+    }
+    Ok(written)
+}
+
+///The store of the bucket of `url`.
+#[cfg(feature = "sink-s3")]
+pub fn connect(url: &ObjectUrl) -> Result<Box<dyn ObjectStore>> {
+    Ok(Box::new(S3Store::new(&url.bucket)?))
+}
+
+///The store of the bucket of `url`.
+#[cfg(not(feature = "sink-s3"))]
+pub fn connect(url: &ObjectUrl) -> Result<Box<dyn ObjectStore>> {
+    bail!("Writing to {} needs a build with the sink-s3 feature", url)
+}
+
+///A bucket reached through the AWS SDK, with credentials and region from
+///the standard provider chain.
+#[cfg(feature = "sink-s3")]
+pub struct S3Store {
+    bucket: String,
+    client: aws_sdk_s3::Client,
+    runtime: tokio::runtime::Runtime,
+}
+
+#[cfg(feature = "sink-s3")]
+impl S3Store {
+    pub fn new(bucket: &str) -> Result<Self> {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?;
+        let config = runtime.block_on(aws_config::load_from_env());
+        Ok(S3Store {
+            bucket: bucket.to_string(),
+            client: aws_sdk_s3::Client::new(&config),
+            runtime,
+        })
+    }
+}
+
+#[cfg(feature = "sink-s3")]
+impl ObjectStore for S3Store {
+    fn exists(&self, key: &str) -> Result<bool> {
+        let request = self.client.head_object().bucket(&self.bucket).key(key);
+        match self.runtime.block_on(request.send()) {
+            Ok(_) => Ok(true),
+            Err(e) if e.as_service_error().is_some_and(|e| e.is_not_found()) => Ok(false),
+            Err(e) => Err(anyhow::Error::new(e)
+                .context(format!("Failed to look up s3://{}/{}", self.bucket, key))),
+        }
+    }
+
+    fn put(&self, key: &str, content: Vec<u8>) -> Result<()> {
+        let request = self
+            .client
+            .put_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .content_type("application/yaml")
+            .body(content.into());
+        match self.runtime.block_on(request.send()) {
+            Ok(_) => Ok(()),
+            Err(e) => Err(anyhow::Error::new(e)
+                .context(format!("Failed to upload s3://{}/{}", self.bucket, key))),
+        }
+    }
+}
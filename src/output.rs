@@ -0,0 +1,177 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use anstyle::Style;
+
+use crate::{
+    migrate::{WriteStatus, WrittenFile, XmlApplication},
+    pipeline::Preview,
+    style::{paint, CHANGED, DIM, ERROR, SUCCESS, WARNING},
+};
+
+impl WriteStatus {
+    fn glyph(&self) -> char {
+        match self {
+            WriteStatus::Created => '+',
+            WriteStatus::Overwritten => '~',
+            WriteStatus::Unchanged => '=',
+            WriteStatus::Skipped(_) => '-',
+            WriteStatus::Failed(_) => '!',
+        }
+    }
+
+    fn style(&self) -> Style {
+        match self {
+            WriteStatus::Created => SUCCESS,
+            WriteStatus::Overwritten => CHANGED,
+            WriteStatus::Unchanged => DIM,
+            WriteStatus::Skipped(_) => WARNING,
+            WriteStatus::Failed(_) => ERROR,
+        }
+    }
+
+    fn reason(&self) -> Option<&str> {
+        match self {
+            WriteStatus::Skipped(reason) | WriteStatus::Failed(reason) => Some(reason),
+            _ => None,
+        }
+    }
+}
+
+///The directories the applications were read from, each with the names of
+///the applications it contributed. Merged applications show up under every
+///source.
+fn source_directories(applications: &[XmlApplication]) -> BTreeMap<PathBuf, BTreeSet<&str>> {
+    let mut directories = BTreeMap::<PathBuf, BTreeSet<&str>>::new();
+    for app in applications {
+        let directory = app.source.parent().unwrap_or(Path::new("")).to_path_buf();
+        directories.entry(directory).or_default().insert(&app.name);
+    }
+    directories
+}
+
+///Prints the written files grouped by source directory, sorted, with a
+///status glyph per file.
+pub fn print_grouped(files: &[WrittenFile], applications: &[XmlApplication]) {
+    for (directory, names) in source_directories(applications) {
+        println!("{}", directory.display());
+        println!(
+            "  applications: {}",
+            names.iter().copied().collect::<Vec<_>>().join(", ")
+        );
+        let mut files = files
+            .iter()
+            .filter(|file| names.contains(file.application.as_str()))
+            .collect::<Vec<_>>();
+        files.sort_by(|a, b| a.path.cmp(&b.path));
+        for file in files {
+            let style = file.status.style();
+            let status = paint(
+                style,
+                format_args!(
+                    "{} {} {}",
+                    file.status.glyph(),
+                    file.path.display(),
+                    file.status.label()
+                ),
+            );
+            match file.status.reason() {
+                Some(reason) => anstream::println!("  {}: {}", status, reason),
+                None => anstream::println!("  {}", status),
+            }
+        }
+    }
+}
+
+///Writes one line per file in the stable porcelain format:
+///`STATUS\tAPPLICATION\tPATH\n`, sorted by path. STATUS is one of
+///`created`, `overwritten`, `unchanged`, `skipped` and `failed`, the path
+///is written as is, without quotes or escapes. Scripts may rely on this
+///format, new columns or statuses are breaking changes.
+///
+///```
+///use subscription_migrator::migrate::{WriteStatus, WrittenFile};
+///use subscription_migrator::output::write_porcelain;
+///
+///let file = |application: &str, path: &str, status| WrittenFile {
+///    application: application.to_string(),
+///    path: path.into(),
+///    status,
+///};
+///let files = [
+///    file("shop", "out/shop-subscription/subscription.yaml", WriteStatus::Created),
+///    file("billing", "out/billing-subscription/subscription.yaml", WriteStatus::Skipped("exists".to_string())),
+///];
+///let mut out = Vec::new();
+///write_porcelain(&mut out, &files)?;
+///assert_eq!(
+///    String::from_utf8(out)?,
+///    "skipped\tbilling\tout/billing-subscription/subscription.yaml\n\
+///     created\tshop\tout/shop-subscription/subscription.yaml\n"
+///);
+///# anyhow::Ok(())
+///```
+pub fn write_porcelain(out: &mut impl Write, files: &[WrittenFile]) -> io::Result<()> {
+    let mut files = files.iter().collect::<Vec<_>>();
+    files.sort_by(|a, b| a.path.cmp(&b.path));
+    for file in files {
+        writeln!(
+            out,
+            "{}\t{}\t{}",
+            file.status.label(),
+            file.application,
+            file.path.display()
+        )?;
+    }
+    Ok(())
+}
+
+///Prints the files in the porcelain format of [`write_porcelain`] to
+///stdout, the reasons of skipped and failed ones go to stderr.
+pub fn print_porcelain(files: &[WrittenFile]) -> io::Result<()> {
+    write_porcelain(&mut io::stdout().lock(), files)?;
+    for file in files {
+        if let Some(reason) = file.status.reason() {
+            eprintln!("{}: {}", file.path.display(), reason);
+        }
+    }
+    Ok(())
+}
+
+///Writes the previews of a dry run: a `new file` header with the full
+///content, an `unchanged` line, or a colored unified diff.
+pub fn write_preview(out: &mut impl Write, previews: &[(PathBuf, Preview)]) -> io::Result<()> {
+    for (path, preview) in previews {
+        match preview {
+            Preview::New(content) => {
+                let header = format!("new file: {}", path.display());
+                writeln!(out, "{}", paint(SUCCESS, header))?;
+                for line in content.lines() {
+                    writeln!(out, "{}", paint(SUCCESS, format_args!("+{}", line)))?;
+                }
+            }
+            Preview::Unchanged => {
+                let line = format!("unchanged: {}", path.display());
+                writeln!(out, "{}", paint(DIM, line))?;
+            }
+            Preview::Changed(diff) => {
+                for line in diff.lines() {
+                    let style = match line {
+                        _ if line.starts_with("+++") || line.starts_with("---") => {
+                            Style::new().bold()
+                        }
+                        _ if line.starts_with('+') => SUCCESS,
+                        _ if line.starts_with('-') => ERROR,
+                        _ if line.starts_with("@@") => CHANGED,
+                        _ => Style::new(),
+                    };
+                    writeln!(out, "{}", paint(style, line))?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
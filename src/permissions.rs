@@ -0,0 +1,82 @@
+use std::path::Path;
+
+use anyhow::{Context, Result};
+
+use crate::style::warn;
+
+///Permissions for generated files and the directories holding them. Unset
+///modes leave whatever the umask produced.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Permissions {
+    pub file_mode: Option<u32>,
+    pub dir_mode: Option<u32>,
+    ///Drop the write bits of every written file
+    pub read_only: bool,
+}
+
+///Parses an octal mode like `644` or `0o755`.
+pub fn parse_mode(value: &str) -> Result<u32, String> {
+    let digits = value
+        .strip_prefix("0o")
+        .or_else(|| value.strip_prefix("0O"))
+        .unwrap_or(value);
+    match u32::from_str_radix(digits, 8) {
+        Ok(mode) if !digits.is_empty() && mode <= 0o7777 => Ok(mode),
+        _ => Err(format!(
+            "'{value}' is not an octal mode between 0 and 7777, e.g. 644"
+        )),
+    }
+}
+
+impl Permissions {
+    ///Warns once if modes were requested on a platform without Unix
+    ///permissions, where they are ignored.
+    pub fn warn_unsupported(&self) {
+        if cfg!(not(unix)) && (self.file_mode.is_some() || self.dir_mode.is_some()) {
+            warn("--file-mode and --dir-mode are only supported on Unix, ignoring");
+        }
+    }
+
+    ///Mode recorded for files in an archive, where no umask applies.
+    pub fn archived_file_mode(&self) -> u32 {
+        let mode = self.file_mode.unwrap_or(0o644);
+        if self.read_only {
+            mode & !0o222
+        } else {
+            mode
+        }
+    }
+
+    pub fn apply_to_file(&self, path: &Path) -> Result<()> {
+        if let Some(mode) = self.file_mode {
+            set_mode(path, mode)?;
+        }
+        if self.read_only {
+            let mut permissions = std::fs::metadata(path)?.permissions();
+            permissions.set_readonly(true);
+            std::fs::set_permissions(path, permissions)
+                .with_context(|| format!("Failed to make {path:?} read-only"))?;
+        }
+        Ok(())
+    }
+
+    pub fn apply_to_dir(&self, path: &Path) -> Result<()> {
+        match self.dir_mode {
+            Some(mode) => set_mode(path, mode),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(unix)]
+fn set_mode(path: &Path, mode: u32) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))
+        .with_context(|| format!("Failed to set mode {mode:o} on {path:?}"))
+}
+
+#[cfg(not(unix))]
+fn set_mode(_path: &Path, _mode: u32) -> Result<()> {
+    Ok(())
+}
@@ -0,0 +1,645 @@
+//!The stages of a migration, callable on their own: [`scan`] finds the
+//!exports, [`parse`] reads them, [`convert`] builds the documents and
+//![`write`] stores them. The CLI subcommands are built from these.
+//!
+//!```
+//!use subscription_migrator::cancel::CancellationToken;
+//!use subscription_migrator::convert::ConvertOptions;
+//!use subscription_migrator::pipeline::{self, Matcher, Sink, WritePolicy};
+//!use subscription_migrator::timing::Timings;
+//!
+//!let root = std::env::temp_dir().join(format!("pipeline-doc-{}", std::process::id()));
+//!std::fs::create_dir_all(root.join("app-billing"))?;
+//!std::fs::write(
+//!    root.join("app-billing/subscribe.xml"),
+//!    r#"<applications><application name="billing">
+//!        <subscription apiName="invoices" apiVersion="v1" environment="dev"/>
+//!    </application></applications>"#,
+//!)?;
+//!let output = root.join("out");
+//!std::fs::create_dir_all(&output)?;
+//!
+//!let matcher = Matcher {
+//!    prefix: "app-".to_string(),
+//!    ..Default::default()
+//!};
+//!let scan = pipeline::scan(&[root.clone()], &matcher)?;
+//!let mut timings = Timings::default();
+//!let parsed = pipeline::parse(&scan.sources(), &mut timings)?;
+//!let documents = pipeline::convert(&parsed, &ConvertOptions::default());
+//!let outcome = pipeline::write(
+//!    &documents,
+//!    &parsed.applications,
+//!    &Sink::Directory(output.clone()),
+//!    &WritePolicy::default(),
+//!    &CancellationToken::default(),
+//!    &mut timings,
+//!)?;
+//!
+//!assert_eq!(
+//!    outcome.written_paths(),
+//!    [output.join("billing-subscription/subscription.yaml")]
+//!);
+//!std::fs::remove_dir_all(&root)?;
+//!# anyhow::Ok(())
+//!```
+
+use std::{
+    borrow::Cow,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use anyhow::{bail, Context, Result};
+
+#[cfg(feature = "archive")]
+use crate::{
+    archive::{write_archive, ArchiveTarget},
+    checksum::sidecar_content,
+    index::INDEX_FILE,
+    manifest::MANIFEST_FILE,
+};
+use crate::{
+    cache::ParseCache,
+    cancel::CancellationToken,
+    checksum::{write_sidecar, ChecksumAlgorithm},
+    convert::{convert_applications, ConvertOptions},
+    directory_overrides::{AppliedDirectoryOverride, DirectoryOverrides},
+    failure::Conflicts,
+    index::Index,
+    input::{parse_export_until, Export, InputFormat},
+    inventory::ElementInventory,
+    line_ending::LineEnding,
+    manifest::Manifest,
+    migrate::{
+        find_conflicts, is_unchanged, subscription_path, write_to_file, Conflict, Deadline,
+        TimedOut, WrittenFile, XmlApplication, YamlApiSubscription,
+    },
+    naming::original_names,
+    objects::{connect, write_objects, ObjectUrl},
+    permissions::Permissions,
+    plan::reader_hash,
+    retry::RetryPolicy,
+    scan::{scan_directories, subscribe_file, MatchMode, Scan, UnreadableExport},
+    secret::redact_yaml,
+    template::ExtraFile,
+    timing::Timings,
+};
+
+///A directory selected for migration and the export inside it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceDir {
+    pub directory: PathBuf,
+    pub file: PathBuf,
+    pub format: InputFormat,
+    ///Used for opening and reading the export
+    pub retry: RetryPolicy,
+    ///How long parsing the export may take, unlimited if not set
+    pub parse_timeout: Option<Duration>,
+    ///Where the parsed export is looked up before and stored after parsing
+    pub cache: Option<ParseCache>,
+}
+
+impl SourceDir {
+    ///The export of `directory`, or `directory` itself if it is a file,
+    ///like an export archive.
+    pub fn new(directory: PathBuf) -> Self {
+        let file = match directory.is_file() {
+            true => directory.clone(),
+            false => subscribe_file(&directory),
+        };
+        SourceDir {
+            file,
+            directory,
+            format: InputFormat::Auto,
+            retry: RetryPolicy::default(),
+            parse_timeout: None,
+            cache: None,
+        }
+    }
+
+    pub fn format(mut self, format: InputFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    pub fn retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    pub fn parse_timeout(mut self, parse_timeout: Option<Duration>) -> Self {
+        self.parse_timeout = parse_timeout;
+        self
+    }
+
+    pub fn cache(mut self, cache: Option<ParseCache>) -> Self {
+        self.cache = cache;
+        self
+    }
+}
+
+///Which directories below the scanned paths are migrated.
+#[derive(Debug, Clone, Default)]
+pub struct Matcher {
+    pub prefix: String,
+    pub include_hidden: bool,
+    pub mode: MatchMode,
+    ///Directories belonging to this output path are left out
+    pub output: Option<PathBuf>,
+    ///Keeps matched directories that are another name of an earlier one,
+    ///see [`Scan::dedupe_sources`]
+    pub keep_aliases: bool,
+}
+
+impl Scan {
+    ///The matched directories as sources.
+    pub fn sources(&self) -> Vec<SourceDir> {
+        self.matched.iter().cloned().map(SourceDir::new).collect()
+    }
+}
+
+///Scans the directories below every path in `paths`.
+pub fn scan(paths: &[PathBuf], matcher: &Matcher) -> Result<Scan> {
+    let mut total = Scan::default();
+    for path in paths {
+        let mut scan =
+            scan_directories(path, &matcher.prefix, matcher.include_hidden, matcher.mode)?;
+        if let Some(output) = &matcher.output {
+            scan.exclude_output(output);
+        }
+        total.merge(scan);
+    }
+    if !matcher.keep_aliases {
+        total.dedupe_sources();
+    }
+    Ok(total)
+}
+
+///The applications of every parsed source.
+#[derive(Debug, Default)]
+pub struct ParseOutcome {
+    ///In source order, each with its `source` set
+    pub applications: Vec<XmlApplication>,
+    ///Elements of the exports the parser doesn't read
+    pub unknown_elements: ElementInventory,
+    ///Files of export archives that were skipped, with their archive
+    pub ignored_entries: Vec<(PathBuf, String)>,
+    ///Override files found next to the exports
+    pub directory_overrides: Vec<AppliedDirectoryOverride>,
+    ///Source directories that failed to parse, only filled by
+    ///[`parse_skipping_failures`]
+    pub failures: Vec<(PathBuf, anyhow::Error)>,
+    ///Exports taken from the parse cache instead of being parsed
+    pub cache_hits: usize,
+}
+
+impl ParseOutcome {
+    ///Notes about what the parser dropped, one per line.
+    pub fn warnings(&self) -> Vec<String> {
+        let duplicates = self
+            .applications
+            .iter()
+            .filter(|app| app.duplicate_subscriptions > 0)
+            .map(|app| {
+                format!(
+                    "Dropped {} duplicate subscription(s) of {:?} in {:?}",
+                    app.duplicate_subscriptions, app.name, app.source
+                )
+            });
+        let ignored = self
+            .ignored_entries
+            .iter()
+            .map(|(archive, entry)| format!("Ignored {:?} in {:?}", entry, archive));
+        duplicates.chain(ignored).collect()
+    }
+}
+
+///Parses the export of every source, timing each one.
+pub fn parse(sources: &[SourceDir], timings: &mut Timings) -> Result<ParseOutcome> {
+    let mut outcome = ParseOutcome::default();
+    for source in sources {
+        parse_source(source, &mut outcome, timings)?;
+    }
+    Ok(outcome)
+}
+
+///Like [`parse`], but a source that fails to parse is added to
+///[`ParseOutcome::failures`] and the others are still parsed.
+pub fn parse_skipping_failures(sources: &[SourceDir], timings: &mut Timings) -> ParseOutcome {
+    let mut outcome = ParseOutcome::default();
+    for source in sources {
+        if let Err(e) = parse_source(source, &mut outcome, timings) {
+            outcome.failures.push((source.directory.clone(), e));
+        }
+    }
+    outcome
+}
+
+fn parse_source(
+    source: &SourceDir,
+    outcome: &mut ParseOutcome,
+    timings: &mut Timings,
+) -> Result<()> {
+    UnreadableExport::check(&source.directory, &source.file)?;
+    let mut cached = false;
+    let export = timings.time_parse(&source.file, || -> Result<Export> {
+        // the cache key is hashed in a pass of its own, so that the parse
+        // still streams the export
+        let cache = match &source.cache {
+            Some(cache) => Some((cache, reader_hash(source.retry.open(&source.file)?)?)),
+            None => None,
+        };
+        if let Some(export) = cache
+            .as_ref()
+            .and_then(|(cache, hash)| cache.get(hash, source.format))
+        {
+            cached = true;
+            return Ok(export);
+        }
+        let deadline = source.parse_timeout.map(Deadline::after);
+        let file = source.retry.open(&source.file)?;
+        let export = parse_export_until(file, source.format, deadline);
+        let export = export.map_err(|e| match e.is::<TimedOut>() {
+            true => e.context(format!("Gave up parsing {:?}", source.file)),
+            false => e,
+        })?;
+        if let Some((cache, hash)) = &cache {
+            cache.put(hash, source.format, &export);
+        }
+        Ok(export)
+    })?;
+    outcome.cache_hits += usize::from(cached);
+    let mut applications = export.applications;
+    let overrides = match source.directory.is_dir() {
+        true => DirectoryOverrides::load(&source.directory)?,
+        false => None,
+    };
+    if let Some((file, overrides)) = overrides {
+        let changes = overrides
+            .apply(&mut applications)
+            .with_context(|| format!("Failed to apply {:?}", file))?;
+        outcome
+            .directory_overrides
+            .push(AppliedDirectoryOverride { file, changes });
+    }
+    outcome
+        .unknown_elements
+        .add(export.unknown_elements, &source.file);
+    outcome.ignored_entries.extend(
+        export
+            .ignored_entries
+            .into_iter()
+            .map(|entry| (source.file.clone(), entry)),
+    );
+    outcome
+        .applications
+        .extend(applications.into_iter().map(|mut app| {
+            app.source.clone_from(&source.file);
+            app
+        }));
+    Ok(())
+}
+
+///Builds the documents of the parsed applications.
+pub fn convert(outcome: &ParseOutcome, options: &ConvertOptions) -> Vec<YamlApiSubscription> {
+    convert_applications(outcome.applications.clone(), options)
+}
+
+///Where the documents go.
+#[derive(Debug, Clone)]
+pub enum Sink {
+    ///One directory per application below this path, which must exist
+    Directory(PathBuf),
+    #[cfg(feature = "archive")]
+    Archive(ArchiveTarget),
+    ///An S3 bucket, needs the `sink-s3` feature
+    Objects(ObjectUrl),
+}
+
+///How the documents are written. The default only writes the documents,
+///refusing to overwrite changed files.
+#[derive(Debug, Clone, Default)]
+pub struct WritePolicy {
+    pub force: bool,
+    ///Without `force`, write the other documents and leave out those whose
+    ///directory exists instead of writing nothing
+    pub skip_existing: bool,
+    pub permissions: Permissions,
+    pub line_ending: LineEnding,
+    ///Write a checksum sidecar next to every document
+    pub checksums: Option<ChecksumAlgorithm>,
+    pub manifest: bool,
+    pub index: bool,
+    ///Used for writing and renaming every document
+    pub retry: RetryPolicy,
+    ///Only check what would be uploaded to an object storage sink. Directory
+    ///sinks are left alone, use [`preview`] for them
+    pub dry_run: bool,
+    ///Rendered next to every document
+    pub extra_files: Vec<ExtraFile>,
+}
+
+impl WritePolicy {
+    ///The number of files writing `documents` creates: every document with
+    ///its sidecar and extra files, the manifest and the index.
+    pub fn file_count(&self, documents: usize) -> usize {
+        let per_document = 1 + self.checksums.iter().count() + self.extra_files.len();
+        documents * per_document + usize::from(self.manifest) + usize::from(self.index)
+    }
+}
+
+///What [`write`] did.
+#[derive(Debug, Default)]
+pub struct WriteOutcome {
+    ///Every document written to a directory sink, in document order
+    pub files: Vec<WrittenFile>,
+    ///Application directories this run created
+    pub created_directories: Vec<PathBuf>,
+    ///Application directories that already existed and were written into
+    ///with `force`
+    pub reused_directories: Vec<PathBuf>,
+    ///Documents left out with `skip_existing`
+    pub skipped_existing: Vec<Conflict>,
+    ///The archive and its entries when writing to an archive sink
+    pub archive: Option<(PathBuf, Vec<PathBuf>)>,
+    pub manifest: Option<PathBuf>,
+    pub index: Option<PathBuf>,
+}
+
+impl WriteOutcome {
+    ///The written documents, or all archive entries.
+    pub fn written_paths(&self) -> Vec<PathBuf> {
+        match &self.archive {
+            Some((_, entries)) => entries.clone(),
+            None => self
+                .files
+                .iter()
+                .filter(|file| file.status.is_written())
+                .map(|file| file.path.clone())
+                .collect(),
+        }
+    }
+
+    ///Documents that were skipped or failed.
+    pub fn not_written(&self) -> usize {
+        self.files
+            .iter()
+            .filter(|file| !file.status.is_written())
+            .count()
+    }
+}
+
+///Writes the documents to `sink`. `applications` are the ones the
+///documents were converted from, named in the manifest. Sidecars, the
+///manifest and the index are only written when every document was. Nothing
+///is written if an extra file fails to render for any document.
+pub fn write(
+    documents: &[YamlApiSubscription],
+    applications: &[XmlApplication],
+    sink: &Sink,
+    policy: &WritePolicy,
+    cancel: &CancellationToken,
+    timings: &mut Timings,
+) -> Result<WriteOutcome> {
+    let failures = documents
+        .iter()
+        .flat_map(|document| {
+            policy
+                .extra_files
+                .iter()
+                .map(move |extra| (document, extra))
+        })
+        .filter_map(|(document, extra)| extra.render(document).err())
+        .map(|e| format!("{:#}", e))
+        .collect::<Vec<_>>();
+    if !failures.is_empty() {
+        bail!("Failed to render extra files:\n  {}", failures.join("\n  "));
+    }
+    let _writing = cancel.writing();
+    match sink {
+        Sink::Directory(output) => {
+            write_directory(documents, applications, output, policy, cancel, timings)
+        }
+        #[cfg(feature = "archive")]
+        Sink::Archive(target) => write_to_archive(documents, applications, target, policy, cancel),
+        Sink::Objects(url) => write_to_objects(documents, url, policy, cancel),
+    }
+}
+
+#[cfg(feature = "archive")]
+fn write_to_archive(
+    documents: &[YamlApiSubscription],
+    applications: &[XmlApplication],
+    target: &ArchiveTarget,
+    policy: &WritePolicy,
+    cancel: &CancellationToken,
+) -> Result<WriteOutcome> {
+    let line_ending = policy.line_ending;
+    let entries = documents
+        .iter()
+        .map(|doc| {
+            let content = doc.to_yaml()?;
+            Ok((
+                subscription_path(Path::new(""), doc),
+                line_ending.apply(&content).as_bytes().to_vec(),
+            ))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let mut extra = Vec::new();
+    for (doc, (path, _)) in documents.iter().zip(&entries) {
+        for file in &policy.extra_files {
+            let content = file.render(doc)?;
+            extra.push((
+                file.path(path),
+                line_ending.apply(&content).as_bytes().to_vec(),
+            ));
+        }
+    }
+    let mut checksums = Vec::new();
+    if let Some(algorithm) = policy.checksums {
+        for (path, content) in &entries {
+            let digest = algorithm.digest(content);
+            let sidecar = line_ending
+                .apply(&sidecar_content(&digest, path))
+                .into_owned();
+            extra.push((algorithm.sidecar_path(path), sidecar.into_bytes()));
+            checksums.push(digest);
+        }
+    }
+    if policy.manifest {
+        let paths = entries
+            .iter()
+            .map(|(path, _)| path.clone())
+            .collect::<Vec<_>>();
+        let manifest = Manifest::new(
+            Path::new(""),
+            documents,
+            &paths,
+            &original_names(applications),
+            &checksums,
+        );
+        let json = line_ending.apply(&manifest.to_json()?).into_owned();
+        extra.push((PathBuf::from(MANIFEST_FILE), json.into_bytes()));
+    }
+    if policy.index {
+        let index = Index::from_documents(documents, &entries);
+        let yaml = line_ending.apply(&index.to_yaml()?).into_owned();
+        extra.push((PathBuf::from(INDEX_FILE), yaml.into_bytes()));
+    }
+    let mut entries = entries;
+    entries.extend(extra);
+    let archived = write_archive(target, &entries, policy.force, &policy.permissions, cancel)?;
+    Ok(WriteOutcome {
+        archive: Some((target.path.clone(), archived)),
+        ..Default::default()
+    })
+}
+
+///Uploads the documents. Object storage has no index, manifests, checksums
+///and extra files aren't supported.
+fn write_to_objects(
+    documents: &[YamlApiSubscription],
+    url: &ObjectUrl,
+    policy: &WritePolicy,
+    cancel: &CancellationToken,
+) -> Result<WriteOutcome> {
+    if policy.manifest || policy.checksums.is_some() || !policy.extra_files.is_empty() {
+        bail!(
+            "--manifest, --checksums and --extra-file aren't supported when writing to {}",
+            url
+        );
+    }
+    let store = connect(url)?;
+    let files = write_objects(
+        documents,
+        url,
+        store.as_ref(),
+        policy.force,
+        policy.dry_run,
+        policy.line_ending,
+        cancel,
+    )?;
+    Ok(WriteOutcome {
+        files,
+        ..Default::default()
+    })
+}
+
+///How a document compares with the file it would be written to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Preview {
+    ///No file exists yet, with the content it would be written with
+    New(String),
+    ///The file holds the document, compared like a write does
+    Unchanged,
+    ///Unified diff from the existing file to the new content
+    Changed(String),
+}
+
+///What writing `documents` below `output` would change, without touching
+///the filesystem. Files count as unchanged under the same normalization
+///a write run and `plan` skip them with.
+pub fn preview(
+    documents: &[YamlApiSubscription],
+    output: &Path,
+    line_ending: LineEnding,
+) -> Result<Vec<(PathBuf, Preview)>> {
+    documents
+        .iter()
+        .map(|document| {
+            let path = subscription_path(output, document);
+            let content = line_ending.apply(&document.to_yaml()?).into_owned();
+            // previews end up in terminals and CI logs, unlike the files
+            let content = redact_yaml(&content);
+            let preview = match std::fs::read_to_string(&path) {
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                    Preview::New(content.into_owned())
+                }
+                Err(e) => return Err(e).with_context(|| format!("Failed to read {:?}", path)),
+                Ok(_) if is_unchanged(&path, document) => Preview::Unchanged,
+                Ok(existing) => {
+                    let name = path.display().to_string();
+                    let diff = similar::TextDiff::from_lines(&*redact_yaml(&existing), &*content)
+                        .unified_diff()
+                        .header(&name, &name)
+                        .to_string();
+                    match diff.is_empty() {
+                        true => Preview::Changed(format!("changed: {} (credentials only)\n", name)),
+                        false => Preview::Changed(diff),
+                    }
+                }
+            };
+            Ok((path, preview))
+        })
+        .collect()
+}
+
+fn write_directory(
+    documents: &[YamlApiSubscription],
+    applications: &[XmlApplication],
+    output: &Path,
+    policy: &WritePolicy,
+    cancel: &CancellationToken,
+    timings: &mut Timings,
+) -> Result<WriteOutcome> {
+    let line_ending = policy.line_ending;
+    let mut index = match policy.index {
+        true => Some(Index::load(output)?),
+        false => None,
+    };
+    let mut skipped_existing = Vec::new();
+    let documents = match policy.force {
+        true => Cow::Borrowed(documents),
+        false => {
+            let conflicts = find_conflicts(documents, output);
+            if !conflicts.is_empty() && !policy.skip_existing {
+                return Err(Conflicts { conflicts }.into());
+            }
+            let kept = documents
+                .iter()
+                .filter(|document| {
+                    let path = subscription_path(output, document);
+                    !conflicts
+                        .iter()
+                        .any(|conflict| path.parent() == Some(conflict.directory.as_path()))
+                })
+                .cloned()
+                .collect::<Vec<_>>();
+            skipped_existing = conflicts;
+            Cow::Owned(kept)
+        }
+    };
+    let documents = documents.as_ref();
+    let mut outcome = write_to_file(documents, output.to_path_buf(), policy, cancel, timings)?;
+    outcome.skipped_existing = skipped_existing;
+    if outcome.not_written() > 0 {
+        return Ok(outcome);
+    }
+    let files_written = outcome.written_paths();
+    let mut checksums = Vec::new();
+    if let Some(algorithm) = policy.checksums {
+        for file in &files_written {
+            checksums.push(write_sidecar(algorithm, file, line_ending)?);
+            policy
+                .permissions
+                .apply_to_file(&algorithm.sidecar_path(file))?;
+        }
+    }
+    if policy.manifest {
+        let manifest = Manifest::new(
+            output,
+            documents,
+            &files_written,
+            &original_names(applications),
+            &checksums,
+        )
+        .created_directories(output, &outcome.created_directories);
+        outcome.manifest = Some(manifest.write(output, line_ending)?);
+    }
+    if let Some(index) = &mut index {
+        index.update(output, documents, &files_written)?;
+        outcome.index = Some(index.write(output, line_ending)?);
+    }
+    Ok(outcome)
+}
@@ -0,0 +1,356 @@
+use std::{
+    collections::BTreeSet,
+    fmt,
+    io::{self, Read},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    cancel::{CancellationToken, Cancelled},
+    formatting::SUBSCRIPTION_FILE,
+    migrate::{ensure_inside, is_unchanged, subscription_path, write_atomic, YamlApiSubscription},
+    template::ExtraFile,
+};
+
+///Hex encoded SHA-256 of `contents`.
+pub fn content_hash(contents: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(contents))
+}
+
+///Like [`content_hash`], but reads `reader` to its end without keeping what
+///it read.
+///
+///```
+///use subscription_migrator::plan::{content_hash, reader_hash};
+///
+///let xml = b"<subscriptions></subscriptions>";
+///assert_eq!(reader_hash(&xml[..])?, content_hash(xml));
+///# std::io::Result::Ok(())
+///```
+pub fn reader_hash(mut reader: impl Read) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut reader, &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+///Hash of the file at `path`, `None` if it doesn't exist.
+fn file_hash(path: &Path) -> Result<Option<String>> {
+    match std::fs::read(path) {
+        Ok(contents) => Ok(Some(content_hash(&contents))),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e).with_context(|| format!("Failed to read {:?}", path)),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+    Create,
+    Change,
+    Unchanged,
+    ///A generated directory no application maps to anymore
+    Prune,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SourceHash {
+    pub path: PathBuf,
+    pub hash: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PlannedFile {
+    pub application: String,
+    pub path: PathBuf,
+    pub action: Action,
+    ///Hash of the file when the plan was made
+    pub previous_hash: Option<String>,
+    ///Hash of the file once the plan is applied
+    pub hash: Option<String>,
+    ///What gets written, only for created and changed files
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content: Option<String>,
+}
+
+///Everything a run would write, saved by `plan` and executed by `apply`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Plan {
+    pub output: PathBuf,
+    ///The subscribe.xml files the documents were converted from
+    pub sources: Vec<SourceHash>,
+    pub files: Vec<PlannedFile>,
+}
+
+impl Plan {
+    ///Plans writing `documents` and their `extra_files` below `output`. With
+    ///`prune` generated directories in `output` that no document maps to
+    ///are removed.
+    pub fn new(
+        output: &Path,
+        documents: &[YamlApiSubscription],
+        extra_files: &[ExtraFile],
+        sources: &[PathBuf],
+        prune: bool,
+    ) -> Result<Self> {
+        let sources = sources
+            .iter()
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .map(|path| {
+                let hash = file_hash(path)?
+                    .ok_or_else(|| anyhow::anyhow!("Source {:?} does not exist", path))?;
+                Ok(SourceHash {
+                    path: path.clone(),
+                    hash,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut files = Vec::new();
+        for document in documents {
+            let path = subscription_path(output, document);
+            ensure_inside(output, &path)?;
+            let previous_hash = file_hash(&path)?;
+            let (action, content) = if previous_hash.is_none() {
+                (Action::Create, Some(document.to_yaml()?))
+            } else if is_unchanged(&path, document) {
+                (Action::Unchanged, None)
+            } else {
+                (Action::Change, Some(document.to_yaml()?))
+            };
+            for extra in extra_files {
+                let path = extra.path(&path);
+                ensure_inside(output, &path)?;
+                let content = extra.render(document)?;
+                let hash = content_hash(content.as_bytes());
+                let previous_hash = file_hash(&path)?;
+                let (action, content) = match &previous_hash {
+                    None => (Action::Create, Some(content)),
+                    Some(previous) if *previous == hash => (Action::Unchanged, None),
+                    Some(_)
+                        if std::fs::read_to_string(&path)
+                            .is_ok_and(|existing| extra.is_current(&existing, &content)) =>
+                    {
+                        (Action::Unchanged, None)
+                    }
+                    Some(_) => (Action::Change, Some(content)),
+                };
+                // an unchanged README keeps the time it was generated at
+                let hash = match action {
+                    Action::Unchanged => previous_hash.clone(),
+                    _ => Some(hash),
+                };
+                files.push(PlannedFile {
+                    application: document.name().to_string(),
+                    path,
+                    action,
+                    previous_hash,
+                    hash,
+                    content,
+                });
+            }
+            files.push(PlannedFile {
+                application: document.name().to_string(),
+                hash: content
+                    .as_ref()
+                    .map(|content| content_hash(content.as_bytes()))
+                    .or_else(|| previous_hash.clone()),
+                path,
+                action,
+                previous_hash,
+                content,
+            });
+        }
+
+        if prune {
+            let planned = files
+                .iter()
+                .map(|file| file.path.clone())
+                .collect::<BTreeSet<_>>();
+            for path in generated_files(output)? {
+                if planned.contains(&path) {
+                    continue;
+                }
+                let extras = extra_files
+                    .iter()
+                    .map(|extra| extra.path(&path))
+                    .filter(|extra| extra.is_file())
+                    .collect::<Vec<_>>();
+                // the directory is removed with the last of its files
+                for path in std::iter::once(path).chain(extras) {
+                    files.push(PlannedFile {
+                        application: application_name(&path),
+                        previous_hash: file_hash(&path)?,
+                        hash: None,
+                        path,
+                        action: Action::Prune,
+                        content: None,
+                    });
+                }
+            }
+        }
+
+        files.sort_by(|a, b| a.application.cmp(&b.application));
+        Ok(Plan {
+            output: output.to_path_buf(),
+            sources,
+            files,
+        })
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let content =
+            std::fs::read_to_string(path).with_context(|| format!("Failed to read {:?}", path))?;
+        serde_json::from_str(&content).with_context(|| format!("Failed to parse plan {:?}", path))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        write_atomic(path, serde_json::to_string_pretty(self)?.as_bytes())
+    }
+
+    ///JSON for printing, without the file contents as they may hold
+    ///credentials.
+    pub fn to_report_json(&self) -> Result<String> {
+        let mut value = serde_json::to_value(self)?;
+        if let Some(files) = value["files"].as_array_mut() {
+            for file in files {
+                if let Some(file) = file.as_object_mut() {
+                    file.remove("content");
+                }
+            }
+        }
+        Ok(serde_json::to_string_pretty(&value)?)
+    }
+
+    pub fn count(&self, action: Action) -> usize {
+        self.files
+            .iter()
+            .filter(|file| file.action == action)
+            .count()
+    }
+
+    ///Fails listing every source and target whose content changed since the
+    ///plan was made, as the plan would no longer reflect them.
+    pub fn verify(&self) -> Result<()> {
+        let mut stale = Vec::new();
+        for source in &self.sources {
+            if file_hash(&source.path)?.as_ref() != Some(&source.hash) {
+                stale.push(format!("source {:?}", source.path));
+            }
+        }
+        for file in &self.files {
+            if file_hash(&file.path)? != file.previous_hash {
+                stale.push(format!("output {:?}", file.path));
+            }
+        }
+
+        if !stale.is_empty() {
+            return Err(anyhow::anyhow!(
+                "Changed since the plan was made, plan again: {}",
+                stale.join(", ")
+            ));
+        }
+        Ok(())
+    }
+
+    ///Verifies and executes the plan, returning the files written or
+    ///removed. Cancellation is checked before every file. A plan file may
+    ///have been edited, so nothing is done if any of its files is outside
+    ///its output.
+    pub fn apply(&self, cancel: &CancellationToken) -> Result<Vec<PathBuf>> {
+        for file in &self.files {
+            ensure_inside(&self.output, &file.path)?;
+        }
+        self.verify()?;
+        let _writing = cancel.writing();
+        let mut files_written = Vec::new();
+        for file in &self.files {
+            if cancel.is_cancelled() {
+                return Err(Cancelled { files_written }.into());
+            }
+            match (file.action, &file.content) {
+                (Action::Create | Action::Change, Some(content)) => {
+                    if let Some(parent) = file.path.parent() {
+                        std::fs::create_dir_all(parent)?;
+                    }
+                    write_atomic(&file.path, content.as_bytes())?;
+                }
+                (Action::Create | Action::Change, None) => {
+                    return Err(anyhow::anyhow!("Plan has no content for {:?}", file.path));
+                }
+                (Action::Prune, _) => {
+                    std::fs::remove_file(&file.path)?;
+                    if let Some(parent) = file.path.parent() {
+                        // other files in the directory are kept
+                        let _ = std::fs::remove_dir(parent);
+                    }
+                }
+                (Action::Unchanged, _) => continue,
+            }
+            files_written.push(file.path.clone());
+        }
+        Ok(files_written)
+    }
+}
+
+///subscription.yaml files of the generated directories in `output`.
+pub fn generated_files(output: &Path) -> Result<Vec<PathBuf>> {
+    let entries = match std::fs::read_dir(output) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+    let mut files = Vec::new();
+    for entry in entries {
+        let entry = entry?;
+        let file = entry.path().join(SUBSCRIPTION_FILE);
+        if entry
+            .file_name()
+            .to_string_lossy()
+            .ends_with("-subscription")
+            && file.is_file()
+        {
+            files.push(file);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+///Application name of a generated file, from its directory.
+pub fn application_name(path: &Path) -> String {
+    path.parent()
+        .and_then(Path::file_name)
+        .map(|name| name.to_string_lossy())
+        .unwrap_or_default()
+        .trim_end_matches("-subscription")
+        .to_string()
+}
+
+impl fmt::Display for Plan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Plan for {:?}: {} to create, {} to change, {} unchanged, {} to prune",
+            self.output,
+            self.count(Action::Create),
+            self.count(Action::Change),
+            self.count(Action::Unchanged),
+            self.count(Action::Prune)
+        )?;
+        for file in &self.files {
+            let symbol = match file.action {
+                Action::Create => "+",
+                Action::Change => "~",
+                Action::Prune => "-",
+                Action::Unchanged => continue,
+            };
+            writeln!(f, "  {} {} ({:?})", symbol, file.application, file.path)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,155 @@
+#[cfg(feature = "cli")]
+use std::path::Path;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+    path::PathBuf,
+};
+
+#[cfg(feature = "cli")]
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "cli")]
+use crate::migrate::write_atomic;
+use crate::migrate::XmlApplication;
+
+///The sources one API version of an application was subscribed in.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ApiProvenance {
+    pub name: String,
+    pub version: String,
+    pub sources: BTreeSet<PathBuf>,
+}
+
+///Where the APIs of a merged application came from.
+#[derive(Debug, Clone, Serialize)]
+pub struct ApplicationProvenance {
+    pub application: String,
+    pub sources: BTreeSet<PathBuf>,
+    ///Sorted by name and version
+    pub apis: Vec<ApiProvenance>,
+}
+
+impl fmt::Display for ApplicationProvenance {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Application {:?} merged from {} source(s):",
+            self.application,
+            self.sources.len()
+        )?;
+        for api in &self.apis {
+            let sources = api
+                .sources
+                .iter()
+                .map(|source| source.display().to_string())
+                .collect::<Vec<_>>();
+            writeln!(f, "  {} {}: {}", api.name, api.version, sources.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+///Records for every application name which sources contributed each API
+///version, as unification merges them into one document.
+///
+///```
+///use std::path::PathBuf;
+///use subscription_migrator::migrate::{XmlApplication, XmlSubscription};
+///use subscription_migrator::provenance::provenance;
+///
+///let app = |source: &str, apis: &[&str]| XmlApplication {
+///    name: "checkout".to_string(),
+///    source: source.into(),
+///    apis: apis
+///        .iter()
+///        .map(|api| XmlSubscription {
+///            api_name: api.to_string(),
+///            api_version: "1".to_string(),
+///            env: vec!["dev".to_string()],
+///        })
+///        .collect(),
+///    ..Default::default()
+///};
+///let apps = [
+///    app("eu/subscribe.xml", &["orders", "users"]),
+///    app("us/subscribe.xml", &["orders", "mystery"]),
+///];
+///
+///let provenance = provenance(&apps);
+///assert_eq!(provenance.len(), 1);
+///let sources = |api: &str| {
+///    let api = provenance[0].apis.iter().find(|entry| entry.name == api).unwrap();
+///    api.sources.iter().cloned().collect::<Vec<_>>()
+///};
+///assert_eq!(sources("orders"), [PathBuf::from("eu/subscribe.xml"), "us/subscribe.xml".into()]);
+///assert_eq!(sources("mystery"), [PathBuf::from("us/subscribe.xml")]);
+///```
+pub fn provenance(applications: &[XmlApplication]) -> Vec<ApplicationProvenance> {
+    let mut tracker = ProvenanceTracker::default();
+    tracker.add(applications);
+    tracker.finish()
+}
+
+///The sources of each (API, version) of an application
+type ApiSources = BTreeMap<(String, String), BTreeSet<PathBuf>>;
+
+///Records the provenance of applications added a source at a time, giving
+///the same result as [`provenance`] over all of them.
+#[derive(Debug, Default)]
+pub struct ProvenanceTracker {
+    ///Name with the sources of the application and of each API version
+    by_name: BTreeMap<String, (BTreeSet<PathBuf>, ApiSources)>,
+}
+
+impl ProvenanceTracker {
+    pub fn add(&mut self, applications: &[XmlApplication]) {
+        for app in applications {
+            let (sources, apis) = self.by_name.entry(app.name.clone()).or_default();
+            sources.insert(app.source.clone());
+            for sub in &app.apis {
+                apis.entry((sub.api_name.clone(), sub.api_version.clone()))
+                    .or_default()
+                    .insert(app.source.clone());
+            }
+        }
+    }
+
+    pub fn finish(self) -> Vec<ApplicationProvenance> {
+        self.by_name
+            .into_iter()
+            .map(|(application, (sources, apis))| ApplicationProvenance {
+                application,
+                sources,
+                apis: apis
+                    .into_iter()
+                    .map(|((name, version), sources)| ApiProvenance {
+                        name,
+                        version,
+                        sources,
+                    })
+                    .collect(),
+            })
+            .collect()
+    }
+}
+
+///The provenance of every application, written as JSON with
+///`--provenance-report`.
+#[derive(Debug, Default, Serialize)]
+pub struct ProvenanceReport {
+    ///The subscribe.xml at the root of the scanned path, if it was parsed
+    ///with `--include-root`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub root: Option<PathBuf>,
+    pub applications: Vec<ApplicationProvenance>,
+}
+
+#[cfg(feature = "cli")]
+impl ProvenanceReport {
+    pub fn write(&self, path: &Path) -> Result<()> {
+        write_atomic(path, serde_json::to_string_pretty(self)?.as_bytes())
+    }
+}
@@ -0,0 +1,158 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Write,
+    path::PathBuf,
+};
+
+use crate::migrate::{YamlApiSubscription, MANAGED_BY};
+
+///Written next to every subscription.yaml with `--write-readme`
+pub const README_FILE: &str = "README.md";
+
+///Starts the last line of every README, which names the tool and the time
+///of the run
+const GENERATED_PREFIX: &str = "_Generated by ";
+
+///What the README of an application shows besides its document.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Readme {
+    ///The export files every application was parsed from, by name
+    pub sources: BTreeMap<String, BTreeSet<PathBuf>>,
+    ///RFC 3339 time of the run, left out with `--reproducible`
+    pub generated_at: Option<String>,
+}
+
+impl Readme {
+    ///Renders the README of `document` as Markdown: its name and
+    ///description, its APIs, its control planes and where it came from.
+    ///
+    ///```
+    ///use subscription_migrator::migrate::{parse_xml_file, unify_applilcations};
+    ///use subscription_migrator::readme::Readme;
+    ///
+    ///let xml = r#"<applications><application name="checkout">
+    ///    <subscription apiName="orders" apiVersion="1" environment="prod"/>
+    ///    <subscription apiName="users" apiVersion="2" environment="dev"/>
+    ///    <subscription apiName="users" apiVersion="2" environment="test"/>
+    ///</application></applications>"#;
+    ///let document = &unify_applilcations(&parse_xml_file(xml.as_bytes())?.0)[0];
+    ///let readme = Readme {
+    ///    sources: [("checkout".to_string(), ["app-checkout/subscribe.xml".into()].into())].into(),
+    ///    generated_at: None,
+    ///};
+    ///
+    ///assert_eq!(
+    ///    readme.render(document),
+    ///    "\
+    ///## checkout
+    ///
+    ///checkout-subscription
+    ///
+    ///### APIs
+    ///
+    ///| API | Version |
+    ///| --- | --- |
+    ///| orders | 1 |
+    ///| users | 2 |
+    ///
+    ///### Environments
+    ///
+    ///| Control plane | Environments |
+    ///| --- | --- |
+    ///| https://non-prod.control-plane.com | dev, test |
+    ///| https://prod.control-plane.com | prod |
+    ///
+    ///### Sources
+    ///
+    ///- app-checkout/subscribe.xml
+    ///
+    ///_Generated by subscription-migrator from the same data as subscription.yaml, do not edit_
+    ///"
+    ///);
+    ///# anyhow::Ok(())
+    ///```
+    pub fn render(&self, document: &YamlApiSubscription) -> String {
+        // lists in the order subscription.yaml has them
+        let mut document = document.clone();
+        document.canonicalize();
+        let application = &document.subscription.application;
+        let mut readme = format!("# {}\n\n", application.name);
+        if !application.description.is_empty() {
+            writeln!(readme, "{}\n", application.description).unwrap();
+        }
+        readme.push_str("## APIs\n\n");
+        match application.apis.is_empty() {
+            true => readme.push_str("None\n\n"),
+            false => {
+                readme.push_str("| API | Version |\n| --- | --- |\n");
+                for api in &application.apis {
+                    writeln!(readme, "| {} | {} |", cell(&api.name), cell(&api.version)).unwrap();
+                }
+                readme.push('\n');
+            }
+        }
+        readme.push_str("## Environments\n\n");
+        match document.environments.is_empty() {
+            true => readme.push_str("None\n\n"),
+            false => {
+                readme.push_str("| Control plane | Environments |\n| --- | --- |\n");
+                for block in &document.environments {
+                    let names = block
+                        .environments
+                        .iter()
+                        .map(|env| cell(&env.name))
+                        .collect::<Vec<_>>();
+                    let url = cell(&block.control_plane_url);
+                    writeln!(readme, "| {} | {} |", url, names.join(", ")).unwrap();
+                }
+                readme.push('\n');
+            }
+        }
+        if let Some(sources) = self.sources.get(&application.name) {
+            readme.push_str("## Sources\n\n");
+            for source in sources {
+                writeln!(readme, "- {}", source.display()).unwrap();
+            }
+            readme.push('\n');
+        }
+        readme.push_str(GENERATED_PREFIX);
+        readme.push_str(MANAGED_BY);
+        if let Some(generated_at) = &self.generated_at {
+            write!(readme, " at {}", generated_at).unwrap();
+        }
+        writeln!(
+            readme,
+            " from the same data as {}, do not edit_",
+            document.file_name()
+        )
+        .unwrap();
+        readme
+    }
+
+    ///Whether `existing` holds the `rendered` README apart from the time
+    ///it was generated at, so reruns leave it alone.
+    ///
+    ///```
+    ///use subscription_migrator::readme::Readme;
+    ///
+    ///let rendered = "# checkout\n\n_Generated by subscription-migrator at 2026-10-15T08:00:00Z_\n";
+    ///let existing = "# checkout\n\n_Generated by subscription-migrator at 2026-10-01T08:00:00Z_\n";
+    ///assert!(Readme::is_current(existing, rendered));
+    ///assert!(!Readme::is_current("# billing\n", rendered));
+    ///```
+    pub fn is_current(existing: &str, rendered: &str) -> bool {
+        let content = |readme: &str| {
+            readme
+                .lines()
+                .filter(|line| !line.starts_with(GENERATED_PREFIX))
+                .map(str::to_string)
+                .collect::<Vec<_>>()
+        };
+        content(existing) == content(rendered)
+    }
+}
+
+///`text` safe to put in a table cell.
+fn cell(text: &str) -> String {
+    text.replace('|', "\\|")
+}
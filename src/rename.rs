@@ -0,0 +1,147 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+    path::Path,
+};
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+
+use crate::migrate::XmlApplication;
+
+///Old to new names, read from a TOML or YAML file with an `applications`,
+///`apis` and `environments` section.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RenameMap {
+    #[serde(default)]
+    applications: BTreeMap<String, String>,
+    #[serde(default)]
+    apis: BTreeMap<String, String>,
+    #[serde(default)]
+    environments: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Default)]
+pub struct RenameReport {
+    ///(section, old, new) with the number of replaced occurrences
+    applied: BTreeMap<(&'static str, String, String), usize>,
+    ///New application name with the distinct original names it was given,
+    ///a merge if there are several
+    originals: BTreeMap<String, BTreeSet<String>>,
+    ///(section, old) entries that never matched anything
+    unmatched: Vec<(&'static str, String)>,
+}
+
+impl RenameMap {
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read rename map {:?}", path))?;
+        let map = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("toml") => toml::from_str(&content)?,
+            _ => serde_yaml::from_str(&content)?,
+        };
+        Ok(map)
+    }
+
+    pub fn apply(&self, applications: &mut [XmlApplication]) -> RenameReport {
+        let mut report = RenameReport::default();
+
+        for app in applications.iter_mut() {
+            let original = app.name.clone();
+            rename(
+                &self.applications,
+                "applications",
+                &mut app.name,
+                &mut report,
+            );
+            report
+                .originals
+                .entry(app.name.clone())
+                .or_default()
+                .insert(original);
+
+            for sub in &mut app.apis {
+                rename(&self.apis, "apis", &mut sub.api_name, &mut report);
+                for env in &mut sub.env {
+                    rename(&self.environments, "environments", env, &mut report);
+                }
+            }
+        }
+
+        for (section, map) in [
+            ("applications", &self.applications),
+            ("apis", &self.apis),
+            ("environments", &self.environments),
+        ] {
+            for old in map.keys() {
+                let matched = report
+                    .applied
+                    .keys()
+                    .any(|(applied_section, applied_old, _)| {
+                        *applied_section == section && applied_old == old
+                    });
+                if !matched {
+                    report.unmatched.push((section, old.clone()));
+                }
+            }
+        }
+
+        report
+    }
+}
+
+fn rename(
+    map: &BTreeMap<String, String>,
+    section: &'static str,
+    value: &mut String,
+    report: &mut RenameReport,
+) {
+    if let Some(new) = map.get(value.as_str()) {
+        *report
+            .applied
+            .entry((section, value.clone(), new.clone()))
+            .or_default() += 1;
+        value.clone_from(new);
+    }
+}
+
+impl RenameReport {
+    ///Adds the report of the next applications the map was applied to. An
+    ///entry is only unmatched if it matched nothing in either.
+    pub fn merge(&mut self, other: RenameReport) {
+        for (rename, count) in other.applied {
+            *self.applied.entry(rename).or_default() += count;
+        }
+        for (new, names) in other.originals {
+            self.originals.entry(new).or_default().extend(names);
+        }
+        self.unmatched
+            .retain(|entry| other.unmatched.contains(entry));
+    }
+}
+
+impl fmt::Display for RenameReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for ((section, old, new), count) in &self.applied {
+            writeln!(f, "Renamed {} {:?} -> {:?} ({}x)", section, old, new, count)?;
+        }
+        for (new, names) in self.originals.iter().filter(|(_, names)| names.len() > 1) {
+            let names = names.iter().cloned().collect::<Vec<_>>();
+            writeln!(
+                f,
+                "Merged applications [{}] into {:?}",
+                names.join(", "),
+                new
+            )?;
+        }
+        for (section, old) in &self.unmatched {
+            writeln!(
+                f,
+                "warning: rename map entry {} {:?} matched nothing",
+                section, old
+            )?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,87 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use serde::Serialize;
+
+use crate::{
+    convert::DroppedEnvironments,
+    directory_overrides::AppliedDirectoryOverride,
+    expect::Expectations,
+    inventory::ElementInventory,
+    migrate::write_atomic,
+    naming::NameFix,
+    scan::Candidates,
+    selection::{SkippedApplication, UnexpectedEnvironments, UnmappedApplication},
+    stats::ApiFanout,
+    timing::Timings,
+    validate::Finding,
+    version_overrides::AppliedOverride,
+};
+
+///Everything a bulk run found out besides the files it wrote, written as
+///JSON with `--report`.
+#[derive(Debug, Default, Serialize)]
+pub struct MigrationReport {
+    pub files_written: Vec<PathBuf>,
+    ///Elements of the subscribe.xml files the migrator doesn't read
+    pub unknown_elements: ElementInventory,
+    ///Names changed by `--fix-names`
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub name_fixes: Vec<NameFix>,
+    ///Versions changed by `--override-versions`
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub version_overrides: Vec<AppliedOverride>,
+    ///Override files next to the exports and what they changed
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub directory_overrides: Vec<AppliedDirectoryOverride>,
+    ///Applications left out by `--allow-list`, `--deny-list` and
+    ///`--include-tag`
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub skipped_by_policy: Vec<SkippedApplication>,
+    ///Applications with only unmapped environments, skipped unless
+    ///`--migrate-unmapped` is given
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub unmapped_only: Vec<UnmappedApplication>,
+    ///Warnings of the checks, e.g. `--check-version-parity`
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub findings: Vec<Finding>,
+    ///APIs subscribed by more applications than `--api-fanout-threshold`
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub api_fanout: Vec<ApiFanout>,
+    ///Applications whose environments don't fit `--environments`
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub unexpected_environments: Vec<UnexpectedEnvironments>,
+    ///What `--drop-env` removed, when given
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dropped_environments: Option<DroppedEnvironments>,
+    ///Applications parsed from the subscribe.xml at the root of the scanned
+    ///path with `--include-root`
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub root_applications: Vec<String>,
+    ///Exports deleted in git since the commit of `--only-changed`, whose
+    ///applications may need decommissioning
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub deleted_exports: Vec<PathBuf>,
+    ///The counts of `--expect-applications` and `--expect-directories`
+    #[serde(skip_serializing_if = "Expectations::is_empty")]
+    pub expectations: Expectations,
+    ///Every directory the scan saw and what happened to it, with
+    ///`--report-candidates`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub candidates: Option<Candidates>,
+    #[serde(flatten)]
+    pub timings: Timings,
+}
+
+impl MigrationReport {
+    pub fn new(timings: Timings) -> Self {
+        MigrationReport {
+            timings,
+            ..Default::default()
+        }
+    }
+
+    pub fn write(&self, path: &Path) -> Result<()> {
+        write_atomic(path, serde_json::to_string_pretty(self)?.as_bytes())
+    }
+}
@@ -0,0 +1,314 @@
+use std::{
+    fmt,
+    fs::File,
+    io::{self, BufRead, BufReader, Read},
+    path::Path,
+    thread,
+    time::Duration,
+};
+
+use anyhow::Result;
+
+///How often IO steps are retried on transient errors, like a flaky network
+///filesystem returning EIO. The default doesn't retry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    pub retries: u32,
+    ///Delay before the first retry, doubled for every further one
+    pub delay: Duration,
+    ///Print every retry
+    pub verbose: bool,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            retries: 0,
+            delay: Duration::from_millis(100),
+            verbose: false,
+        }
+    }
+}
+
+///Whether `error` may go away when the step is repeated. Missing files and
+///denied permissions never do.
+pub fn is_transient(error: &io::Error) -> bool {
+    #[cfg(unix)]
+    if error.raw_os_error() == Some(libc::EIO) {
+        return true;
+    }
+    matches!(
+        error.kind(),
+        io::ErrorKind::Interrupted
+            | io::ErrorKind::TimedOut
+            | io::ErrorKind::StaleNetworkFileHandle
+    )
+}
+
+impl RetryPolicy {
+    ///Runs `step` until it succeeds, fails with an error that isn't a
+    ///transient IO error or the retries are used up. `what` names the step
+    ///in the retry messages.
+    ///
+    ///```
+    ///use std::io::{self, Read};
+    ///use std::time::Duration;
+    ///use subscription_migrator::retry::RetryPolicy;
+    ///
+    /////A reader failing the first `failures` reads with `kind`
+    ///struct Flaky {
+    ///    failures: usize,
+    ///    kind: io::ErrorKind,
+    ///}
+    ///impl Read for Flaky {
+    ///    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+    ///        if self.failures > 0 {
+    ///            self.failures -= 1;
+    ///            return Err(self.kind.into());
+    ///        }
+    ///        buf[0] = b'x';
+    ///        Ok(1)
+    ///    }
+    ///}
+    ///let policy = RetryPolicy {
+    ///    retries: 2,
+    ///    delay: Duration::ZERO,
+    ///    ..Default::default()
+    ///};
+    ///let read = |reader: &mut Flaky| {
+    ///    policy.run("read", || {
+    ///        let mut buf = [0; 1];
+    ///        reader.read(&mut buf)?;
+    ///        Ok(buf[0])
+    ///    })
+    ///};
+    ///
+    ///let mut reader = Flaky { failures: 2, kind: io::ErrorKind::TimedOut };
+    ///assert_eq!(read(&mut reader)?, b'x');
+    ///let mut reader = Flaky { failures: 3, kind: io::ErrorKind::TimedOut };
+    ///assert!(read(&mut reader).is_err());
+    ///
+    ///// a missing file fails right away
+    ///let mut reader = Flaky { failures: 2, kind: io::ErrorKind::NotFound };
+    ///assert!(read(&mut reader).is_err());
+    ///assert_eq!(reader.failures, 1);
+    ///# anyhow::Ok(())
+    ///```
+    pub fn run<T>(
+        &self,
+        what: impl fmt::Display,
+        mut step: impl FnMut() -> Result<T>,
+    ) -> Result<T> {
+        let mut delay = self.delay;
+        let mut attempt = 0;
+        loop {
+            match step() {
+                Err(e) if attempt < self.retries && transient(&e) => {
+                    attempt += 1;
+                    if self.verbose {
+                        eprintln!(
+                            "Retrying {} in {:?} ({}/{}): {:#}",
+                            what, delay, attempt, self.retries, e
+                        );
+                    }
+                    thread::sleep(delay);
+                    delay = delay.saturating_mul(2);
+                }
+                result => return result,
+            }
+        }
+    }
+
+    ///Opens `path` and reads its first buffer, retrying both on transient
+    ///errors. The rest is read while it is parsed and isn't retried, as the
+    ///parser has already consumed part of it by then.
+    pub fn open(&self, path: &Path) -> Result<BufReader<File>> {
+        self.open_with(format_args!("reading {:?}", path), || File::open(path))
+    }
+
+    fn open_with<R: Read>(
+        &self,
+        what: impl fmt::Display,
+        mut open: impl FnMut() -> io::Result<R>,
+    ) -> Result<BufReader<R>> {
+        self.run(what, || {
+            let mut reader = BufReader::new(open()?);
+            reader.fill_buf()?;
+            Ok(reader)
+        })
+    }
+}
+
+///Whether any IO error in the chain of `error` is transient.
+fn transient(error: &anyhow::Error) -> bool {
+    error
+        .chain()
+        .filter_map(|cause| cause.downcast_ref::<io::Error>())
+        .any(is_transient)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use super::*;
+
+    const EXPORT: &[u8] = b"<subscriptions></subscriptions>";
+
+    ///Yields `EXPORT` in chunks of `chunk` bytes, failing the reads listed
+    ///in `failing` with `kind`
+    struct Flaky {
+        read: usize,
+        failing: Vec<usize>,
+        kind: io::ErrorKind,
+        chunk: usize,
+        position: usize,
+    }
+
+    impl Flaky {
+        fn new(failing: &[usize], kind: io::ErrorKind) -> Self {
+            Flaky {
+                read: 0,
+                failing: failing.to_vec(),
+                kind,
+                chunk: EXPORT.len(),
+                position: 0,
+            }
+        }
+    }
+
+    impl Read for Flaky {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.read += 1;
+            if self.failing.contains(&self.read) {
+                return Err(self.kind.into());
+            }
+            let end = EXPORT.len().min(self.position + self.chunk.min(buf.len()));
+            let read = end - self.position;
+            buf[..read].copy_from_slice(&EXPORT[self.position..end]);
+            self.position = end;
+            Ok(read)
+        }
+    }
+
+    fn policy(retries: u32) -> RetryPolicy {
+        RetryPolicy {
+            retries,
+            delay: Duration::ZERO,
+            verbose: false,
+        }
+    }
+
+    fn read_all(mut reader: impl Read) -> io::Result<Vec<u8>> {
+        let mut content = Vec::new();
+        reader.read_to_end(&mut content)?;
+        Ok(content)
+    }
+
+    #[test]
+    fn transient_open_errors_are_retried() {
+        let opens = Cell::new(0);
+        let reader = policy(2)
+            .open_with("reading", || {
+                opens.set(opens.get() + 1);
+                match opens.get() {
+                    1 | 2 => Err(io::ErrorKind::Interrupted.into()),
+                    _ => Ok(Flaky::new(&[], io::ErrorKind::Interrupted)),
+                }
+            })
+            .unwrap();
+        assert_eq!(opens.get(), 3);
+        assert_eq!(read_all(reader).unwrap(), EXPORT);
+    }
+
+    #[test]
+    fn a_failed_initial_read_reopens_the_file() {
+        let opens = Cell::new(0);
+        let reader = policy(1)
+            .open_with("reading", || {
+                opens.set(opens.get() + 1);
+                let failing: &[usize] = match opens.get() {
+                    1 => &[1],
+                    _ => &[],
+                };
+                Ok(Flaky::new(failing, io::ErrorKind::TimedOut))
+            })
+            .unwrap();
+        assert_eq!(opens.get(), 2);
+        assert_eq!(read_all(reader).unwrap(), EXPORT);
+    }
+
+    #[test]
+    fn retries_run_out() {
+        let opens = Cell::new(0);
+        let result = policy(2).open_with("reading", || {
+            opens.set(opens.get() + 1);
+            Ok(Flaky::new(&[1], io::ErrorKind::StaleNetworkFileHandle))
+        });
+        assert!(result.is_err());
+        assert_eq!(opens.get(), 3);
+    }
+
+    #[test]
+    fn errors_that_stay_are_not_retried() {
+        for kind in [io::ErrorKind::NotFound, io::ErrorKind::PermissionDenied] {
+            let opens = Cell::new(0);
+            let result = policy(3).open_with("reading", || -> io::Result<Flaky> {
+                opens.set(opens.get() + 1);
+                Err(kind.into())
+            });
+            assert!(result.is_err(), "{:?}", kind);
+            assert_eq!(opens.get(), 1, "{:?}", kind);
+        }
+    }
+
+    #[test]
+    fn only_the_first_buffer_is_read_up_front() {
+        let opens = Cell::new(0);
+        let reader = policy(3)
+            .open_with("reading", || {
+                opens.set(opens.get() + 1);
+                Ok(Flaky {
+                    chunk: 4,
+                    ..Flaky::new(&[], io::ErrorKind::Interrupted)
+                })
+            })
+            .unwrap();
+        // only the first chunk has been read so far
+        assert_eq!(reader.buffer(), &EXPORT[..4]);
+        assert_eq!(reader.get_ref().read, 1);
+        assert_eq!(read_all(reader).unwrap(), EXPORT);
+        assert_eq!(opens.get(), 1);
+    }
+
+    #[test]
+    fn a_failure_while_parsing_is_not_retried() {
+        let opens = Cell::new(0);
+        let reader = policy(3)
+            .open_with("reading", || {
+                opens.set(opens.get() + 1);
+                Ok(Flaky {
+                    chunk: 4,
+                    ..Flaky::new(&[3], io::ErrorKind::TimedOut)
+                })
+            })
+            .unwrap();
+        assert_eq!(
+            read_all(reader).unwrap_err().kind(),
+            io::ErrorKind::TimedOut
+        );
+        assert_eq!(opens.get(), 1);
+    }
+
+    #[test]
+    fn open_reads_a_file_from_its_start() {
+        let temp = std::env::temp_dir().join(format!("retry-open-{}", std::process::id()));
+        std::fs::write(&temp, EXPORT).unwrap();
+        let reader = policy(0).open(&temp);
+        std::fs::remove_file(&temp).unwrap();
+        assert_eq!(read_all(reader.unwrap()).unwrap(), EXPORT);
+
+        let missing = policy(3).open(&temp).unwrap_err();
+        assert!(format!("{:#}", missing).contains("No such file"));
+    }
+}
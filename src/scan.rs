@@ -0,0 +1,658 @@
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    fmt,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use anyhow::Result;
+use serde::Serialize;
+
+use crate::{
+    formatting::SUBSCRIPTION_FILE,
+    ignore::IgnoreFile,
+    input::InputFormat,
+    migrate::XmlApplication,
+    pipeline::{self, SourceDir},
+    timing::Timings,
+};
+
+///Outcome of scanning the directories directly below the scan root.
+#[derive(Debug, Default)]
+pub struct Scan {
+    ///Matching directories, sorted so that slices are stable across runs
+    pub matched: Vec<PathBuf>,
+    pub hidden: usize,
+    pub ignored: usize,
+    pub not_matching: usize,
+    ///Matching directories that are the output path or were generated into it
+    pub in_output: usize,
+    ///The first few names of directories not matching the prefix, sorted
+    pub not_matching_sample: Vec<String>,
+    ///Matched directories dropped as another name of a directory that is
+    ///migrated, each with the one that is kept
+    pub aliases: Vec<(PathBuf, PathBuf)>,
+    ///Every directory the scan saw and what happened to it
+    pub candidates: Candidates,
+}
+
+///What happened to a directory a scan saw.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "outcome", rename_all = "kebab-case")]
+pub enum CandidateOutcome {
+    Hidden,
+    ///Excluded by the `.migratorignore`
+    ExcludedByPattern,
+    NotMatching,
+    ///The output path or generated into it
+    InOutput,
+    ///Another name of the directory `of`, which is migrated instead
+    DedupedSymlink {
+        of: PathBuf,
+    },
+    ///Matched and not yet processed
+    Matched,
+    ///Left out by `--offset`, `--limit` or `--sample`
+    FilteredBySlice,
+    ///Not modified since `--since`
+    FilteredBySince,
+    ///Unchanged in git since the commit of `--only-changed`
+    UnchangedInGit,
+    MissingSubscribeXml,
+    ///The export is a symlink to nothing
+    DanglingSymlink,
+    ///The export is a directory
+    ExportIsDirectory,
+    ///The export can't be read
+    PermissionDenied,
+    ParseFailed {
+        error: String,
+    },
+    Processed,
+}
+
+impl CandidateOutcome {
+    ///The outcome of a matched directory that failed to parse.
+    pub fn parse_failed(error: &anyhow::Error) -> Self {
+        let unreadable = error
+            .chain()
+            .find_map(|cause| cause.downcast_ref::<UnreadableExport>());
+        match unreadable.map(|unreadable| &unreadable.problem) {
+            Some(ExportProblem::Missing) => CandidateOutcome::MissingSubscribeXml,
+            Some(ExportProblem::DanglingSymlink { .. }) => CandidateOutcome::DanglingSymlink,
+            Some(ExportProblem::IsDirectory) => CandidateOutcome::ExportIsDirectory,
+            Some(ExportProblem::PermissionDenied) => CandidateOutcome::PermissionDenied,
+            None => CandidateOutcome::ParseFailed {
+                error: format!("{:#}", error),
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Candidate {
+    pub directory: PathBuf,
+    #[serde(flatten)]
+    pub outcome: CandidateOutcome,
+}
+
+///The directories a scan saw, sorted, written to the report with
+///`--report-candidates`.
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(transparent)]
+pub struct Candidates(pub Vec<Candidate>);
+
+impl Candidates {
+    pub fn push(&mut self, directory: PathBuf, outcome: CandidateOutcome) {
+        self.0.push(Candidate { directory, outcome });
+    }
+
+    ///Sets the outcome of `directory`, if the scan saw it.
+    pub fn classify(&mut self, directory: &Path, outcome: CandidateOutcome) {
+        if let Some(candidate) = self.0.iter_mut().find(|c| c.directory == directory) {
+            candidate.outcome = outcome;
+        }
+    }
+
+    ///Marks every directory that is still only matched as processed.
+    pub fn finish(&mut self) {
+        for candidate in &mut self.0 {
+            if candidate.outcome == CandidateOutcome::Matched {
+                candidate.outcome = CandidateOutcome::Processed;
+            }
+        }
+    }
+
+    fn sort(&mut self) {
+        self.0.sort_by(|a, b| a.directory.cmp(&b.directory));
+    }
+}
+
+///How many non-matching directory names [`NothingToMigrate`] shows
+const NOT_MATCHING_SHOWN: usize = 5;
+
+impl fmt::Display for Scan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} matched, {} hidden, {} ignored, {} not matching the prefix",
+            self.matched.len(),
+            self.hidden,
+            self.ignored,
+            self.not_matching
+        )?;
+        if self.in_output > 0 {
+            write!(f, ", {} in the output path", self.in_output)?;
+        }
+        if !self.aliases.is_empty() {
+            write!(f, ", {} aliases of another match", self.aliases.len())?;
+        }
+        Ok(())
+    }
+}
+
+impl Scan {
+    ///Drops matched directories that belong to the output: the output path
+    ///itself, or a generated directory inside it, recognized by holding a
+    ///subscription.yaml but no subscribe.xml. Paths are compared canonically,
+    ///so nesting through symlinks is caught too. This keeps a run whose output
+    ///is inside the scanned path from parsing its own results.
+    pub fn exclude_output(&mut self, output: &Path) {
+        let Ok(output) = output.canonicalize() else {
+            return;
+        };
+        let mut excluded = Vec::new();
+        self.matched.retain(|directory| {
+            let Ok(canonical) = directory.canonicalize() else {
+                return true;
+            };
+            let generated =
+                directory.join(SUBSCRIPTION_FILE).is_file() && !subscribe_file(directory).exists();
+            let keep = canonical != output && !(canonical.starts_with(&output) && generated);
+            if !keep {
+                excluded.push(directory.clone());
+            }
+            keep
+        });
+        self.in_output += excluded.len();
+        for directory in excluded {
+            self.candidates
+                .classify(&directory, CandidateOutcome::InOutput);
+        }
+    }
+
+    ///Drops matched directories that resolve to the same canonical path as
+    ///an earlier one, like a symlink to another matching directory, so that
+    ///no export is parsed twice. Directories that can't be canonicalized are
+    ///kept.
+    pub fn dedupe_sources(&mut self) {
+        let mut kept = HashMap::<PathBuf, PathBuf>::new();
+        let mut aliases = Vec::new();
+        self.matched.retain(|directory| {
+            let Ok(canonical) = directory.canonicalize() else {
+                return true;
+            };
+            match kept.entry(canonical) {
+                Entry::Occupied(first) => {
+                    aliases.push((directory.clone(), first.get().clone()));
+                    false
+                }
+                Entry::Vacant(entry) => {
+                    entry.insert(directory.clone());
+                    true
+                }
+            }
+        });
+        for (alias, kept) in &aliases {
+            let outcome = CandidateOutcome::DedupedSymlink { of: kept.clone() };
+            self.candidates.classify(alias, outcome);
+        }
+        self.aliases.extend(aliases);
+    }
+
+    ///Adds the outcome of scanning another root.
+    pub fn merge(&mut self, other: Scan) {
+        self.matched.extend(other.matched);
+        self.matched.sort();
+        self.hidden += other.hidden;
+        self.ignored += other.ignored;
+        self.not_matching += other.not_matching;
+        self.in_output += other.in_output;
+        self.aliases.extend(other.aliases);
+        self.candidates.0.extend(other.candidates.0);
+        self.candidates.sort();
+        self.not_matching_sample.extend(other.not_matching_sample);
+        self.not_matching_sample.sort();
+        self.not_matching_sample.truncate(NOT_MATCHING_SHOWN);
+    }
+}
+
+///Returned when a bulk run is left without directories to migrate.
+#[derive(Debug)]
+pub enum NothingToMigrate {
+    NoMatches {
+        path: PathBuf,
+        prefix: String,
+        not_matching_sample: Vec<String>,
+    },
+    ///Directories matched but `--offset`, `--limit`, `--sample` or `--since`
+    ///selected none of them
+    AllFiltered { path: PathBuf, matched: usize },
+}
+
+impl NothingToMigrate {
+    pub fn no_matches(path: &Path, prefix: &str, scan: &Scan) -> Self {
+        NothingToMigrate::NoMatches {
+            path: path.to_path_buf(),
+            prefix: prefix.to_string(),
+            not_matching_sample: scan.not_matching_sample.clone(),
+        }
+    }
+}
+
+impl fmt::Display for NothingToMigrate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NothingToMigrate::NoMatches {
+                path,
+                prefix,
+                not_matching_sample,
+            } => {
+                write!(f, "No directory below {:?} starts with {:?}", path, prefix)?;
+                if !not_matching_sample.is_empty() {
+                    write!(f, ", found e.g. {}", not_matching_sample.join(", "))?;
+                }
+                Ok(())
+            }
+            NothingToMigrate::AllFiltered { path, matched } => write!(
+                f,
+                "All {} matching directories below {:?} were filtered out",
+                matched, path
+            ),
+        }
+    }
+}
+
+impl std::error::Error for NothingToMigrate {}
+
+///What the prefix of a scan is matched against.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MatchMode {
+    ///The names of the directories directly below the scan root
+    #[default]
+    Name,
+    ///The `/` separated paths below the scan root. Only the directories
+    ///on the way to the prefix are descended into, so `team-a/payments-`
+    ///matches `team-a/payments-eu` but not `team-b/payments-eu`.
+    Path,
+}
+
+///Scans the directories below `path` for names, or with [`MatchMode::Path`]
+///relative paths, starting with `prefix`. Dot-directories are skipped
+///unless `include_hidden` is set and a `.migratorignore` at the scan root
+///excludes further directories, matched against their relative path.
+///
+///```
+///use subscription_migrator::scan::{scan_directories, MatchMode};
+///
+///let root = std::env::temp_dir().join(format!("match-path-{}", std::process::id()));
+///for dir in ["team-a/payments-eu", "team-a/payments-us", "team-b/payments-eu", "payments-old"] {
+///    std::fs::create_dir_all(root.join(dir))?;
+///}
+///std::fs::write(root.join(".migratorignore"), "/team-a/payments-us\n")?;
+///
+///let by_name = scan_directories(&root, "payments-", false, MatchMode::Name)?;
+///assert_eq!(by_name.matched, [root.join("payments-old")]);
+///
+///let by_path = scan_directories(&root, "team-a/payments-", false, MatchMode::Path)?;
+///assert_eq!(by_path.matched, [root.join("team-a/payments-eu")]);
+///assert_eq!(by_path.ignored, 1);
+///assert_eq!(by_path.not_matching_sample, ["payments-old", "team-b"]);
+///# std::fs::remove_dir_all(&root)?;
+///# anyhow::Ok(())
+///```
+pub fn scan_directories(
+    path: &Path,
+    prefix: &str,
+    include_hidden: bool,
+    mode: MatchMode,
+) -> Result<Scan> {
+    let ignore_file = IgnoreFile::load(path)?.unwrap_or_default();
+    let mut scan = Scan::default();
+
+    let mut pending = vec![(path.to_path_buf(), String::new())];
+    while let Some((directory, relative)) = pending.pop() {
+        for entry in std::fs::read_dir(&directory)? {
+            let entry = entry?;
+            let dir_path = entry.path();
+            if !dir_path.is_dir() {
+                continue;
+            }
+
+            let file_name = entry.file_name();
+            let name = file_name.to_string_lossy();
+            let relative = match relative.is_empty() {
+                true => name.to_string(),
+                false => format!("{}/{}", relative, name),
+            };
+            let matched = match mode {
+                MatchMode::Name => name.as_ref(),
+                MatchMode::Path => relative.as_str(),
+            };
+            let outcome = if name.starts_with('.') && !include_hidden {
+                scan.hidden += 1;
+                CandidateOutcome::Hidden
+            } else if ignore_file.is_ignored(Path::new(&relative), true) {
+                scan.ignored += 1;
+                CandidateOutcome::ExcludedByPattern
+            } else if matched.starts_with(prefix) {
+                scan.matched.push(dir_path.clone());
+                CandidateOutcome::Matched
+            } else if mode == MatchMode::Path && prefix.starts_with(&format!("{}/", relative)) {
+                pending.push((dir_path, relative));
+                continue;
+            } else {
+                scan.not_matching += 1;
+                scan.not_matching_sample.push(matched.to_string());
+                CandidateOutcome::NotMatching
+            };
+            scan.candidates.push(dir_path, outcome);
+        }
+    }
+    scan.matched.sort();
+    scan.candidates.sort();
+    scan.not_matching_sample.sort();
+    scan.not_matching_sample.truncate(NOT_MATCHING_SHOWN);
+
+    Ok(scan)
+}
+
+///Parses the export of every directory, see [`pipeline::parse`].
+pub fn parse_directories(paths: &[PathBuf], format: InputFormat) -> Result<Vec<XmlApplication>> {
+    let sources = paths
+        .iter()
+        .map(|path| SourceDir::new(path.clone()).format(format))
+        .collect::<Vec<_>>();
+    Ok(pipeline::parse(&sources, &mut Timings::default())?.applications)
+}
+
+///The export of a source directory: its subscribe.xml, a subscribe.json if
+///there is only that, or the only zip archive if there is neither.
+pub fn subscribe_file(directory: &Path) -> PathBuf {
+    let xml = directory.join("subscribe.xml");
+    if xml.exists() {
+        return xml;
+    }
+    let json = directory.join("subscribe.json");
+    if json.exists() {
+        return json;
+    }
+    let archives = std::fs::read_dir(directory)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "zip") && path.is_file())
+        .collect::<Vec<_>>();
+    match <[PathBuf; 1]>::try_from(archives) {
+        Ok([archive]) => archive,
+        Err(_) => xml,
+    }
+}
+
+///Why the export of a directory can't be opened.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExportProblem {
+    Missing,
+    DanglingSymlink { target: PathBuf },
+    IsDirectory,
+    PermissionDenied,
+}
+
+///Returned before opening an export that can't be read, with the directory
+///being processed and how to fix it.
+#[derive(Debug)]
+pub struct UnreadableExport {
+    pub directory: PathBuf,
+    pub file: PathBuf,
+    pub problem: ExportProblem,
+}
+
+impl UnreadableExport {
+    ///Checks that `file`, the export of `directory`, is a file that can be
+    ///opened. Other errors are left to reading it.
+    ///
+    ///```
+    ///use subscription_migrator::scan::{ExportProblem, UnreadableExport};
+    ///
+    ///let root = std::env::temp_dir().join(format!("unreadable-export-{}", std::process::id()));
+    ///let file = root.join("subscribe.xml");
+    ///std::fs::create_dir_all(&root)?;
+    ///let problem = |file| UnreadableExport::check(&root, file).map_err(|e| e.problem);
+    ///assert_eq!(problem(&file), Err(ExportProblem::Missing));
+    ///
+    ///std::fs::create_dir(&file)?;
+    ///assert_eq!(problem(&file), Err(ExportProblem::IsDirectory));
+    ///std::fs::remove_dir(&file)?;
+    ///
+    ///# #[cfg(unix)]
+    ///# {
+    ///std::os::unix::fs::symlink("gone.xml", &file)?;
+    ///assert_eq!(
+    ///    problem(&file),
+    ///    Err(ExportProblem::DanglingSymlink { target: "gone.xml".into() })
+    ///);
+    ///let error = UnreadableExport::check(&root, &file).unwrap_err().to_string();
+    ///assert!(error.contains("a symlink to \"gone.xml\", which doesn't exist"));
+    ///std::fs::remove_file(&file)?;
+    ///# }
+    ///
+    ///std::fs::write(&file, "<applications/>")?;
+    ///assert_eq!(problem(&file), Ok(()));
+    ///# std::fs::remove_dir_all(&root)?;
+    ///# anyhow::Ok(())
+    ///```
+    pub fn check(directory: &Path, file: &Path) -> Result<(), UnreadableExport> {
+        use std::io::ErrorKind;
+
+        let problem = match std::fs::metadata(file) {
+            Ok(metadata) if metadata.is_dir() => ExportProblem::IsDirectory,
+            Ok(_) => match std::fs::File::open(file) {
+                Err(e) if e.kind() == ErrorKind::PermissionDenied => {
+                    ExportProblem::PermissionDenied
+                }
+                _ => return Ok(()),
+            },
+            Err(e) if e.kind() == ErrorKind::PermissionDenied => ExportProblem::PermissionDenied,
+            Err(e) => match std::fs::read_link(file) {
+                Ok(target) => ExportProblem::DanglingSymlink { target },
+                Err(_) if e.kind() == ErrorKind::NotFound => ExportProblem::Missing,
+                Err(_) => return Ok(()),
+            },
+        };
+        Err(UnreadableExport {
+            directory: directory.to_path_buf(),
+            file: file.to_path_buf(),
+            problem,
+        })
+    }
+}
+
+impl fmt::Display for UnreadableExport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let UnreadableExport {
+            directory,
+            file,
+            problem,
+        } = self;
+        match problem {
+            ExportProblem::Missing => write!(
+                f,
+                "{:?} has no export, expected {:?}. Add the export or exclude the directory in a .migratorignore",
+                directory, file
+            ),
+            ExportProblem::DanglingSymlink { target } => write!(
+                f,
+                "The export {:?} of {:?} is a symlink to {:?}, which doesn't exist. Fix or remove the link",
+                file, directory, target
+            ),
+            ExportProblem::IsDirectory => write!(
+                f,
+                "The export {:?} of {:?} is a directory, not a file. Replace it with the exported file",
+                file, directory
+            ),
+            ExportProblem::PermissionDenied => write!(
+                f,
+                "The export {:?} of {:?} can't be read, permission denied. Grant the migrator read access to it",
+                file, directory
+            ),
+        }
+    }
+}
+
+impl std::error::Error for UnreadableExport {}
+
+///Which part of the matched directories a run processes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Slice {
+    Range { offset: usize, limit: Option<usize> },
+    Sample { size: usize, seed: u64 },
+}
+
+impl Slice {
+    ///Selects the directories of this slice from the sorted `paths`. Samples
+    ///are returned sorted as well.
+    pub fn select(self, mut paths: Vec<PathBuf>) -> Vec<PathBuf> {
+        match self {
+            Slice::Range { offset, limit } => {
+                let remaining = paths.into_iter().skip(offset);
+                match limit {
+                    Some(limit) => remaining.take(limit).collect(),
+                    None => remaining.collect(),
+                }
+            }
+            Slice::Sample { size, seed } => {
+                let size = size.min(paths.len());
+                let mut rng = SplitMix64(seed);
+                // partial Fisher-Yates, the first `size` entries are the sample
+                for i in 0..size {
+                    let j = i + (rng.next() % (paths.len() - i) as u64) as usize;
+                    paths.swap(i, j);
+                }
+                paths.truncate(size);
+                paths.sort();
+                paths
+            }
+        }
+    }
+
+    ///Describes the processed slice so a follow-up run can continue after it.
+    pub fn describe(self, selected: usize, total: usize) -> String {
+        match self {
+            Slice::Range { offset, .. } if selected == 0 => {
+                format!("Processed no directories (offset {} of {})", offset, total)
+            }
+            Slice::Range { offset, .. } => format!(
+                "Processed directories {}..{} of {} (next offset: {})",
+                offset + 1,
+                offset + selected,
+                total,
+                offset + selected
+            ),
+            Slice::Sample { seed, .. } => format!(
+                "Processed a sample of {} of {} directories (seed {})",
+                selected, total, seed
+            ),
+        }
+    }
+}
+
+///Small deterministic generator so samples are reproducible across platforms
+///and releases for a given seed.
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+}
+
+///Parses durations such as `500ms`, `90m`, `24h` or `7d`.
+pub fn parse_duration(value: &str) -> std::result::Result<Duration, String> {
+    let unit_start = value
+        .find(|c: char| !c.is_ascii_digit())
+        .ok_or_else(|| format!("missing unit in duration {:?}", value))?;
+    let (amount, unit) = value.split_at(unit_start);
+    let amount: u64 = amount
+        .parse()
+        .map_err(|_| format!("expected a duration, got {:?}", value))?;
+    let seconds = match unit {
+        "ms" => return Ok(Duration::from_millis(amount)),
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        _ => {
+            return Err(format!(
+                "unknown duration unit {:?}, use ms, s, m, h, d or w",
+                unit
+            ))
+        }
+    };
+    Ok(Duration::from_secs(amount.saturating_mul(seconds)))
+}
+
+///Parses `--since` values: RFC 3339 timestamps or a duration before now such
+///as `90m`, `24h` or `7d`.
+pub fn parse_since(value: &str) -> std::result::Result<SystemTime, String> {
+    if let Ok(timestamp) = chrono::DateTime::parse_from_rfc3339(value) {
+        return Ok(timestamp.into());
+    }
+
+    let duration = parse_duration(value)
+        .map_err(|e| format!("expected an RFC 3339 timestamp or a duration: {}", e))?;
+    SystemTime::now()
+        .checked_sub(duration)
+        .ok_or_else(|| format!("duration {:?} is too long", value))
+}
+
+///Splits the directories into those whose subscribe.xml was modified at or
+///after `since` and those that are up to date. Directories without a readable
+///subscribe.xml are kept so parsing reports them.
+pub fn partition_modified_since(
+    paths: Vec<PathBuf>,
+    since: SystemTime,
+) -> (Vec<PathBuf>, Vec<PathBuf>) {
+    paths.into_iter().partition(|path| {
+        std::fs::metadata(subscribe_file(path))
+            .and_then(|metadata| metadata.modified())
+            .map_or(true, |modified| modified >= since)
+    })
+}
+
+///Recursively collects the files called `file_name` below `root`, skipping
+///dot-directories. The result is sorted.
+pub fn find_files_named(root: &Path, file_name: &str) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let mut pending = vec![root.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        for entry in std::fs::read_dir(&dir)? {
+            let entry = entry?;
+            let path = entry.path();
+            if entry.file_type()?.is_dir() {
+                if !entry.file_name().to_string_lossy().starts_with('.') {
+                    pending.push(path);
+                }
+            } else if entry.file_name() == file_name {
+                files.push(path);
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
@@ -0,0 +1,161 @@
+use std::collections::BTreeMap;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    migrate::{YamlApi, YamlApiSubscription},
+    secret::Credentials,
+    versions::compare_versions,
+    yaml_quoting::quote_yaml_11_scalars,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SchemaVersion {
+    ///Environments and APIs listed separately per application
+    V1,
+    ///APIs grouped per environment
+    V2,
+}
+
+///The `schemaVersion`s that are read, the last one is written
+pub const SCHEMA_VERSIONS: [u64; 2] = [1, 2];
+
+///Detects the schema of a subscription document from its explicit
+///`schemaVersion` or, for older files without one, from its shape.
+pub fn detect_schema(content: &str) -> Result<SchemaVersion> {
+    let value: serde_yaml::Value = serde_yaml::from_str(content)?;
+    match value
+        .get("schemaVersion")
+        .and_then(serde_yaml::Value::as_u64)
+    {
+        Some(1) => return Ok(SchemaVersion::V1),
+        Some(2) => return Ok(SchemaVersion::V2),
+        Some(version) => return Err(anyhow::anyhow!("Unsupported schemaVersion {}", version)),
+        None => {}
+    }
+
+    if value.get("subscriptions").is_some() {
+        Ok(SchemaVersion::V1)
+    } else if value.get("application").is_some() {
+        Ok(SchemaVersion::V2)
+    } else {
+        Err(anyhow::anyhow!("Not a subscription document"))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "camelCase")]
+pub struct YamlApiSubscriptionV2 {
+    pub schema_version: u32,
+    pub application: YamlApplicationV2,
+    pub environments: Vec<YamlEnvironmentV2>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct YamlApplicationV2 {
+    pub name: String,
+    pub description: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub credentials: Option<Credentials>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "camelCase")]
+pub struct YamlEnvironmentV2 {
+    pub name: String,
+    pub control_plane_url: String,
+    pub apis: Vec<YamlApi>,
+}
+
+impl YamlApiSubscriptionV2 {
+    ///Sorts environments by name and APIs by name and version.
+    pub fn canonicalize(&mut self) {
+        self.environments.sort_by(|a, b| {
+            a.name
+                .cmp(&b.name)
+                .then_with(|| a.control_plane_url.cmp(&b.control_plane_url))
+        });
+        for env in &mut self.environments {
+            env.apis.sort_by(|a, b| {
+                a.name
+                    .cmp(&b.name)
+                    .then_with(|| compare_versions(&a.version, &b.version))
+                    // versions equal as numbers, like v3 and v3.0, in a fixed order
+                    .then_with(|| a.version.cmp(&b.version))
+            });
+            env.apis.dedup();
+        }
+    }
+
+    pub fn to_yaml(&self) -> Result<String> {
+        let mut document = self.clone();
+        document.canonicalize();
+        Ok(quote_yaml_11_scalars(&serde_yaml::to_string(&document)?))
+    }
+}
+
+impl TryFrom<&YamlApiSubscription> for YamlApiSubscriptionV2 {
+    type Error = anyhow::Error;
+
+    ///v1 doesn't record which API belongs to which environment, so every
+    ///environment gets all APIs of the application. APIs without any
+    ///environment can't be represented in v2 and fail the upgrade.
+    fn try_from(v1: &YamlApiSubscription) -> Result<Self> {
+        let application = &v1.subscription.application;
+        let mut environments: BTreeMap<(&str, &str), YamlEnvironmentV2> = BTreeMap::new();
+        for block in &v1.environments {
+            for env in &block.environments {
+                environments
+                    .entry((&env.name, &block.control_plane_url))
+                    .or_insert_with(|| YamlEnvironmentV2 {
+                        name: env.name.clone(),
+                        control_plane_url: block.control_plane_url.clone(),
+                        apis: Vec::new(),
+                    })
+                    .apis
+                    .extend(application.apis.iter().cloned());
+            }
+        }
+
+        if environments.is_empty() && !application.apis.is_empty() {
+            return Err(anyhow::anyhow!(
+                "Application {:?} has APIs but no environments, which v2 can't represent",
+                application.name
+            ));
+        }
+
+        let mut v2 = YamlApiSubscriptionV2 {
+            schema_version: 2,
+            application: YamlApplicationV2 {
+                name: application.name.clone(),
+                description: application.description.clone(),
+                credentials: application.credentials.clone(),
+            },
+            environments: environments.into_values().collect(),
+        };
+        v2.canonicalize();
+        Ok(v2)
+    }
+}
+
+///Outcome of upgrading a single document.
+pub enum Upgrade {
+    ///Already v2, nothing to do
+    Current,
+    Upgraded {
+        yaml: String,
+    },
+}
+
+pub fn upgrade_document(content: &str) -> Result<Upgrade> {
+    match detect_schema(content)? {
+        SchemaVersion::V2 => Ok(Upgrade::Current),
+        SchemaVersion::V1 => {
+            let v1: YamlApiSubscription = serde_yaml::from_str(content)?;
+            let yaml = YamlApiSubscriptionV2::try_from(&v1)?.to_yaml()?;
+            Ok(Upgrade::Upgraded { yaml })
+        }
+    }
+}
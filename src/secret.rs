@@ -0,0 +1,96 @@
+use std::{borrow::Cow, fmt};
+
+use serde::{Deserialize, Serialize};
+
+///Shown instead of a secret in every diagnostic
+pub const REDACTED: &str = "***";
+
+///A value that must only ever end up in the generated YAML. `Debug` and
+///`Display` print [`REDACTED`], so secrets can't leak through logs, error
+///messages or reports. Only serialization writes the actual value.
+#[derive(Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Secret(String);
+
+impl Secret {
+    pub fn new(value: String) -> Self {
+        Secret(value)
+    }
+
+    ///The actual value, only for writing it back into an export.
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(REDACTED)
+    }
+}
+
+impl fmt::Display for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(REDACTED)
+    }
+}
+
+///Consumer credentials of an application, only written with
+///`--include-credentials`.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "camelCase")]
+pub struct Credentials {
+    pub consumer_key: Secret,
+    pub consumer_secret: Secret,
+}
+
+///The keys [`Credentials`] are written with in YAML
+const CREDENTIAL_KEYS: [&str; 2] = ["consumerKey:", "consumerSecret:"];
+
+///`yaml` with the values of the credential keys replaced by [`REDACTED`],
+///for showing generated documents in previews and diffs. Block scalars are
+///dropped along with their continuation lines.
+///
+///```
+///use subscription_migrator::secret::redact_yaml;
+///
+///let yaml = "credentials:\n  consumerKey: key-1\n  consumerSecret: |-\n    line one\n    line two\nlabels:\n- web\n";
+///assert_eq!(
+///    redact_yaml(yaml),
+///    "credentials:\n  consumerKey: ***\n  consumerSecret: ***\nlabels:\n- web\n"
+///);
+///assert_eq!(redact_yaml("consumerKeys: 3\n"), "consumerKeys: 3\n");
+///```
+pub fn redact_yaml(yaml: &str) -> Cow<'_, str> {
+    if !CREDENTIAL_KEYS.iter().any(|key| yaml.contains(key)) {
+        return Cow::Borrowed(yaml);
+    }
+    let mut redacted = String::with_capacity(yaml.len());
+    // indentation of a redacted key whose block scalar is being skipped
+    let mut skipping = None;
+    for line in yaml.split_inclusive('\n') {
+        let content = line.trim_end_matches(['\r', '\n']);
+        let trimmed = content.trim_start();
+        let indent = content.len() - trimmed.len();
+        if let Some(key_indent) = skipping {
+            if trimmed.is_empty() || indent > key_indent {
+                continue;
+            }
+            skipping = None;
+        }
+        let Some(key) = CREDENTIAL_KEYS.iter().find(|key| trimmed.starts_with(*key)) else {
+            redacted.push_str(line);
+            continue;
+        };
+        let value = trimmed[key.len()..].trim();
+        if value.starts_with(['|', '>']) || value.is_empty() {
+            skipping = Some(indent);
+        }
+        redacted.push_str(&content[..indent]);
+        redacted.push_str(key);
+        redacted.push(' ');
+        redacted.push_str(REDACTED);
+        redacted.push_str(&line[content.len()..]);
+    }
+    Cow::Owned(redacted)
+}
@@ -0,0 +1,360 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+    path::{Path, PathBuf},
+};
+
+#[cfg(feature = "cli")]
+use anyhow::{Context, Result};
+use serde::Serialize;
+
+use crate::{convert::ConvertOptions, migrate::XmlApplication};
+
+///Application names read from a file, one per line. Everything after a `#`
+///is a comment.
+#[derive(Debug, Clone, Default)]
+pub struct ApplicationList {
+    pub path: PathBuf,
+    pub names: BTreeSet<String>,
+}
+
+impl ApplicationList {
+    #[cfg(feature = "cli")]
+    pub fn load(path: &Path) -> Result<Self> {
+        let content =
+            std::fs::read_to_string(path).with_context(|| format!("Failed to read {:?}", path))?;
+        Ok(Self::parse(path, &content))
+    }
+
+    pub fn parse(path: &Path, content: &str) -> Self {
+        let names = content
+            .lines()
+            .map(|line| line.split('#').next().unwrap_or_default().trim())
+            .filter(|name| !name.is_empty())
+            .map(str::to_string)
+            .collect();
+        ApplicationList {
+            path: path.to_path_buf(),
+            names,
+        }
+    }
+
+    fn contains(&self, name: &str) -> bool {
+        self.names.contains(name)
+    }
+}
+
+///Why an application was left out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SkipReason {
+    NotAllowed,
+    Denied,
+    ///Has none of the tags of `--include-tag`
+    Untagged,
+}
+
+impl fmt::Display for SkipReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SkipReason::NotAllowed => write!(f, "not on the allow list"),
+            SkipReason::Denied => write!(f, "on the deny list"),
+            SkipReason::Untagged => write!(f, "has none of the included tags"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SkippedApplication {
+    pub name: String,
+    pub source: PathBuf,
+    pub reason: SkipReason,
+}
+
+#[derive(Debug, Default)]
+pub struct SelectionReport {
+    pub skipped: Vec<SkippedApplication>,
+    ///Names of a list that matched no application, with the list
+    pub unmatched: Vec<(PathBuf, String)>,
+}
+
+impl SelectionReport {
+    ///Adds the report of the next applications the selection was applied
+    ///to. A name is only unmatched if it matched nothing in either.
+    pub fn merge(&mut self, other: SelectionReport) {
+        self.skipped.extend(other.skipped);
+        self.unmatched
+            .retain(|entry| other.unmatched.contains(entry));
+    }
+}
+
+///Which applications are migrated. With an allow list only the listed ones
+///are, a denied one never is, even if it is allowed too. With included tags
+///only applications with one of them are, counting the tags of every
+///same-named application, as they are merged.
+///
+///```
+///use std::path::Path;
+///use subscription_migrator::migrate::XmlApplication;
+///use subscription_migrator::selection::{ApplicationList, Selection, SkipReason};
+///
+///let mut apps = ["billing", "checkout", "ledger"]
+///    .map(|name| XmlApplication {
+///        name: name.to_string(),
+///        ..Default::default()
+///    })
+///    .to_vec();
+///let selection = Selection {
+///    allow: Some(ApplicationList::parse(
+///        Path::new("allow.txt"),
+///        "# approved this quarter\nbilling\ncheckout # pending\nshop\n",
+///    )),
+///    deny: Some(ApplicationList::parse(Path::new("deny.txt"), "checkout\n")),
+///    ..Default::default()
+///};
+///
+///let report = selection.apply(&mut apps);
+///assert_eq!(apps.len(), 1);
+///assert_eq!(apps[0].name, "billing");
+///let skipped = report.skipped.iter().map(|skip| (skip.name.as_str(), skip.reason));
+///assert_eq!(
+///    skipped.collect::<Vec<_>>(),
+///    [("checkout", SkipReason::Denied), ("ledger", SkipReason::NotAllowed)]
+///);
+///assert_eq!(report.unmatched, [("allow.txt".into(), "shop".to_string())]);
+///
+///let app = |name: &str, tags: &[&str]| XmlApplication {
+///    name: name.to_string(),
+///    tags: tags.iter().map(|tag| tag.to_string()).collect(),
+///    ..Default::default()
+///};
+///let mut apps = vec![app("billing", &["pci"]), app("billing", &[]), app("shop", &["internal"])];
+///let selection = Selection::default().include_tags(["pci".to_string()]);
+///let report = selection.apply(&mut apps);
+///assert_eq!(apps.len(), 2);
+///assert_eq!(report.skipped[0].name, "shop");
+///assert_eq!(report.skipped[0].reason, SkipReason::Untagged);
+///```
+#[derive(Debug, Clone, Default)]
+pub struct Selection {
+    pub allow: Option<ApplicationList>,
+    pub deny: Option<ApplicationList>,
+    ///Empty to not filter by tag
+    pub include_tags: BTreeSet<String>,
+}
+
+impl Selection {
+    #[cfg(feature = "cli")]
+    pub fn load(allow: Option<&Path>, deny: Option<&Path>) -> Result<Self> {
+        Ok(Selection {
+            allow: allow.map(ApplicationList::load).transpose()?,
+            deny: deny.map(ApplicationList::load).transpose()?,
+            include_tags: BTreeSet::new(),
+        })
+    }
+
+    pub fn include_tags(mut self, tags: impl IntoIterator<Item = String>) -> Self {
+        self.include_tags.extend(tags);
+        self
+    }
+
+    fn reason(&self, name: &str) -> Option<SkipReason> {
+        if self
+            .allow
+            .as_ref()
+            .is_some_and(|allow| !allow.contains(name))
+        {
+            return Some(SkipReason::NotAllowed);
+        }
+        if self.deny.as_ref().is_some_and(|deny| deny.contains(name)) {
+            return Some(SkipReason::Denied);
+        }
+        None
+    }
+
+    ///Removes the applications that aren't selected.
+    pub fn apply(&self, applications: &mut Vec<XmlApplication>) -> SelectionReport {
+        let mut report = SelectionReport::default();
+        let names = applications
+            .iter()
+            .map(|app| app.name.clone())
+            .collect::<BTreeSet<_>>();
+        for list in self.allow.iter().chain(&self.deny) {
+            for name in list.names.difference(&names) {
+                report.unmatched.push((list.path.clone(), name.clone()));
+            }
+        }
+        let tagged = applications
+            .iter()
+            .filter(|app| app.tags.iter().any(|tag| self.include_tags.contains(tag)))
+            .map(|app| app.name.clone())
+            .collect::<BTreeSet<_>>();
+        applications.retain(|app| {
+            let untagged = !self.include_tags.is_empty() && !tagged.contains(&app.name);
+            match self
+                .reason(&app.name)
+                .or(untagged.then_some(SkipReason::Untagged))
+            {
+                Some(reason) => {
+                    report.skipped.push(SkippedApplication {
+                        name: app.name.clone(),
+                        source: app.source.clone(),
+                        reason,
+                    });
+                    false
+                }
+                None => true,
+            }
+        });
+        report
+    }
+}
+
+///An application none of whose environments is known, so its whole
+///environments block would end up under the non-prod control plane.
+#[derive(Debug, Clone, Serialize)]
+pub struct UnmappedApplication {
+    pub name: String,
+    pub sources: BTreeSet<PathBuf>,
+    ///After aliasing
+    pub environments: BTreeSet<String>,
+}
+
+///The applications whose environments, after aliasing, are all outside
+///`known`. Same-named applications are classified together, as they are
+///merged into one document. Applications without environments aren't
+///flagged.
+///
+///```
+///use subscription_migrator::convert::ConvertOptions;
+///use subscription_migrator::migrate::{XmlApplication, XmlSubscription};
+///use subscription_migrator::selection::unmapped_only;
+///
+///let app = |name: &str, envs: &[&str]| XmlApplication {
+///    name: name.to_string(),
+///    apis: vec![XmlSubscription {
+///        api_name: "orders".to_string(),
+///        api_version: "v1".to_string(),
+///        env: envs.iter().map(|env| env.to_string()).collect(),
+///    }],
+///    ..Default::default()
+///};
+///let apps = [
+///    app("perf-tests", &["perf", "sandbox"]),
+///    app("shop", &["sandbox"]),
+///    app("shop", &["dev"]),
+///    app("billing", &["production"]),
+///];
+///let options = ConvertOptions::default().env_alias("production", "prod");
+///let known = ["prod", "dev", "test"].map(String::from);
+///
+///let unmapped = unmapped_only(&apps, &known, &options);
+///assert_eq!(unmapped.len(), 1);
+///assert_eq!(unmapped[0].name, "perf-tests");
+///assert_eq!(unmapped[0].environments.iter().collect::<Vec<_>>(), ["perf", "sandbox"]);
+///```
+pub fn unmapped_only(
+    applications: &[XmlApplication],
+    known: &[String],
+    options: &ConvertOptions,
+) -> Vec<UnmappedApplication> {
+    by_name(applications, options)
+        .into_iter()
+        .filter(|app| {
+            !app.environments.is_empty() && app.environments.iter().all(|env| !known.contains(env))
+        })
+        .collect()
+}
+
+///Same-named applications with their sources and aliased environments.
+fn by_name(applications: &[XmlApplication], options: &ConvertOptions) -> Vec<UnmappedApplication> {
+    let mut by_name = BTreeMap::<&str, UnmappedApplication>::new();
+    for app in applications {
+        let entry = by_name
+            .entry(&app.name)
+            .or_insert_with(|| UnmappedApplication {
+                name: app.name.clone(),
+                sources: BTreeSet::new(),
+                environments: BTreeSet::new(),
+            });
+        entry.sources.insert(app.source.clone());
+        for env in app.apis.iter().flat_map(|sub| &sub.env) {
+            entry.environments.insert(options.alias(env).to_string());
+        }
+    }
+    by_name.into_values().collect()
+}
+
+///An application whose environments don't belong in the run: prod ones in
+///a non-prod run, or only non-prod ones in a prod run.
+#[derive(Debug, Clone, Serialize)]
+pub struct UnexpectedEnvironments {
+    pub name: String,
+    pub sources: BTreeSet<PathBuf>,
+    ///The prod environments in a non-prod run, all of them in a prod run
+    pub environments: BTreeSet<String>,
+}
+
+impl fmt::Display for UnexpectedEnvironments {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let environments = self.environments.iter().cloned().collect::<Vec<_>>();
+        write!(f, "{:?} [{}]", self.name, environments.join(", "))
+    }
+}
+
+///The applications that don't fit a run for the prod environments, with
+///`prod` set, or for the other ones. Environments are classified after
+///aliasing, by [`ConvertOptions::prod_environments`].
+///
+///```
+///use subscription_migrator::convert::ConvertOptions;
+///use subscription_migrator::migrate::{XmlApplication, XmlSubscription};
+///use subscription_migrator::selection::unexpected_environments;
+///
+///let app = |name: &str, envs: &[&str]| XmlApplication {
+///    name: name.to_string(),
+///    apis: vec![XmlSubscription {
+///        api_name: "orders".to_string(),
+///        api_version: "v1".to_string(),
+///        env: envs.iter().map(|env| env.to_string()).collect(),
+///    }],
+///    ..Default::default()
+///};
+///let apps = [app("shop", &["dev", "production"]), app("tools", &["dev"])];
+///let options = ConvertOptions::default().env_alias("production", "prod");
+///
+///let in_dev_run = unexpected_environments(&apps, false, &options);
+///assert_eq!(in_dev_run.len(), 1);
+///assert_eq!(in_dev_run[0].to_string(), r#""shop" [prod]"#);
+///
+///let in_prod_run = unexpected_environments(&apps, true, &options);
+///assert_eq!(in_prod_run.len(), 1);
+///assert_eq!(in_prod_run[0].to_string(), r#""tools" [dev]"#);
+///```
+pub fn unexpected_environments(
+    applications: &[XmlApplication],
+    prod: bool,
+    options: &ConvertOptions,
+) -> Vec<UnexpectedEnvironments> {
+    by_name(applications, options)
+        .into_iter()
+        .filter_map(|app| {
+            let environments = match prod {
+                true if app.environments.iter().all(|env| !options.is_prod(env)) => {
+                    app.environments
+                }
+                true => BTreeSet::new(),
+                false => app
+                    .environments
+                    .into_iter()
+                    .filter(|env| options.is_prod(env))
+                    .collect(),
+            };
+            (!environments.is_empty()).then_some(UnexpectedEnvironments {
+                name: app.name,
+                sources: app.sources,
+                environments,
+            })
+        })
+        .collect()
+}
@@ -0,0 +1,134 @@
+#[cfg(feature = "cli")]
+use std::path::Path;
+
+use anyhow::{Context, Result};
+
+use crate::{
+    line_ending::LineEnding,
+    migrate::{parse_xml_file, unify_applilcations},
+};
+
+///A subscribe.xml embedded in the binary, and the path it is parsed from.
+#[derive(Debug)]
+pub struct Source {
+    pub path: &'static str,
+    pub xml: &'static str,
+}
+
+///Embedded exports and the YAML the binary has to produce for them, one
+///document per application separated by `---`.
+#[derive(Debug)]
+pub struct Case {
+    pub name: &'static str,
+    pub sources: &'static [Source],
+    ///Newlines the sources are fed to the parser with
+    pub line_ending: LineEnding,
+    pub expected: &'static str,
+}
+
+pub static CASES: &[Case] = &[
+    Case {
+        name: "environments",
+        sources: &[Source {
+            path: "app-checkout/subscribe.xml",
+            xml: include_str!("selftest/checkout.xml"),
+        }],
+        line_ending: LineEnding::Lf,
+        expected: include_str!("selftest/checkout.yaml"),
+    },
+    Case {
+        name: "crlf",
+        sources: &[Source {
+            path: "app-checkout/subscribe.xml",
+            xml: include_str!("selftest/checkout.xml"),
+        }],
+        line_ending: LineEnding::Crlf,
+        expected: include_str!("selftest/checkout.yaml"),
+    },
+    Case {
+        name: "merge",
+        sources: &[
+            Source {
+                path: "eu-checkout/subscribe.xml",
+                xml: include_str!("selftest/checkout_eu.xml"),
+            },
+            Source {
+                path: "us-checkout/subscribe.xml",
+                xml: include_str!("selftest/checkout_us.xml"),
+            },
+        ],
+        line_ending: LineEnding::Lf,
+        expected: include_str!("selftest/merged.yaml"),
+    },
+];
+
+///How a case turned out.
+#[derive(Debug)]
+pub enum Outcome {
+    Pass,
+    ///Unified diff from the expected to the produced YAML
+    Mismatch(String),
+    Error(anyhow::Error),
+}
+
+impl Case {
+    ///Parses, unifies and serializes the sources in memory.
+    ///
+    ///```
+    ///use subscription_migrator::selftest::CASES;
+    ///
+    ///for case in CASES {
+    ///    assert_eq!(case.run()?, case.expected, "{}", case.name);
+    ///}
+    ///# anyhow::Ok(())
+    ///```
+    pub fn run(&self) -> Result<String> {
+        let mut applications = Vec::new();
+        for source in self.sources {
+            let xml = self.line_ending.apply(source.xml);
+            let (parsed, _) = parse_xml_file(xml.as_bytes())
+                .with_context(|| format!("failed to parse {}", source.path))?;
+            applications.extend(parsed.into_iter().map(|mut app| {
+                app.source = source.path.into();
+                app
+            }));
+        }
+        let documents = unify_applilcations(&applications)
+            .iter()
+            .map(|document| document.to_yaml())
+            .collect::<Result<Vec<_>>>()?;
+        Ok(documents.join("---\n"))
+    }
+
+    pub fn check(&self) -> Outcome {
+        match self.run() {
+            Ok(yaml) if yaml == self.expected => Outcome::Pass,
+            Ok(yaml) => Outcome::Mismatch(
+                similar::TextDiff::from_lines(self.expected, &yaml)
+                    .unified_diff()
+                    .header("expected", "actual")
+                    .to_string(),
+            ),
+            Err(e) => Outcome::Error(e),
+        }
+    }
+}
+
+///Writes the sources of every case below `directory/<case>` exactly as
+///they are fed to the parser, next to an `expected.yaml`.
+#[cfg(feature = "cli")]
+pub fn write_fixtures(directory: &Path) -> Result<()> {
+    for case in CASES {
+        let root = directory.join(case.name);
+        for source in case.sources {
+            let path = root.join(source.path);
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            std::fs::write(&path, case.line_ending.apply(source.xml).as_bytes())
+                .with_context(|| format!("failed to write {:?}", path))?;
+        }
+        std::fs::write(root.join("expected.yaml"), case.expected)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,130 @@
+use anyhow::{bail, Result};
+
+use crate::{
+    line_ending::LineEnding,
+    migrate::{YamlApi, YamlApiSubscription},
+};
+
+///Size of the file `document` is written as, in bytes.
+fn written_size(document: &YamlApiSubscription, line_ending: LineEnding) -> Result<usize> {
+    Ok(line_ending.apply(&document.to_yaml()?).len())
+}
+
+///The part of `document` holding only `apis`, with the provenance of
+///those APIs.
+fn part(document: &YamlApiSubscription, apis: &[YamlApi], number: usize) -> YamlApiSubscription {
+    let mut part = document.clone();
+    part.part = Some(number);
+    part.subscription.application.apis = apis.to_vec();
+    if let Some(metadata) = &mut part.metadata {
+        metadata.apis.retain(|api| {
+            apis.iter()
+                .any(|kept| kept.name == api.name && kept.version == api.version)
+        });
+    }
+    part
+}
+
+///Splits the sorted APIs of `document` into the fewest consecutive,
+///equally sized parts whose files are at most `max_bytes`, numbered from 1.
+///Every part keeps the environments and settings of the document.
+pub fn split_document(
+    document: &YamlApiSubscription,
+    max_bytes: usize,
+    line_ending: LineEnding,
+) -> Result<Vec<YamlApiSubscription>> {
+    let mut document = document.clone();
+    document.canonicalize();
+    let size = written_size(&document, line_ending)?;
+    let apis = document.subscription.application.apis.clone();
+    for count in size.div_ceil(max_bytes.max(1)).max(2)..=apis.len() {
+        let parts = apis
+            .chunks(apis.len().div_ceil(count))
+            .enumerate()
+            .map(|(i, apis)| part(&document, apis, i + 1))
+            .collect::<Vec<_>>();
+        let sizes = parts
+            .iter()
+            .map(|part| written_size(part, line_ending))
+            .collect::<Result<Vec<_>>>()?;
+        if sizes.iter().all(|&size| size <= max_bytes) {
+            return Ok(parts);
+        }
+    }
+    bail!(
+        "Application {:?} can't be split into files of at most {} bytes, \
+         a single one of its {} APIs is already too large",
+        document.name(),
+        max_bytes,
+        apis.len()
+    )
+}
+
+///Checks the size of the file of every document before anything is
+///written. An oversized document fails with its name and size, or with
+///`split` is replaced by the parts of [`split_document`].
+///
+///```
+///use subscription_migrator::convert::{convert_application, ConvertOptions};
+///use subscription_migrator::line_ending::LineEnding;
+///use subscription_migrator::migrate::{XmlApplication, XmlSubscription};
+///use subscription_migrator::split::fit_documents;
+///
+///let app = XmlApplication {
+///    name: "everything".to_string(),
+///    apis: (0..2000)
+///        .map(|i| XmlSubscription {
+///            api_name: format!("api-{:04}", i),
+///            api_version: "v1".to_string(),
+///            env: vec!["dev".to_string()],
+///        })
+///        .collect(),
+///    ..Default::default()
+///};
+///let document = convert_application(app, &ConvertOptions::default());
+///let size = document.to_yaml()?.len();
+///assert!(size > 40_000);
+///
+///let error = fit_documents(&[document.clone()], 10_000, false, LineEnding::Lf).unwrap_err();
+///assert!(error.to_string().contains(&format!("{} bytes", size)));
+///
+///let parts = fit_documents(&[document.clone()], 10_000, true, LineEnding::Lf)?;
+///assert!(parts.len() > 4);
+///let mut apis = Vec::new();
+///for (i, part) in parts.iter().enumerate() {
+///    assert_eq!(part.file_name(), format!("subscription-{}.yaml", i + 1));
+///    assert!(part.to_yaml()?.len() <= 10_000);
+///    assert_eq!(part.environments, document.environments);
+///    apis.extend(part.subscription.application.apis.iter().map(|api| api.name.clone()));
+///}
+///let expected = (0..2000).map(|i| format!("api-{:04}", i)).collect::<Vec<_>>();
+///assert_eq!(apis, expected);
+///
+///assert_eq!(fit_documents(&[document.clone()], size, false, LineEnding::Lf)?, [document]);
+///# anyhow::Ok(())
+///```
+pub fn fit_documents(
+    documents: &[YamlApiSubscription],
+    max_bytes: usize,
+    split: bool,
+    line_ending: LineEnding,
+) -> Result<Vec<YamlApiSubscription>> {
+    let mut fitted = Vec::with_capacity(documents.len());
+    for document in documents {
+        let size = written_size(document, line_ending)?;
+        if size <= max_bytes {
+            fitted.push(document.clone());
+        } else if split {
+            fitted.extend(split_document(document, max_bytes, line_ending)?);
+        } else {
+            bail!(
+                "The subscription.yaml of application {:?} is {} bytes, more than --max-output-size {}. \
+                 Pass --split-large to spread its APIs over several files",
+                document.name(),
+                size,
+                max_bytes
+            );
+        }
+    }
+    Ok(fitted)
+}
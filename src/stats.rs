@@ -0,0 +1,250 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+};
+
+use serde::Serialize;
+
+use crate::migrate::XmlApplication;
+
+#[derive(Debug, Serialize)]
+pub struct Stats {
+    applications: usize,
+    apis: Vec<ApiCount>,
+    multi_version_apis: Vec<ApiVersions>,
+    environments: Vec<EnvironmentCount>,
+    ///APIs over the fanout threshold, if one was given
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    high_fanout_apis: Vec<ApiFanout>,
+}
+
+///An API subscribed by more distinct applications than the fanout
+///threshold.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ApiFanout {
+    pub api: String,
+    ///Names of the subscribing applications, sorted
+    pub applications: Vec<String>,
+}
+
+impl fmt::Display for ApiFanout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "API {:?} is subscribed by {} applications: {}",
+            self.api,
+            self.applications.len(),
+            self.applications.join(", ")
+        )
+    }
+}
+
+///The APIs subscribed by more than `threshold` distinct applications, most
+///subscribed first. Applications are counted by name, so run it after
+///renaming for same-named applications to count once.
+///
+///```
+///use subscription_migrator::migrate::{XmlApplication, XmlSubscription};
+///use subscription_migrator::stats::api_fanout;
+///
+///let app = |name: &str, apis: &[&str]| XmlApplication {
+///    name: name.to_string(),
+///    apis: apis
+///        .iter()
+///        .map(|api| XmlSubscription {
+///            api_name: api.to_string(),
+///            api_version: "v1".to_string(),
+///            env: vec!["dev".to_string()],
+///        })
+///        .collect(),
+///    ..Default::default()
+///};
+///let apps = [
+///    app("billing", &["accounts", "invoices"]),
+///    app("checkout", &["accounts"]),
+///    app("ledger", &["accounts", "invoices"]),
+///    // merged with the first one, not counted again
+///    app("billing", &["accounts"]),
+///];
+///
+///let fanout = api_fanout(&apps, 2);
+///assert_eq!(fanout.len(), 1);
+///assert_eq!(fanout[0].api, "accounts");
+///assert_eq!(fanout[0].applications, ["billing", "checkout", "ledger"]);
+///assert!(api_fanout(&apps, 3).is_empty());
+///```
+pub fn api_fanout(applications: &[XmlApplication], threshold: usize) -> Vec<ApiFanout> {
+    let mut api_apps: BTreeMap<&str, BTreeSet<&str>> = BTreeMap::new();
+    for app in applications {
+        for sub in &app.apis {
+            api_apps
+                .entry(sub.api_name.as_str())
+                .or_default()
+                .insert(app.name.as_str());
+        }
+    }
+    let mut fanout = api_apps
+        .into_iter()
+        .filter(|(_, apps)| apps.len() > threshold)
+        .map(|(api, apps)| ApiFanout {
+            api: api.to_string(),
+            applications: apps.into_iter().map(str::to_string).collect(),
+        })
+        .collect::<Vec<_>>();
+    fanout.sort_by_key(|api| std::cmp::Reverse(api.applications.len()));
+    fanout
+}
+
+#[derive(Debug, Serialize)]
+struct ApiCount {
+    name: String,
+    applications: usize,
+}
+
+#[derive(Debug, Serialize)]
+struct ApiVersions {
+    name: String,
+    versions: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct EnvironmentCount {
+    name: String,
+    applications: usize,
+}
+
+impl Stats {
+    ///Aggregates the parsed applications. Applications sharing a name across
+    ///several source files are counted once. With `fanout_threshold` the
+    ///APIs over it are listed, see [`api_fanout`].
+    pub fn compute(
+        applications: &[XmlApplication],
+        top: Option<usize>,
+        fanout_threshold: Option<usize>,
+    ) -> Self {
+        let mut app_names = BTreeSet::new();
+        let mut api_apps: BTreeMap<&str, BTreeSet<&str>> = BTreeMap::new();
+        let mut api_versions: BTreeMap<&str, BTreeSet<&str>> = BTreeMap::new();
+        let mut env_apps: BTreeMap<&str, BTreeSet<&str>> = BTreeMap::new();
+
+        for app in applications {
+            app_names.insert(app.name.as_str());
+            for sub in &app.apis {
+                api_apps
+                    .entry(sub.api_name.as_str())
+                    .or_default()
+                    .insert(app.name.as_str());
+                api_versions
+                    .entry(sub.api_name.as_str())
+                    .or_default()
+                    .insert(sub.api_version.as_str());
+                for env in &sub.env {
+                    env_apps
+                        .entry(env.as_str())
+                        .or_default()
+                        .insert(app.name.as_str());
+                }
+            }
+        }
+
+        let mut apis = histogram(api_apps)
+            .map(|(name, applications)| ApiCount { name, applications })
+            .collect::<Vec<_>>();
+        if let Some(top) = top {
+            apis.truncate(top);
+        }
+
+        let multi_version_apis = api_versions
+            .into_iter()
+            .filter(|(_, versions)| versions.len() > 1)
+            .map(|(name, versions)| ApiVersions {
+                name: name.to_string(),
+                versions: versions.into_iter().map(str::to_string).collect(),
+            })
+            .collect();
+
+        let environments = histogram(env_apps)
+            .map(|(name, applications)| EnvironmentCount { name, applications })
+            .collect();
+
+        Stats {
+            applications: app_names.len(),
+            apis,
+            multi_version_apis,
+            environments,
+            high_fanout_apis: fanout_threshold
+                .map(|threshold| api_fanout(applications, threshold))
+                .unwrap_or_default(),
+        }
+    }
+}
+
+///Sorts by count descending. The input map is ordered by key and the sort is
+///stable, so ties stay alphabetical.
+fn histogram(map: BTreeMap<&str, BTreeSet<&str>>) -> impl Iterator<Item = (String, usize)> {
+    let mut entries = map
+        .into_iter()
+        .map(|(key, apps)| (key.to_string(), apps.len()))
+        .collect::<Vec<_>>();
+    entries.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+    entries.into_iter()
+}
+
+fn write_table(
+    f: &mut fmt::Formatter<'_>,
+    headers: [&str; 2],
+    rows: &[(&str, String)],
+) -> fmt::Result {
+    let width = rows
+        .iter()
+        .map(|(key, _)| key.chars().count())
+        .chain(std::iter::once(headers[0].len()))
+        .max()
+        .unwrap_or_default();
+    writeln!(f, "{:<width$}  {}", headers[0], headers[1])?;
+    for (key, value) in rows {
+        writeln!(f, "{:<width$}  {}", key, value)?;
+    }
+    Ok(())
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Applications: {}", self.applications)?;
+        writeln!(f)?;
+
+        let rows = self
+            .apis
+            .iter()
+            .map(|api| (api.name.as_str(), api.applications.to_string()))
+            .collect::<Vec<_>>();
+        write_table(f, ["API", "APPLICATIONS"], &rows)?;
+        writeln!(f)?;
+
+        let rows = self
+            .multi_version_apis
+            .iter()
+            .map(|api| (api.name.as_str(), api.versions.join(", ")))
+            .collect::<Vec<_>>();
+        write_table(f, ["API", "VERSIONS"], &rows)?;
+        writeln!(f)?;
+
+        let rows = self
+            .environments
+            .iter()
+            .map(|env| (env.name.as_str(), env.applications.to_string()))
+            .collect::<Vec<_>>();
+        write_table(f, ["ENVIRONMENT", "APPLICATIONS"], &rows)?;
+
+        if !self.high_fanout_apis.is_empty() {
+            writeln!(f)?;
+            let rows = self
+                .high_fanout_apis
+                .iter()
+                .map(|api| (api.api.as_str(), api.applications.join(", ")))
+                .collect::<Vec<_>>();
+            write_table(f, ["HIGH FANOUT API", "APPLICATIONS"], &rows)?;
+        }
+        Ok(())
+    }
+}
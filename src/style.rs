@@ -0,0 +1,81 @@
+use std::fmt;
+
+use anstyle::{AnsiColor, Style};
+use clap::ValueEnum;
+
+pub const ERROR: Style = AnsiColor::Red.on_default().bold();
+pub const WARNING: Style = AnsiColor::Yellow.on_default();
+pub const SUCCESS: Style = AnsiColor::Green.on_default();
+pub const CHANGED: Style = AnsiColor::Cyan.on_default();
+pub const DIM: Style = Style::new().dimmed();
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum ColorMode {
+    ///Color when writing to a terminal and `NO_COLOR` isn't set
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+///Environment variables asking for plain output when set to anything but
+///an empty string, `0` or `false`
+pub const PLAIN_VARS: [&str; 2] = ["NO_COLOR", "CI"];
+
+///Whether the output must be plain: uncolored, line oriented and never
+///interactive. Set by `--plain` or one of [`PLAIN_VARS`], looked up with
+///`var`.
+///
+///```
+///use subscription_migrator::style::{is_plain, ColorMode};
+///
+///let env = |vars: &'static [(&str, &str)]| {
+///    move |name: &str| vars.iter().find(|(var, _)| *var == name).map(|(_, value)| value.to_string())
+///};
+///assert!(is_plain(true, env(&[])));
+///assert!(is_plain(false, env(&[("CI", "true")])));
+///assert!(is_plain(false, env(&[("NO_COLOR", "1")])));
+///assert!(!is_plain(false, env(&[("CI", "false"), ("NO_COLOR", "")])));
+///
+///assert_eq!(ColorMode::Auto.plain(true), ColorMode::Never);
+///assert_eq!(ColorMode::Always.plain(false), ColorMode::Always);
+///```
+pub fn is_plain(flag: bool, var: impl Fn(&str) -> Option<String>) -> bool {
+    flag || PLAIN_VARS
+        .iter()
+        .filter_map(|name| var(name))
+        .any(|value| !matches!(value.as_str(), "" | "0" | "false"))
+}
+
+impl ColorMode {
+    ///The mode to use in plain mode. Only an explicit `--color always`
+    ///still colors, `--plain` itself conflicts with it.
+    pub fn plain(self, plain: bool) -> Self {
+        match (self, plain) {
+            (ColorMode::Auto, true) => ColorMode::Never,
+            (mode, _) => mode,
+        }
+    }
+
+    ///Sets the choice for every `anstream` print. Output printed with the
+    ///plain `std` macros, like porcelain and JSON, is never colored.
+    pub fn apply(self) {
+        let choice = match self {
+            ColorMode::Auto => return,
+            ColorMode::Always => anstream::ColorChoice::Always,
+            ColorMode::Never => anstream::ColorChoice::Never,
+        };
+        choice.write_global();
+    }
+}
+
+///`text` wrapped in the escape codes of `style`. Only print it through
+///`anstream`, which strips the codes when color is off.
+pub fn paint(style: Style, text: impl fmt::Display) -> String {
+    format!("{style}{text}{style:#}")
+}
+
+///Prints a warning to stderr.
+pub fn warn(message: impl fmt::Display) {
+    anstream::eprintln!("{} {}", paint(WARNING, "warning:"), message);
+}
@@ -0,0 +1,262 @@
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use anyhow::{bail, Context, Result};
+
+use crate::{
+    formatting::SUBSCRIPTION_FILE,
+    migrate::YamlApiSubscription,
+    readme::{Readme, README_FILE},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Placeholder {
+    Name,
+    Description,
+    ///`- name version` per API
+    Apis,
+    ///`- name` per environment
+    Environments,
+}
+
+impl Placeholder {
+    const ALL: [Placeholder; 4] = [
+        Placeholder::Name,
+        Placeholder::Description,
+        Placeholder::Apis,
+        Placeholder::Environments,
+    ];
+
+    fn key(self) -> &'static str {
+        match self {
+            Placeholder::Name => "name",
+            Placeholder::Description => "description",
+            Placeholder::Apis => "apis",
+            Placeholder::Environments => "environments",
+        }
+    }
+
+    fn value(self, document: &YamlApiSubscription) -> String {
+        let application = &document.subscription.application;
+        match self {
+            Placeholder::Name => application.name.clone(),
+            Placeholder::Description => application.description.clone(),
+            Placeholder::Apis => application
+                .apis
+                .iter()
+                .map(|api| format!("- {} {}", api.name, api.version))
+                .collect::<Vec<_>>()
+                .join("\n"),
+            Placeholder::Environments => document
+                .environments
+                .iter()
+                .flat_map(|block| &block.environments)
+                .map(|env| format!("- {}", env.name))
+                .collect::<Vec<_>>()
+                .join("\n"),
+        }
+    }
+}
+
+impl fmt::Display for Placeholder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{{{}}}", self.key())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Part {
+    Text(String),
+    Placeholder(Placeholder),
+}
+
+///Text with `{name}`, `{description}`, `{apis}` and `{environments}`
+///placeholders filled in per application. `{{` and `}}` stand for literal
+///braces.
+///
+///```
+///use subscription_migrator::migrate::{parse_xml_file, unify_applilcations};
+///use subscription_migrator::template::Template;
+///
+///let xml = r#"<applications><application name="billing">
+///    <subscription apiName="invoices" apiVersion="v1" environment="dev"/>
+///</application></applications>"#;
+///let document = &unify_applilcations(&parse_xml_file(xml.as_bytes())?.0)[0];
+///
+///let template: Template = "# {name}\n{{{description}}}\n{apis}\n".parse()?;
+///assert_eq!(
+///    template.render(document)?,
+///    "# billing\n{billing-subscription}\n- invoices v1\n"
+///);
+///assert!("{owner}".parse::<Template>().is_err());
+///assert!("{name".parse::<Template>().is_err());
+///# anyhow::Ok(())
+///```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Template {
+    parts: Vec<Part>,
+}
+
+impl FromStr for Template {
+    type Err = anyhow::Error;
+
+    fn from_str(source: &str) -> Result<Self> {
+        let mut parts = Vec::new();
+        let mut text = String::new();
+        let mut rest = source;
+        while let Some(index) = rest.find(['{', '}']) {
+            text.push_str(&rest[..index]);
+            let tail = &rest[index..];
+            if tail.starts_with("{{") || tail.starts_with("}}") {
+                text.push_str(&tail[..1]);
+                rest = &tail[2..];
+                continue;
+            }
+            if let Some(after) = tail.strip_prefix('}') {
+                text.push('}');
+                rest = after;
+                continue;
+            }
+            let line = source[..source.len() - tail.len()].matches('\n').count() + 1;
+            let Some(end) = tail.find('}') else {
+                bail!("Unclosed placeholder on line {}", line);
+            };
+            let key = &tail[1..end];
+            let Some(placeholder) = Placeholder::ALL.into_iter().find(|p| p.key() == key) else {
+                bail!(
+                    "Unknown placeholder {{{}}} on line {}, expected one of {}",
+                    key,
+                    line,
+                    Placeholder::ALL.map(|p| p.to_string()).join(", ")
+                );
+            };
+            if !text.is_empty() {
+                parts.push(Part::Text(std::mem::take(&mut text)));
+            }
+            parts.push(Part::Placeholder(placeholder));
+            rest = &tail[end + 1..];
+        }
+        text.push_str(rest);
+        if !text.is_empty() {
+            parts.push(Part::Text(text));
+        }
+        Ok(Template { parts })
+    }
+}
+
+impl Template {
+    ///Fills in the placeholders, failing on one the document has no value
+    ///for, like `{apis}` of an application without subscriptions.
+    pub fn render(&self, document: &YamlApiSubscription) -> Result<String> {
+        // lists in the order subscription.yaml has them
+        let mut document = document.clone();
+        document.canonicalize();
+        let document = &document;
+        let mut rendered = String::new();
+        for part in &self.parts {
+            match part {
+                Part::Text(text) => rendered.push_str(text),
+                Part::Placeholder(placeholder) => {
+                    let value = placeholder.value(document);
+                    if value.is_empty() {
+                        bail!(
+                            "Application {:?} has no value for {}",
+                            document.name(),
+                            placeholder
+                        );
+                    }
+                    rendered.push_str(&value);
+                }
+            }
+        }
+        Ok(rendered)
+    }
+}
+
+///What an [`ExtraFile`] is rendered from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExtraContent {
+    Template {
+        path: PathBuf,
+        template: Template,
+    },
+    ///The README of `--write-readme`
+    Readme(Readme),
+}
+
+///A file rendered into every application directory, from a template given
+///as `TEMPLATE=DEST` or the built in README.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExtraFile {
+    pub content: ExtraContent,
+    ///File name inside the application directory
+    pub destination: String,
+}
+
+impl FromStr for ExtraFile {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (template_path, destination) = value
+            .split_once('=')
+            .ok_or_else(|| format!("expected TEMPLATE=DEST, got {:?}", value))?;
+        let destination = destination.trim();
+        let is_file_name = Path::new(destination)
+            .file_name()
+            .is_some_and(|name| name == destination);
+        if !is_file_name || destination == SUBSCRIPTION_FILE {
+            return Err(format!(
+                "{:?} must be a file name other than {}",
+                destination, SUBSCRIPTION_FILE
+            ));
+        }
+        let template_path = PathBuf::from(template_path.trim());
+        let template = std::fs::read_to_string(&template_path)
+            .with_context(|| format!("Failed to read template {:?}", template_path))
+            .and_then(|source| source.parse())
+            .map_err(|e| format!("{:#}", e))?;
+        Ok(ExtraFile {
+            content: ExtraContent::Template {
+                path: template_path,
+                template,
+            },
+            destination: destination.to_string(),
+        })
+    }
+}
+
+impl ExtraFile {
+    ///The README.md of `--write-readme`.
+    pub fn readme(readme: Readme) -> Self {
+        ExtraFile {
+            content: ExtraContent::Readme(readme),
+            destination: README_FILE.to_string(),
+        }
+    }
+
+    ///Path of the file next to the subscription.yaml at `document_path`.
+    pub fn path(&self, document_path: &Path) -> PathBuf {
+        document_path.with_file_name(&self.destination)
+    }
+
+    pub fn render(&self, document: &YamlApiSubscription) -> Result<String> {
+        match &self.content {
+            ExtraContent::Template { path, template } => template
+                .render(document)
+                .with_context(|| format!("Failed to render {:?}", path)),
+            ExtraContent::Readme(readme) => Ok(readme.render(document)),
+        }
+    }
+
+    ///Whether `existing` already holds the `rendered` content, ignoring
+    ///when a README was generated.
+    pub fn is_current(&self, existing: &str, rendered: &str) -> bool {
+        match &self.content {
+            ExtraContent::Template { .. } => existing == rendered,
+            ExtraContent::Readme(_) => Readme::is_current(existing, rendered),
+        }
+    }
+}
@@ -0,0 +1,163 @@
+//!Test helpers behind the `testing` feature: `proptest` strategies for the
+//!parsed structures and [`FixtureTree`] to lay out export directories for
+//!running the binary. Names are drawn from small pools so generated
+//!applications share names, APIs and environments, and merging gets
+//!exercised.
+//!
+//!```
+//!use proptest::{strategy::{Strategy, ValueTree}, test_runner::TestRunner};
+//!use subscription_migrator::migrate::{parse_xml_file, to_subscribe_xml};
+//!use subscription_migrator::testing::applications;
+//!
+//!let mut runner = TestRunner::deterministic();
+//!let apps = applications().new_tree(&mut runner).unwrap().current();
+//!let parsed = parse_xml_file(to_subscribe_xml(&apps).as_bytes())?.0;
+//!assert_eq!(parsed.len(), apps.len());
+//!# anyhow::Ok(())
+//!```
+
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+use proptest::{
+    collection::{btree_set, vec},
+    prelude::*,
+    sample::select,
+};
+
+use crate::migrate::{to_subscribe_xml, XmlApplication, XmlSubscription};
+
+///Environments generated subscriptions use, some without a control plane
+///mapping
+pub const ENVIRONMENTS: [&str; 5] = ["dev", "test", "prod", "sandbox", "perf"];
+
+///A name of one to two letters from `a` to `c`, lots of collisions.
+pub fn application_name() -> impl Strategy<Value = String> {
+    "[a-c]{1,2}"
+}
+
+pub fn api_name() -> impl Strategy<Value = String> {
+    "[a-e]{1,2}(-api)?"
+}
+
+pub fn api_version() -> impl Strategy<Value = String> {
+    "v?[1-3](\\.[0-9]){0,2}"
+}
+
+///Up to three environments, possibly none and possibly repeated.
+pub fn environments() -> impl Strategy<Value = Vec<String>> {
+    vec(select(&ENVIRONMENTS[..]).prop_map(str::to_string), 0..=3)
+}
+
+pub fn subscription() -> impl Strategy<Value = XmlSubscription> {
+    (api_name(), api_version(), environments()).prop_map(|(api_name, api_version, env)| {
+        XmlSubscription {
+            api_name,
+            api_version,
+            env,
+        }
+    })
+}
+
+pub fn tag() -> impl Strategy<Value = String> {
+    select(&["internal", "pci", "gdpr"][..]).prop_map(str::to_string)
+}
+
+///An application as the parser returns it, with exact duplicate
+///subscriptions dropped and without a source.
+pub fn application() -> impl Strategy<Value = XmlApplication> {
+    (
+        application_name(),
+        select(&["", "JWT", "OAUTH"][..]),
+        0..3600i32,
+        vec(subscription(), 0..6),
+        btree_set(tag(), 0..3),
+    )
+        .prop_map(|(name, token_type, token_validity, subscriptions, tags)| {
+            XmlApplication {
+                original_name: name.clone(),
+                name,
+                token_type: token_type.to_string(),
+                token_validity,
+                tags: tags.into_iter().collect(),
+                ..Default::default()
+            }
+            .with_subscriptions(subscriptions)
+        })
+}
+
+pub fn applications() -> impl Strategy<Value = Vec<XmlApplication>> {
+    vec(application(), 0..8)
+}
+
+///An application subscribing `(api, version, environment)` triples, one
+///subscription each.
+pub fn app(name: &str, subscriptions: &[(&str, &str, &str)]) -> XmlApplication {
+    XmlApplication {
+        name: name.to_string(),
+        original_name: name.to_string(),
+        apis: subscriptions
+            .iter()
+            .map(|(api, version, env)| XmlSubscription {
+                api_name: api.to_string(),
+                api_version: version.to_string(),
+                env: vec![env.to_string()],
+            })
+            .collect(),
+        ..Default::default()
+    }
+}
+
+///Export directories written below `root`, laid out like a bulk run
+///expects them.
+///
+///```
+///use subscription_migrator::testing::{app, FixtureTree};
+///
+///let root = std::env::temp_dir().join(format!("fixture-tree-{}", std::process::id()));
+///let tree = FixtureTree::new(&root);
+///let export = tree.export("app-billing", &[app("billing", &[("invoices", "v1", "prod")])])?;
+///tree.file("app-notes/README", "never exported")?;
+///
+///assert_eq!(export, root.join("app-billing/subscribe.xml"));
+///assert!(std::fs::read_to_string(&export)?.contains(r#"apiName="invoices""#));
+///assert!(root.join("app-notes/README").is_file());
+///# std::fs::remove_dir_all(&root)?;
+///# std::io::Result::Ok(())
+///```
+#[derive(Debug, Clone)]
+pub struct FixtureTree {
+    root: PathBuf,
+}
+
+impl FixtureTree {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        FixtureTree { root: root.into() }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    ///Writes `applications` as the subscribe.xml of `directory`, returning
+    ///its path.
+    pub fn export(&self, directory: &str, applications: &[XmlApplication]) -> io::Result<PathBuf> {
+        self.file(
+            &format!("{}/subscribe.xml", directory),
+            &to_subscribe_xml(applications),
+        )
+    }
+
+    ///Writes any file below the root, like a malformed export or a
+    ///document already in the output, creating its directories.
+    pub fn file(&self, path: &str, content: &str) -> io::Result<PathBuf> {
+        let path = self.root.join(path);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&path, content)?;
+        Ok(path)
+    }
+}
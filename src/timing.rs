@@ -0,0 +1,120 @@
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use serde::{Serialize, Serializer};
+
+use crate::style::warn;
+
+///How many of the slowest files the verbose summary lists
+pub const SLOWEST_SHOWN: usize = 5;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Timing {
+    pub path: PathBuf,
+    #[serde(rename = "duration_ms", serialize_with = "as_millis")]
+    pub duration: Duration,
+}
+
+fn as_millis<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_f64(duration.as_secs_f64() * 1000.0)
+}
+
+///Wall clock durations of parsing every directory and writing every
+///application. Nothing is measured unless enabled.
+#[derive(Debug, Default, Serialize)]
+pub struct Timings {
+    #[serde(skip)]
+    enabled: bool,
+    ///Files taking longer are reported as soon as they are done
+    #[serde(skip)]
+    warn_slower_than: Option<Duration>,
+    #[serde(rename = "parse_timings", skip_serializing_if = "Vec::is_empty")]
+    pub parse: Vec<Timing>,
+    #[serde(rename = "write_timings", skip_serializing_if = "Vec::is_empty")]
+    pub write: Vec<Timing>,
+}
+
+impl Timings {
+    pub fn new(enabled: bool, warn_slower_than: Option<Duration>) -> Self {
+        Timings {
+            enabled: enabled || warn_slower_than.is_some(),
+            warn_slower_than,
+            ..Default::default()
+        }
+    }
+
+    pub fn time_parse<T>(&mut self, path: &Path, f: impl FnOnce() -> T) -> T {
+        if !self.enabled {
+            return f();
+        }
+        let (result, timing) = self.measure("parsing", path, f);
+        self.parse.push(timing);
+        result
+    }
+
+    pub fn time_write<T>(&mut self, path: &Path, f: impl FnOnce() -> T) -> T {
+        if !self.enabled {
+            return f();
+        }
+        let (result, timing) = self.measure("writing", path, f);
+        self.write.push(timing);
+        result
+    }
+
+    fn measure<T>(&self, action: &str, path: &Path, f: impl FnOnce() -> T) -> (T, Timing) {
+        let start = Instant::now();
+        let result = f();
+        let duration = start.elapsed();
+        if self.warn_slower_than.is_some_and(|limit| duration > limit) {
+            warn(format_args!(
+                "{} {:?} took {}ms",
+                action,
+                path,
+                duration.as_millis()
+            ));
+        }
+        let path = path.to_path_buf();
+        (result, Timing { path, duration })
+    }
+
+    ///The `count` slowest parses and writes, for the verbose summary.
+    pub fn slowest(&self, count: usize) -> Slowest<'_> {
+        Slowest {
+            timings: self,
+            count,
+        }
+    }
+}
+
+pub struct Slowest<'a> {
+    timings: &'a Timings,
+    count: usize,
+}
+
+impl fmt::Display for Slowest<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (title, timings) in [
+            ("parse", &self.timings.parse),
+            ("write", &self.timings.write),
+        ] {
+            if timings.is_empty() {
+                continue;
+            }
+            let mut sorted = timings.iter().collect::<Vec<_>>();
+            sorted.sort_by_key(|timing| std::cmp::Reverse(timing.duration));
+            writeln!(f, "Slowest {}:", title)?;
+            for timing in sorted.into_iter().take(self.count) {
+                writeln!(
+                    f,
+                    "  {:>8.1}ms {:?}",
+                    timing.duration.as_secs_f64() * 1000.0,
+                    timing.path
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
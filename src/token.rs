@@ -0,0 +1,530 @@
+use std::{collections::BTreeMap, fmt};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    migrate::{XmlApplication, YamlApiSubscription},
+    validate::{Finding, Severity},
+};
+
+///Meaning of a legacy `tokenValidity` value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenValidity {
+    ///`-1`, the token never expires
+    Unlimited,
+    ///`0`, the platform decides
+    PlatformDefault,
+    Seconds(u32),
+    ///Any other negative value
+    Invalid(i32),
+}
+
+impl TokenValidity {
+    pub fn from_raw(raw: i32) -> Self {
+        match raw {
+            -1 => TokenValidity::Unlimited,
+            0 => TokenValidity::PlatformDefault,
+            seconds if seconds > 0 => TokenValidity::Seconds(seconds.unsigned_abs()),
+            invalid => TokenValidity::Invalid(invalid),
+        }
+    }
+
+    ///The YAML value, `None` when the field is left out. `unlimited` is the
+    ///sentinel written for tokens that never expire.
+    ///
+    ///```
+    ///use subscription_migrator::token::{TokenValidity, TokenValidityFormat, YamlTokenValidity};
+    ///use TokenValidityFormat::{Duration, Iso8601, Seconds};
+    ///
+    ///let named = |value: &str| Some(YamlTokenValidity::Named(value.to_string()));
+    ///let table = [
+    ///    // raw, format, sentinel, expected
+    ///    (-1, Seconds, "unlimited", named("unlimited")),
+    ///    (-1, Duration, "unlimited", named("unlimited")),
+    ///    (-1, Iso8601, "unlimited", named("unlimited")),
+    ///    (-1, Seconds, "never", named("never")),
+    ///    (0, Seconds, "unlimited", None),
+    ///    (0, Duration, "unlimited", None),
+    ///    (0, Iso8601, "unlimited", None),
+    ///    (1, Seconds, "unlimited", Some(YamlTokenValidity::Seconds(1))),
+    ///    (1, Duration, "unlimited", named("1s")),
+    ///    (1, Iso8601, "unlimited", named("PT1S")),
+    ///    (3600, Seconds, "unlimited", Some(YamlTokenValidity::Seconds(3600))),
+    ///    (3600, Duration, "unlimited", named("1h")),
+    ///    (3600, Iso8601, "unlimited", named("PT1H")),
+    ///    (i32::MAX, Seconds, "unlimited", Some(YamlTokenValidity::Seconds(2147483647))),
+    ///    (i32::MAX, Duration, "unlimited", named("2147483647s")),
+    ///    (-2, Seconds, "unlimited", None),
+    ///    (-3600, Duration, "unlimited", None),
+    ///    (i32::MIN, Iso8601, "unlimited", None),
+    ///];
+    ///for (raw, format, sentinel, expected) in table {
+    ///    assert_eq!(
+    ///        TokenValidity::from_raw(raw).to_yaml(sentinel, format),
+    ///        expected,
+    ///        "{} as {:?}",
+    ///        raw,
+    ///        format
+    ///    );
+    ///}
+    ///
+    ///for (raw, expected) in [
+    ///    (-1, TokenValidity::Unlimited),
+    ///    (0, TokenValidity::PlatformDefault),
+    ///    (1, TokenValidity::Seconds(1)),
+    ///    (i32::MAX, TokenValidity::Seconds(2147483647)),
+    ///    (-2, TokenValidity::Invalid(-2)),
+    ///    (i32::MIN, TokenValidity::Invalid(i32::MIN)),
+    ///] {
+    ///    assert_eq!(TokenValidity::from_raw(raw), expected);
+    ///}
+    ///```
+    pub fn to_yaml(
+        self,
+        unlimited: &str,
+        format: TokenValidityFormat,
+    ) -> Option<YamlTokenValidity> {
+        match self {
+            TokenValidity::Unlimited => Some(YamlTokenValidity::Named(unlimited.to_string())),
+            TokenValidity::Seconds(seconds) => Some(format.apply(seconds)),
+            TokenValidity::PlatformDefault | TokenValidity::Invalid(_) => None,
+        }
+    }
+}
+
+///Reads like the value in reviews and reports.
+///
+///```
+///use subscription_migrator::token::TokenValidity;
+///
+///assert_eq!(TokenValidity::from_raw(86400).to_string(), "1d");
+///assert_eq!(TokenValidity::from_raw(-1).to_string(), "unlimited");
+///assert_eq!(TokenValidity::from_raw(0).to_string(), "platform default");
+///assert_eq!(TokenValidity::from_raw(-5).to_string(), "-5");
+///```
+impl fmt::Display for TokenValidity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TokenValidity::Unlimited => f.write_str("unlimited"),
+            TokenValidity::PlatformDefault => f.write_str("platform default"),
+            TokenValidity::Seconds(seconds) => f.write_str(&format_duration(*seconds)),
+            TokenValidity::Invalid(raw) => write!(f, "{}", raw),
+        }
+    }
+}
+
+///How a token validity in seconds is written to the YAML.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum TokenValidityFormat {
+    ///An integer, `86400`
+    #[default]
+    Seconds,
+    ///A string like `1d`, see [`format_duration`]
+    Duration,
+    ///An ISO-8601 duration like `PT24H`, see [`format_iso8601`]
+    Iso8601,
+}
+
+impl TokenValidityFormat {
+    pub fn apply(self, seconds: u32) -> YamlTokenValidity {
+        match self {
+            TokenValidityFormat::Seconds => YamlTokenValidity::Seconds(seconds),
+            TokenValidityFormat::Duration => YamlTokenValidity::Named(format_duration(seconds)),
+            TokenValidityFormat::Iso8601 => YamlTokenValidity::Named(format_iso8601(seconds)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum YamlTokenValidity {
+    Seconds(u32),
+    Named(String),
+}
+
+impl YamlTokenValidity {
+    ///The validity in seconds, whichever format it was written in. `None`
+    ///for the unlimited sentinel and anything else that is no duration.
+    ///
+    ///```
+    ///use subscription_migrator::token::YamlTokenValidity;
+    ///
+    ///let named = |value: &str| YamlTokenValidity::Named(value.to_string()).seconds();
+    ///assert_eq!(YamlTokenValidity::Seconds(86400).seconds(), Some(86400));
+    ///assert_eq!(named("1d"), Some(86400));
+    ///assert_eq!(named("PT24H"), Some(86400));
+    ///assert_eq!(named("unlimited"), None);
+    ///```
+    pub fn seconds(&self) -> Option<u32> {
+        match self {
+            YamlTokenValidity::Seconds(seconds) => Some(*seconds),
+            YamlTokenValidity::Named(value) => parse_duration(value),
+        }
+    }
+}
+
+const MINUTE: u32 = 60;
+const HOUR: u32 = 60 * MINUTE;
+const DAY: u32 = 24 * HOUR;
+
+///Renders seconds in the largest of days, hours, minutes and seconds that
+///divides them evenly, so the value reads back exactly.
+///
+///```
+///use subscription_migrator::token::format_duration;
+///
+///assert_eq!(format_duration(0), "0s");
+///assert_eq!(format_duration(45), "45s");
+///assert_eq!(format_duration(60), "1m");
+///assert_eq!(format_duration(5400), "90m");
+///assert_eq!(format_duration(3600), "1h");
+///assert_eq!(format_duration(43200), "12h");
+///assert_eq!(format_duration(86400), "1d");
+///assert_eq!(format_duration(90000), "25h");
+///assert_eq!(format_duration(3661), "3661s");
+///assert_eq!(format_duration(u32::MAX), "4294967295s");
+///```
+pub fn format_duration(seconds: u32) -> String {
+    if seconds == 0 {
+        return "0s".to_string();
+    }
+    [(DAY, 'd'), (HOUR, 'h'), (MINUTE, 'm')]
+        .into_iter()
+        .find(|(unit, _)| seconds.is_multiple_of(*unit))
+        .map(|(unit, suffix)| format!("{}{}", seconds / unit, suffix))
+        .unwrap_or_else(|| format!("{}s", seconds))
+}
+
+///Renders seconds as an ISO-8601 duration in hours, minutes and seconds.
+///Days are left out as they are not always 24 hours long.
+///
+///```
+///use subscription_migrator::token::format_iso8601;
+///
+///assert_eq!(format_iso8601(0), "PT0S");
+///assert_eq!(format_iso8601(45), "PT45S");
+///assert_eq!(format_iso8601(5400), "PT1H30M");
+///assert_eq!(format_iso8601(86400), "PT24H");
+///assert_eq!(format_iso8601(90061), "PT25H1M1S");
+///```
+pub fn format_iso8601(seconds: u32) -> String {
+    if seconds == 0 {
+        return "PT0S".to_string();
+    }
+    let mut iso = "PT".to_string();
+    for (value, suffix) in [
+        (seconds / HOUR, 'H'),
+        (seconds % HOUR / MINUTE, 'M'),
+        (seconds % MINUTE, 'S'),
+    ] {
+        if value > 0 {
+            iso.push_str(&format!("{}{}", value, suffix));
+        }
+    }
+    iso
+}
+
+///Parses a token validity written as seconds, as a duration like `1d` or
+///`1h30m`, or as an ISO-8601 duration like `PT24H` or `P1DT12H`. Units
+///have to be in descending order, years, months and weeks aren't accepted.
+///
+///```
+///use subscription_migrator::token::parse_duration;
+///
+///assert_eq!(parse_duration("86400"), Some(86400));
+///assert_eq!(parse_duration("0"), Some(0));
+///assert_eq!(parse_duration("1d"), Some(86400));
+///assert_eq!(parse_duration("12h"), Some(43200));
+///assert_eq!(parse_duration("90m"), Some(5400));
+///assert_eq!(parse_duration("1h30m"), Some(5400));
+///assert_eq!(parse_duration("3661s"), Some(3661));
+///assert_eq!(parse_duration("PT24H"), Some(86400));
+///assert_eq!(parse_duration("PT1H30M"), Some(5400));
+///assert_eq!(parse_duration("P1DT12H"), Some(129600));
+///assert_eq!(parse_duration("PT0S"), Some(0));
+///
+///assert_eq!(parse_duration("-1"), None);
+///assert_eq!(parse_duration("unlimited"), None);
+///assert_eq!(parse_duration(""), None);
+///assert_eq!(parse_duration("30m1h"), None);
+///assert_eq!(parse_duration("1h1h"), None);
+///assert_eq!(parse_duration("P"), None);
+///assert_eq!(parse_duration("P1DT"), None);
+///assert_eq!(parse_duration("P1M"), None);
+///assert_eq!(parse_duration("50000d"), None);
+///
+///for seconds in [0, 1, 59, 60, 3599, 3661, 5400, 86399, 86400, 90061] {
+///    assert_eq!(parse_duration(&seconds.to_string()), Some(seconds));
+///    assert_eq!(parse_duration(&subscription_migrator::token::format_duration(seconds)), Some(seconds));
+///    assert_eq!(parse_duration(&subscription_migrator::token::format_iso8601(seconds)), Some(seconds));
+///}
+///```
+pub fn parse_duration(value: &str) -> Option<u32> {
+    let value = value.trim();
+    if !value.is_empty() && value.bytes().all(|b| b.is_ascii_digit()) {
+        return value.parse().ok();
+    }
+    let Some(iso) = value.strip_prefix('P') else {
+        return parse_units(value, &[('d', DAY), ('h', HOUR), ('m', MINUTE), ('s', 1)]);
+    };
+    let (date, time) = match iso.split_once('T') {
+        Some((_, "")) => return None,
+        Some((date, time)) => (date, time),
+        None => (iso, ""),
+    };
+    if date.is_empty() && time.is_empty() {
+        return None;
+    }
+    let days = match date {
+        "" => 0,
+        date => parse_units(date, &[('D', DAY)])?,
+    };
+    let time = match time {
+        "" => 0,
+        time => parse_units(time, &[('H', HOUR), ('M', MINUTE), ('S', 1)])?,
+    };
+    days.checked_add(time)
+}
+
+///Sums numbers followed by one of `units`, each at most once and in the
+///given order.
+fn parse_units(value: &str, units: &[(char, u32)]) -> Option<u32> {
+    if value.is_empty() {
+        return None;
+    }
+    let mut units = units.iter();
+    let mut total = 0u32;
+    let mut rest = value;
+    while !rest.is_empty() {
+        let digits = rest.find(|c: char| !c.is_ascii_digit())?;
+        if digits == 0 {
+            return None;
+        }
+        let number: u32 = rest[..digits].parse().ok()?;
+        let unit = rest[digits..].chars().next()?;
+        let (_, factor) = units.find(|(name, _)| *name == unit)?;
+        total = total.checked_add(number.checked_mul(*factor)?)?;
+        rest = &rest[digits + unit.len_utf8()..];
+    }
+    Some(total)
+}
+
+///Sets the token validity of every document from the application it was
+///converted from. Merged applications use the first source, like
+///[`crate::migrate::unify_applilcations`].
+pub fn apply_token_validity(
+    documents: &mut [YamlApiSubscription],
+    applications: &[XmlApplication],
+    unlimited: &str,
+    format: TokenValidityFormat,
+) {
+    for document in documents {
+        let raw = applications
+            .iter()
+            .filter(|app| app.name == document.name())
+            .min_by(|a, b| a.source.cmp(&b.source))
+            .map(|app| app.token_validity);
+        document.subscription.application.token_validity =
+            raw.and_then(|raw| TokenValidity::from_raw(raw).to_yaml(unlimited, format));
+    }
+}
+
+///Flags token validities without a meaning on the new platform.
+pub fn check_token_validity(applications: &[XmlApplication]) -> Vec<Finding> {
+    applications
+        .iter()
+        .filter_map(|app| match TokenValidity::from_raw(app.token_validity) {
+            TokenValidity::Invalid(raw) => Some(Finding {
+                severity: Severity::Warning,
+                rule: "token-validity",
+                application: app.name.clone(),
+                api: None,
+                version: None,
+                source: app.source.clone(),
+                message: format!("tokenValidity {} is neither -1, 0 nor positive", raw),
+            }),
+            _ => None,
+        })
+        .collect()
+}
+
+///Structured replacement for the legacy `tokenType` string.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "camelCase")]
+pub struct YamlSecurity {
+    #[serde(rename = "type")]
+    pub security_type: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token_format: Option<String>,
+    ///The legacy value of `custom` blocks
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token_type: Option<String>,
+}
+
+impl YamlSecurity {
+    fn new(security_type: &str, token_format: Option<&str>) -> Self {
+        YamlSecurity {
+            security_type: security_type.to_string(),
+            token_format: token_format.map(str::to_string),
+            token_type: None,
+        }
+    }
+}
+
+///The security block of a known token type. `map` comes from the
+///`[token_type_map]` config section and takes precedence over the built in
+///types.
+fn security_for(token_type: &str, map: &BTreeMap<String, YamlSecurity>) -> Option<YamlSecurity> {
+    if let Some(security) = map.get(token_type) {
+        return Some(security.clone());
+    }
+    match token_type {
+        "JWT" => Some(YamlSecurity::new("oauth2", Some("jwt"))),
+        "OAUTH" | "DEFAULT" => Some(YamlSecurity::new("oauth2", Some("opaque"))),
+        "API_KEY" => Some(YamlSecurity::new("apiKey", None)),
+        _ => None,
+    }
+}
+
+///Sets the security block of every document from the token type of the
+///application it was converted from. Unknown token types get a `custom`
+///block carrying the legacy value, applications without one no block.
+pub fn apply_security(
+    documents: &mut [YamlApiSubscription],
+    applications: &[XmlApplication],
+    map: &BTreeMap<String, YamlSecurity>,
+) {
+    for document in documents {
+        let token_type = applications
+            .iter()
+            .filter(|app| app.name == document.name())
+            .min_by(|a, b| a.source.cmp(&b.source))
+            .map(|app| app.token_type.as_str())
+            .filter(|token_type| !token_type.is_empty());
+        document.subscription.application.security = token_type.map(|token_type| {
+            security_for(token_type, map).unwrap_or_else(|| YamlSecurity {
+                token_type: Some(token_type.to_string()),
+                ..YamlSecurity::new("custom", None)
+            })
+        });
+    }
+}
+
+///Flags token types that are neither built in nor in `map`.
+pub fn check_token_types(
+    applications: &[XmlApplication],
+    map: &BTreeMap<String, YamlSecurity>,
+) -> Vec<Finding> {
+    applications
+        .iter()
+        .filter(|app| !app.token_type.is_empty() && security_for(&app.token_type, map).is_none())
+        .map(|app| Finding {
+            severity: Severity::Warning,
+            rule: "unknown-token-type",
+            application: app.name.clone(),
+            api: None,
+            version: None,
+            source: app.source.clone(),
+            message: format!(
+                "tokenType {:?} has no mapping, add it to [token_type_map]; written as custom",
+                app.token_type
+            ),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn zero_is_the_platform_default() {
+        assert_eq!(TokenValidity::from_raw(0), TokenValidity::PlatformDefault);
+        for format in [
+            TokenValidityFormat::Seconds,
+            TokenValidityFormat::Duration,
+            TokenValidityFormat::Iso8601,
+        ] {
+            assert_eq!(
+                TokenValidity::from_raw(0).to_yaml("unlimited", format),
+                None
+            );
+        }
+        assert_eq!(format_duration(0), "0s");
+        assert_eq!(format_iso8601(0), "PT0S");
+        assert_eq!(parse_duration("0s"), Some(0));
+        assert_eq!(parse_duration("PT0S"), Some(0));
+    }
+
+    #[test]
+    fn minus_one_is_unlimited_in_every_format() {
+        assert_eq!(TokenValidity::from_raw(-1), TokenValidity::Unlimited);
+        for format in [
+            TokenValidityFormat::Seconds,
+            TokenValidityFormat::Duration,
+            TokenValidityFormat::Iso8601,
+        ] {
+            let yaml = TokenValidity::from_raw(-1)
+                .to_yaml("never", format)
+                .unwrap();
+            assert_eq!(yaml, YamlTokenValidity::Named("never".to_string()));
+            assert_eq!(yaml.seconds(), None);
+        }
+        assert_eq!(parse_duration("-1"), None);
+    }
+
+    #[test]
+    fn other_negatives_are_left_out() {
+        for raw in [-2, -60, -86400, i32::MIN] {
+            assert_eq!(TokenValidity::from_raw(raw), TokenValidity::Invalid(raw));
+            assert_eq!(
+                TokenValidity::from_raw(raw).to_yaml("unlimited", TokenValidityFormat::Duration),
+                None
+            );
+        }
+    }
+
+    #[test]
+    fn uneven_values_round_trip() {
+        for (seconds, duration, iso) in [
+            (1, "1s", "PT1S"),
+            (61, "61s", "PT1M1S"),
+            (3601, "3601s", "PT1H1S"),
+            (3660, "61m", "PT1H1M"),
+            (5400, "90m", "PT1H30M"),
+            (86401, "86401s", "PT24H1S"),
+            (90000, "25h", "PT25H"),
+            (90061, "90061s", "PT25H1M1S"),
+        ] {
+            assert_eq!(format_duration(seconds), duration);
+            assert_eq!(format_iso8601(seconds), iso);
+            for format in [
+                TokenValidityFormat::Seconds,
+                TokenValidityFormat::Duration,
+                TokenValidityFormat::Iso8601,
+            ] {
+                assert_eq!(
+                    format.apply(seconds).seconds(),
+                    Some(seconds),
+                    "{:?}",
+                    format
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn largest_value_round_trips() {
+        let raw = TokenValidity::from_raw(i32::MAX);
+        let seconds = i32::MAX.unsigned_abs();
+        assert_eq!(raw, TokenValidity::Seconds(seconds));
+        assert_eq!(parse_duration(&format_duration(seconds)), Some(seconds));
+        assert_eq!(parse_duration(&format_iso8601(seconds)), Some(seconds));
+        assert_eq!(parse_duration(&format_iso8601(u32::MAX)), Some(u32::MAX));
+        assert_eq!(
+            parse_duration(&format!("{}s", u64::from(u32::MAX) + 1)),
+            None
+        );
+    }
+}
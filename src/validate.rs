@@ -0,0 +1,401 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use serde::Serialize;
+
+#[cfg(feature = "cli")]
+use crate::style::{paint, ERROR, WARNING};
+use crate::{
+    convert::ConvertOptions, migrate::XmlApplication, token::TokenValidity,
+    versions::compare_versions,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Finding {
+    pub severity: Severity,
+    pub rule: &'static str,
+    pub application: String,
+    pub api: Option<String>,
+    pub version: Option<String>,
+    pub source: PathBuf,
+    pub message: String,
+}
+
+impl Finding {
+    ///The severity and rule the display starts with, e.g. `error[rule]`.
+    fn label(&self) -> String {
+        let severity = match self.severity {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        };
+        format!("{}[{}]", severity, self.rule)
+    }
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: ", self.label())?;
+        FindingDetails(self).fmt(f)
+    }
+}
+
+///Everything of a finding's display after its label.
+struct FindingDetails<'a>(&'a Finding);
+
+impl fmt::Display for FindingDetails<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let FindingDetails(finding) = self;
+        if !finding.application.is_empty() {
+            write!(f, "{} ", finding.application)?;
+        }
+        if let Some(api) = &finding.api {
+            write!(f, "{} ", api)?;
+        }
+        if let Some(version) = &finding.version {
+            write!(f, "v{} ", version)?;
+        }
+        write!(f, "({}): {}", finding.source.display(), finding.message)
+    }
+}
+
+///Prints the findings to stderr and fails if any of them is an error. With
+///`strict` every warning is treated as an error.
+#[cfg(feature = "cli")]
+pub fn report_findings(findings: &mut [Finding], strict: bool) -> anyhow::Result<()> {
+    if strict {
+        for finding in findings.iter_mut() {
+            finding.severity = Severity::Error;
+        }
+    }
+
+    for finding in findings.iter() {
+        let style = match finding.severity {
+            Severity::Warning => WARNING,
+            Severity::Error => ERROR,
+        };
+        anstream::eprintln!(
+            "{}: {}",
+            paint(style, finding.label()),
+            FindingDetails(finding)
+        );
+    }
+
+    let errors = findings
+        .iter()
+        .filter(|finding| finding.severity == Severity::Error)
+        .count();
+    if errors > 0 {
+        return Err(anyhow::anyhow!(
+            "Validation failed with {} error(s)",
+            errors
+        ));
+    }
+    Ok(())
+}
+
+///Flags APIs subscribed in a prod environment without a subscription in any
+///pre-prod environment. Subscriptions are grouped per application name across
+///all sources, so a dev subscription in one directory covers a prod one in
+///another. Applications without any pre-prod environment at all are reported
+///once as prod-only instead of once per API.
+pub fn check_env_parity(
+    applications: &[XmlApplication],
+    prod_envs: &[String],
+    pre_prod_envs: &[String],
+) -> Vec<Finding> {
+    let mut by_app: BTreeMap<&str, Vec<&XmlApplication>> = BTreeMap::new();
+    for app in applications {
+        by_app.entry(app.name.as_str()).or_default().push(app);
+    }
+
+    let mut findings = Vec::new();
+    for (name, apps) in by_app {
+        let mut api_envs: BTreeMap<&str, BTreeSet<&str>> = BTreeMap::new();
+        for sub in apps.iter().flat_map(|app| &app.apis) {
+            api_envs
+                .entry(sub.api_name.as_str())
+                .or_default()
+                .extend(sub.env.iter().map(String::as_str));
+        }
+
+        let is_prod = |env: &&str| prod_envs.iter().any(|prod| prod == env);
+        let is_pre_prod = |env: &&str| pre_prod_envs.iter().any(|pre| pre == env);
+
+        let app_has_pre_prod = api_envs.values().flatten().any(is_pre_prod);
+        if !app_has_pre_prod {
+            if api_envs.values().flatten().any(is_prod) {
+                findings.push(Finding {
+                    severity: Severity::Warning,
+                    rule: "prod-only-application",
+                    application: name.to_string(),
+                    api: None,
+                    version: None,
+                    source: apps[0].source.clone(),
+                    message: format!(
+                        "application has no subscription in any of [{}]",
+                        pre_prod_envs.join(", ")
+                    ),
+                });
+            }
+            continue;
+        }
+
+        for app in &apps {
+            for sub in &app.apis {
+                let envs = &api_envs[sub.api_name.as_str()];
+                let in_prod = sub.env.iter().map(String::as_str).any(|env| is_prod(&env));
+                if in_prod && !envs.iter().any(is_pre_prod) {
+                    findings.push(Finding {
+                        severity: Severity::Warning,
+                        rule: "env-parity",
+                        application: name.to_string(),
+                        api: Some(sub.api_name.clone()),
+                        version: Some(sub.api_version.clone()),
+                        source: app.source.clone(),
+                        message: format!(
+                            "subscribed in prod but not in any of [{}]",
+                            pre_prod_envs.join(", ")
+                        ),
+                    });
+                }
+            }
+        }
+    }
+
+    findings
+}
+
+///Flags API versions an application subscribes in a prod environment while
+///its other environments only have other versions of the API, so the prod
+///version was never exercised before. Versions are compared per
+///subscription and grouped per application name across all sources like
+///[`check_env_parity`]. APIs without any non-prod subscription are left to
+///[`check_env_parity`].
+///
+///```
+///use subscription_migrator::migrate::{XmlApplication, XmlSubscription};
+///use subscription_migrator::validate::check_version_parity;
+///
+///let app = |source: &str, subscriptions: &[(&str, &str, &[&str])]| XmlApplication {
+///    name: "checkout".to_string(),
+///    source: source.into(),
+///    apis: subscriptions
+///        .iter()
+///        .map(|(api, version, envs)| XmlSubscription {
+///            api_name: api.to_string(),
+///            api_version: version.to_string(),
+///            env: envs.iter().map(|env| env.to_string()).collect(),
+///        })
+///        .collect(),
+///    ..Default::default()
+///};
+///let applications = [
+///    app("eu/subscribe.xml", &[
+///        ("orders", "v2", &["dev", "test"]),
+///        ("orders", "v3", &["prod"]),
+///        ("users", "1.0", &["prod"]),
+///        ("audit", "1", &["prod"]),
+///    ]),
+///    // tests users 1.0 and audit 1, written differently
+///    app("us/subscribe.xml", &[("users", "1", &["dev"]), ("audit", "v1", &["test"])]),
+///];
+///
+///let findings = check_version_parity(&applications, &["prod".to_string()]);
+///assert_eq!(findings.len(), 1);
+///assert_eq!(findings[0].api.as_deref(), Some("orders"));
+///assert_eq!(findings[0].version.as_deref(), Some("v3"));
+///assert_eq!(findings[0].source, std::path::PathBuf::from("eu/subscribe.xml"));
+///assert_eq!(findings[0].message, "subscribed in prod but the other environments only have [v2]");
+///```
+pub fn check_version_parity(applications: &[XmlApplication], prod_envs: &[String]) -> Vec<Finding> {
+    let is_prod = |env: &String| prod_envs.contains(env);
+    let mut by_app: BTreeMap<&str, Vec<&XmlApplication>> = BTreeMap::new();
+    for app in applications {
+        by_app.entry(app.name.as_str()).or_default().push(app);
+    }
+
+    let mut findings = Vec::new();
+    for (name, apps) in by_app {
+        let mut non_prod: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+        for sub in apps.iter().flat_map(|app| &app.apis) {
+            if sub.env.iter().any(|env| !is_prod(env)) {
+                let versions = non_prod.entry(sub.api_name.as_str()).or_default();
+                if !versions.contains(&sub.api_version.as_str()) {
+                    versions.push(&sub.api_version);
+                }
+            }
+        }
+
+        let mut seen = BTreeSet::new();
+        for app in &apps {
+            for sub in app.apis.iter().filter(|sub| sub.env.iter().any(is_prod)) {
+                let Some(versions) = non_prod.get_mut(sub.api_name.as_str()) else {
+                    continue;
+                };
+                let tested = versions
+                    .iter()
+                    .any(|version| compare_versions(version, &sub.api_version).is_eq());
+                if tested || !seen.insert((&sub.api_name, &sub.api_version, &app.source)) {
+                    continue;
+                }
+                versions.sort_by(|a, b| compare_versions(a, b));
+                findings.push(Finding {
+                    severity: Severity::Warning,
+                    rule: "version-parity",
+                    application: name.to_string(),
+                    api: Some(sub.api_name.clone()),
+                    version: Some(sub.api_version.clone()),
+                    source: app.source.clone(),
+                    message: format!(
+                        "subscribed in prod but the other environments only have [{}]",
+                        versions.join(", ")
+                    ),
+                });
+            }
+        }
+    }
+
+    findings
+}
+
+///Flags environment names outside `allowed`, suggesting the closest allowed
+///name when it is only a typo away. Names are checked after the
+///`--env-alias` aliases of `options`, as they end up in the YAML.
+///
+///```
+///use subscription_migrator::convert::ConvertOptions;
+///use subscription_migrator::migrate::parse_xml_file;
+///use subscription_migrator::validate::check_allowed_envs;
+///
+///let xml = r#"<applications><application name="checkout">
+///    <subscription apiName="orders" apiVersion="1" environment="production"/>
+///    <subscription apiName="orders" apiVersion="1" environment="tset"/>
+///</application></applications>"#;
+///let (applications, _) = parse_xml_file(xml.as_bytes())?;
+///let allowed = ["dev".to_string(), "test".to_string(), "prod".to_string()];
+///let options = ConvertOptions::default().env_alias("production", "prod");
+///
+///let findings = check_allowed_envs(&applications, &allowed, &options);
+///assert_eq!(findings.len(), 1);
+///assert_eq!(
+///    findings[0].message,
+///    r#"environment "tset" is not one of [dev, test, prod], did you mean "test"?"#
+///);
+///# anyhow::Ok(())
+///```
+pub fn check_allowed_envs(
+    applications: &[XmlApplication],
+    allowed: &[String],
+    options: &ConvertOptions,
+) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    for app in applications {
+        for sub in &app.apis {
+            for env in sub
+                .env
+                .iter()
+                .map(|env| options.alias(env))
+                .filter(|env| !allowed.iter().any(|allowed| allowed == env))
+            {
+                let mut message = format!(
+                    "environment {:?} is not one of [{}]",
+                    env,
+                    allowed.join(", ")
+                );
+                if let Some(suggestion) = closest(env, allowed) {
+                    message.push_str(&format!(", did you mean {:?}?", suggestion));
+                }
+                findings.push(Finding {
+                    severity: Severity::Warning,
+                    rule: "unknown-environment",
+                    application: app.name.clone(),
+                    api: Some(sub.api_name.clone()),
+                    version: Some(sub.api_version.clone()),
+                    source: app.source.clone(),
+                    message,
+                });
+            }
+        }
+    }
+    findings
+}
+
+///The candidate closest to `name`, if at most two edits away.
+fn closest<'a>(name: &str, candidates: &'a [String]) -> Option<&'a str> {
+    candidates
+        .iter()
+        .map(|candidate| (strsim::damerau_levenshtein(name, candidate), candidate))
+        .filter(|(distance, _)| *distance <= 2)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate.as_str())
+}
+
+///Flags applications merged from several sources that disagree on their
+///token settings. Merging keeps the settings of the lexicographically first
+///source.
+pub fn check_token_conflicts(applications: &[XmlApplication], strict: bool) -> Vec<Finding> {
+    let mut by_app: BTreeMap<&str, BTreeMap<(&str, i32), BTreeSet<&Path>>> = BTreeMap::new();
+    for app in applications {
+        by_app
+            .entry(app.name.as_str())
+            .or_default()
+            .entry((app.token_type.as_str(), app.token_validity))
+            .or_default()
+            .insert(&app.source);
+    }
+
+    by_app
+        .into_iter()
+        .filter(|(_, settings)| settings.len() > 1)
+        .map(|(name, settings)| {
+            let first_source = settings
+                .values()
+                .flatten()
+                .min()
+                .map(|source| source.to_path_buf())
+                .unwrap_or_default();
+            let listing = settings
+                .iter()
+                .map(|((token_type, validity), sources)| {
+                    let files = sources
+                        .iter()
+                        .map(|source| source.display().to_string())
+                        .collect::<Vec<_>>();
+                    format!(
+                        "{}/{} ({})",
+                        token_type,
+                        TokenValidity::from_raw(*validity),
+                        files.join(", ")
+                    )
+                })
+                .collect::<Vec<_>>();
+            Finding {
+                severity: if strict {
+                    Severity::Error
+                } else {
+                    Severity::Warning
+                },
+                rule: "token-conflict",
+                application: name.to_string(),
+                api: None,
+                version: None,
+                message: format!(
+                    "conflicting token type/validity {}, keeping the one from {}",
+                    listing.join(", "),
+                    first_source.display()
+                ),
+                source: first_source,
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,212 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+    path::Path,
+};
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::migrate::XmlApplication;
+
+///Key of the version used for environments without their own override
+pub const DEFAULT_KEY: &str = "default";
+
+///Versions to use per API and environment, read from a YAML or TOML file
+///mapping every API name to `{environment or default: version}`.
+///
+///The v1 schema lists one version per subscription for all of its
+///environments, so a subscription whose environments end up on different
+///versions is an error.
+///
+///```
+///use subscription_migrator::migrate::{XmlApplication, XmlSubscription};
+///use subscription_migrator::version_overrides::VersionOverrides;
+///
+///let subscription = |version: &str, envs: &[&str]| XmlSubscription {
+///    api_name: "orders".to_string(),
+///    api_version: version.to_string(),
+///    env: envs.iter().map(|env| env.to_string()).collect(),
+///};
+///let mut apps = vec![XmlApplication {
+///    name: "shop".to_string(),
+///    apis: vec![subscription("2.4", &["dev"]), subscription("2.4", &["prod"])],
+///    ..Default::default()
+///}];
+///
+///// a default alone applies to every environment
+///let overrides = VersionOverrides::from_yaml("orders: {default: '2.5'}")?;
+///let mut defaulted = apps.clone();
+///overrides.apply(&mut defaulted)?;
+///assert!(defaulted[0].apis.iter().all(|sub| sub.api_version == "2.5"));
+///
+///// an environment override wins over the default
+///let overrides = VersionOverrides::from_yaml("orders: {dev: '3.0', default: '2.4'}")?;
+///let report = overrides.apply(&mut apps)?;
+///assert_eq!(apps[0].apis[0].api_version, "3.0");
+///assert_eq!(apps[0].apis[1].api_version, "2.4");
+///assert_eq!(report.applied.len(), 1);
+///
+///// dev and prod can't share a subscription with different versions
+///let mut apps = vec![XmlApplication {
+///    name: "shop".to_string(),
+///    apis: vec![subscription("2.4", &["dev", "prod"])],
+///    ..Default::default()
+///}];
+///assert!(overrides.apply(&mut apps).is_err());
+///# anyhow::Ok(())
+///```
+#[derive(Debug, Default, Deserialize)]
+#[serde(transparent)]
+pub struct VersionOverrides(BTreeMap<String, BTreeMap<String, String>>);
+
+///A version rewritten by an override.
+#[derive(Debug, Clone, Serialize)]
+pub struct AppliedOverride {
+    pub application: String,
+    pub api: String,
+    ///The environments of the subscription, empty for one without any
+    pub environments: Vec<String>,
+    pub from: String,
+    pub to: String,
+}
+
+#[derive(Debug, Default)]
+pub struct OverrideReport {
+    pub applied: Vec<AppliedOverride>,
+    ///(api, environment or default) entries that never matched anything
+    pub unmatched: Vec<(String, String)>,
+}
+
+impl VersionOverrides {
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read version overrides {:?}", path))?;
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("toml") => Ok(toml::from_str(&content)?),
+            _ => Self::from_yaml(&content),
+        }
+    }
+
+    pub fn from_yaml(content: &str) -> Result<Self> {
+        Ok(serde_yaml::from_str(content)?)
+    }
+
+    ///Rewrites the version of every subscription with an override for its
+    ///API in its environments. Nothing is changed if any subscription would
+    ///need different versions for its environments.
+    pub fn apply(&self, applications: &mut [XmlApplication]) -> Result<OverrideReport> {
+        let mut report = OverrideReport::default();
+        let mut matched = BTreeSet::new();
+        let mut rewrites = Vec::new();
+        let mut conflicts = Vec::new();
+
+        for (app_index, app) in applications.iter().enumerate() {
+            for (sub_index, sub) in app.apis.iter().enumerate() {
+                let Some((api, versions)) = self.0.get_key_value(&sub.api_name) else {
+                    continue;
+                };
+                let mut targets = BTreeMap::<&str, Vec<&str>>::new();
+                let environments = match sub.env.is_empty() {
+                    true => vec![None],
+                    false => sub.env.iter().map(|env| Some(env.as_str())).collect(),
+                };
+                for env in environments {
+                    let key = env
+                        .filter(|env| versions.contains_key(*env))
+                        .unwrap_or(DEFAULT_KEY);
+                    let target = match versions.get_key_value(key) {
+                        Some((key, version)) => {
+                            matched.insert((api.as_str(), key.as_str()));
+                            version.as_str()
+                        }
+                        None => sub.api_version.as_str(),
+                    };
+                    targets
+                        .entry(target)
+                        .or_default()
+                        .push(env.unwrap_or(DEFAULT_KEY));
+                }
+                if targets.len() > 1 {
+                    let versions = targets
+                        .iter()
+                        .map(|(version, envs)| format!("{} for {}", version, envs.join(", ")))
+                        .collect::<Vec<_>>();
+                    conflicts.push(format!(
+                        "{:?} subscribes {:?} {} in one subscription, but the overrides need {}",
+                        app.name,
+                        sub.api_name,
+                        sub.api_version,
+                        versions.join(" and ")
+                    ));
+                    continue;
+                }
+                if let Some(&target) = targets.keys().next() {
+                    if target != sub.api_version {
+                        rewrites.push((app_index, sub_index, target.to_string()));
+                    }
+                }
+            }
+        }
+        if !conflicts.is_empty() {
+            bail!(
+                "Conflicting version overrides, the v1 schema has one version per subscription:\n  {}",
+                conflicts.join("\n  ")
+            );
+        }
+
+        for (app_index, sub_index, to) in rewrites {
+            let app = &mut applications[app_index];
+            let sub = &mut app.apis[sub_index];
+            report.applied.push(AppliedOverride {
+                application: app.name.clone(),
+                api: sub.api_name.clone(),
+                environments: sub.env.clone(),
+                from: std::mem::replace(&mut sub.api_version, to.clone()),
+                to,
+            });
+        }
+        for (api, versions) in &self.0 {
+            for key in versions.keys() {
+                if !matched.contains(&(api.as_str(), key.as_str())) {
+                    report.unmatched.push((api.clone(), key.clone()));
+                }
+            }
+        }
+        Ok(report)
+    }
+}
+
+impl OverrideReport {
+    ///Adds the report of the next applications the overrides were applied
+    ///to. An entry is only unmatched if it matched nothing in either.
+    pub fn merge(&mut self, other: OverrideReport) {
+        self.applied.extend(other.applied);
+        self.unmatched
+            .retain(|entry| other.unmatched.contains(entry));
+    }
+}
+
+impl fmt::Display for OverrideReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for applied in &self.applied {
+            writeln!(
+                f,
+                "Overrode {:?} {} -> {} in {:?} [{}]",
+                applied.api,
+                applied.from,
+                applied.to,
+                applied.application,
+                applied.environments.join(", ")
+            )?;
+        }
+        for (api, key) in &self.unmatched {
+            writeln!(
+                f,
+                "warning: version override {:?} {} matched nothing",
+                api, key
+            )?;
+        }
+        Ok(())
+    }
+}
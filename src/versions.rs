@@ -0,0 +1,261 @@
+use std::{
+    cmp::Ordering,
+    collections::{BTreeMap, BTreeSet},
+    path::PathBuf,
+};
+
+use regex::Regex;
+
+use crate::{
+    migrate::XmlApplication,
+    provenance::{provenance, ApiProvenance, ApplicationProvenance},
+    validate::{Finding, Severity},
+};
+
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum VersionPolicy {
+    ///Keep every version of an API
+    #[default]
+    All,
+    ///Keep only the highest version of an API
+    Latest,
+    ///Fail if an API is subscribed with more than one version
+    Error,
+}
+
+///Compares two versions numerically when both look like (optionally
+///`v`-prefixed) semver, falling back to a plain string comparison otherwise.
+///
+///```
+///use std::cmp::Ordering;
+///use subscription_migrator::versions::compare_versions;
+///
+///assert_eq!(compare_versions("10", "9"), Ordering::Greater);
+///assert_eq!(compare_versions("v1.2.0", "1.10"), Ordering::Less);
+///assert_eq!(compare_versions("2.0.0-rc.10", "2.0.0-rc.2"), Ordering::Greater);
+///assert_eq!(compare_versions("2.0.0-rc.1", "2.0.0"), Ordering::Less);
+///assert_eq!(compare_versions("1.0.0-alpha", "1.0.0-1"), Ordering::Greater);
+///assert_eq!(compare_versions("beta", "alpha"), Ordering::Greater);
+///```
+pub fn compare_versions(a: &str, b: &str) -> Ordering {
+    match (parse_numeric(a), parse_numeric(b)) {
+        (Some((a_core, a_pre)), Some((b_core, b_pre))) => {
+            let len = a_core.len().max(b_core.len());
+            let component = |core: &[u64], i: usize| core.get(i).copied().unwrap_or(0);
+            (0..len)
+                .map(|i| component(&a_core, i).cmp(&component(&b_core, i)))
+                .find(|ordering| ordering.is_ne())
+                .unwrap_or_else(|| match (a_pre, b_pre) {
+                    (None, None) => Ordering::Equal,
+                    (None, Some(_)) => Ordering::Greater,
+                    (Some(_), None) => Ordering::Less,
+                    (Some(a_pre), Some(b_pre)) => compare_pre_releases(a_pre, b_pre),
+                })
+        }
+        _ => a.cmp(b),
+    }
+}
+
+///Compares pre-release tags identifier by identifier like semver: numeric
+///identifiers numerically and below alphanumeric ones, and a tag that is a
+///prefix of the other first.
+fn compare_pre_releases(a: &str, b: &str) -> Ordering {
+    let mut a = a.split('.');
+    let mut b = b.split('.');
+    loop {
+        let ordering = match (a.next(), b.next()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(a), Some(b)) => match (a.parse::<u64>(), b.parse::<u64>()) {
+                (Ok(a), Ok(b)) => a.cmp(&b),
+                (Ok(_), Err(_)) => Ordering::Less,
+                (Err(_), Ok(_)) => Ordering::Greater,
+                (Err(_), Err(_)) => a.cmp(b),
+            },
+        };
+        if ordering.is_ne() {
+            return ordering;
+        }
+    }
+}
+
+fn parse_numeric(version: &str) -> Option<(Vec<u64>, Option<&str>)> {
+    let version = version.strip_prefix(['v', 'V']).unwrap_or(version);
+    let version = version.split_once('+').map_or(version, |(core, _)| core);
+    let (core, pre) = match version.split_once('-') {
+        Some((core, pre)) => (core, Some(pre)),
+        None => (version, None),
+    };
+    let numbers = core
+        .split('.')
+        .map(|part| part.parse().ok())
+        .collect::<Option<Vec<u64>>>()?;
+    Some((numbers, pre))
+}
+
+///Resolves APIs subscribed with several versions within the same application
+///name. With [`VersionPolicy::Latest`] the lower versions are rewritten to the
+///highest one so their environments are kept, leaving the conversion to merge
+///the subscriptions that end up the same. With [`VersionPolicy::Error`] every
+///conflict is returned as an error finding.
+pub fn apply_version_policy(
+    applications: &mut [XmlApplication],
+    policy: VersionPolicy,
+) -> Vec<Finding> {
+    if policy == VersionPolicy::All {
+        return Vec::new();
+    }
+    apply_version_policy_with(applications, &mut provenance(applications), policy)
+}
+
+///Like [`apply_version_policy`], taking the versions and the sources they
+///were subscribed in from `provenance`, so the applications may already be
+///merged. Rewritten versions are rewritten in `provenance` too.
+pub fn apply_version_policy_with(
+    applications: &mut [XmlApplication],
+    provenance: &mut [ApplicationProvenance],
+    policy: VersionPolicy,
+) -> Vec<Finding> {
+    if policy == VersionPolicy::All {
+        return Vec::new();
+    }
+
+    let mut versions: BTreeMap<(String, String), BTreeMap<String, BTreeSet<PathBuf>>> =
+        BTreeMap::new();
+    for app in provenance.iter() {
+        for api in &app.apis {
+            versions
+                .entry((app.application.clone(), api.name.clone()))
+                .or_default()
+                .entry(api.version.clone())
+                .or_default()
+                .extend(api.sources.iter().cloned());
+        }
+    }
+
+    let mut findings = Vec::new();
+    for ((app_name, api_name), sources) in versions {
+        if sources.len() < 2 {
+            continue;
+        }
+
+        let first_source = sources
+            .values()
+            .flatten()
+            .next()
+            .cloned()
+            .unwrap_or_default();
+        let mut ordered = sources.keys().cloned().collect::<Vec<_>>();
+        ordered.sort_by(|a, b| compare_versions(a, b));
+
+        match policy {
+            VersionPolicy::All => unreachable!(),
+            VersionPolicy::Latest => {
+                let latest = ordered.pop().unwrap();
+                for app in applications.iter_mut().filter(|app| app.name == app_name) {
+                    for sub in app.apis.iter_mut().filter(|sub| sub.api_name == api_name) {
+                        sub.api_version.clone_from(&latest);
+                    }
+                }
+                for app in provenance
+                    .iter_mut()
+                    .filter(|app| app.application == app_name)
+                {
+                    let (merged, mut apis) = std::mem::take(&mut app.apis)
+                        .into_iter()
+                        .partition::<Vec<_>, _>(|api| api.name == api_name);
+                    apis.push(ApiProvenance {
+                        name: api_name.clone(),
+                        version: latest.clone(),
+                        sources: merged.into_iter().flat_map(|api| api.sources).collect(),
+                    });
+                    apis.sort_by(|a, b| (&a.name, &a.version).cmp(&(&b.name, &b.version)));
+                    app.apis = apis;
+                }
+                findings.push(Finding {
+                    severity: Severity::Warning,
+                    rule: "version-policy",
+                    application: app_name,
+                    api: Some(api_name),
+                    version: Some(latest),
+                    source: first_source,
+                    message: format!("dropped version(s) {}", ordered.join(", ")),
+                });
+            }
+            VersionPolicy::Error => {
+                let listing = ordered
+                    .iter()
+                    .map(|version| {
+                        let files = sources[version]
+                            .iter()
+                            .map(|source| source.display().to_string())
+                            .collect::<Vec<_>>();
+                        format!("{} ({})", version, files.join(", "))
+                    })
+                    .collect::<Vec<_>>();
+                findings.push(Finding {
+                    severity: Severity::Error,
+                    rule: "version-policy",
+                    application: app_name,
+                    api: Some(api_name),
+                    version: None,
+                    source: first_source,
+                    message: format!("conflicting versions {}", listing.join(", ")),
+                });
+            }
+        }
+    }
+
+    findings
+}
+
+///Versions the target platform accepts, used when `--version-format` is given
+///without a pattern or under `--strict`
+pub const DEFAULT_VERSION_FORMAT: &str = r"v?\d+(\.\d+)*";
+
+///Compiles a `--version-format` pattern, anchored to match whole versions.
+pub fn parse_version_format(pattern: &str) -> Result<Regex, String> {
+    Regex::new(&format!("^(?:{})$", pattern)).map_err(|e| e.to_string())
+}
+
+///Parses a `--version-map` entry like `LATEST=2.0`.
+pub fn parse_version_mapping(mapping: &str) -> Result<(String, String), String> {
+    let (from, to) = mapping
+        .split_once('=')
+        .ok_or_else(|| format!("expected FROM=TO, got {:?}", mapping))?;
+    Ok((from.trim().to_string(), to.trim().to_string()))
+}
+
+///Rewrites every version listed in `mapping` to its replacement.
+pub fn apply_version_map(applications: &mut [XmlApplication], mapping: &[(String, String)]) {
+    for sub in applications.iter_mut().flat_map(|app| app.apis.iter_mut()) {
+        if let Some((_, to)) = mapping.iter().find(|(from, _)| *from == sub.api_version) {
+            sub.api_version.clone_from(to);
+        }
+    }
+}
+
+///Warns about every subscription whose version doesn't match `format`,
+///`--strict` turns the warnings into errors like any other.
+pub fn check_version_format(applications: &[XmlApplication], format: &Regex) -> Vec<Finding> {
+    applications
+        .iter()
+        .flat_map(|app| app.apis.iter().map(move |sub| (app, sub)))
+        .filter(|(_, sub)| !format.is_match(&sub.api_version))
+        .map(|(app, sub)| Finding {
+            severity: Severity::Warning,
+            rule: "version-format",
+            application: app.name.clone(),
+            api: Some(sub.api_name.clone()),
+            version: None,
+            source: app.source.clone(),
+            message: format!(
+                "version {:?} doesn't match {}",
+                sub.api_version,
+                format.as_str()
+            ),
+        })
+        .collect()
+}
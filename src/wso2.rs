@@ -0,0 +1,174 @@
+use std::{
+    io::{Read, Seek},
+    path::Path,
+};
+
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+use zip::ZipArchive;
+
+use crate::migrate::{XmlApplication, XmlSubscription};
+
+///An `apictl export app` definition, either wrapped in the `data` envelope
+///of newer versions or bare.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum Wso2Document {
+    Wrapped { data: Wso2Application },
+    Bare(Wso2Application),
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Wso2Application {
+    #[serde(default)]
+    application_info: Option<Wso2ApplicationInfo>,
+    #[serde(default, alias = "name")]
+    application_name: Option<String>,
+    #[serde(rename = "subscribedAPIs", default)]
+    subscribed_apis: Vec<Wso2Subscription>,
+    ///Used for subscriptions without their own environments
+    #[serde(default)]
+    gateway_environments: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Wso2ApplicationInfo {
+    #[serde(default)]
+    name: Option<String>,
+    #[serde(default)]
+    token_type: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Wso2Subscription {
+    #[serde(default)]
+    api_id: Option<Wso2ApiId>,
+    #[serde(default, alias = "apiName")]
+    name: Option<String>,
+    #[serde(default)]
+    version: Option<String>,
+    #[serde(default)]
+    gateway_environments: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Wso2ApiId {
+    #[serde(alias = "name")]
+    api_name: String,
+    version: String,
+}
+
+impl Wso2Application {
+    fn into_application(self) -> Result<XmlApplication> {
+        let info = self.application_info;
+        let name = info
+            .as_ref()
+            .and_then(|info| info.name.clone())
+            .or(self.application_name)
+            .map(|name| name.trim().to_string())
+            .filter(|name| !name.is_empty())
+            .context("The application definition has no name")?;
+        let token_type = info.and_then(|info| info.token_type).unwrap_or_default();
+        let gateway_environments = self.gateway_environments;
+        let subscriptions = self
+            .subscribed_apis
+            .into_iter()
+            .map(|sub| {
+                let (api_name, api_version) = match sub.api_id {
+                    Some(id) => (id.api_name, id.version),
+                    None => (
+                        sub.name.unwrap_or_default(),
+                        sub.version.unwrap_or_default(),
+                    ),
+                };
+                let env = match sub.gateway_environments.is_empty() {
+                    true => gateway_environments.clone(),
+                    false => sub.gateway_environments,
+                };
+                XmlSubscription {
+                    api_name: api_name.trim().to_string(),
+                    api_version: api_version.trim().to_string(),
+                    env,
+                }
+            })
+            .collect();
+        Ok(XmlApplication {
+            original_name: name.clone(),
+            name,
+            token_type,
+            ..Default::default()
+        }
+        .with_subscriptions(subscriptions))
+    }
+}
+
+///Whether `entry` is the application definition of an export: the JSON
+///file named after the directory holding it, like `Billing/Billing.json`.
+fn is_definition(entry: &str) -> bool {
+    let path = Path::new(entry);
+    let directory = path.parent().and_then(Path::file_name);
+    path.extension().is_some_and(|ext| ext == "json")
+        && (directory.is_none() || directory == path.file_stem())
+}
+
+///Reads the application of an `apictl export app` archive along with the
+///names of the entries that were ignored. The first definition found is
+///used, any other file is ignored.
+///
+///```
+///use std::io::{Cursor, Write};
+///use subscription_migrator::migrate::YamlApiSubscription;
+///use subscription_migrator::wso2::parse_wso2_archive;
+///
+///let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+///let options = zip::write::SimpleFileOptions::default();
+///zip.start_file("Billing/Billing.json", options)?;
+///zip.write_all(br#"{"type": "application", "data": {
+///    "applicationInfo": {"name": "Billing", "tokenType": "JWT"},
+///    "subscribedAPIs": [
+///        {"apiId": {"apiName": "Invoices", "version": "1.0.0"}, "gatewayEnvironments": ["prod"]}
+///    ]
+///}}"#)?;
+///zip.start_file("Billing/keys/client.pem", options)?;
+///let archive = zip.finish()?;
+///
+///let (application, ignored) = parse_wso2_archive(Cursor::new(archive.into_inner()))?;
+///assert_eq!(ignored, ["Billing/keys/client.pem"]);
+///let yaml = YamlApiSubscription::from(application).to_yaml()?;
+///assert!(yaml.contains("name: Billing\n"));
+///assert!(yaml.contains("- name: Invoices\n      version: 1.0.0\n"));
+///# anyhow::Ok(())
+///```
+pub fn parse_wso2_archive(file: impl Read + Seek) -> Result<(XmlApplication, Vec<String>)> {
+    let mut archive = ZipArchive::new(file).context("Invalid WSO2 export archive")?;
+    let mut application = None;
+    let mut ignored = Vec::new();
+    for index in 0..archive.len() {
+        let mut entry = archive.by_index(index)?;
+        let name = entry.name()?.into_owned();
+        if entry.is_dir() {
+            continue;
+        }
+        if application.is_some() || !is_definition(&name) {
+            ignored.push(name);
+            continue;
+        }
+        let mut content = String::new();
+        entry.read_to_string(&mut content)?;
+        let document: Wso2Document =
+            serde_json::from_str(&content).with_context(|| format!("Invalid {name}"))?;
+        let definition = match document {
+            Wso2Document::Wrapped { data } => data,
+            Wso2Document::Bare(definition) => definition,
+        };
+        application = Some(definition.into_application()?);
+    }
+    match application {
+        Some(application) => Ok((application, ignored)),
+        None => bail!("The archive holds no application definition"),
+    }
+}
@@ -0,0 +1,124 @@
+use std::sync::LazyLock;
+
+use regex::Regex;
+
+///Plain scalars a YAML 1.1 parser resolves to something other than a
+///string: booleans, nulls, and numbers including the sexagesimal, binary
+///and underscore forms YAML 1.2 dropped.
+static YAML_11_NON_STRING: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"(?x)^(?:
+            y|Y|yes|Yes|YES|n|N|no|No|NO|true|True|TRUE|false|False|FALSE|on|On|ON|off|Off|OFF
+            |~|null|Null|NULL
+            |[-+]?0b[01_]+
+            |[-+]?0x[0-9a-fA-F_]+
+            |[-+]?[0-9][0-9_]*(?::[0-5]?[0-9])*(?:\.[0-9_]*)?(?:[eE][-+]?[0-9]+)?
+            |[-+]?\.[0-9][0-9_]*(?:[eE][-+]?[0-9]+)?
+            |[-+]?\.(?:inf|Inf|INF)
+            |\.(?:nan|NaN|NAN)
+        )$",
+    )
+    .unwrap()
+});
+
+///Whether `value`, written as a plain scalar, is a string to this crate's
+///YAML 1.2 serializer but not to a YAML 1.1 parser. Plain scalars that are
+///numbers or booleans in YAML 1.2 too are real numbers and booleans, the
+///serializer quotes strings that look like them.
+fn needs_quotes(value: &str) -> bool {
+    YAML_11_NON_STRING.is_match(value)
+        && matches!(
+            serde_yaml::from_str::<serde_yaml::Value>(value),
+            Ok(serde_yaml::Value::String(_))
+        )
+}
+
+///The plain scalar value of a serialized line, after the indentation, any
+///sequence dashes and the key.
+fn plain_value(line: &str) -> Option<(usize, &str)> {
+    let mut rest = line.trim_start_matches(' ');
+    let mut in_sequence = false;
+    while let Some(item) = rest.strip_prefix("- ") {
+        rest = item;
+        in_sequence = true;
+    }
+    let value = match rest.split_once(": ") {
+        Some((_, value)) => value,
+        None if in_sequence => rest,
+        None => return None,
+    };
+    let plain = !value.is_empty()
+        && !value.starts_with(['\'', '"', '|', '>', '[', '{', '&', '*', '!', '#']);
+    plain.then_some((line.len() - value.len(), value))
+}
+
+///Single-quotes the plain string scalars of serialized YAML that a YAML 1.1
+///parser would read as a number, boolean or null, like the version `2.0`
+///or the environment `on`. `serde_yaml` only quotes what YAML 1.2 would
+///misread. Comment lines are left alone.
+///
+///```
+///use subscription_migrator::convert::{convert_application, ConvertOptions};
+///use subscription_migrator::migrate::{XmlApplication, XmlSubscription};
+///
+///let subscription = |api: &str, version: &str, env: &str| XmlSubscription {
+///    api_name: api.to_string(),
+///    api_version: version.to_string(),
+///    env: vec![env.to_string()],
+///};
+///let app = XmlApplication {
+///    name: "yes".to_string(),
+///    apis: vec![
+///        subscription("null", "2.0", "on"),
+///        subscription("no", "1_000", "off"),
+///        subscription("orders", "1:30", "dev"),
+///        subscription("users", "v2.0.1", "prod"),
+///    ],
+///    ..Default::default()
+///};
+///let document = convert_application(app, &ConvertOptions::default());
+///assert_eq!(
+///    document.to_yaml()?,
+///    "environments:
+///- controlPlaneUrl: https://non-prod.control-plane.com
+///  environment:
+///  - name: dev
+///  - name: 'off'
+///  - name: 'on'
+///- controlPlaneUrl: https://prod.control-plane.com
+///  environment:
+///  - name: prod
+///subscriptions:
+///  application:
+///    name: 'yes'
+///    description: yes-subscription
+///    apis:
+///    - name: 'no'
+///      version: '1_000'
+///    - name: 'null'
+///      version: '2.0'
+///    - name: orders
+///      version: '1:30'
+///    - name: users
+///      version: v2.0.1
+///"
+///);
+///# anyhow::Ok(())
+///```
+pub fn quote_yaml_11_scalars(yaml: &str) -> String {
+    let mut quoted = String::with_capacity(yaml.len());
+    for line in yaml.split_inclusive('\n') {
+        let content = line.strip_suffix('\n').unwrap_or(line);
+        match plain_value(content) {
+            Some((start, value)) if !content.starts_with('#') && needs_quotes(value) => {
+                quoted.push_str(&content[..start]);
+                quoted.push('\'');
+                quoted.push_str(value);
+                quoted.push('\'');
+                quoted.push_str(&line[content.len()..]);
+            }
+            _ => quoted.push_str(line),
+        }
+    }
+    quoted
+}
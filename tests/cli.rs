@@ -0,0 +1,3172 @@
+//!Runs the binary against temp copies of the fixture trees in
+//!`tests/fixtures`. Every tree has an `exports` directory to migrate, some
+//!an `output` directory of earlier runs and an `expected` directory with
+//!the exact files a successful run leaves in the output.
+#![cfg(feature = "cli")]
+
+use std::path::{Path, PathBuf};
+
+use assert_cmd::{cargo::cargo_bin_cmd, Command};
+use assert_fs::{prelude::*, TempDir};
+use predicates::prelude::*;
+use subscription_migrator::{
+    secret::{Credentials, Secret},
+    testing::{app, FixtureTree},
+};
+
+fn fixture(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures")
+        .join(name)
+}
+
+///A temp copy of the fixture tree `name`, with an `output` directory.
+fn copy_fixture(name: &str) -> TempDir {
+    let temp = TempDir::new().unwrap();
+    temp.copy_from(fixture(name), &["**"]).unwrap();
+    temp.child("output").create_dir_all().unwrap();
+    temp
+}
+
+///The binary, run in `dir` with plain output whatever the environment.
+fn migrator(dir: &Path) -> Command {
+    let mut command = cargo_bin_cmd!();
+    command
+        .current_dir(dir)
+        .env_remove("NO_COLOR")
+        .env_remove("CI")
+        .arg("--plain");
+    command
+}
+
+fn bulk(dir: &Path) -> Command {
+    bulk_with(dir, "app-", "all")
+}
+
+fn bulk_matching(dir: &Path, prefix: &str) -> Command {
+    bulk_with(dir, prefix, "all")
+}
+
+fn bulk_for(dir: &Path, environments: &str) -> Command {
+    bulk_with(dir, "app-", environments)
+}
+
+///A bulk run migrating `exports` into `output`.
+fn bulk_with(dir: &Path, prefix: &str, environments: &str) -> Command {
+    let mut command = migrator(dir);
+    command.args([
+        "bulk",
+        "--path",
+        "exports",
+        "--name-prefix",
+        prefix,
+        "--output-path",
+        "output",
+        "--environments",
+        environments,
+    ]);
+    command
+}
+
+///Every file below `dir`, relative to it and sorted.
+fn files(dir: &Path) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    let mut pending = vec![dir.to_path_buf()];
+    while let Some(current) = pending.pop() {
+        for entry in std::fs::read_dir(&current).unwrap() {
+            let path = entry.unwrap().path();
+            match path.is_dir() {
+                true => pending.push(path),
+                false => files.push(path.strip_prefix(dir).unwrap().to_path_buf()),
+            }
+        }
+    }
+    files.sort();
+    files
+}
+
+///Asserts that `actual` holds exactly the files of `expected`, byte for
+///byte.
+fn assert_same_tree(expected: &Path, actual: &Path) {
+    assert_eq!(files(actual), files(expected), "files of {:?}", actual);
+    for file in files(expected) {
+        assert_eq!(
+            String::from_utf8(std::fs::read(actual.join(&file)).unwrap()).unwrap(),
+            String::from_utf8(std::fs::read(expected.join(&file)).unwrap()).unwrap(),
+            "content of {:?}",
+            file
+        );
+    }
+}
+
+#[test]
+fn happy_path_writes_the_expected_documents() {
+    let temp = copy_fixture("happy");
+    bulk(temp.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "+ output/billing-subscription/subscription.yaml created",
+        ))
+        .stdout(predicate::str::contains(
+            "+ output/checkout-subscription/subscription.yaml created",
+        ))
+        .stdout(predicate::str::contains(
+            "Created 2 directories, reused 0 existing ones",
+        ))
+        .stdout(predicate::str::ends_with(
+            "Processed directories 1..2 of 2 (next offset: 2)\n",
+        ));
+    assert_same_tree(&fixture("happy/expected"), &temp.child("output"));
+}
+
+#[test]
+fn rerunning_without_changes_leaves_the_output_alone() {
+    let temp = copy_fixture("happy");
+    bulk(temp.path()).assert().success();
+    bulk(temp.path())
+        .arg("--force")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "= output/billing-subscription/subscription.yaml unchanged",
+        ));
+    assert_same_tree(&fixture("happy/expected"), &temp.child("output"));
+}
+
+#[test]
+fn duplicate_applications_are_merged_into_one_document() {
+    let temp = copy_fixture("duplicates");
+    bulk(temp.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("exports/app-shop-eu"))
+        .stdout(predicate::str::contains("exports/app-shop-us"));
+    assert_same_tree(&fixture("duplicates/expected"), &temp.child("output"));
+}
+
+#[test]
+fn wrapper_defaults_are_inherited_overridden_and_layered() {
+    let temp = copy_fixture("wrapper_defaults");
+    bulk(temp.path())
+        .args(["--emit-security", "--emit-token-validity"])
+        .assert()
+        .success();
+    assert_same_tree(&fixture("wrapper_defaults/expected"), &temp.child("output"));
+}
+
+#[test]
+fn attribute_whitespace_is_trimmed_and_collapsed_on_request() {
+    let temp = copy_fixture("whitespace");
+    bulk(temp.path())
+        .args(["--normalize-whitespace", "--emit-security"])
+        .assert()
+        .success();
+    assert_same_tree(&fixture("whitespace/expected"), &temp.child("output"));
+
+    std::fs::remove_dir_all(temp.child("output")).unwrap();
+    temp.child("output").create_dir_all().unwrap();
+    bulk(temp.path()).assert().success();
+    temp.child("output/padded   shop-subscription/subscription.yaml")
+        .assert(predicate::str::contains("name: catalog  search\n"))
+        .assert(predicate::str::contains("  - name: prod\n"));
+}
+
+#[test]
+fn duplicate_subscriptions_are_dropped_before_anything_counts_them() {
+    let temp = TempDir::new().unwrap();
+    let tree = FixtureTree::new(temp.path());
+    tree.export(
+        "exports/app-shop",
+        &[app(
+            "shop",
+            &[
+                ("orders", "1", "dev"),
+                ("orders", "1", "dev"),
+                ("orders", "1", "prod"),
+                ("catalog", "2", "dev"),
+                ("catalog", "2", "dev"),
+            ],
+        )],
+    )
+    .unwrap();
+    temp.child("output").create_dir_all().unwrap();
+
+    let stats = migrator(tree.root())
+        .args([
+            "--verbose",
+            "stats",
+            "--path",
+            "exports",
+            "--name-prefix",
+            "app-",
+        ])
+        .args(["--format", "json"])
+        .assert()
+        .success()
+        .stderr(predicate::str::contains(
+            "Dropped 2 duplicate subscription(s) of \"shop\"",
+        ))
+        .get_output()
+        .stdout
+        .clone();
+    let stats: serde_json::Value = serde_json::from_slice(&stats).unwrap();
+    assert_eq!(stats["applications"], 1);
+    assert_eq!(
+        stats["apis"],
+        serde_json::json!([
+            {"name": "catalog", "applications": 1},
+            {"name": "orders", "applications": 1},
+        ])
+    );
+
+    bulk(tree.root())
+        .args(["--max-apis-per-application", "2"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("has 3 subscriptions"));
+    bulk(tree.root())
+        .args(["--max-apis-per-application", "3"])
+        .assert()
+        .success();
+    temp.child("output/index.yaml")
+        .assert(predicate::str::contains("  apis: 2\n"));
+
+    let single = temp.child("single");
+    migrator(tree.root())
+        .args([
+            "single",
+            "--input-dir",
+            "exports/app-shop",
+            "--output-dir",
+            "single",
+        ])
+        .arg("--create-output-dir")
+        .assert()
+        .success();
+    let document =
+        std::fs::read_to_string(single.child("shop-subscription/subscription.yaml")).unwrap();
+    assert_eq!(document.matches("name: orders").count(), 1, "{}", document);
+    assert_eq!(document.matches("name: catalog").count(), 1, "{}", document);
+}
+
+#[test]
+fn conflicting_token_settings_keep_the_first_source() {
+    let temp = TempDir::new().unwrap();
+    let tree = FixtureTree::new(temp.path());
+    let mut jwt = app("shop", &[("orders", "1", "dev")]);
+    jwt.token_type = "JWT".to_string();
+    jwt.token_validity = 3600;
+    let mut api_key = app("shop", &[("catalog", "2", "prod")]);
+    api_key.token_type = "API_KEY".to_string();
+    api_key.token_validity = 60;
+    // created out of order, the kept settings follow the source paths
+    tree.export("exports/app-shop-b", &[jwt]).unwrap();
+    tree.export("exports/app-shop-a", &[api_key]).unwrap();
+    temp.child("output").create_dir_all().unwrap();
+
+    bulk(tree.root())
+        .args(["--strict-token-conflicts"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("error[token-conflict]: shop"));
+    assert!(files(&temp.child("output")).is_empty());
+
+    bulk(tree.root())
+        .args(["--emit-security", "--emit-token-validity"])
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("warning[token-conflict]: shop"))
+        .stderr(predicate::str::contains(
+            "API_KEY/1m (exports/app-shop-a/subscribe.xml), JWT/1h (exports/app-shop-b/subscribe.xml), \
+             keeping the one from exports/app-shop-a/subscribe.xml",
+        ));
+    temp.child("output/shop-subscription/subscription.yaml")
+        .assert(predicate::str::contains("tokenValidity: 60\n"))
+        .assert(predicate::str::contains("type: apiKey\n"))
+        .assert(predicate::str::contains("name: orders"));
+}
+
+#[test]
+fn emit_security_writes_the_mapped_security_blocks() {
+    let temp = copy_fixture("security");
+    migrator(temp.path())
+        .args(["--config", "migrator.toml"])
+        .args(["bulk", "--path", "exports", "--name-prefix", "app-"])
+        .args(["--output-path", "output", "--environments", "all"])
+        .arg("--emit-security")
+        .assert()
+        .success()
+        .stderr(predicate::str::contains(
+            "warning[unknown-token-type]: unknown (exports/app-platform/subscribe.xml): \
+             tokenType \"KERBEROS\" has no mapping",
+        ))
+        .stderr(predicate::str::contains("SAML").not());
+    assert_same_tree(&fixture("security/expected"), &temp.child("output"));
+
+    // without the option the token types stay out of the documents
+    bulk(temp.path())
+        .arg("--force")
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("unknown-token-type").not());
+    for file in files(&temp.child("output")) {
+        let content = std::fs::read_to_string(temp.child("output").join(&file)).unwrap();
+        assert!(!content.contains("security:"), "{:?}: {}", file, content);
+    }
+}
+
+#[test]
+fn scalars_yaml_1_1_misreads_are_quoted() {
+    let temp = copy_fixture("ambiguous_scalars");
+    bulk(temp.path()).assert().success();
+    assert_same_tree(
+        &fixture("ambiguous_scalars/expected"),
+        &temp.child("output"),
+    );
+}
+
+#[test]
+fn provenance_report_leaves_the_documents_alone() {
+    let temp = copy_fixture("duplicates");
+    bulk(temp.path())
+        .args(["--provenance-report", "provenance.json"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Provenance report written: provenance.json",
+        ));
+    assert_same_tree(&fixture("duplicates/expected"), &temp.child("output"));
+
+    let report: serde_json::Value =
+        serde_json::from_slice(&std::fs::read(temp.child("provenance.json")).unwrap()).unwrap();
+    let apis = &report["applications"][0]["apis"];
+    assert_eq!(apis[1]["name"], "orders");
+    assert_eq!(
+        apis[1]["sources"],
+        serde_json::json!([
+            "exports/app-shop-eu/subscribe.xml",
+            "exports/app-shop-us/subscribe.xml"
+        ])
+    );
+    assert_eq!(apis[2]["name"], "payments");
+    assert_eq!(
+        apis[2]["sources"],
+        serde_json::json!(["exports/app-shop-us/subscribe.xml"])
+    );
+}
+
+#[test]
+fn missing_export_fails_before_writing() {
+    let temp = copy_fixture("missing_export");
+    bulk(temp.path())
+        .assert()
+        .code(1)
+        .stderr(predicate::str::contains("has no export, expected"));
+    assert!(files(&temp.child("output")).is_empty());
+}
+
+#[test]
+fn malformed_xml_fails_before_writing() {
+    let temp = copy_fixture("malformed");
+    bulk(temp.path())
+        .assert()
+        .code(1)
+        .stderr(predicate::str::contains("Syntax"));
+    assert!(files(&temp.child("output")).is_empty());
+}
+
+#[test]
+fn existing_output_is_only_replaced_with_force() {
+    let temp = TempDir::new().unwrap();
+    temp.copy_from(fixture("existing_output"), &["**"]).unwrap();
+    let existing = temp.child("output/billing-subscription/subscription.yaml");
+    let before = std::fs::read_to_string(&existing).unwrap();
+
+    bulk(temp.path())
+        .assert()
+        .code(1)
+        .stderr(predicate::str::contains(
+            "1 application(s) would be written into an existing directory, nothing was written",
+        ))
+        .stderr(predicate::str::contains(
+            "directory \"output/billing-subscription\" of application \"billing\" already exists",
+        ));
+    existing.assert(before);
+
+    bulk(temp.path())
+        .arg("--force")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Created 0 directories, reused 1 existing ones",
+        ));
+    assert_same_tree(&fixture("existing_output/expected"), &temp.child("output"));
+}
+
+#[test]
+fn porcelain_prints_only_the_file_lines() {
+    let temp = copy_fixture("happy");
+    bulk(temp.path())
+        .arg("--porcelain")
+        .assert()
+        .success()
+        .stdout(
+            "created\tbilling\toutput/billing-subscription/subscription.yaml\n\
+             created\tcheckout\toutput/checkout-subscription/subscription.yaml\n",
+        );
+}
+
+#[test]
+fn plain_conflicts_with_forced_color() {
+    let temp = TempDir::new().unwrap();
+    migrator(temp.path())
+        .args(["--color", "always", "fmt"])
+        .assert()
+        .code(2)
+        .stderr(predicate::str::contains("cannot be used with"));
+}
+
+///Runs the binary with `args` in `dir` on a pseudo terminal, through the
+///`script` of util-linux, returning what the terminal showed. `None` when
+///`script` isn't installed.
+#[cfg(target_os = "linux")]
+fn on_terminal(dir: &Path, args: &str) -> Option<String> {
+    let command = format!("'{}' {}", env!("CARGO_BIN_EXE_subscription_migrator"), args);
+    let output = std::process::Command::new("script")
+        .args(["--quiet", "--return", "--command", &command, "/dev/null"])
+        .current_dir(dir)
+        .env_remove("NO_COLOR")
+        .env_remove("CI")
+        .output()
+        .ok()?;
+    assert!(output.status.success(), "{:?}", output);
+    Some(String::from_utf8(output.stdout).unwrap())
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn plain_keeps_escape_codes_off_a_terminal() {
+    let temp = copy_fixture("happy");
+    let args =
+        "bulk --path exports --name-prefix app- --output-path output --environments all --force";
+    let Some(colored) = on_terminal(temp.path(), args) else {
+        eprintln!("skipped, script is not installed");
+        return;
+    };
+    assert!(
+        colored.contains("\x1b[32m+ output/billing-subscription/subscription.yaml"),
+        "{:?}",
+        colored
+    );
+
+    let plain = on_terminal(temp.path(), &format!("--plain {}", args)).unwrap();
+    assert!(plain.contains("output/billing-subscription/subscription.yaml"));
+    assert!(!plain.contains('\x1b'), "{:?}", plain);
+}
+
+#[test]
+fn uncolored_output_matches_its_snapshot() {
+    let temp = copy_fixture("happy");
+    let mut custom = app("zz", &[("orders", "1", "dev")]);
+    custom.token_type = "KERBEROS".to_string();
+    FixtureTree::new(temp.path())
+        .export("exports/app-zz", &[custom])
+        .unwrap();
+    // one unchanged, two created and a warning, from the same output each time
+    let run = |args: &[&str], no_color: bool| {
+        std::fs::remove_dir_all(temp.child("output")).unwrap();
+        temp.child("output/billing-subscription/subscription.yaml")
+            .write_file(&fixture(
+                "happy/expected/billing-subscription/subscription.yaml",
+            ))
+            .unwrap();
+        let mut command = cargo_bin_cmd!();
+        command
+            .current_dir(temp.path())
+            .env_remove("CI")
+            .env_remove("NO_COLOR")
+            .env("RUST_LIB_BACKTRACE", "0")
+            .args(["bulk", "--path", "exports", "--name-prefix", "app-"])
+            .args(["--output-path", "output", "--environments", "all"])
+            .args(["--emit-security", "--force"])
+            .args(args);
+        if no_color {
+            command.env("NO_COLOR", "1");
+        }
+        command.assert()
+    };
+    let warning =
+        "tokenType \"KERBEROS\" has no mapping, add it to [token_type_map]; written as custom";
+
+    for (args, no_color) in [(&["--color", "never"][..], false), (&[], true)] {
+        run(args, no_color)
+            .success()
+            .stdout(
+                "exports/app-billing
+  applications: billing
+  = output/billing-subscription/subscription.yaml unchanged
+exports/app-checkout
+  applications: checkout
+  + output/checkout-subscription/subscription.yaml created
+exports/app-zz
+  applications: zz
+  + output/zz-subscription/subscription.yaml created
+Created 2 directories, reused 1 existing ones
+Index written: output/index.yaml
+Processed directories 1..3 of 3 (next offset: 3)
+",
+            )
+            .stderr(format!(
+                "warning[unknown-token-type]: zz (exports/app-zz/subscribe.xml): {warning}\n"
+            ));
+    }
+
+    run(&["--color", "never", "--strict"], false)
+        .failure()
+        .stdout("")
+        .stderr(format!(
+            "error[unknown-token-type]: zz (exports/app-zz/subscribe.xml): {warning}
+Error: Validation failed with 1 error(s)
+"
+        ));
+
+    let colored = run(&["--color", "always"], false).success();
+    let output = colored.get_output();
+    assert!(output.stdout.contains(&0x1b) && output.stderr.contains(&0x1b));
+}
+
+#[test]
+fn fixture_trees_can_be_built_in_code() {
+    let temp = TempDir::new().unwrap();
+    let tree = FixtureTree::new(temp.path());
+    tree.export(
+        "exports/app-shop",
+        &[app(
+            "shop",
+            &[("orders", "1", "dev"), ("orders", "1", "qa2")],
+        )],
+    )
+    .unwrap();
+    tree.export(
+        "exports/app-legacy",
+        &[app("legacy", &[("reports", "2", "qa2")])],
+    )
+    .unwrap();
+    temp.child("output").create_dir_all().unwrap();
+
+    bulk(tree.root())
+        .args(["--drop-env", "qa2"])
+        .assert()
+        .success()
+        .stderr(predicate::str::contains(
+            "Application \"legacy\" in \"exports/app-legacy/subscribe.xml\" has no subscriptions left after --drop-env",
+        ))
+        .stdout(predicate::str::contains(
+            "Dropped 2 environment reference(s) by --drop-env, removing 2 subscription(s)",
+        ));
+    temp.child("output/shop-subscription/subscription.yaml")
+        .assert(predicate::str::contains("- name: dev").and(predicate::str::contains("qa2").not()));
+}
+
+#[test]
+fn prefixes_match_names_or_relative_paths() {
+    let temp = copy_fixture("two_teams");
+    bulk_matching(temp.path(), "payments-").assert().success();
+    assert_eq!(
+        files(&temp.child("output")),
+        [
+            PathBuf::from("index.yaml"),
+            "payments-legacy-subscription/subscription.yaml".into(),
+        ]
+    );
+
+    let temp = copy_fixture("two_teams");
+    bulk_matching(temp.path(), "team-a/payments-")
+        .args(["--match-path", "--verbose"])
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("Matched \"team-a/payments-eu\""))
+        .stderr(predicate::str::contains("Matched \"team-a/payments-us\""))
+        .stderr(predicate::str::contains("team-b").not());
+    assert_eq!(
+        files(&temp.child("output")),
+        [
+            PathBuf::from("index.yaml"),
+            "team-a-payments-eu-subscription/subscription.yaml".into(),
+            "team-a-payments-us-subscription/subscription.yaml".into(),
+        ]
+    );
+}
+
+#[test]
+fn match_path_composes_with_the_ignore_file() {
+    let temp = copy_fixture("two_teams");
+    temp.child("exports/.migratorignore")
+        .write_str("/team-a/payments-us\n")
+        .unwrap();
+    bulk_matching(temp.path(), "team-a/")
+        .arg("--match-path")
+        .assert()
+        .success();
+    assert_eq!(
+        files(&temp.child("output")),
+        [
+            PathBuf::from("index.yaml"),
+            "team-a-payments-eu-subscription/subscription.yaml".into(),
+        ]
+    );
+}
+
+///A tree of 20 exports, `app-01` to `app-20`, each with one application of
+///the same number.
+fn numbered_tree(temp: &TempDir) -> FixtureTree {
+    let tree = FixtureTree::new(temp.path());
+    for i in 1..=20 {
+        tree.export(
+            &format!("exports/app-{i:02}"),
+            &[app(&format!("shop{i:02}"), &[("orders", "1", "dev")])],
+        )
+        .unwrap();
+    }
+    tree
+}
+
+///The applications written to `output`, sorted.
+fn written_applications(output: &Path) -> Vec<String> {
+    let mut names = std::fs::read_dir(output)
+        .map(|entries| {
+            entries
+                .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+                .filter_map(|name| name.strip_suffix("-subscription").map(str::to_string))
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+    names.sort();
+    names
+}
+
+#[test]
+fn limit_and_offset_walk_the_sorted_directories_in_chunks() {
+    let temp = TempDir::new().unwrap();
+    let tree = numbered_tree(&temp);
+    let output = temp.child("output");
+    output.create_dir_all().unwrap();
+
+    let mut written = Vec::new();
+    for (offset, summary) in [
+        ("0", "Processed directories 1..6 of 20 (next offset: 6)"),
+        ("6", "Processed directories 7..12 of 20 (next offset: 12)"),
+        ("12", "Processed directories 13..18 of 20 (next offset: 18)"),
+        ("18", "Processed directories 19..20 of 20 (next offset: 20)"),
+    ] {
+        bulk(tree.root())
+            .args(["--limit", "6", "--offset", offset])
+            .assert()
+            .success()
+            .stdout(predicate::str::contains(summary));
+        let now = written_applications(output.path());
+        let chunk = now
+            .iter()
+            .filter(|name| !written.contains(*name))
+            .cloned()
+            .collect::<Vec<_>>();
+        let start = offset.parse::<usize>().unwrap() + 1;
+        let expected = (start..(start + 6).min(21))
+            .map(|i| format!("shop{i:02}"))
+            .collect::<Vec<_>>();
+        assert_eq!(chunk, expected, "offset {}", offset);
+        written = now;
+    }
+    assert_eq!(written.len(), 20);
+
+    bulk(tree.root())
+        .args(["--offset", "20", "--allow-empty"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Processed no directories (offset 20 of 20)",
+        ));
+}
+
+#[test]
+fn sample_picks_the_same_directories_for_a_seed() {
+    let temp = TempDir::new().unwrap();
+    let tree = numbered_tree(&temp);
+    let sample = |output: &str, seed: &str| {
+        migrator(tree.root())
+            .args(["bulk", "--path", "exports", "--name-prefix", "app-"])
+            .args(["--output-path", output, "--environments", "all"])
+            .args(["--sample", "5", "--seed", seed])
+            .assert()
+            .success()
+            .stdout(predicate::str::contains(format!(
+                "Processed a sample of 5 of 20 directories (seed {})",
+                seed
+            )));
+        written_applications(&tree.root().join(output))
+    };
+    for output in ["first", "second", "other"] {
+        temp.child(output).create_dir_all().unwrap();
+    }
+
+    let first = sample("first", "7");
+    assert_eq!(first.len(), 5);
+    assert_eq!(sample("second", "7"), first);
+    assert_ne!(sample("other", "8"), first);
+
+    bulk(tree.root())
+        .args(["--sample", "5", "--limit", "5"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("cannot be used with"));
+}
+
+///Sets the modification time of `file` to `seconds` after the epoch.
+fn set_modified(file: &Path, seconds: u64) {
+    std::fs::File::options()
+        .append(true)
+        .open(file)
+        .unwrap()
+        .set_modified(std::time::UNIX_EPOCH + std::time::Duration::from_secs(seconds))
+        .unwrap();
+}
+
+#[test]
+fn since_skips_exports_older_than_the_cutoff() {
+    let temp = TempDir::new().unwrap();
+    let tree = FixtureTree::new(temp.path());
+    let old = tree
+        .export(
+            "exports/app-shop-old",
+            &[app("shop", &[("legacy", "1", "dev")])],
+        )
+        .unwrap();
+    tree.export(
+        "exports/app-shop-new",
+        &[app("shop", &[("orders", "2", "prod")])],
+    )
+    .unwrap();
+    let billing = tree
+        .export(
+            "exports/app-billing",
+            &[app("billing", &[("invoices", "v1", "prod")])],
+        )
+        .unwrap();
+    // 2024-01-01 and 2024-07-01
+    set_modified(&old, 1_704_067_200);
+    set_modified(&billing, 1_719_792_000);
+    temp.child("output").create_dir_all().unwrap();
+
+    bulk(tree.root())
+        .args(["--since", "24h"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Skipped 2 up-to-date directories not modified since the cutoff",
+        ));
+    assert_eq!(written_applications(&temp.child("output")), ["shop"]);
+    let shop =
+        std::fs::read_to_string(temp.child("output/shop-subscription/subscription.yaml")).unwrap();
+    assert!(shop.contains("name: orders"), "{}", shop);
+    assert!(!shop.contains("legacy"), "{}", shop);
+
+    bulk(tree.root())
+        .args(["--since", "2024-06-01T00:00:00Z", "--force"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Skipped 1 up-to-date directories not modified since the cutoff",
+        ));
+    assert_eq!(
+        written_applications(&temp.child("output")),
+        ["billing", "shop"]
+    );
+
+    bulk(tree.root())
+        .args(["--since", "yesterday"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "expected an RFC 3339 timestamp or a duration",
+        ));
+}
+
+#[test]
+fn prod_environments_in_a_dev_run_are_flagged() {
+    let temp = copy_fixture("happy");
+    bulk_for(temp.path(), "dev")
+        .assert()
+        .success()
+        .stderr(predicate::str::contains(
+            "2 application(s) left after filtering have prod environments, but the run is for --environments dev:",
+        ))
+        .stderr(predicate::str::contains("\"checkout\" [prod]"));
+
+    let temp = copy_fixture("happy");
+    bulk_for(temp.path(), "dev")
+        .arg("--forbid-unexpected-envs")
+        .assert()
+        .code(1);
+    assert!(files(&temp.child("output")).is_empty());
+
+    // dropped environments are not unexpected
+    let temp = copy_fixture("happy");
+    bulk_for(temp.path(), "dev")
+        .args(["--drop-env", "prod", "--forbid-unexpected-envs"])
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("run is for").not());
+}
+
+#[test]
+fn non_prod_only_applications_in_a_prod_run_are_flagged() {
+    let temp = TempDir::new().unwrap();
+    let tree = FixtureTree::new(temp.path());
+    tree.export(
+        "exports/app-shop",
+        &[app("shop", &[("orders", "1", "prod")])],
+    )
+    .unwrap();
+    tree.export(
+        "exports/app-tools",
+        &[app("tools", &[("metrics", "2", "dev")])],
+    )
+    .unwrap();
+    temp.child("output").create_dir_all().unwrap();
+
+    bulk_for(tree.root(), "prod")
+        .assert()
+        .success()
+        .stderr(predicate::str::contains(
+            "1 application(s) left after filtering have only non-prod environments, but the run is for --environments prod:",
+        ))
+        .stderr(predicate::str::contains("\"tools\" [dev]"))
+        .stderr(predicate::str::contains("shop").not());
+    bulk_for(tree.root(), "all")
+        .arg("--force")
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("run is for").not());
+}
+
+///Two exports of `shop` subscribing numeric, semver and non-numeric
+///versions of the same APIs.
+fn versioned_tree(temp: &TempDir) -> FixtureTree {
+    let tree = FixtureTree::new(temp.path());
+    tree.export(
+        "exports/app-shop-dev",
+        &[app(
+            "shop",
+            &[
+                ("orders", "9", "dev"),
+                ("invoices", "1.2.0-rc.2", "dev"),
+                ("search", "beta", "dev"),
+            ],
+        )],
+    )
+    .unwrap();
+    tree.export(
+        "exports/app-shop-prod",
+        &[app(
+            "shop",
+            &[
+                ("orders", "10", "prod"),
+                ("invoices", "1.2.0-rc.10", "prod"),
+                ("search", "alpha", "prod"),
+            ],
+        )],
+    )
+    .unwrap();
+    temp.child("output").create_dir_all().unwrap();
+    tree
+}
+
+///The API versions `document` lists, unquoted, in order.
+fn api_versions(document: &Path) -> Vec<String> {
+    std::fs::read_to_string(document)
+        .unwrap()
+        .lines()
+        .filter_map(|line| line.trim().strip_prefix("version: "))
+        .map(|version| version.trim_matches('\'').to_string())
+        .collect()
+}
+
+#[test]
+fn version_policy_keeps_the_latest_or_lists_the_conflicts() {
+    let temp = TempDir::new().unwrap();
+    let tree = versioned_tree(&temp);
+    let document = tree
+        .root()
+        .join("output/shop-subscription/subscription.yaml");
+
+    bulk(tree.root()).assert().success();
+    assert_eq!(
+        api_versions(&document),
+        ["1.2.0-rc.2", "1.2.0-rc.10", "9", "10", "alpha", "beta"]
+    );
+
+    bulk(tree.root())
+        .args(["--version-policy", "latest", "--force"])
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("dropped version(s) 9"))
+        .stderr(predicate::str::contains("dropped version(s) 1.2.0-rc.2"))
+        .stderr(predicate::str::contains("dropped version(s) alpha"));
+    assert_eq!(api_versions(&document), ["1.2.0-rc.10", "10", "beta"]);
+    let latest = std::fs::read_to_string(&document).unwrap();
+    assert!(latest.contains("name: dev") && latest.contains("name: prod"));
+
+    std::fs::remove_dir_all(tree.root().join("output/shop-subscription")).unwrap();
+    bulk(tree.root())
+        .args(["--version-policy", "error"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "conflicting versions 9 (exports/app-shop-dev/subscribe.xml), 10 (exports/app-shop-prod/subscribe.xml)",
+        ))
+        .stderr(predicate::str::contains("conflicting versions alpha"));
+    assert!(!document.exists());
+}
+
+#[test]
+fn version_policy_applies_to_the_single_command() {
+    let temp = TempDir::new().unwrap();
+    let tree = FixtureTree::new(temp.path());
+    tree.export(
+        "export",
+        &[app(
+            "shop",
+            &[
+                ("orders", "v2.0.0-rc.10", "prod"),
+                ("orders", "v2.0.0-rc.9", "dev"),
+            ],
+        )],
+    )
+    .unwrap();
+    let single = || {
+        let mut command = migrator(tree.root());
+        command.args(["single", "--input-dir", "export", "--output-dir", "output"]);
+        command.arg("--create-output-dir");
+        command
+    };
+
+    single()
+        .args(["--version-policy", "error"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("conflicting versions v2.0.0-rc.9"));
+    single()
+        .args(["--version-policy", "latest"])
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("dropped version(s) v2.0.0-rc.9"));
+    assert_eq!(
+        api_versions(
+            &tree
+                .root()
+                .join("output/shop-subscription/subscription.yaml")
+        ),
+        ["v2.0.0-rc.10"]
+    );
+}
+
+#[test]
+fn manifest_lists_only_the_created_directories() {
+    let temp = copy_fixture("happy");
+    temp.child("output/billing-subscription")
+        .create_dir_all()
+        .unwrap();
+    bulk(temp.path())
+        .args(["--force", "--manifest", "--verbose"])
+        .assert()
+        .success()
+        .stderr(predicate::str::contains(
+            "Reused existing directory \"output/billing-subscription\"",
+        ))
+        .stderr(predicate::str::contains(
+            "Created directory \"output/checkout-subscription\"",
+        ));
+
+    let manifest: serde_json::Value =
+        serde_json::from_slice(&std::fs::read(temp.child("output/manifest.json")).unwrap())
+            .unwrap();
+    assert_eq!(
+        manifest["created_directories"],
+        serde_json::json!(["checkout-subscription"])
+    );
+}
+
+#[test]
+fn selftest_passes_and_only_writes_when_asked() {
+    let temp = TempDir::new().unwrap();
+    migrator(temp.path())
+        .arg("selftest")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("[pass] crlf"))
+        .stdout(predicate::str::contains("[fail]").not());
+    assert!(files(temp.path()).is_empty());
+
+    migrator(temp.path())
+        .args(["selftest", "--write-fixtures", "fixtures"])
+        .assert()
+        .success();
+    let crlf =
+        std::fs::read_to_string(temp.child("fixtures/crlf/app-checkout/subscribe.xml")).unwrap();
+    assert!(crlf.contains("\r\n"));
+    assert!(files(temp.path()).contains(&PathBuf::from("fixtures/merge/expected.yaml")));
+
+    migrator(temp.path())
+        .arg("--help")
+        .assert()
+        .stdout(predicate::str::contains("selftest").not());
+}
+
+#[test]
+fn include_root_merges_the_root_export_with_the_directories() {
+    let temp = copy_fixture("root_and_children");
+    bulk(temp.path())
+        .args(["--include-root", "--provenance-report", "provenance.json"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Included 2 application(s) from the root exports/subscribe.xml",
+        ));
+    assert_same_tree(
+        &fixture("root_and_children/expected"),
+        &temp.child("output"),
+    );
+    let provenance = std::fs::read_to_string(temp.child("provenance.json")).unwrap();
+    assert!(provenance.contains(r#""root": "exports/subscribe.xml""#));
+
+    let without_root = copy_fixture("root_and_children");
+    bulk(without_root.path()).assert().success();
+    assert!(!files(&without_root.child("output"))
+        .contains(&PathBuf::from("platform-subscription/subscription.yaml")));
+}
+
+#[test]
+fn require_root_fails_without_a_root_export() {
+    let temp = copy_fixture("happy");
+    bulk(temp.path()).arg("--include-root").assert().success();
+    bulk(temp.path())
+        .args(["--include-root", "--require-root", "--force"])
+        .assert()
+        .code(1)
+        .stderr(predicate::str::contains(
+            "--require-root: there is no subscribe.xml in \"exports\"",
+        ));
+}
+
+///An export with the small application `billing` and the application
+///`everything` subscribing 1500 APIs.
+fn oversized_tree(temp: &TempDir) -> FixtureTree {
+    let tree = FixtureTree::new(temp.path());
+    let names = (0..1500)
+        .map(|i| format!("api-{:04}", i))
+        .collect::<Vec<_>>();
+    let apis = names
+        .iter()
+        .map(|name| (name.as_str(), "1", "dev"))
+        .collect::<Vec<_>>();
+    tree.export(
+        "exports/app-shared",
+        &[
+            app("everything", &apis),
+            app("billing", &[("invoices", "v1", "prod")]),
+        ],
+    )
+    .unwrap();
+    temp.child("output").create_dir_all().unwrap();
+    tree
+}
+
+#[test]
+fn oversized_documents_fail_before_writing() {
+    let temp = TempDir::new().unwrap();
+    let tree = oversized_tree(&temp);
+    bulk(tree.root())
+        .args(["--max-output-size", "16384"])
+        .assert()
+        .code(1)
+        .stderr(predicate::str::contains(
+            "The subscription.yaml of application \"everything\" is",
+        ));
+    assert!(files(&temp.child("output")).is_empty());
+}
+
+#[test]
+fn split_large_spreads_the_apis_over_several_files() {
+    let temp = TempDir::new().unwrap();
+    let tree = oversized_tree(&temp);
+    bulk(tree.root())
+        .args(["--max-output-size", "16384", "--split-large", "--manifest"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Split application \"everything\" into 4 files to stay within --max-output-size",
+        ));
+    let output = temp.child("output");
+    let parts = (1..=4)
+        .map(|part| format!("everything-subscription/subscription-{}.yaml", part))
+        .collect::<Vec<_>>();
+    let mut expected = parts.iter().map(PathBuf::from).collect::<Vec<_>>();
+    expected.extend(
+        [
+            "billing-subscription/subscription.yaml",
+            "index.yaml",
+            "manifest.json",
+        ]
+        .map(PathBuf::from),
+    );
+    expected.sort();
+    assert_eq!(files(&output), expected);
+
+    let mut apis = Vec::new();
+    for part in &parts {
+        let content = std::fs::read_to_string(output.join(part)).unwrap();
+        assert!(content.len() <= 16384);
+        assert!(content
+            .starts_with("environments:\n- controlPlaneUrl: https://non-prod.control-plane.com\n"));
+        apis.extend(
+            content
+                .lines()
+                .filter_map(|line| line.strip_prefix("    - name: "))
+                .map(str::to_string),
+        );
+    }
+    let names = (0..1500)
+        .map(|i| format!("api-{:04}", i))
+        .collect::<Vec<_>>();
+    assert_eq!(apis, names);
+
+    let index = std::fs::read_to_string(output.join("index.yaml")).unwrap();
+    let manifest = std::fs::read_to_string(output.join("manifest.json")).unwrap();
+    for part in &parts {
+        assert!(index.contains(part.as_str()), "{} in the index", part);
+        assert!(manifest.contains(part.as_str()), "{} in the manifest", part);
+    }
+
+    bulk(tree.root())
+        .args(["--max-output-size", "16384", "--split-large", "--force"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "= output/everything-subscription/subscription-4.yaml unchanged",
+        ));
+}
+
+///An export of the applications `alpha`, `beta`, `gamma` and `delta`,
+///where `beta` and `delta` were migrated before.
+fn conflicting_tree(temp: &TempDir) -> FixtureTree {
+    let tree = FixtureTree::new(temp.path());
+    for name in ["alpha", "beta", "gamma", "delta"] {
+        tree.export(
+            &format!("exports/app-{}", name),
+            &[app(name, &[("orders", "1", "dev")])],
+        )
+        .unwrap();
+    }
+    tree.file("output/beta-subscription/subscription.yaml", "old beta\n")
+        .unwrap();
+    tree.file("output/delta-subscription/subscription.yaml", "old delta\n")
+        .unwrap();
+    tree
+}
+
+#[test]
+fn existing_directories_are_all_listed_and_nothing_is_written() {
+    let temp = TempDir::new().unwrap();
+    let tree = conflicting_tree(&temp);
+    bulk(tree.root())
+        .assert()
+        .code(1)
+        .stderr(predicate::str::contains(
+            "2 application(s) would be written into an existing directory",
+        ))
+        .stderr(predicate::str::contains(
+            "directory \"output/beta-subscription\" of application \"beta\" already exists",
+        ))
+        .stderr(predicate::str::contains(
+            "directory \"output/delta-subscription\" of application \"delta\" already exists",
+        ));
+    assert_eq!(
+        files(&temp.child("output")),
+        [
+            "beta-subscription/subscription.yaml",
+            "delta-subscription/subscription.yaml"
+        ]
+        .map(PathBuf::from)
+    );
+}
+
+#[test]
+fn if_exists_skip_writes_the_others_and_exits_with_4() {
+    let temp = TempDir::new().unwrap();
+    let tree = conflicting_tree(&temp);
+    bulk(tree.root())
+        .args(["--if-exists", "skip"])
+        .assert()
+        .code(4)
+        .stdout(predicate::str::contains(
+            "+ output/alpha-subscription/subscription.yaml created",
+        ))
+        .stdout(predicate::str::contains(
+            "- output/beta-subscription skipped: directory \"output/beta-subscription\" of application \"beta\" already exists",
+        ))
+        .stdout(predicate::str::contains(
+            "Skipped 2 application(s) whose directory already exists",
+        ))
+        .stderr(predicate::str::contains(
+            "2 application(s) were skipped by --if-exists skip",
+        ));
+    temp.child("output/beta-subscription/subscription.yaml")
+        .assert("old beta\n");
+    temp.child("output/gamma-subscription/subscription.yaml")
+        .assert(predicate::path::exists());
+    let index = std::fs::read_to_string(temp.child("output/index.yaml")).unwrap();
+    assert!(index.contains("alpha-subscription") && !index.contains("beta-subscription"));
+
+    let clean = TempDir::new().unwrap();
+    let tree = FixtureTree::new(clean.path());
+    tree.export(
+        "exports/app-alpha",
+        &[app("alpha", &[("orders", "1", "dev")])],
+    )
+    .unwrap();
+    clean.child("output").create_dir_all().unwrap();
+    bulk(tree.root())
+        .args(["--if-exists", "skip"])
+        .assert()
+        .success();
+}
+
+#[test]
+fn if_exists_overwrite_replaces_every_directory() {
+    let temp = TempDir::new().unwrap();
+    let tree = conflicting_tree(&temp);
+    bulk(tree.root())
+        .args(["--if-exists", "overwrite"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Created 2 directories, reused 2 existing ones",
+        ));
+    temp.child("output/delta-subscription/subscription.yaml")
+        .assert(predicate::str::contains("name: delta"));
+}
+
+#[test]
+fn tags_become_labels_and_select_applications() {
+    let temp = TempDir::new().unwrap();
+    let tree = FixtureTree::new(temp.path());
+    tree.file(
+        "exports/app-eu/subscribe.xml",
+        r#"<applications>
+  <application name="billing">
+    <tag>pci</tag><tag> internal </tag><tag>pci</tag><tag></tag>
+    <subscription apiName="invoices" apiVersion="v1" environment="prod"/>
+  </application>
+  <application name="shop">
+    <tag>internal</tag>
+    <subscription apiName="orders" apiVersion="1" environment="dev"/>
+  </application>
+</applications>"#,
+    )
+    .unwrap();
+    tree.file(
+        "exports/app-us/subscribe.xml",
+        r#"<applications>
+  <application name="billing">
+    <tag>gdpr</tag>
+    <subscription apiName="invoices" apiVersion="v1" environment="dev"/>
+  </application>
+</applications>"#,
+    )
+    .unwrap();
+    temp.child("output").create_dir_all().unwrap();
+
+    bulk(tree.root())
+        .args(["--emit-labels", "--include-tag", "pci"])
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("element").not())
+        .stdout(predicate::str::contains(
+            "Skipped 1 application(s) by the allow and deny lists and --include-tag",
+        ));
+    temp.child("output/billing-subscription/subscription.yaml")
+        .assert(predicate::str::ends_with(
+            "    labels:\n    - gdpr\n    - internal\n    - pci\n",
+        ));
+    temp.child("output/shop-subscription")
+        .assert(predicate::path::missing());
+
+    bulk(tree.root()).arg("--force").assert().success();
+    temp.child("output/billing-subscription/subscription.yaml")
+        .assert(predicate::str::contains("labels").not());
+}
+
+#[test]
+fn token_validity_is_written_in_the_requested_format() {
+    let temp = TempDir::new().unwrap();
+    let tree = FixtureTree::new(temp.path());
+    let mut billing = app("billing", &[("invoices", "v1", "prod")]);
+    billing.token_validity = 86400;
+    let mut shop = app("shop", &[("orders", "1", "dev")]);
+    shop.token_validity = 5400;
+    tree.export("exports/app-eu", &[billing, shop]).unwrap();
+    temp.child("output").create_dir_all().unwrap();
+
+    bulk(tree.root())
+        .args(["--token-validity-format", "duration"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("--emit-token-validity"));
+
+    bulk(tree.root())
+        .args([
+            "--emit-token-validity",
+            "--token-validity-format",
+            "iso8601",
+        ])
+        .assert()
+        .success();
+    temp.child("output/billing-subscription/subscription.yaml")
+        .assert(predicate::str::contains("tokenValidity: PT24H\n"));
+    temp.child("output/shop-subscription/subscription.yaml")
+        .assert(predicate::str::contains("tokenValidity: PT1H30M\n"));
+
+    // the same validity in another format counts as unchanged
+    bulk(tree.root())
+        .args(["--force", "--emit-token-validity"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "= output/billing-subscription/subscription.yaml unchanged",
+        ));
+
+    std::fs::remove_dir_all(temp.child("output")).unwrap();
+    temp.child("output").create_dir_all().unwrap();
+    bulk(tree.root())
+        .args([
+            "--emit-token-validity",
+            "--token-validity-format",
+            "duration",
+        ])
+        .assert()
+        .success();
+    temp.child("output/billing-subscription/subscription.yaml")
+        .assert(predicate::str::contains("tokenValidity: 1d\n"));
+    temp.child("output/shop-subscription/subscription.yaml")
+        .assert(predicate::str::contains("tokenValidity: 90m\n"));
+}
+
+#[cfg(unix)]
+#[test]
+fn symlinked_aliases_of_a_source_directory_are_parsed_once() {
+    let temp = TempDir::new().unwrap();
+    let tree = FixtureTree::new(temp.path());
+    tree.export(
+        "exports/app-billing",
+        &[app("billing", &[("invoices", "v1", "prod")])],
+    )
+    .unwrap();
+    std::os::unix::fs::symlink("app-billing", temp.child("exports/app-billing-old")).unwrap();
+    temp.child("output").create_dir_all().unwrap();
+
+    let sources = |report: &str| {
+        let report: serde_json::Value =
+            serde_json::from_slice(&std::fs::read(temp.child(report)).unwrap()).unwrap();
+        report["applications"][0]["sources"].clone()
+    };
+
+    bulk(tree.root())
+        .args(["--verbose", "--provenance-report", "provenance.json"])
+        .assert()
+        .success()
+        .stderr(predicate::str::contains(
+            "1 matched, 0 hidden, 0 ignored, 0 not matching the prefix, 1 aliases of another match",
+        ))
+        .stderr(predicate::str::contains(
+            "Skipped \"exports/app-billing-old\", the same directory as \"exports/app-billing\"",
+        ));
+    assert_eq!(
+        sources("provenance.json"),
+        serde_json::json!(["exports/app-billing/subscribe.xml"])
+    );
+
+    bulk(tree.root())
+        .args(["--force", "--no-dedupe-sources"])
+        .args(["--provenance-report", "aliased.json"])
+        .assert()
+        .success();
+    assert_eq!(
+        sources("aliased.json"),
+        serde_json::json!([
+            "exports/app-billing/subscribe.xml",
+            "exports/app-billing-old/subscribe.xml"
+        ])
+    );
+}
+
+#[test]
+fn names_differing_only_by_case_are_reported_or_merged() {
+    let temp = TempDir::new().unwrap();
+    let tree = FixtureTree::new(temp.path());
+    tree.export(
+        "exports/app-eu",
+        &[app("Checkout", &[("orders", "1", "dev")])],
+    )
+    .unwrap();
+    tree.export(
+        "exports/app-us",
+        &[app("checkout", &[("users", "2", "prod")])],
+    )
+    .unwrap();
+    temp.child("output").create_dir_all().unwrap();
+
+    bulk(tree.root())
+        .arg("--strict")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("error[case-collision]"));
+    temp.child("output/Checkout-subscription")
+        .assert(predicate::path::missing());
+
+    bulk(tree.root())
+        .assert()
+        .success()
+        .stderr(predicate::str::contains(
+            "warning[case-collision]: Checkout (exports/app-eu/subscribe.xml): names differ only by case",
+        ))
+        .stderr(predicate::str::contains(
+            "Checkout (exports/app-eu/subscribe.xml), checkout (exports/app-us/subscribe.xml)",
+        ));
+
+    std::fs::remove_dir_all(temp.child("output")).unwrap();
+    temp.child("output").create_dir_all().unwrap();
+    bulk(tree.root())
+        .args(["--merge-case-insensitive", "--verbose"])
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("case-collision").not())
+        .stderr(predicate::str::contains(
+            "Merged application \"checkout\" into \"Checkout\"",
+        ));
+    temp.child("output/Checkout-subscription/subscription.yaml")
+        .assert(predicate::str::contains("name: orders"))
+        .assert(predicate::str::contains("name: users"));
+    temp.child("output/checkout-subscription")
+        .assert(predicate::path::missing());
+}
+
+#[test]
+fn empty_environments_and_apis_are_written_as_empty_lists() {
+    let temp = TempDir::new().unwrap();
+    let tree = FixtureTree::new(temp.path());
+    tree.export(
+        "exports/app-legacy",
+        &[app("legacy", &[("orders", "v1", "qa2")])],
+    )
+    .unwrap();
+    temp.child("output").create_dir_all().unwrap();
+
+    bulk(tree.root())
+        .args(["--drop-env", "qa2"])
+        .assert()
+        .success();
+    temp.child("output/legacy-subscription/subscription.yaml")
+        .assert(
+            "environments: []
+subscriptions:
+  application:
+    name: legacy
+    description: legacy-subscription
+    apis: []
+",
+        );
+}
+
+#[cfg(unix)]
+#[test]
+fn report_candidates_classifies_every_scanned_directory() {
+    let temp = TempDir::new().unwrap();
+    let tree = FixtureTree::new(temp.path());
+    let export = |directory: &str| {
+        tree.export(directory, &[app("billing", &[("invoices", "v1", "prod")])])
+            .unwrap()
+    };
+    export("exports/app-ok");
+    export("exports/.app-hidden");
+    export("exports/app-ignored");
+    export("exports/other");
+    export("exports/app-zz-sliced");
+    set_modified(&export("exports/app-old"), 0);
+    tree.file("exports/app-broken/subscribe.xml", "<applications>")
+        .unwrap();
+    tree.file("exports/app-empty/README", "no export").unwrap();
+    tree.file("exports/.migratorignore", "/app-ignored\n")
+        .unwrap();
+    std::os::unix::fs::symlink("app-ok", temp.child("exports/app-ok-link")).unwrap();
+    temp.child("exports/app-out").create_dir_all().unwrap();
+
+    migrator(tree.root())
+        .args(["bulk", "--path", "exports", "--name-prefix", "app-"])
+        .args(["--output-path", "exports/app-out", "--environments", "all"])
+        .args(["--limit", "4", "--since", "1d"])
+        .args(["--report", "report.json", "--report-candidates"])
+        .args(["--error-report", "errors.json"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("2 directories failed to parse"));
+
+    let report: serde_json::Value =
+        serde_json::from_slice(&std::fs::read(temp.child("report.json")).unwrap()).unwrap();
+    let outcomes = report["candidates"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|candidate| {
+            (
+                candidate["directory"].as_str().unwrap(),
+                candidate["outcome"].as_str().unwrap(),
+            )
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        outcomes,
+        [
+            ("exports/.app-hidden", "hidden"),
+            ("exports/app-broken", "parse-failed"),
+            ("exports/app-empty", "missing-subscribe-xml"),
+            ("exports/app-ignored", "excluded-by-pattern"),
+            ("exports/app-ok", "processed"),
+            ("exports/app-ok-link", "deduped-symlink"),
+            ("exports/app-old", "filtered-by-since"),
+            ("exports/app-out", "in-output"),
+            ("exports/app-zz-sliced", "filtered-by-slice"),
+            ("exports/other", "not-matching"),
+        ]
+    );
+    assert_eq!(report["candidates"][5]["of"], "exports/app-ok");
+    assert!(report["candidates"][1]["error"].is_string());
+}
+
+#[test]
+fn field_order_changes_the_key_order_but_not_the_content() {
+    let temp = copy_fixture("happy");
+    bulk(temp.path())
+        .args(["--field-order", "subscription-first"])
+        .assert()
+        .success();
+    temp.child("output/billing-subscription/subscription.yaml")
+        .assert(predicate::str::starts_with("subscriptions:\n"));
+
+    bulk(temp.path())
+        .arg("--force")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "= output/billing-subscription/subscription.yaml unchanged",
+        ));
+}
+
+#[test]
+fn version_parity_findings_are_warned_and_reported() {
+    let temp = TempDir::new().unwrap();
+    let tree = FixtureTree::new(temp.path());
+    tree.export(
+        "exports/app-checkout",
+        &[app(
+            "checkout",
+            &[("orders", "2", "dev"), ("orders", "3", "prod")],
+        )],
+    )
+    .unwrap();
+    temp.child("output").create_dir_all().unwrap();
+
+    bulk(tree.root())
+        .args(["--check-version-parity", "--report", "report.json"])
+        .assert()
+        .success()
+        .stderr(predicate::str::contains(
+            "warning[version-parity]: checkout orders v3",
+        ));
+    let report: serde_json::Value =
+        serde_json::from_slice(&std::fs::read(temp.child("report.json")).unwrap()).unwrap();
+    assert_eq!(report["findings"][0]["rule"], "version-parity");
+    assert_eq!(report["findings"][0]["version"], "3");
+
+    bulk(tree.root())
+        .args(["--check-version-parity", "--strict", "--force"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("error[version-parity]"));
+}
+
+#[test]
+fn version_lists_the_enabled_features() {
+    let temp = TempDir::new().unwrap();
+    let output = migrator(temp.path()).arg("--version").output().unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let features = stdout
+        .lines()
+        .find_map(|line| line.strip_prefix("features: "))
+        .unwrap();
+    assert_eq!(
+        features.contains("archive"),
+        cfg!(feature = "archive"),
+        "{}",
+        stdout
+    );
+    assert_eq!(
+        features.contains("git"),
+        cfg!(feature = "git"),
+        "{}",
+        stdout
+    );
+
+    #[cfg(not(feature = "archive"))]
+    migrator(temp.path())
+        .args(["bulk", "--help"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("--archive").not());
+
+    #[cfg(not(feature = "git"))]
+    migrator(temp.path())
+        .args(["bulk", "--help"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("--only-changed").not());
+}
+
+#[cfg(feature = "archive")]
+#[test]
+fn archives_unpack_to_the_files_of_a_directory_run() {
+    let temp = copy_fixture("happy");
+    bulk(temp.path()).assert().success();
+    for archive in ["out.tar.gz", "out.zip"] {
+        bulk(temp.path())
+            .args(["--archive", archive])
+            .assert()
+            .success()
+            .stdout(predicate::str::contains(format!(
+                "Archive written: {}",
+                archive
+            )));
+        let unpacked = temp.child(format!("unpacked-{}", archive));
+        let file = std::fs::File::open(temp.child(archive)).unwrap();
+        match archive.ends_with(".zip") {
+            true => zip::ZipArchive::new(file)
+                .unwrap()
+                .extract(unpacked.path())
+                .unwrap(),
+            false => tar::Archive::new(flate2::read::GzDecoder::new(file))
+                .unpack(unpacked.path())
+                .unwrap(),
+        }
+        assert_same_tree(&temp.child("output"), &unpacked);
+    }
+}
+
+#[test]
+fn version_json_describes_the_build() {
+    let temp = TempDir::new().unwrap();
+    let output = migrator(temp.path())
+        .args(["--version", "--json"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let version: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(version["version"], env!("CARGO_PKG_VERSION"));
+    let commit = version["commit"].as_str().unwrap();
+    assert!(commit == "unknown" || commit.len() == 40, "{}", commit);
+    let built_at = version["build_timestamp"].as_str().unwrap();
+    assert!(chrono::DateTime::parse_from_rfc3339(built_at).is_ok());
+    assert_eq!(version["schema_versions"], serde_json::json!([1, 2]));
+    let strings = |key: &str| {
+        version[key]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|value| value.as_str().unwrap().to_string())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        strings("features").contains(&"archive".to_string()),
+        cfg!(feature = "archive")
+    );
+    assert!(strings("output_formats").contains(&"directory".to_string()));
+
+    migrator(temp.path())
+        .arg("--version")
+        .assert()
+        .success()
+        .stdout(predicate::str::starts_with(format!(
+            "Migrator {} ({})\n",
+            env!("CARGO_PKG_VERSION"),
+            commit.get(..7).unwrap_or(commit)
+        )));
+}
+
+#[test]
+fn dry_run_previews_new_files_without_writing() {
+    let temp = copy_fixture("happy");
+    bulk(temp.path())
+        .arg("--dry-run")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "new file: output/billing-subscription/subscription.yaml\n+environments:\n",
+        ))
+        .stdout(predicate::str::contains("+    - name: invoices\n"))
+        .stdout(predicate::str::contains(
+            "Dry run, nothing was written to output",
+        ));
+    assert!(files(&temp.child("output")).is_empty());
+}
+
+#[test]
+fn dry_run_diffs_existing_files() {
+    let temp = copy_fixture("happy");
+    bulk(temp.path()).assert().success();
+    let checkout = temp.child("output/checkout-subscription/subscription.yaml");
+    let content = std::fs::read_to_string(checkout.path()).unwrap();
+    checkout
+        .write_str(&content.replace("version: '2'", "version: '3'"))
+        .unwrap();
+
+    bulk(temp.path())
+        .args(["--dry-run", "--force"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "unchanged: output/billing-subscription/subscription.yaml",
+        ))
+        .stdout(predicate::str::contains(
+            "--- output/checkout-subscription/subscription.yaml\n+++ output/checkout-subscription/subscription.yaml\n",
+        ))
+        .stdout(predicate::str::contains("-      version: '3'\n+      version: '2'\n"));
+    assert!(std::fs::read_to_string(checkout.path())
+        .unwrap()
+        .contains("version: '3'"));
+}
+
+#[test]
+fn dry_run_previews_redact_credentials() {
+    let temp = TempDir::new().unwrap();
+    let tree = FixtureTree::new(temp.path());
+    let with_secret = |name: &str, secret: &str| {
+        let mut application = app(name, &[("orders", "1", "dev")]);
+        application.credentials = Some(Credentials {
+            consumer_key: Secret::new("key-3f9a".to_string()),
+            consumer_secret: Secret::new(secret.to_string()),
+        });
+        application
+    };
+    tree.export(
+        "exports/app-checkout",
+        &[with_secret("checkout", "old-secret-71c2")],
+    )
+    .unwrap();
+    temp.child("output").create_dir_all().unwrap();
+    bulk(temp.path())
+        .arg("--include-credentials")
+        .assert()
+        .success();
+    tree.export(
+        "exports/app-checkout",
+        &[with_secret("checkout", "new-secret-e05d")],
+    )
+    .unwrap();
+    tree.export(
+        "exports/app-billing",
+        &[with_secret("billing", "new-secret-e05d")],
+    )
+    .unwrap();
+
+    let output = bulk(temp.path())
+        .args(["--include-credentials", "--dry-run", "--force"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "changed: output/checkout-subscription/subscription.yaml (credentials only)\n",
+        ))
+        .stdout(predicate::str::contains("+      consumerSecret: ***\n"))
+        .get_output()
+        .clone();
+    for stream in [&output.stdout, &output.stderr] {
+        let text = String::from_utf8_lossy(stream);
+        for secret in ["key-3f9a", "old-secret-71c2", "new-secret-e05d"] {
+            assert!(!text.contains(secret), "{} leaked into:\n{}", secret, text);
+        }
+    }
+}
+
+#[test]
+fn upgrade_and_plan_keep_credentials_out_of_their_output() {
+    let temp = TempDir::new().unwrap();
+    let tree = FixtureTree::new(temp.path());
+    let mut checkout = app("checkout", &[("orders", "1", "dev")]);
+    checkout.credentials = Some(Credentials {
+        consumer_key: Secret::new("key-3f9a".to_string()),
+        consumer_secret: Secret::new("secret-71c2".to_string()),
+    });
+    tree.export("exports/app-checkout", &[checkout]).unwrap();
+    temp.child("output").create_dir_all().unwrap();
+    bulk(temp.path())
+        .arg("--include-credentials")
+        .assert()
+        .success();
+
+    let assert_redacted = |output: &std::process::Output| {
+        for stream in [&output.stdout, &output.stderr] {
+            let text = String::from_utf8_lossy(stream);
+            for secret in ["key-3f9a", "secret-71c2"] {
+                assert!(!text.contains(secret), "{} leaked into:\n{}", secret, text);
+            }
+        }
+    };
+    let upgrade = migrator(temp.path())
+        .args(["upgrade", "--path", "output", "--dry-run"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("+schemaVersion: 2\n"))
+        .stdout(predicate::str::contains("     consumerSecret: ***\n"))
+        .get_output()
+        .clone();
+    assert_redacted(&upgrade);
+
+    let plan = migrator(temp.path())
+        .args(["plan", "--path", "exports", "--name-prefix", "app-"])
+        .args(["--output-path", "output", "--plan", "plan.json"])
+        .arg("--include-credentials")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "--include-credentials can't be used with plan",
+        ))
+        .get_output()
+        .clone();
+    assert_redacted(&plan);
+    temp.child("plan.json").assert(predicate::path::missing());
+}
+
+#[test]
+fn allowed_envs_are_checked_after_env_aliases() {
+    let temp = TempDir::new().unwrap();
+    let tree = FixtureTree::new(temp.path());
+    tree.export(
+        "exports/app-checkout",
+        &[app(
+            "checkout",
+            &[("orders", "1", "production"), ("orders", "1", "dev")],
+        )],
+    )
+    .unwrap();
+    temp.child("output").create_dir_all().unwrap();
+    let checked = |aliases: &[&str]| {
+        let mut command = bulk(temp.path());
+        command
+            .args(aliases)
+            .args(["--allowed-envs", "dev,test,prod", "--strict", "--dry-run"]);
+        command
+    };
+
+    checked(&[])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "environment \"production\" is not one of [dev, test, prod]",
+        ));
+    checked(&["--env-alias", "production=prod"])
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("unknown-environment").not());
+}
+
+#[cfg(unix)]
+#[test]
+fn ctrl_c_outside_a_write_exits_at_once() {
+    use std::time::{Duration, Instant};
+
+    let temp = TempDir::new().unwrap();
+    // opening the pipe blocks until a writer shows up, which never happens
+    let fifo = temp.child("all-subscriptions.xml");
+    std::process::Command::new("mkfifo")
+        .arg(fifo.path())
+        .status()
+        .unwrap();
+    let mut child = std::process::Command::new(env!("CARGO_BIN_EXE_subscription_migrator"))
+        .current_dir(temp.path())
+        .args(["--plain", "file", "--input", "all-subscriptions.xml"])
+        .args(["--output-dir", "output", "--create-output-dir"])
+        .stderr(std::process::Stdio::piped())
+        .spawn()
+        .unwrap();
+    std::thread::sleep(Duration::from_millis(500));
+    std::process::Command::new("kill")
+        .args(["-INT", &child.id().to_string()])
+        .status()
+        .unwrap();
+
+    let started = Instant::now();
+    let status = loop {
+        if let Some(status) = child.try_wait().unwrap() {
+            break Some(status);
+        }
+        if started.elapsed() > Duration::from_secs(5) {
+            child.kill().unwrap();
+            break None;
+        }
+        std::thread::sleep(Duration::from_millis(20));
+    };
+    assert_eq!(status.and_then(|status| status.code()), Some(130));
+}
+
+#[cfg(unix)]
+#[test]
+fn stale_locks_are_reclaimed_without_leftovers() {
+    let temp = copy_fixture("happy");
+    let mut finished = std::process::Command::new("true").spawn().unwrap();
+    finished.wait().unwrap();
+    temp.child("output/.migrator.lock")
+        .write_str(&format!(
+            "pid={}\nstarted=2026-01-01T00:00:00+00:00\n",
+            finished.id()
+        ))
+        .unwrap();
+
+    bulk(temp.path())
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("removing stale lock"));
+    let leftovers = files(&temp.child("output"))
+        .into_iter()
+        .filter(|file| file.to_string_lossy().contains(".migrator.lock"))
+        .collect::<Vec<_>>();
+    assert_eq!(leftovers, Vec::<PathBuf>::new());
+}
+
+#[test]
+fn parse_timeout_gives_up_on_a_pathological_export() {
+    let temp = TempDir::new().unwrap();
+    let tree = FixtureTree::new(temp.path());
+    tree.export(
+        "exports/app-ok",
+        &[app("checkout", &[("orders", "1", "dev")])],
+    )
+    .unwrap();
+    let noise = "<noise/>".repeat(200_000);
+    tree.file(
+        "exports/app-slow/subscribe.xml",
+        &format!("<applications>{}</applications>", noise),
+    )
+    .unwrap();
+    temp.child("output").create_dir_all().unwrap();
+
+    let started = std::time::Instant::now();
+    bulk(tree.root())
+        .args(["--parse-timeout", "1ms", "--error-report", "errors.json"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "Gave up parsing \"exports/app-slow/subscribe.xml\": parsing timed out after 1ms",
+        ))
+        .stderr(predicate::str::contains("1 directories failed to parse"));
+    assert!(started.elapsed() < std::time::Duration::from_secs(30));
+    temp.child("output/checkout-subscription/subscription.yaml")
+        .assert(predicate::path::exists());
+}
+
+#[test]
+fn options_fall_back_to_migrator_environment_variables() {
+    let temp = copy_fixture("happy");
+    temp.child("elsewhere").create_dir_all().unwrap();
+    migrator(temp.path())
+        .args(["bulk", "--path", "exports", "--environments", "all"])
+        .env("MIGRATOR_NAME_PREFIX", "app-")
+        .env("MIGRATOR_OUTPUT_PATH", "elsewhere")
+        .assert()
+        .success();
+    temp.child("elsewhere/checkout-subscription/subscription.yaml")
+        .assert(predicate::path::exists());
+
+    // the flag wins over the environment, booleans take 1/true/yes
+    migrator(temp.path())
+        .args(["bulk", "--path", "exports", "--environments", "all"])
+        .args(["--output-path", "elsewhere"])
+        .env("MIGRATOR_NAME_PREFIX", "app-")
+        .env("MIGRATOR_OUTPUT_PATH", "output")
+        .env("MIGRATOR_FORCE", "yes")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "= elsewhere/checkout-subscription/subscription.yaml unchanged",
+        ));
+    assert!(files(&temp.child("output")).is_empty());
+
+    migrator(temp.path())
+        .args(["bulk", "--path", "exports", "--environments", "all"])
+        .args(["--output-path", "elsewhere", "--print-config"])
+        .env("MIGRATOR_NAME_PREFIX", "app-")
+        .env("MIGRATOR_OUTPUT_PATH", "output")
+        .env("MIGRATOR_FORCE", "1")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "--output-path = elsewhere (flag)\n",
+        ))
+        .stdout(predicate::str::contains(
+            "--name-prefix = app- (env MIGRATOR_NAME_PREFIX)\n",
+        ))
+        .stdout(predicate::str::contains(
+            "--force = 1 (env MIGRATOR_FORCE)\n",
+        ))
+        .stdout(predicate::str::contains("--if-exists = error (default)\n"));
+
+    temp.child("migrator.toml")
+        .write_str("allowed_envs = [\"dev\", \"prod\"]\n")
+        .unwrap();
+    bulk(temp.path())
+        .args(["--print-config", "--config", "migrator.toml"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "--allowed-envs = dev,prod (config)\n",
+        ));
+}
+
+#[test]
+fn write_readme_describes_every_application() {
+    let temp = copy_fixture("happy");
+    bulk(temp.path())
+        .args(["--write-readme", "--reproducible"])
+        .assert()
+        .success();
+    let readme = temp.child("output/checkout-subscription/README.md");
+    readme.assert(
+        "\
+# checkout
+
+checkout-subscription
+
+## APIs
+
+| API | Version |
+| --- | --- |
+| orders | 1 |
+| users | 2 |
+
+## Environments
+
+| Control plane | Environments |
+| --- | --- |
+| https://non-prod.control-plane.com | dev, test |
+| https://prod.control-plane.com | prod |
+
+## Sources
+
+- app-checkout/subscribe.xml
+
+_Generated by subscription-migrator from the same data as subscription.yaml, do not edit_
+",
+    );
+
+    // only the time of the run differs
+    bulk(temp.path())
+        .args(["--write-readme", "--force"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "= output/checkout-subscription/subscription.yaml unchanged",
+        ));
+    readme.assert(predicate::str::contains("subscription-migrator from"));
+
+    readme.write_str("# notes\n").unwrap();
+    bulk(temp.path())
+        .args(["--write-readme", "--force"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "~ output/checkout-subscription/subscription.yaml overwritten",
+        ));
+    readme.assert(predicate::str::is_match("subscription-migrator at \\d{4}-").unwrap());
+}
+
+#[test]
+fn check_env_parity_flags_prod_subscriptions_without_pre_prod_ones() {
+    let temp = TempDir::new().unwrap();
+    let tree = FixtureTree::new(temp.path());
+    tree.export(
+        "exports/app-compliant",
+        &[app(
+            "compliant",
+            &[("orders", "1", "prod"), ("orders", "1", "dev")],
+        )],
+    )
+    .unwrap();
+    tree.export(
+        "exports/app-violating",
+        &[app(
+            "violating",
+            &[
+                ("orders", "1", "prod"),
+                ("orders", "1", "test"),
+                ("payments", "2", "prod"),
+            ],
+        )],
+    )
+    .unwrap();
+    tree.export(
+        "exports/app-prod-only",
+        &[app(
+            "prod-only",
+            &[("orders", "1", "prod"), ("payments", "2", "prod")],
+        )],
+    )
+    .unwrap();
+    let validate = |strict: bool| {
+        let mut command = migrator(temp.path());
+        command
+            .args(["validate", "--path", "exports", "--name-prefix", "app-"])
+            .arg("--check-env-parity");
+        if strict {
+            command.arg("--strict");
+        }
+        command
+    };
+
+    let output = validate(false).assert().success().get_output().clone();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    let findings = stderr
+        .lines()
+        .filter(|line| line.starts_with("warning["))
+        .collect::<Vec<_>>();
+    assert_eq!(findings.len(), 2, "{}", stderr);
+    assert!(findings[0].starts_with(&format!(
+        "warning[prod-only-application]: prod-only ({}): application has no subscription in any of [dev, test]",
+        Path::new("exports/app-prod-only/subscribe.xml").display()
+    )));
+    assert!(findings[1].starts_with("warning[env-parity]: violating payments v2 ("));
+    assert!(!stderr.contains("compliant"));
+
+    validate(true)
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "error[env-parity]: violating payments v2",
+        ))
+        .stderr(predicate::str::contains(
+            "Validation failed with 2 error(s)",
+        ));
+}
+
+#[test]
+fn cache_dir_reuses_unchanged_exports() {
+    let temp = copy_fixture("happy");
+    let validate = || {
+        let mut command = migrator(temp.path());
+        command
+            .args(["validate", "--path", "exports", "--name-prefix", "app-"])
+            .args(["--cache-dir", "cache", "--verbose"]);
+        command
+    };
+    validate()
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("from the cache").not());
+    validate()
+        .assert()
+        .success()
+        .stderr(predicate::str::contains(
+            "Reused 2 parsed export(s) from the cache",
+        ));
+
+    // a changed export is parsed again
+    let export = temp.child("exports/app-billing/subscribe.xml");
+    let content = std::fs::read_to_string(export.path()).unwrap();
+    export.write_str(&format!("{}\n", content)).unwrap();
+    validate()
+        .assert()
+        .success()
+        .stderr(predicate::str::contains(
+            "Reused 1 parsed export(s) from the cache",
+        ));
+
+    // broken entries are misses, not errors
+    for entry in std::fs::read_dir(temp.child("cache")).unwrap() {
+        std::fs::write(entry.unwrap().path(), "{ not json").unwrap();
+    }
+    validate()
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("from the cache").not());
+}
+
+#[cfg(feature = "git")]
+#[test]
+fn only_changed_migrates_the_exports_changed_in_git() {
+    let temp = copy_fixture("happy");
+    let git = |args: &[&str]| {
+        let status = std::process::Command::new("git")
+            .current_dir(temp.path())
+            .args(["-c", "user.name=test", "-c", "user.email=test@example.com"])
+            .args(args)
+            .output()
+            .unwrap()
+            .status;
+        assert!(status.success(), "git {:?} failed", args);
+    };
+    let legacy = temp.child("exports/app-legacy/subscribe.xml");
+    legacy
+        .write_str(r#"<applications><application name="legacy"/></applications>"#)
+        .unwrap();
+    git(&["init", "--quiet"]);
+    git(&["add", "exports"]);
+    git(&["commit", "--quiet", "--no-gpg-sign", "-m", "exports"]);
+
+    let export = temp.child("exports/app-billing/subscribe.xml");
+    let content = std::fs::read_to_string(export.path()).unwrap();
+    export.write_str(&format!("{}\n", content)).unwrap();
+    std::fs::remove_dir_all(temp.child("exports/app-legacy")).unwrap();
+
+    bulk(temp.path())
+        .args(["--only-changed", "--report", "report.json"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Skipped 1 directories unchanged since HEAD",
+        ))
+        .stderr(predicate::str::contains(
+            "\"exports/app-legacy/subscribe.xml\" was deleted since HEAD",
+        ));
+    assert_eq!(
+        files(&temp.child("output")),
+        [
+            PathBuf::from("billing-subscription/subscription.yaml"),
+            PathBuf::from("index.yaml"),
+        ]
+    );
+    let report: serde_json::Value =
+        serde_json::from_slice(&std::fs::read(temp.child("report.json")).unwrap()).unwrap();
+    assert_eq!(
+        report["deleted_exports"],
+        serde_json::json!(["exports/app-legacy/subscribe.xml"])
+    );
+
+    let outside = copy_fixture("happy");
+    bulk(outside.path())
+        .arg("--only-changed")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("to be inside a git work tree"));
+}
+
+#[cfg(unix)]
+#[test]
+fn unreadable_exports_are_reported_by_what_is_wrong() {
+    let temp = TempDir::new().unwrap();
+    let tree = FixtureTree::new(temp.path());
+    tree.export(
+        "exports/app-checkout",
+        &[app("checkout", &[("orders", "1", "dev")])],
+    )
+    .unwrap();
+    temp.child("exports/app-dir/subscribe.xml")
+        .create_dir_all()
+        .unwrap();
+    temp.child("exports/app-link").create_dir_all().unwrap();
+    std::os::unix::fs::symlink("../gone.xml", temp.child("exports/app-link/subscribe.xml"))
+        .unwrap();
+    temp.child("output").create_dir_all().unwrap();
+
+    bulk(tree.root())
+        .args(["--error-report", "errors.json"])
+        .args(["--report", "report.json", "--report-candidates"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "The export \"exports/app-dir/subscribe.xml\" of \"exports/app-dir\" is a directory",
+        ))
+        .stderr(predicate::str::contains(
+            "is a symlink to \"../gone.xml\", which doesn't exist. Fix or remove the link",
+        ));
+    temp.child("output/checkout-subscription/subscription.yaml")
+        .assert(predicate::path::exists());
+
+    let read = |file: &str| -> serde_json::Value {
+        serde_json::from_slice(&std::fs::read(temp.child(file)).unwrap()).unwrap()
+    };
+    let kinds = read("errors.json")
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|failure| failure["kind"].as_str().unwrap().to_string())
+        .collect::<Vec<_>>();
+    assert_eq!(kinds, ["export_is_directory", "dangling_symlink"]);
+    let outcomes = read("report.json")["candidates"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|candidate| candidate["outcome"].as_str().unwrap().to_string())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        outcomes,
+        ["processed", "export-is-directory", "dangling-symlink"]
+    );
+}
+
+#[test]
+fn expect_applications_aborts_a_run_of_another_size() {
+    let temp = copy_fixture("happy");
+    bulk(temp.path())
+        .args(["--expect-applications", "3", "--expect-directories", "2"])
+        .args(["--report", "report.json"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "Applications: expected 3, found 2. Nothing was written",
+        ));
+    assert!(files(&temp.child("output")).is_empty());
+    let report: serde_json::Value =
+        serde_json::from_slice(&std::fs::read(temp.child("report.json")).unwrap()).unwrap();
+    assert_eq!(
+        report["expectations"],
+        serde_json::json!({
+            "applications": {"expected": 3, "actual": 2, "tolerance": 0.0},
+            "directories": {"expected": 2, "actual": 2, "tolerance": 0.0},
+        })
+    );
+
+    bulk(temp.path())
+        .args(["--expect-applications", "3", "--expect-tolerance", "50%"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Applications: expected 3 ±50%, found 2",
+        ));
+    assert_same_tree(&fixture("happy/expected"), &temp.child("output"));
+}
+
+#[test]
+fn metrics_file_is_valid_prometheus_text() {
+    let temp = copy_fixture("happy");
+    bulk(temp.path())
+        .args(["--metrics-file", "migrator.prom"])
+        .assert()
+        .success();
+    bulk(temp.path())
+        .args(["--metrics-file", "migrator.prom", "--force"])
+        .assert()
+        .success();
+
+    let text = std::fs::read_to_string(temp.child("migrator.prom")).unwrap();
+    let sample =
+        regex::Regex::new(r#"^(subscription_migrator_[a-z_]+)(\{environment="[^"\\]*"\})? (\d+)$"#)
+            .unwrap();
+    let mut values = std::collections::BTreeMap::new();
+    let mut declared = Vec::new();
+    for line in text.lines() {
+        if let Some(comment) = line.strip_prefix("# TYPE ") {
+            let (name, kind) = comment.split_once(' ').unwrap();
+            assert_eq!(kind, "gauge", "{}", line);
+            declared.push(name.to_string());
+        } else if !line.starts_with("# HELP subscription_migrator_") {
+            let captures = sample.captures(line).unwrap_or_else(|| panic!("{}", line));
+            assert!(declared.contains(&captures[1].to_string()), "{}", line);
+            let name = format!(
+                "{}{}",
+                &captures[1],
+                captures.get(2).map_or("", |m| m.as_str())
+            );
+            values.insert(name, captures[3].parse::<u64>().unwrap());
+        }
+    }
+    assert_eq!(values["subscription_migrator_applications_total"], 2);
+    assert_eq!(values["subscription_migrator_files_written_total"], 0);
+    assert_eq!(values["subscription_migrator_files_unchanged_total"], 2);
+    assert_eq!(values["subscription_migrator_failures_total"], 0);
+    assert!(values["subscription_migrator_last_run_timestamp_seconds"] > 0);
+    assert!(values
+        .keys()
+        .any(|name| name.starts_with("subscription_migrator_subscriptions{environment=")));
+}
+
+#[test]
+fn application_names_cannot_write_outside_the_output_path() {
+    let temp = TempDir::new().unwrap();
+    let tree = FixtureTree::new(temp.path());
+    let outside = temp.child("absolute");
+    tree.export(
+        "work/exports/app-evil",
+        &[
+            app("../../escaped", &[("orders", "1", "dev")]),
+            app(outside.path().to_str().unwrap(), &[("orders", "1", "dev")]),
+            app(r"..\..\backslashed", &[("orders", "1", "dev")]),
+            app("billing", &[("orders", "1", "dev")]),
+        ],
+    )
+    .unwrap();
+    let work = temp.child("work");
+    work.child("output").create_dir_all().unwrap();
+
+    bulk(work.path())
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains(
+            "Refusing to write \"output/../../escaped-subscription/subscription.yaml\", \
+             it is outside the output directory \"output\"",
+        ))
+        .stderr(predicate::str::contains(format!(
+            "{} file(s) were skipped or failed",
+            if cfg!(windows) { 3 } else { 2 }
+        )));
+    temp.child("escaped-subscription")
+        .assert(predicate::path::missing());
+    temp.child("absolute-subscription")
+        .assert(predicate::path::missing());
+    work.child("output/billing-subscription/subscription.yaml")
+        .assert(predicate::path::exists());
+    // a backslash is an ordinary character outside Windows
+    #[cfg(unix)]
+    work.child(r"output/..\..\backslashed-subscription/subscription.yaml")
+        .assert(predicate::path::exists());
+}
+
+#[test]
+fn file_migrates_every_application_of_a_concatenated_export() {
+    let temp = copy_fixture("concatenated");
+    migrator(temp.path())
+        .args([
+            "file",
+            "--input",
+            "all-subscriptions.xml",
+            "--output-dir",
+            "output",
+        ])
+        .args(["--error-report", "errors.json"])
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains(
+            "Migrated 49 of 50 application(s) read from all-subscriptions.xml",
+        ))
+        .stderr(predicate::str::contains(
+            "Failed to parse application 25 of \"all-subscriptions.xml\": \
+             Application \"app25\" has an invalid tokenValidity \"forever\"",
+        ))
+        .stderr(predicate::str::contains("1 application(s) failed to parse"));
+
+    let documents = files(&temp.child("output"))
+        .into_iter()
+        .filter(|file| file.ends_with("subscription.yaml"))
+        .collect::<Vec<_>>();
+    assert_eq!(documents.len(), 49);
+    assert!(!documents.contains(&PathBuf::from("app25-subscription/subscription.yaml")));
+    let errors: serde_json::Value =
+        serde_json::from_slice(&std::fs::read(temp.child("errors.json")).unwrap()).unwrap();
+    assert_eq!(errors.as_array().unwrap().len(), 1);
+    assert_eq!(errors[0]["phase"], "parse");
+    assert_eq!(errors[0]["directory"], "all-subscriptions.xml");
+    assert_eq!(errors[0]["application_index"], 25);
+}
+
+#[test]
+fn file_unify_merges_applications_while_reading() {
+    let temp = TempDir::new().unwrap();
+    let tree = FixtureTree::new(temp.path());
+    tree.file(
+        "all-subscriptions.xml",
+        r#"<applications>
+    <application name="checkout" tokenType="JWT">
+        <subscription apiName="orders" apiVersion="v1" environment="dev"/>
+    </application>
+    <application name="billing">
+        <subscription apiName="invoices" apiVersion="v2" environment="prod"/>
+    </application>
+    <application name="checkout" tokenType="OAUTH">
+        <subscription apiName="orders" apiVersion="v1" environment="dev"/>
+        <subscription apiName="users" apiVersion="v1" environment="dev"/>
+    </application>
+</applications>"#,
+    )
+    .unwrap();
+    temp.child("output").create_dir_all().unwrap();
+    migrator(temp.path())
+        .args([
+            "file",
+            "--input",
+            "all-subscriptions.xml",
+            "--output-dir",
+            "output",
+        ])
+        .arg("--unify")
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("token-conflict"))
+        .stdout(predicate::str::contains(
+            "Migrated 2 of 3 application(s) read from all-subscriptions.xml",
+        ));
+
+    let checkout =
+        std::fs::read_to_string(temp.child("output/checkout-subscription/subscription.yaml"))
+            .unwrap();
+    assert_eq!(checkout.matches("- name: orders").count(), 1);
+    assert!(checkout.contains("- name: users"));
+}
+
+#[test]
+fn plan_and_apply_cannot_write_outside_the_output_path() {
+    let temp = TempDir::new().unwrap();
+    let tree = FixtureTree::new(temp.path());
+    tree.export(
+        "work/exports/app-evil",
+        &[app("../../escaped", &[("orders", "1", "dev")])],
+    )
+    .unwrap();
+    tree.export(
+        "work/exports/app-billing",
+        &[app("billing", &[("orders", "1", "dev")])],
+    )
+    .unwrap();
+    let work = temp.child("work");
+    work.child("output").create_dir_all().unwrap();
+    let plan = |prefix: &str| {
+        let mut command = migrator(work.path());
+        command
+            .args(["plan", "--path", "exports", "--name-prefix", prefix])
+            .args(["--output-path", "output", "--plan", "plan.json"]);
+        command
+    };
+
+    plan("app-")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "Refusing to write \"output/../../escaped-subscription/subscription.yaml\"",
+        ));
+    work.child("plan.json").assert(predicate::path::missing());
+
+    // a hand-edited plan is refused before anything is written
+    plan("app-billing").assert().success();
+    let content = std::fs::read_to_string(work.child("plan.json")).unwrap();
+    let edited = content.replace(
+        "output/billing-subscription/subscription.yaml",
+        "output/../../escaped-subscription/subscription.yaml",
+    );
+    assert_ne!(content, edited);
+    work.child("plan.json").write_str(&edited).unwrap();
+    migrator(work.path())
+        .args(["apply", "--plan", "plan.json"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "it is outside the output directory",
+        ));
+    temp.child("escaped-subscription")
+        .assert(predicate::path::missing());
+    assert!(files(&work.child("output")).is_empty());
+}
+
+#[test]
+fn apply_refuses_a_plan_whose_sources_changed() {
+    let temp = TempDir::new().unwrap();
+    let tree = FixtureTree::new(temp.path());
+    tree.export(
+        "exports/app-billing",
+        &[app("billing", &[("invoices", "v1", "prod")])],
+    )
+    .unwrap();
+    tree.export(
+        "exports/app-shop",
+        &[app("shop", &[("orders", "1", "dev")])],
+    )
+    .unwrap();
+    temp.child("output").create_dir_all().unwrap();
+    let plan = || {
+        migrator(temp.path())
+            .args(["plan", "--path", "exports", "--name-prefix", "app-"])
+            .args(["--output-path", "output", "--plan", "plan.json"])
+            .assert()
+            .success()
+    };
+    let apply = || {
+        let mut command = migrator(temp.path());
+        command.args(["apply", "--plan", "plan.json"]);
+        command
+    };
+
+    plan()
+        .stdout(predicate::str::contains(
+            "2 to create, 0 to change, 0 unchanged, 0 to prune",
+        ))
+        .stdout(predicate::str::contains("+ billing"));
+    let recorded: serde_json::Value =
+        serde_json::from_slice(&std::fs::read(temp.child("plan.json")).unwrap()).unwrap();
+    assert_eq!(recorded["sources"].as_array().unwrap().len(), 2);
+    assert!(recorded["files"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .all(|file| file["action"] == "create" && file["hash"].is_string()));
+
+    tree.export(
+        "exports/app-billing",
+        &[app("billing", &[("invoices", "v2", "prod")])],
+    )
+    .unwrap();
+    apply()
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "Changed since the plan was made, plan again: source",
+        ))
+        .stderr(predicate::str::contains("app-billing/subscribe.xml"))
+        .stderr(predicate::str::contains("app-shop").not());
+    assert!(files(&temp.child("output")).is_empty());
+
+    plan();
+    apply().assert().success();
+    temp.child("output/billing-subscription/subscription.yaml")
+        .assert(predicate::str::contains("version: v2"));
+
+    // a file edited in the output is refused as well
+    plan().stdout(predicate::str::contains(
+        "0 to create, 0 to change, 2 unchanged",
+    ));
+    temp.child("output/shop-subscription/subscription.yaml")
+        .write_str("edited")
+        .unwrap();
+    apply().assert().failure().stderr(predicate::str::contains(
+        "output \"output/shop-subscription/subscription.yaml\"",
+    ));
+}
+
+#[cfg(unix)]
+#[test]
+fn written_files_and_directories_get_the_requested_modes() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let mode = |path: &Path| std::fs::metadata(path).unwrap().permissions().mode() & 0o7777;
+    let temp = copy_fixture("happy");
+    bulk(temp.path())
+        .args(["--file-mode", "640", "--dir-mode", "0o750"])
+        .assert()
+        .success();
+    for application in ["billing", "checkout"] {
+        let directory = temp.child(format!("output/{application}-subscription"));
+        assert_eq!(mode(&directory), 0o750, "{:?}", directory.path());
+        let file = directory.child("subscription.yaml");
+        assert_eq!(mode(&file), 0o640, "{:?}", file.path());
+    }
+
+    let read_only = temp.child("read-only");
+    read_only.create_dir_all().unwrap();
+    migrator(temp.path())
+        .args(["bulk", "--path", "exports", "--name-prefix", "app-"])
+        .args(["--output-path", "read-only", "--environments", "all"])
+        .args(["--file-mode", "664", "--read-only"])
+        .assert()
+        .success();
+    let file = read_only.child("billing-subscription/subscription.yaml");
+    assert_eq!(mode(&file), 0o444);
+    assert!(std::fs::metadata(&file).unwrap().permissions().readonly());
+
+    bulk(temp.path())
+        .args(["--file-mode", "0o888"])
+        .assert()
+        .failure()
+        .code(2)
+        .stderr(predicate::str::contains(
+            "'0o888' is not an octal mode between 0 and 7777",
+        ));
+}
+
+///Asserts that the index of `output` lists exactly the subscription files
+///below it, with their API counts, environments and hashes.
+fn assert_index_matches_disk(output: &Path) {
+    let index: serde_yaml::Value =
+        serde_yaml::from_slice(&std::fs::read(output.join("index.yaml")).unwrap()).unwrap();
+    let entries = index["applications"].as_sequence().unwrap();
+    let mut indexed = Vec::new();
+    for entry in entries {
+        let path = PathBuf::from(entry["path"].as_str().unwrap());
+        let contents = std::fs::read(output.join(&path)).unwrap();
+        let document: serde_yaml::Value = serde_yaml::from_slice(&contents).unwrap();
+        let application = &document["subscriptions"]["application"];
+        assert_eq!(entry["name"], application["name"], "{:?}", path);
+        assert_eq!(
+            entry["apis"].as_u64().unwrap() as usize,
+            application["apis"].as_sequence().unwrap().len(),
+            "{:?}",
+            path
+        );
+        let environments = document["environments"]
+            .as_sequence()
+            .unwrap()
+            .iter()
+            .flat_map(|block| block["environment"].as_sequence().unwrap())
+            .map(|env| env["name"].clone())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            entry["environments"].as_sequence().unwrap(),
+            &environments,
+            "{:?}",
+            path
+        );
+        assert_eq!(
+            entry["hash"].as_str().unwrap(),
+            subscription_migrator::plan::content_hash(&contents),
+            "{:?}",
+            path
+        );
+        indexed.push(path);
+    }
+    let names = entries
+        .iter()
+        .map(|entry| entry["name"].as_str().unwrap())
+        .collect::<Vec<_>>();
+    let mut sorted = names.clone();
+    sorted.sort();
+    assert_eq!(names, sorted);
+    indexed.sort();
+    let on_disk = files(output)
+        .into_iter()
+        .filter(|file| file.ends_with("subscription.yaml"))
+        .collect::<Vec<_>>();
+    assert_eq!(indexed, on_disk);
+}
+
+#[test]
+fn index_matches_the_files_on_disk_across_partial_runs() {
+    let temp = TempDir::new().unwrap();
+    let tree = FixtureTree::new(temp.path());
+    for (name, env) in [("gamma", "prod"), ("alpha", "dev"), ("beta", "test")] {
+        tree.export(
+            &format!("exports/app-{name}"),
+            &[app(name, &[("orders", "1", env)])],
+        )
+        .unwrap();
+    }
+    let output = temp.child("output");
+    output.create_dir_all().unwrap();
+
+    bulk(tree.root()).assert().success();
+    assert_index_matches_disk(&output);
+    let before = std::fs::read_to_string(output.child("index.yaml")).unwrap();
+
+    // a run over alpha only keeps the entries of beta and gamma
+    tree.export(
+        "exports/app-alpha",
+        &[app(
+            "alpha",
+            &[("orders", "1", "dev"), ("catalog", "2", "prod")],
+        )],
+    )
+    .unwrap();
+    bulk_matching(tree.root(), "app-alpha")
+        .arg("--force")
+        .assert()
+        .success();
+    assert_index_matches_disk(&output);
+    let after = std::fs::read_to_string(output.child("index.yaml")).unwrap();
+    assert_ne!(before, after);
+    assert!(after.contains("name: beta") && after.contains("name: gamma"));
+
+    // a file removed by hand drops out with the next run
+    std::fs::remove_dir_all(output.child("beta-subscription")).unwrap();
+    bulk_matching(tree.root(), "app-gamma")
+        .arg("--force")
+        .assert()
+        .success();
+    assert_index_matches_disk(&output);
+    assert!(!std::fs::read_to_string(output.child("index.yaml"))
+        .unwrap()
+        .contains("beta"));
+
+    let unindexed = temp.child("unindexed");
+    unindexed.create_dir_all().unwrap();
+    migrator(tree.root())
+        .args(["bulk", "--path", "exports", "--name-prefix", "app-"])
+        .args(["--output-path", "unindexed", "--environments", "all"])
+        .arg("--no-index")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Index written").not());
+    assert_eq!(files(&unindexed).len(), 3);
+    unindexed
+        .child("index.yaml")
+        .assert(predicate::path::missing());
+}
+
+///Asserts that every newline of `file` is a CRLF.
+fn assert_crlf(file: &Path) {
+    let bytes = std::fs::read(file).unwrap();
+    let newlines = bytes.iter().filter(|&&byte| byte == b'\n').count();
+    assert!(newlines > 0, "{:?}", file);
+    assert_eq!(
+        bytes.windows(2).filter(|pair| pair == b"\r\n").count(),
+        newlines,
+        "{:?}",
+        file
+    );
+}
+
+#[test]
+fn line_endings_are_applied_byte_for_byte() {
+    let temp = copy_fixture("happy");
+    let output = temp.child("output");
+    let expected = fixture("happy/expected");
+    bulk(temp.path())
+        .args(["--line-endings", "crlf"])
+        .assert()
+        .success();
+    assert_eq!(files(&output), files(&expected));
+    for file in files(&expected) {
+        if file.ends_with("index.yaml") {
+            // the hashes are of the CRLF files
+            continue;
+        }
+        let lf = std::fs::read(expected.join(&file)).unwrap();
+        let crlf = String::from_utf8(lf).unwrap().replace('\n', "\r\n");
+        assert_eq!(
+            std::fs::read(output.join(&file)).unwrap(),
+            crlf.into_bytes(),
+            "{:?}",
+            file
+        );
+    }
+    assert_crlf(&output.join("index.yaml"));
+    assert_index_matches_disk(&output);
+
+    // CRLF files with the same content are neither drift nor rewritten
+    bulk(temp.path())
+        .arg("--force")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "= output/billing-subscription/subscription.yaml unchanged",
+        ));
+    assert_crlf(&output.join("billing-subscription/subscription.yaml"));
+    migrator(temp.path())
+        .args(["fmt", "--path", "output", "--check"])
+        .assert()
+        .success();
+
+    // the metadata block follows the same style
+    for (style, crlf) in [("crlf", true), ("lf", false)] {
+        std::fs::remove_dir_all(&output).unwrap();
+        output.create_dir_all().unwrap();
+        bulk(temp.path())
+            .args([
+                "--line-endings",
+                style,
+                "--emit-metadata",
+                "--emit-source-info",
+            ])
+            .assert()
+            .success();
+        for file in files(&output) {
+            let file = output.join(file);
+            match crlf {
+                true => assert_crlf(&file),
+                false => assert!(
+                    !std::fs::read(&file).unwrap().contains(&b'\r'),
+                    "{:?}",
+                    file
+                ),
+            }
+        }
+    }
+}
+
+#[test]
+fn nothing_to_migrate_exits_with_3_unless_allowed() {
+    let temp = copy_fixture("happy");
+    bulk_matching(temp.path(), "app-typo")
+        .assert()
+        .failure()
+        .code(3)
+        .stderr(predicate::str::contains(
+            "No directory below \"exports\" starts with \"app-typo\", found e.g. app-billing, app-checkout",
+        ));
+    bulk_matching(temp.path(), "app-typo")
+        .arg("--allow-empty")
+        .assert()
+        .success();
+
+    for filter in [
+        &["--offset", "2"][..],
+        &["--since", "2999-01-01T00:00:00Z"][..],
+    ] {
+        bulk(temp.path())
+            .args(filter)
+            .assert()
+            .failure()
+            .code(3)
+            .stderr(predicate::str::contains(
+                "All 2 matching directories below \"exports\" were filtered out",
+            ));
+        bulk(temp.path())
+            .args(filter)
+            .arg("--allow-empty")
+            .assert()
+            .success();
+    }
+    // an allowed empty run only writes the empty index
+    assert_eq!(files(&temp.child("output")), [PathBuf::from("index.yaml")]);
+}
+
+#[test]
+fn invalid_output_directories_are_reported_by_what_is_wrong() {
+    let temp = copy_fixture("malformed");
+    temp.child("a-file").write_str("not a directory").unwrap();
+    let tree = FixtureTree::new(temp.path());
+    tree.export("single/app-shop", &[app("shop", &[("orders", "1", "dev")])])
+        .unwrap();
+    let single = |output: &str| {
+        let mut command = migrator(temp.path());
+        command.args([
+            "single",
+            "--input-dir",
+            "single/app-shop",
+            "--output-dir",
+            output,
+        ]);
+        command
+    };
+    // the malformed export shows that bulk checks the output before parsing
+    let bulk_into = |output: &str| {
+        let mut command = migrator(temp.path());
+        command
+            .args(["bulk", "--path", "exports", "--name-prefix", "app-"])
+            .args(["--output-path", output, "--environments", "all"]);
+        command
+    };
+
+    for mut command in [single("missing/output"), bulk_into("missing/output")] {
+        command.assert().failure().stderr(predicate::str::contains(
+            "Output directory \"missing/output\" does not exist, create it or pass --create-output-dir",
+        ));
+    }
+    for mut command in [single("a-file"), bulk_into("a-file")] {
+        command.assert().failure().stderr(predicate::str::contains(
+            "Output path \"a-file\" is a file, not a directory",
+        ));
+    }
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+
+        let locked = temp.child("locked");
+        locked.create_dir_all().unwrap();
+        std::fs::set_permissions(&locked, std::fs::Permissions::from_mode(0o555)).unwrap();
+        // permissions don't stop root
+        if std::fs::write(locked.child("probe"), "").is_err() {
+            for mut command in [single("locked"), bulk_into("locked")] {
+                command.assert().failure().stderr(predicate::str::contains(
+                    "Output directory \"locked\" is not writable",
+                ));
+            }
+        }
+        std::fs::set_permissions(&locked, std::fs::Permissions::from_mode(0o755)).unwrap();
+    }
+
+    single("created/output")
+        .arg("--create-output-dir")
+        .assert()
+        .success();
+    temp.child("created/output/shop-subscription/subscription.yaml")
+        .assert(predicate::path::exists());
+    bulk_into("created/bulk")
+        .arg("--create-output-dir")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "Unexpected token inside opening tag",
+        ));
+    temp.child("created/bulk").assert(predicate::path::is_dir());
+}
+
+#[test]
+fn output_nested_in_the_scanned_path_is_not_scanned() {
+    let temp = TempDir::new().unwrap();
+    let tree = FixtureTree::new(temp.path());
+    // the generated app-shop-subscription matches the prefix as well
+    tree.export(
+        "exports/app-shop",
+        &[app("app-shop", &[("orders", "1", "dev")])],
+    )
+    .unwrap();
+    let nested = |output: &str| {
+        let mut command = migrator(tree.root());
+        command
+            .args([
+                "--verbose",
+                "bulk",
+                "--path",
+                "exports",
+                "--name-prefix",
+                "app-",
+            ])
+            .args(["--output-path", output, "--environments", "all", "--force"]);
+        command
+    };
+
+    nested("exports").assert().success();
+    temp.child("exports/app-shop-subscription/subscription.yaml")
+        .assert(predicate::path::exists());
+    nested("exports")
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("1 matched"))
+        .stderr(predicate::str::contains("1 in the output path"))
+        .stdout(predicate::str::contains(
+            "= exports/app-shop-subscription/subscription.yaml unchanged",
+        ));
+
+    #[cfg(unix)]
+    {
+        std::os::unix::fs::symlink("exports", temp.child("output-link")).unwrap();
+        nested("output-link")
+            .assert()
+            .success()
+            .stderr(predicate::str::contains("1 in the output path"))
+            .stdout(predicate::str::contains(
+                "= output-link/app-shop-subscription/subscription.yaml unchanged",
+            ));
+    }
+    assert_eq!(
+        files(&temp.child("exports")),
+        [
+            PathBuf::from("app-shop/subscribe.xml"),
+            PathBuf::from("app-shop-subscription/subscription.yaml"),
+            PathBuf::from("index.yaml"),
+        ]
+    );
+}
+
+#[test]
+fn malformed_control_plane_urls_are_rejected_before_anything_runs() {
+    let temp = copy_fixture("happy");
+    for args in [
+        ["--prod-plane-url", "htps://cp.example.com"],
+        ["--non-prod-plane-url", "cp.example.com"],
+        ["--add-env", "sandbox=https://cp example.com"],
+    ] {
+        bulk(temp.path())
+            .args(args)
+            .assert()
+            .code(2)
+            .stderr(predicate::str::contains("is no control plane URL"));
+        assert!(files(&temp.child("output")).is_empty(), "{:?}", args);
+    }
+
+    bulk(temp.path())
+        .args(["--prod-plane-url", "https://cp.example.com"])
+        .assert()
+        .success();
+    let document =
+        std::fs::read_to_string(temp.child("output/billing-subscription/subscription.yaml"))
+            .unwrap();
+    assert!(document.contains("https://cp.example.com"));
+}
+
+#[test]
+fn doctor_checks_the_control_plane_urls() {
+    let temp = copy_fixture("happy");
+    let doctor = || {
+        let mut command = migrator(temp.path());
+        command.args(["doctor", "--path", "exports", "--name-prefix", "app-"]);
+        command
+    };
+
+    doctor()
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("control-plane").not());
+    doctor()
+        .args(["--prod-plane-url", "https://cp.example.com"])
+        .args(["--add-env", "sandbox=http://localhost:8080"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "[pass] control-plane: --prod-plane-url \"https://cp.example.com\" is a valid URL",
+        ))
+        .stdout(predicate::str::contains(
+            "[pass] control-plane: --add-env sandbox \"http://localhost:8080\" is a valid URL",
+        ));
+    doctor()
+        .args(["--non-prod-plane-url", "https//cp.example.com"])
+        .assert()
+        .code(2)
+        .stdout(predicate::str::contains(
+            "[fail] control-plane: --non-prod-plane-url: \"https//cp.example.com\" is no control plane URL",
+        ))
+        .stdout(predicate::str::contains("hint: pass a URL like https://cp.example.com"));
+}
+
+#[test]
+fn version_format_warns_unless_strict_and_validate_applies_the_version_map() {
+    let temp = TempDir::new().unwrap();
+    FixtureTree::new(temp.path())
+        .export(
+            "exports/app-shop",
+            &[app(
+                "shop",
+                &[("orders", "LATEST", "dev"), ("search", "v2.1", "dev")],
+            )],
+        )
+        .unwrap();
+    let validate = |args: &[&str]| {
+        let mut command = migrator(temp.path());
+        command
+            .args(["validate", "--path", "exports", "--name-prefix", "app-"])
+            .args(args);
+        command.assert()
+    };
+    let finding = "shop orders (exports/app-shop/subscribe.xml): version \"LATEST\" doesn't match";
+
+    validate(&["--version-format"])
+        .success()
+        .stderr(predicate::str::contains(format!(
+            "warning[version-format]: {}",
+            finding
+        )))
+        .stderr(predicate::str::contains("search").not());
+    for args in [&["--strict"][..], &["--strict", "--version-format"]] {
+        validate(args)
+            .failure()
+            .stderr(predicate::str::contains(format!(
+                "error[version-format]: {}",
+                finding
+            )))
+            .stderr(predicate::str::contains(
+                "Validation failed with 1 error(s)",
+            ));
+    }
+    validate(&["--strict", "--version-map", "LATEST=2.0"])
+        .success()
+        .stderr(predicate::str::contains("version-format").not());
+
+    migrator(temp.path())
+        .args(["bulk", "--path", "exports", "--name-prefix", "app-"])
+        .args([
+            "--output-path",
+            "output",
+            "--create-output-dir",
+            "--environments",
+            "all",
+        ])
+        .arg("--version-format")
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("warning[version-format]"));
+    assert!(temp
+        .child("output/shop-subscription/subscription.yaml")
+        .exists());
+}
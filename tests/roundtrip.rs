@@ -0,0 +1,174 @@
+//!Properties of writing applications to subscribe.xml, parsing them back
+//!and converting them.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use proptest::prelude::*;
+use subscription_migrator::{
+    migrate::{parse_xml_file, to_subscribe_xml, unify_applilcations, XmlApplication},
+    schema::{upgrade_document, Upgrade, YamlApiSubscriptionV2},
+    testing::applications,
+};
+
+fn round_trip(apps: &[XmlApplication]) -> Vec<XmlApplication> {
+    parse_xml_file(to_subscribe_xml(apps).as_bytes())
+        .expect("written XML parses")
+        .0
+}
+
+fn canonical_yaml(apps: &[XmlApplication]) -> Vec<String> {
+    unify_applilcations(apps)
+        .into_iter()
+        .map(|mut document| {
+            document.canonicalize();
+            document.to_yaml().expect("documents serialize")
+        })
+        .collect()
+}
+
+proptest! {
+    #[test]
+    fn parsing_written_xml_keeps_names_and_subscriptions(apps in applications()) {
+        let parsed = round_trip(&apps);
+        prop_assert_eq!(parsed.len(), apps.len());
+        for (parsed, app) in parsed.iter().zip(&apps) {
+            prop_assert_eq!(&parsed.name, &app.name);
+            prop_assert_eq!(&parsed.token_type, &app.token_type);
+            prop_assert_eq!(parsed.token_validity, app.token_validity);
+            prop_assert_eq!(&parsed.tags, &app.tags);
+            let pairs = |app: &XmlApplication| {
+                app.apis
+                    .iter()
+                    .flat_map(|sub| {
+                        let envs = match sub.env.is_empty() {
+                            true => vec![None],
+                            false => sub.env.iter().map(Some).collect(),
+                        };
+                        envs.into_iter()
+                            .map(move |env| (sub.api_name.clone(), sub.api_version.clone(), env.cloned()))
+                    })
+                    .collect::<BTreeSet<_>>()
+            };
+            prop_assert_eq!(pairs(parsed), pairs(app));
+        }
+    }
+
+    #[test]
+    fn conversion_keeps_every_api_version(apps in applications()) {
+        let documents = unify_applilcations(&round_trip(&apps));
+        let converted = documents
+            .iter()
+            .flat_map(|document| {
+                let application = &document.subscription.application;
+                application
+                    .apis
+                    .iter()
+                    .map(|api| (application.name.clone(), api.name.clone(), api.version.clone()))
+            })
+            .collect::<BTreeSet<_>>();
+        for app in &apps {
+            for sub in &app.apis {
+                let key = (app.name.clone(), sub.api_name.clone(), sub.api_version.clone());
+                prop_assert!(converted.contains(&key), "{:?} was dropped", key);
+            }
+        }
+    }
+
+    #[test]
+    fn every_environment_is_under_exactly_one_control_plane(apps in applications()) {
+        let mut environments = BTreeMap::<&str, BTreeSet<&str>>::new();
+        for app in &apps {
+            let envs = app.apis.iter().flat_map(|sub| &sub.env).map(String::as_str);
+            environments.entry(&app.name).or_default().extend(envs);
+        }
+        for document in unify_applilcations(&round_trip(&apps)) {
+            let expected = &environments[document.name()];
+            for env in expected {
+                let blocks = document
+                    .environments
+                    .iter()
+                    .filter(|block| block.environments.iter().any(|name| name.name == *env))
+                    .count();
+                prop_assert_eq!(blocks, 1, "{} of {}", env, document.name());
+            }
+            let listed = document
+                .environments
+                .iter()
+                .flat_map(|block| &block.environments)
+                .count();
+            prop_assert_eq!(listed, expected.len());
+        }
+    }
+
+    #[test]
+    fn upgrading_to_v2_keeps_every_api_and_environment(apps in applications()) {
+        for document in unify_applilcations(&round_trip(&apps)) {
+            let application = &document.subscription.application;
+            // v1 subscribes every API in every environment of the document
+            let expected = document
+                .environments
+                .iter()
+                .flat_map(|block| {
+                    block.environments.iter().flat_map(move |env| {
+                        application.apis.iter().map(move |api| {
+                            (
+                                env.name.clone(),
+                                block.control_plane_url.clone(),
+                                api.name.clone(),
+                                api.version.clone(),
+                            )
+                        })
+                    })
+                })
+                .collect::<BTreeSet<_>>();
+            let v1 = document.to_yaml().expect("documents serialize");
+            let upgraded = upgrade_document(&v1);
+            if document.environments.is_empty() && !application.apis.is_empty() {
+                prop_assert!(upgraded.is_err(), "APIs without environments upgraded: {}", v1);
+                continue;
+            }
+            let Upgrade::Upgraded { yaml } = upgraded.expect("v1 documents upgrade") else {
+                return Err(TestCaseError::fail(format!("v1 reported as current: {}", v1)));
+            };
+            let v2: YamlApiSubscriptionV2 = serde_yaml::from_str(&yaml).expect("v2 parses");
+            prop_assert_eq!(&v2.application.name, &application.name);
+            prop_assert_eq!(&v2.application.description, &application.description);
+            let upgraded = v2
+                .environments
+                .iter()
+                .flat_map(|env| {
+                    env.apis.iter().map(move |api| {
+                        (
+                            env.name.clone(),
+                            env.control_plane_url.clone(),
+                            api.name.clone(),
+                            api.version.clone(),
+                        )
+                    })
+                })
+                .collect::<BTreeSet<_>>();
+            prop_assert_eq!(upgraded, expected);
+            prop_assert!(matches!(upgrade_document(&yaml), Ok(Upgrade::Current)));
+        }
+    }
+
+    #[test]
+    fn unify_ignores_order_and_duplicates((apps, shuffled) in shuffled_with_duplicates()) {
+        prop_assert_eq!(canonical_yaml(&shuffled), canonical_yaml(&apps));
+    }
+}
+
+///Applications, and the same ones with some repeated, in random order.
+fn shuffled_with_duplicates() -> impl Strategy<Value = (Vec<XmlApplication>, Vec<XmlApplication>)> {
+    (
+        applications(),
+        prop::collection::vec(any::<prop::sample::Index>(), 0..4),
+    )
+        .prop_flat_map(|(apps, repeats)| {
+            let mut duplicated = apps.clone();
+            if !apps.is_empty() {
+                duplicated.extend(repeats.iter().map(|index| index.get(&apps).clone()));
+            }
+            (Just(apps), Just(duplicated).prop_shuffle())
+        })
+}
@@ -0,0 +1,78 @@
+//!Repository chores, run with `cargo xtask <task>` from the repository root.
+
+use std::{
+    env,
+    path::Path,
+    process::{exit, Command},
+};
+
+///The optional features of subscription_migrator, every combination of
+///them has to build on its own.
+const FEATURES: &[&str] = &["cli", "archive", "sink-s3", "git"];
+
+///Features that only build together with others.
+const REQUIRES: &[(&str, &str)] = &[("sink-s3", "cli"), ("git", "cli")];
+
+fn main() {
+    let task = env::args().nth(1);
+    let result = match task.as_deref() {
+        Some("features") => check_features(),
+        _ => {
+            eprintln!("Usage: cargo xtask features");
+            exit(2);
+        }
+    };
+    if let Err(failed) = result {
+        eprintln!("Failed feature combinations:");
+        for features in failed {
+            eprintln!("  [{}]", features);
+        }
+        exit(1);
+    }
+}
+
+///Runs clippy over every combination of the optional features, returning
+///the combinations that failed.
+fn check_features() -> Result<(), Vec<String>> {
+    let cargo = env::var("CARGO").unwrap_or_else(|_| "cargo".to_string());
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .parent()
+        .expect("xtask lives in the repository");
+    let mut failed = Vec::new();
+    for features in combinations() {
+        let features = features.join(",");
+        eprintln!("Checking [{}]", features);
+        let status = Command::new(&cargo)
+            .current_dir(root)
+            .args(["clippy", "--all-targets", "--no-default-features"])
+            .args(["--features", &features])
+            .args(["--", "-D", "warnings"])
+            .status();
+        if !status.is_ok_and(|status| status.success()) {
+            failed.push(features);
+        }
+    }
+    match failed.is_empty() {
+        true => Ok(()),
+        false => Err(failed),
+    }
+}
+
+///Every subset of `FEATURES` that satisfies `REQUIRES`.
+fn combinations() -> Vec<Vec<&'static str>> {
+    (0..1u32 << FEATURES.len())
+        .map(|mask| {
+            FEATURES
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| mask & (1 << i) != 0)
+                .map(|(_, feature)| *feature)
+                .collect::<Vec<_>>()
+        })
+        .filter(|features| {
+            REQUIRES.iter().all(|(feature, required)| {
+                !features.contains(feature) || features.contains(required)
+            })
+        })
+        .collect()
+}